@@ -0,0 +1,622 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use account::{
+    command::{
+        application::account::{
+            chain::ChainAnchor,
+            context::RequestContext,
+            ports::{
+                inbound::{
+                    delete_account::DeleteAccountUseCase,
+                    force_snapshot::ForceSnapshotUseCase,
+                    suspend_account::SuspendAccountUseCase,
+                    verify_chain::{ChainBreakReason, VerifyChainUseCase},
+                },
+                outbound::{
+                    repository::AccountEventRepository, subscription::AccountEventLogRepository,
+                },
+            },
+            service::{account::AccountService, chain::AccountChainService},
+        },
+        domain::account::entity::{
+            aggregate::AccountAggregate,
+            command::{DeleteAccountCommand, SuspendAccountCommand},
+        },
+        infrastructure::{
+            adapters::outbound::{
+                postgres::PostgresAccountRepository, sqlite::SQLiteAccountRepository,
+            },
+            dtos::storage::sql::SQLAccountEvent,
+            encryption::{
+                fields::FieldCipher,
+                postgres::PostgresKeyRotation,
+                rotation::{KeyRotation, RotationReport},
+                sqlite::SQLiteKeyRotation,
+            },
+            transfer::{
+                jsonl::ExportFilter,
+                sqlite::{SQLiteEventExport, SQLiteEventImport},
+            },
+        },
+    },
+    common::{
+        application::ports::outbound::account_services,
+        domain::secret::SecretToken,
+        infrastructure::adapters::outbound::{
+            account_services::{argon2::AccountServices, pool::HashingPoolConfig},
+            key_provider::keyfile::KeyfileKeyProvider,
+        },
+    },
+    query::infrastructure::{
+        adapters::outbound::sqlite::SQLiteAccountViewRepository,
+        replay::sqlite::{ReplayMode, SQLiteProjectionReplay},
+    },
+};
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use cqrs_rs::{
+    domain::entity::event::{DomainEvent, EventEnvelope},
+    infrastructure::adapter::secondary::storage::sqlite::SqliteConnector,
+};
+use futures::TryStreamExt;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::sqlite::SqliteJournalMode;
+use sqlx::sqlite::SqliteSynchronous;
+use sqlx::{Pool, Sqlite};
+
+type Envelope = EventEnvelope<AccountAggregate>;
+type Repository = Arc<dyn AccountEventRepository<Envelope, Envelope> + Send + Sync>;
+
+/// Operator tooling for the account context. Every command that changes an
+/// account records the operator in the `actor` metadata of its events.
+#[derive(Parser)]
+#[command(name = "account-admin")]
+struct Cli {
+    /// Event store to operate on, `sqlite:` or `postgres:`.
+    #[arg(long, env = "DATABASE_URL", default_value = "sqlite:data.db")]
+    database_url: String,
+    /// Operator identity recorded on issued commands.
+    #[arg(long, env = "ACCOUNT_ADMIN_ACTOR")]
+    actor: Option<String>,
+    /// Keyfile for encrypted payload fields.
+    #[arg(long, env = "ACCOUNT_KEYFILE")]
+    keyfile: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs the event store migrations, and the read model's when a query
+    /// database is given.
+    Migrate {
+        #[arg(long)]
+        migrations: Option<String>,
+        #[arg(long, env = "QUERY_DATABASE_URL")]
+        query_database_url: Option<String>,
+        #[arg(long, default_value = "../../contexts/account/src/query/migrations")]
+        query_migrations: String,
+    },
+    /// Prints the aggregate's events in sequence order.
+    Events { aggregate_id: String },
+    /// Prints the aggregate's state as hydrated from its snapshot and events.
+    Show { aggregate_id: String },
+    #[command(subcommand)]
+    Outbox(OutboxCommand),
+    /// Snapshots the aggregate's current state.
+    Snapshot { aggregate_id: String },
+    /// Suspends the account.
+    Suspend {
+        aggregate_id: String,
+        #[arg(long)]
+        reason: String,
+    },
+    /// Deletes the account.
+    Delete { aggregate_id: String },
+    /// Writes events and snapshots as JSON Lines. SQLite only.
+    Export {
+        /// Defaults to stdout.
+        #[arg(long)]
+        output: Option<PathBuf>,
+        #[arg(long)]
+        aggregate_id: Option<String>,
+        /// Inclusive RFC 3339 lower bound on timestamps.
+        #[arg(long)]
+        from: Option<String>,
+        /// Exclusive RFC 3339 upper bound on timestamps.
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// Validates an export and appends it to the store. SQLite only.
+    Import {
+        input: PathBuf,
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Re-encrypts payloads under the keyfile's current key, including fields
+    /// still in plaintext or not yet bound to their record.
+    RotateKeys {
+        #[arg(long, default_value_t = 500)]
+        batch_size: i64,
+    },
+    /// Walks the event hash chains, of every account unless one is given,
+    /// and fails on the first broken link.
+    Verify {
+        #[arg(long)]
+        aggregate_id: Option<String>,
+        /// Also fails when an anchored chain lost events or its account.
+        #[arg(long, conflicts_with = "aggregate_id")]
+        anchor: Option<PathBuf>,
+    },
+    /// Verifies every hash chain and writes a signed record of their heads.
+    /// Keep it outside the database, where truncating a chain or deleting an
+    /// account cannot reach it.
+    AnchorChains {
+        /// Defaults to stdout.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Hashes and links the events stored while chaining was off. Run it
+    /// before the command service starts with `EVENT_CHAIN_KEY`, since
+    /// accounts with unhashed events refuse new ones until then.
+    BackfillChain,
+    /// Rebuilds the read model from the event log. Running the same replay
+    /// id again resumes it.
+    ReplayProjection {
+        replay_id: String,
+        #[arg(long, env = "QUERY_DATABASE_URL")]
+        query_database_url: String,
+        /// Builds into a new table and swaps it in, so the query service can
+        /// keep running. Without it the live table is rebuilt in place and
+        /// the query service has to be stopped.
+        #[arg(long)]
+        shadow: bool,
+        /// Subscription whose checkpoint is moved to the replayed position.
+        #[arg(long, default_value = "account_view_projector")]
+        subscription_id: String,
+        #[arg(long, default_value_t = 500)]
+        batch_size: i64,
+    },
+}
+
+#[derive(Subcommand)]
+enum OutboxCommand {
+    /// Lists pending outbox events, oldest first.
+    List {
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+    },
+    /// Lists events that exhausted their delivery attempts.
+    DeadLetters {
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+        #[arg(long, default_value_t = 0)]
+        offset: i64,
+    },
+    /// Makes the event eligible for delivery on the next relay batch, moving
+    /// it back from the dead letters if needed.
+    Retry { sequence: String },
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let cli = Cli::parse();
+    let cipher = match &cli.keyfile {
+        Some(x) => FieldCipher::new(Arc::new(KeyfileKeyProvider::load(x)?)),
+        None => FieldCipher::default(),
+    };
+    let chain_key = chain_key();
+    let (repository, migrations) =
+        connect(&cli.database_url, cipher.clone(), chain_key.clone()).await?;
+    let services: Arc<dyn account_services::AccountServices + Sync + Send> =
+        Arc::new(AccountServices::new(HashingPoolConfig::default())?);
+    let service: AccountService<Envelope, Envelope> =
+        AccountService::new(services, repository.clone());
+
+    match cli.command {
+        Command::Migrate {
+            migrations: path,
+            query_database_url,
+            query_migrations,
+        } => {
+            let path = path.unwrap_or_else(|| migrations.to_string());
+            repository.migrate(path.clone()).await?;
+            println!("migrated event store from {}", path);
+            if let Some(url) = query_database_url {
+                let connector = SqliteConnector::new(sqlite_pool(&url).await).await.unwrap();
+                SQLiteAccountViewRepository::new(connector)
+                    .migrate(query_migrations.clone())
+                    .await?;
+                println!("migrated read model from {}", query_migrations);
+            }
+        }
+        Command::Events { aggregate_id } => {
+            let mut events = repository.stream_events(aggregate_id.clone(), None);
+            let mut count = 0;
+            while let Some(event) = events.try_next().await? {
+                print_event(&event)?;
+                count += 1;
+            }
+            if count == 0 {
+                return Err(anyhow!("account `{}` has no events", aggregate_id));
+            }
+        }
+        Command::Show { aggregate_id } => match service.load_aggregate(aggregate_id.clone()).await?
+        {
+            Some(x) => print_aggregate(&x),
+            None => return Err(anyhow!("account `{}` has no events", aggregate_id)),
+        },
+        Command::Outbox(OutboxCommand::List { limit }) => {
+            for event in repository.retrieve_outbox_batch(limit).await? {
+                print_event(&event)?;
+            }
+        }
+        Command::Outbox(OutboxCommand::DeadLetters { limit, offset }) => {
+            for letter in repository.list_dead_letters(limit, offset).await? {
+                println!(
+                    "attempts={} dead_lettered_at={} last_error={}",
+                    letter.attempts,
+                    letter.dead_lettered_at.to_rfc3339(),
+                    letter.last_error.unwrap_or_default()
+                );
+                print_event(&letter.event)?;
+            }
+        }
+        Command::Outbox(OutboxCommand::Retry { sequence }) => {
+            if repository.retry_outbox_event(sequence.clone()).await? {
+                println!("outbox event {} scheduled for retry", sequence);
+            } else if repository.requeue_dead_letter(sequence.clone()).await? {
+                println!("dead letter {} moved back to the outbox", sequence);
+            } else {
+                return Err(anyhow!("no outbox event or dead letter `{}`", sequence));
+            }
+        }
+        Command::Snapshot { aggregate_id } => {
+            match service.force_snapshot(aggregate_id.clone()).await? {
+                Some(x) => println!("stored snapshot {} of account {}", x, aggregate_id),
+                None => return Err(anyhow!("account `{}` has no events", aggregate_id)),
+            }
+        }
+        Command::Suspend {
+            aggregate_id,
+            reason,
+        } => {
+            let context = operator_context(cli.actor)?;
+            let aggregate: AccountAggregate = service
+                .suspend_account(
+                    SuspendAccountCommand {
+                        id: aggregate_id,
+                        reason,
+                    },
+                    context,
+                )
+                .await?;
+            print_aggregate(&aggregate);
+        }
+        Command::Delete { aggregate_id } => {
+            let context = operator_context(cli.actor)?;
+            let aggregate: AccountAggregate = service
+                .delete_account(DeleteAccountCommand { id: aggregate_id }, context)
+                .await?;
+            print_aggregate(&aggregate);
+        }
+        Command::Export {
+            output,
+            aggregate_id,
+            from,
+            to,
+        } => {
+            let filter = ExportFilter {
+                aggregate_id,
+                from: from.map(|x| parse_timestamp(&x)).transpose()?,
+                to: to.map(|x| parse_timestamp(&x)).transpose()?,
+            };
+            let export = SQLiteEventExport::new(sqlite_connector(&cli.database_url).await?)
+                .with_filter(filter);
+            let report = match output {
+                Some(path) => export.run(&mut BufWriter::new(File::create(path)?)).await?,
+                None => export.run(&mut std::io::stdout()).await?,
+            };
+            eprintln!(
+                "exported {} events and {} snapshots of {} accounts",
+                report.events, report.snapshots, report.aggregates
+            );
+        }
+        Command::Import { input, dry_run } => {
+            let report = SQLiteEventImport::new(sqlite_connector(&cli.database_url).await?)
+                .with_cipher(cipher)
+                .with_chain_key(require_chain_key(&chain_key)?)
+                .with_dry_run(dry_run)
+                .run(BufReader::new(File::open(input)?))
+                .await?;
+            println!(
+                "{} {} events and {} snapshots of {} accounts",
+                if report.dry_run {
+                    "validated"
+                } else {
+                    "imported"
+                },
+                report.events,
+                report.snapshots,
+                report.aggregates
+            );
+        }
+        Command::RotateKeys { batch_size } => {
+            let report = rotate_keys(&cli.database_url, cipher, batch_size).await?;
+            println!(
+                "re-encrypted {} of {} payloads",
+                report.rotated, report.scanned
+            );
+        }
+        Command::Verify {
+            aggregate_id,
+            anchor,
+        } => {
+            let chain: AccountChainService<Envelope, Envelope> =
+                AccountChainService::new(repository.clone())
+                    .with_key(require_chain_key(&chain_key)?);
+            let report = match (aggregate_id, anchor) {
+                (Some(x), _) => chain.verify_aggregate(x).await?,
+                (None, Some(path)) => {
+                    let anchor: ChainAnchor =
+                        serde_json::from_reader(BufReader::new(File::open(path)?))?;
+                    chain.verify_anchor(&anchor).await?
+                }
+                (None, None) => chain.verify_all().await?,
+            };
+            println!(
+                "checked {} events of {} accounts",
+                report.events, report.aggregates
+            );
+            if let Some(x) = report.first_break {
+                return Err(anyhow!(
+                    "hash chain of account `{}` breaks at event {}: {}",
+                    x.aggregate_id,
+                    x.sequence,
+                    describe_break(&x.reason)
+                ));
+            }
+        }
+        Command::AnchorChains { output } => {
+            let chain: AccountChainService<Envelope, Envelope> =
+                AccountChainService::new(repository.clone())
+                    .with_key(require_chain_key(&chain_key)?);
+            let anchor = chain.anchor().await?;
+            match output {
+                Some(path) => serde_json::to_writer(BufWriter::new(File::create(path)?), &anchor)?,
+                None => serde_json::to_writer(std::io::stdout(), &anchor)?,
+            }
+            eprintln!(
+                "anchored the hash chains of {} accounts",
+                anchor.heads.len()
+            );
+        }
+        Command::BackfillChain => {
+            let chain: AccountChainService<Envelope, Envelope> =
+                AccountChainService::new(repository.clone())
+                    .with_key(require_chain_key(&chain_key)?);
+            println!(
+                "hash chain backfilled for {} events",
+                chain.backfill().await?
+            );
+        }
+        Command::ReplayProjection {
+            replay_id,
+            query_database_url,
+            shadow,
+            subscription_id,
+            batch_size,
+        } => {
+            let mode = if shadow {
+                ReplayMode::Shadow
+            } else {
+                ReplayMode::InPlace
+            };
+            let report = SQLiteProjectionReplay::new(
+                sqlite_connector(&query_database_url).await?,
+                event_log(&cli.database_url, cipher).await?,
+                &replay_id,
+                &subscription_id,
+                mode,
+            )?
+            .with_batch_size(batch_size)
+            .run()
+            .await?;
+            println!(
+                "replay {} completed at position {}",
+                report.replay_id, report.position
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn describe_break(reason: &ChainBreakReason) -> String {
+    return match reason {
+        ChainBreakReason::Unhashed => "event has no hash".into(),
+        ChainBreakReason::PrevHashMismatch { expected, found } => format!(
+            "previous hash is {}, expected {}",
+            found.as_deref().unwrap_or("empty"),
+            expected.as_deref().unwrap_or("empty")
+        ),
+        ChainBreakReason::HashMismatch { expected, found } => {
+            format!("hash is {}, contents hash to {}", found, expected)
+        }
+        ChainBreakReason::Truncated {
+            anchored_events,
+            found_events,
+        } => format!(
+            "chain holds {} events, {} when it was anchored",
+            found_events, anchored_events
+        ),
+    };
+}
+
+/// Events are chained with the secret in `EVENT_CHAIN_KEY`, which has to be
+/// the one the command service uses, set or not, for commands that append
+/// events.
+fn chain_key() -> Option<Arc<SecretToken>> {
+    return match std::env::var("EVENT_CHAIN_KEY") {
+        Ok(x) if !x.is_empty() => Some(Arc::new(x.into())),
+        _ => None,
+    };
+}
+
+fn require_chain_key(key: &Option<Arc<SecretToken>>) -> Result<Arc<SecretToken>, anyhow::Error> {
+    return key
+        .clone()
+        .ok_or_else(|| anyhow!("EVENT_CHAIN_KEY must be set"));
+}
+
+async fn connect(
+    database_url: &str,
+    cipher: FieldCipher,
+    chain_key: Option<Arc<SecretToken>>,
+) -> Result<(Repository, &'static str), anyhow::Error> {
+    if database_url.starts_with("postgres:") || database_url.starts_with("postgresql:") {
+        let pool = PgPoolOptions::new().connect(database_url).await?;
+        let mut postgres = PostgresAccountRepository::new(pool).with_cipher(cipher);
+        if let Some(x) = chain_key {
+            postgres = postgres.with_chain_key(x);
+        }
+        return Ok((
+            Arc::new(postgres),
+            "../../contexts/account/src/command/migrations_postgres",
+        ));
+    }
+    let connector = SqliteConnector::new(sqlite_pool(database_url).await)
+        .await
+        .unwrap();
+    let mut sqlite = SQLiteAccountRepository::new(connector).with_cipher(cipher);
+    if let Some(x) = chain_key {
+        sqlite = sqlite.with_chain_key(x);
+    }
+    return Ok((
+        Arc::new(sqlite),
+        "../../contexts/account/src/command/migrations",
+    ));
+}
+
+async fn event_log(
+    database_url: &str,
+    cipher: FieldCipher,
+) -> Result<Arc<dyn AccountEventLogRepository + Send + Sync>, anyhow::Error> {
+    if database_url.starts_with("postgres:") || database_url.starts_with("postgresql:") {
+        let pool = PgPoolOptions::new().connect(database_url).await?;
+        return Ok(Arc::new(
+            PostgresAccountRepository::new(pool).with_cipher(cipher),
+        ));
+    }
+    return Ok(Arc::new(
+        SQLiteAccountRepository::new(sqlite_connector(database_url).await?).with_cipher(cipher),
+    ));
+}
+
+async fn rotate_keys(
+    database_url: &str,
+    cipher: FieldCipher,
+    batch_size: i64,
+) -> Result<RotationReport, anyhow::Error> {
+    if database_url.starts_with("postgres:") || database_url.starts_with("postgresql:") {
+        let pool = PgPoolOptions::new().connect(database_url).await?;
+        return PostgresKeyRotation::new(pool, cipher)
+            .with_batch_size(batch_size)
+            .run()
+            .await;
+    }
+    return SQLiteKeyRotation::new(sqlite_connector(database_url).await?, cipher)
+        .with_batch_size(batch_size)
+        .run()
+        .await;
+}
+
+async fn sqlite_connector(database_url: &str) -> Result<Arc<SqliteConnector>, anyhow::Error> {
+    if !database_url.starts_with("sqlite:") {
+        return Err(anyhow!("this command only supports sqlite databases"));
+    }
+    return Ok(SqliteConnector::new(sqlite_pool(database_url).await)
+        .await
+        .unwrap());
+}
+
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, anyhow::Error> {
+    return Ok(DateTime::parse_from_rfc3339(value)?.with_timezone(&Utc));
+}
+
+async fn sqlite_pool(url: &str) -> Result<Pool<Sqlite>, anyhow::Error> {
+    return sqlx::Pool::connect_with(
+        SqliteConnectOptions::from_str(url)?
+            .journal_mode(SqliteJournalMode::Wal)
+            .create_if_missing(true)
+            .foreign_keys(false)
+            .synchronous(SqliteSynchronous::Normal),
+    )
+    .await
+    .map_err(|e| anyhow!(e));
+}
+
+/// Commands issued from here are attributed to the operator, so one has to
+/// be named either with `--actor` or `ACCOUNT_ADMIN_ACTOR`.
+fn operator_context(actor: Option<String>) -> Result<RequestContext, anyhow::Error> {
+    let actor = match actor {
+        Some(x) if !x.trim().is_empty() => x,
+        _ => return Err(anyhow!("an operator identity is required, pass --actor")),
+    };
+    let mut context = RequestContext::new();
+    context.actor = Some(format!("operator:{}", actor.trim()));
+    context.user_agent = Some(format!("account-admin/{}", env!("CARGO_PKG_VERSION")));
+    return Ok(context);
+}
+
+/// Prints the event on one line, leaving out the password hash.
+fn print_event(event: &EventEnvelope<AccountAggregate>) -> Result<(), anyhow::Error> {
+    let mut payload = serde_json::to_value(SQLAccountEvent::from(event.payload.clone()))?;
+    if let Some(x) = payload.as_object_mut() {
+        x.remove("password_hash");
+    }
+    println!(
+        "{} {} {} {} metadata={} payload={}",
+        event.sequence,
+        event.timestamp.to_rfc3339(),
+        event.aggregate_id,
+        event.payload.event_type(),
+        serde_json::to_string(&event.metadata)?,
+        payload
+    );
+    return Ok(());
+}
+
+/// Prints the aggregate without its password hash.
+fn print_aggregate(aggregate: &AccountAggregate) {
+    println!("id:          {}", aggregate.id.clone().unwrap_or_default());
+    println!(
+        "email:       {}",
+        aggregate.email.clone().unwrap_or_default()
+    );
+    println!(
+        "status:      {}",
+        aggregate.status.clone().unwrap_or_default()
+    );
+    println!(
+        "created_at:  {}",
+        aggregate
+            .created_at
+            .map(|x| x.to_rfc3339())
+            .unwrap_or_default()
+    );
+    println!(
+        "last_event:  {}",
+        aggregate
+            .last_event
+            .as_ref()
+            .map(|x| format!("{} {}", x.event_type(), x.event_id()))
+            .unwrap_or_default()
+    );
+}
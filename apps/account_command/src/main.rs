@@ -1,27 +1,45 @@
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use account::command::application::account::ports::outbound::repository::AccountEventRepository;
+use account::command::domain::account::entity::aggregate::AccountAggregate;
 use account::{
     command::{
-        application::account::service::account::AccountService,
+        application::account::service::{
+            account::AccountService,
+            outbox::AccountOutboxService,
+            relay::{OutboxRelay, OutboxRelayConfig},
+        },
         infrastructure::{
             adapters::{
-                inbound::graphql::GraphQLAccountCommandAdapter,
-                outbound::sqlite::SQLiteAccountRepository,
+                inbound::graphql::{GraphQLAccountCommandAdapter, TrustedProxies},
+                outbound::{
+                    jetstream::JetStreamEventBus, postgres::PostgresAccountRepository,
+                    sqlite::SQLiteAccountRepository,
+                },
+            },
+            encryption::{
+                fields::FieldCipher, postgres::PostgresKeyRotation, rotation::KeyRotation,
+                sqlite::SQLiteKeyRotation,
             },
-            dtos::transport::nats::NATSAccountEvent,
         },
     },
     common::{
         application::ports::outbound::account_services,
-        infrastructure::adapters::outbound::account_services::argon2::AccountServices,
+        domain::secret::SecretToken,
+        infrastructure::adapters::outbound::{
+            account_services::{argon2::AccountServices, pool::HashingPoolConfig},
+            key_provider::keyfile::KeyfileKeyProvider,
+        },
     },
 };
 use anyhow::anyhow;
-use cqrs_rs::infrastructure::{
-    adapter::secondary::storage::sqlite::SqliteConnector, dto::transport::nats::NATSEventEnvelope,
+use cqrs_rs::{
+    application::port::outbound::event_bus::EventBus, domain::entity::event::EventEnvelope,
+    infrastructure::adapter::secondary::storage::sqlite::SqliteConnector,
 };
+use sqlx::postgres::PgPoolOptions;
 use sqlx::sqlite::SqliteConnectOptions;
 use sqlx::sqlite::SqliteJournalMode;
 use sqlx::sqlite::SqliteSynchronous;
@@ -29,7 +47,13 @@ use sqlx::{Pool, Sqlite};
 use tokio::signal;
 use tokio::signal::unix::signal;
 use tokio::signal::unix::SignalKind;
+use tokio::sync::watch;
 use tracing_subscriber::prelude::*;
+use ulid::Ulid;
+
+type Envelope = EventEnvelope<AccountAggregate>;
+type Repository = Arc<dyn AccountEventRepository<Envelope, Envelope> + Send + Sync>;
+type Rotation = Option<Box<dyn KeyRotation + Send + Sync>>;
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
@@ -40,44 +64,138 @@ async fn main() -> Result<(), anyhow::Error> {
         .install_simple()?;
     let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
     tracing_subscriber::registry().with(telemetry).try_init()?;
-    let conn: Result<Pool<Sqlite>, anyhow::Error> = sqlx::Pool::connect_with(
-        SqliteConnectOptions::from_str("sqlite:data.db")?
-            .journal_mode(SqliteJournalMode::Wal)
-            .create_if_missing(true)
-            .foreign_keys(false)
-            .synchronous(SqliteSynchronous::Normal),
-    )
-    .await
-    .map_err(|e| anyhow!(e));
-    let connector = SqliteConnector::new(conn).await.unwrap();
-    let repository: Arc<
-        dyn AccountEventRepository<NATSEventEnvelope<NATSAccountEvent>, String>
-            + Send
-            + Sync
-            + 'static,
-    > = Arc::new(SQLiteAccountRepository {
-        connector: connector,
-    });
-    match repository
-        .migrate("../../contexts/account/src/command/migrations".into())
+    let database_url =
+        std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:data.db".to_string());
+    let cipher = field_cipher()?;
+    let chain_key = chain_key();
+    let use_postgres =
+        database_url.starts_with("postgres:") || database_url.starts_with("postgresql:");
+    let (repository, commits, migrations, rotation) = if use_postgres {
+        let pool = PgPoolOptions::new().connect(&database_url).await?;
+        let rotation: Rotation = if cipher.is_enabled() {
+            Some(Box::new(PostgresKeyRotation::new(
+                pool.clone(),
+                cipher.clone(),
+            )))
+        } else {
+            None
+        };
+        let mut postgres = PostgresAccountRepository::new(pool).with_cipher(cipher.clone());
+        if let Some(x) = &chain_key {
+            postgres = postgres.with_chain_key(x.clone());
+        }
+        let commits = postgres.notifier.subscribe();
+        (
+            Arc::new(postgres) as Repository,
+            commits,
+            "../../contexts/account/src/command/migrations_postgres",
+            rotation,
+        )
+    } else {
+        let conn: Result<Pool<Sqlite>, anyhow::Error> = sqlx::Pool::connect_with(
+            SqliteConnectOptions::from_str(&database_url)?
+                .journal_mode(SqliteJournalMode::Wal)
+                .create_if_missing(true)
+                .foreign_keys(false)
+                .synchronous(SqliteSynchronous::Normal),
+        )
         .await
-    {
-        Err(e) => {
-            println!("ERROR: {:?}", e);
-            std::process::exit(1)
+        .map_err(|e| anyhow!(e));
+        let connector = SqliteConnector::new(conn).await.unwrap();
+        let rotation: Rotation = if cipher.is_enabled() {
+            Some(Box::new(SQLiteKeyRotation::new(
+                connector.clone(),
+                cipher.clone(),
+            )))
+        } else {
+            None
+        };
+        let mut sqlite = SQLiteAccountRepository::new(connector).with_cipher(cipher.clone());
+        if let Some(x) = &chain_key {
+            sqlite = sqlite.with_chain_key(x.clone());
         }
-        _ => {}
+        let commits = sqlite.notifier.subscribe();
+        (
+            Arc::new(sqlite) as Repository,
+            commits,
+            "../../contexts/account/src/command/migrations",
+            rotation,
+        )
+    };
+    if let Err(e) = repository.migrate(migrations.into()).await {
+        println!("ERROR: {:?}", e);
+        std::process::exit(1)
+    }
+    // Re-encrypts payloads written under older keys, or before encryption
+    // was enabled, while the service is already up.
+    if let Some(rotation) = rotation {
+        tokio::spawn(async move {
+            match rotation.run().await {
+                Ok(x) => println!(
+                    "key rotation re-encrypted {} of {} payloads",
+                    x.rotated, x.scanned
+                ),
+                Err(e) => println!("ERROR: key rotation stopped with {:?}", e),
+            }
+        });
     }
     let services: Arc<dyn account_services::AccountServices + Sync + Send> =
-        Arc::new(AccountServices::new());
-    let service: Arc<AccountService<NATSEventEnvelope<NATSAccountEvent>, String>> =
-        Arc::new(AccountService::new(services.clone(), repository.clone()));
+        Arc::new(account_services()?);
+    let mut service: AccountService<Envelope, Envelope> =
+        AccountService::new(services.clone(), repository.clone())
+            .with_idempotency_secret(idempotency_secret()?);
+    if let Some(x) = std::env::var("IDEMPOTENCY_PENDING_TTL_MS")
+        .ok()
+        .and_then(|x| x.parse().ok())
+    {
+        service = service.with_idempotency_ttl(Duration::from_millis(x));
+    }
+    let service = Arc::new(service);
 
-    GraphQLAccountCommandAdapter::new(service).run().await?;
+    // Without a broker the relay is not started, so events stay in the
+    // outbox until one is configured instead of being deleted unpublished.
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let relay = match std::env::var("NATS_URL") {
+        Ok(url) => {
+            let jetstream = JetStreamEventBus::connect(&url).await?;
+            jetstream
+                .ensure_stream("ACCOUNT_EVENTS", Duration::from_secs(120))
+                .await?;
+            let bus: Arc<
+                dyn EventBus<
+                        EventEnvelope<AccountAggregate>,
+                        EventEnvelope<AccountAggregate>,
+                        EventEnvelope<AccountAggregate>,
+                        EventEnvelope<AccountAggregate>,
+                    > + Send
+                    + Sync,
+            > = Arc::new(jetstream);
+            let outbox: Arc<AccountOutboxService<Envelope, Envelope>> = Arc::new(
+                AccountOutboxService::new(repository.clone(), bus)
+                    .with_lease(relay_owner(), relay_lease())
+                    .with_retry_policy(relay_max_attempts(), Duration::from_secs(1)),
+            );
+            Some(tokio::spawn(
+                OutboxRelay::new(outbox, relay_config(), commits, shutdown_rx).run(),
+            ))
+        }
+        Err(_) => {
+            println!("NATS_URL is not set, events are kept in the outbox unpublished");
+            None
+        }
+    };
 
+    let server = GraphQLAccountCommandAdapter::new(service)
+        .with_trusted_proxies(trusted_proxies()?)
+        .run();
     let mut sigterm = signal(SignalKind::terminate())?;
 
     tokio::select! {
+        result = server => {
+            if let Err(e) = result {
+                println!("ERROR: {:?}", e);
+            }
+        }
         _ = signal::ctrl_c() => {
             println!("🎩 Ctrl-C received, shutting down");
         }
@@ -86,7 +204,144 @@ async fn main() -> Result<(), anyhow::Error> {
         }
     }
 
+    let _ = shutdown_tx.send(true);
+    if let Some(relay) = relay {
+        match relay.await {
+            Ok(Err(e)) => println!("ERROR: outbox relay stopped with {:?}", e),
+            Err(e) => println!("ERROR: outbox relay panicked {:?}", e),
+            _ => {}
+        }
+    }
+
     opentelemetry::global::shutdown_tracer_provider();
 
     Ok(())
 }
+
+/// Payload fields are encrypted with the keys in `ACCOUNT_KEYFILE`, and
+/// stored in plaintext when it is not set.
+fn field_cipher() -> Result<FieldCipher, anyhow::Error> {
+    return match std::env::var("ACCOUNT_KEYFILE") {
+        Ok(path) => Ok(FieldCipher::new(Arc::new(KeyfileKeyProvider::load(path)?))),
+        Err(_) => Ok(FieldCipher::default()),
+    };
+}
+
+/// Idempotency fingerprints are keyed with the secret in `IDEMPOTENCY_SECRET`.
+/// It is required so a reused key with another password is always rejected.
+fn idempotency_secret() -> Result<SecretToken, anyhow::Error> {
+    return match std::env::var("IDEMPOTENCY_SECRET") {
+        Ok(x) if !x.is_empty() => Ok(x.into()),
+        _ => Err(anyhow!("IDEMPOTENCY_SECRET must be set")),
+    };
+}
+
+/// Events are chained with the secret in `EVENT_CHAIN_KEY`. It has to stay
+/// out of the database, or whoever can rewrite the events can rehash them.
+/// Without it events are stored unhashed. Turning it on for a store that
+/// already holds events takes one `account_admin backfill-chain` run first,
+/// or appends to those accounts are refused.
+fn chain_key() -> Option<Arc<SecretToken>> {
+    return match std::env::var("EVENT_CHAIN_KEY") {
+        Ok(x) if !x.is_empty() => Some(Arc::new(x.into())),
+        _ => {
+            println!("WARNING: EVENT_CHAIN_KEY is not set, events are stored without a hash chain and tampering goes undetected");
+            None
+        }
+    };
+}
+
+/// New password hashes use the argon2 costs in `ARGON2_MEMORY_KIB`,
+/// `ARGON2_ITERATIONS` and `ARGON2_PARALLELISM`, and the secret in
+/// `PASSWORD_PEPPER` when it is set. `PASSWORD_HASHING_CONCURRENCY` and
+/// `PASSWORD_HASHING_QUEUE_TIMEOUT_MS` bound the hashing pool.
+fn account_services() -> Result<AccountServices, anyhow::Error> {
+    let mut pool = HashingPoolConfig::default();
+    if let Some(x) = std::env::var("PASSWORD_HASHING_CONCURRENCY")
+        .ok()
+        .and_then(|x| x.parse().ok())
+    {
+        pool.concurrency = x;
+    }
+    if let Some(x) = std::env::var("PASSWORD_HASHING_QUEUE_TIMEOUT_MS")
+        .ok()
+        .and_then(|x| x.parse().ok())
+    {
+        pool.queue_timeout = Duration::from_millis(x);
+    }
+    let cost = |name: &str| std::env::var(name).ok().and_then(|x| x.parse().ok());
+    let mut services = AccountServices::new(pool)?;
+    if let Some(x) = cost("ARGON2_MEMORY_KIB") {
+        services = services.with_memory_cost(x)?;
+    }
+    if let Some(x) = cost("ARGON2_ITERATIONS") {
+        services = services.with_iterations(x)?;
+    }
+    if let Some(x) = cost("ARGON2_PARALLELISM") {
+        services = services.with_parallelism(x)?;
+    }
+    if let Ok(x) = std::env::var("PASSWORD_PEPPER") {
+        services = services.with_pepper(x.into());
+    }
+    return Ok(services);
+}
+
+/// `TRUSTED_PROXIES` lists the comma separated addresses of the proxies
+/// whose `x-authenticated-user` and `x-forwarded-for` headers are believed.
+fn trusted_proxies() -> Result<TrustedProxies, anyhow::Error> {
+    let addresses = match std::env::var("TRUSTED_PROXIES") {
+        Ok(x) => x
+            .split(',')
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(|x| {
+                x.parse()
+                    .map_err(|e| anyhow!("invalid proxy `{}`: {}", x, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        Err(_) => vec![],
+    };
+    return Ok(TrustedProxies::new(addresses));
+}
+
+fn relay_config() -> OutboxRelayConfig {
+    let mut config = OutboxRelayConfig::default();
+    if let Some(x) = std::env::var("OUTBOX_POLL_INTERVAL_MS")
+        .ok()
+        .and_then(|x| x.parse().ok())
+    {
+        config.poll_interval = Duration::from_millis(x);
+    }
+    if let Some(x) = std::env::var("OUTBOX_BATCH_SIZE")
+        .ok()
+        .and_then(|x| x.parse().ok())
+    {
+        config.batch_size = x;
+    }
+    if let Some(x) = std::env::var("OUTBOX_MAX_BACKOFF_MS")
+        .ok()
+        .and_then(|x| x.parse().ok())
+    {
+        config.max_backoff = Duration::from_millis(x);
+    }
+    return config;
+}
+
+fn relay_owner() -> String {
+    return std::env::var("OUTBOX_RELAY_ID").unwrap_or_else(|_| Ulid::new().to_string());
+}
+
+fn relay_lease() -> Duration {
+    let lease = std::env::var("OUTBOX_LEASE_MS")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(30_000);
+    return Duration::from_millis(lease);
+}
+
+fn relay_max_attempts() -> i64 {
+    return std::env::var("OUTBOX_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(10);
+}
@@ -0,0 +1,184 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use account::{
+    command::{
+        application::account::{
+            ports::outbound::subscription::AccountEventLogRepository,
+            service::subscription::{CatchUpSubscription, SubscriptionConfig},
+        },
+        infrastructure::{
+            adapters::{
+                inbound::graphql::TrustedProxies,
+                outbound::{postgres::PostgresAccountRepository, sqlite::SQLiteAccountRepository},
+            },
+            encryption::fields::FieldCipher,
+        },
+    },
+    common::infrastructure::adapters::outbound::key_provider::keyfile::KeyfileKeyProvider,
+    query::{
+        application::account::service::{
+            account::{AccountQueryService, QueryServiceTrait},
+            projector::AccountProjector,
+        },
+        infrastructure::{
+            adapters::{
+                inbound::graphql::GraphQLAccountQueryAdapter,
+                outbound::sqlite::SQLiteAccountViewRepository,
+            },
+            dtos::transport::graphql::GraphQLAccountView,
+        },
+    },
+};
+use anyhow::anyhow;
+use cqrs_rs::infrastructure::adapter::secondary::storage::sqlite::SqliteConnector;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::sqlite::SqliteJournalMode;
+use sqlx::sqlite::SqliteSynchronous;
+use sqlx::{Pool, Sqlite};
+use tokio::signal;
+use tokio::signal::unix::signal;
+use tokio::signal::unix::SignalKind;
+use tokio::sync::watch;
+use tracing_subscriber::prelude::*;
+
+const PROJECTOR_SUBSCRIPTION_ID: &str = "account_view_projector";
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    opentelemetry::global::set_text_map_propagator(opentelemetry_jaeger::Propagator::new());
+    let tracer = opentelemetry_jaeger::new_agent_pipeline()
+        .with_endpoint("localhost:6831")
+        .with_service_name("account-query")
+        .install_simple()?;
+    let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
+    tracing_subscriber::registry().with(telemetry).try_init()?;
+
+    let database_url =
+        std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:data.db".to_string());
+    // Events are decrypted with the same keyfile the command side writes with.
+    let cipher = match std::env::var("ACCOUNT_KEYFILE") {
+        Ok(path) => FieldCipher::new(Arc::new(KeyfileKeyProvider::load(path)?)),
+        Err(_) => FieldCipher::default(),
+    };
+    let log: Arc<dyn AccountEventLogRepository + Send + Sync> =
+        if database_url.starts_with("postgres:") || database_url.starts_with("postgresql:") {
+            let pool = PgPoolOptions::new().connect(&database_url).await?;
+            Arc::new(PostgresAccountRepository::new(pool).with_cipher(cipher))
+        } else {
+            Arc::new(SQLiteAccountRepository::new(sqlite(&database_url).await?).with_cipher(cipher))
+        };
+
+    let query_url =
+        std::env::var("QUERY_DATABASE_URL").unwrap_or_else(|_| "sqlite:query.db".to_string());
+    let views = SQLiteAccountViewRepository::new(sqlite(&query_url).await?);
+    if let Err(e) = views
+        .migrate("../../contexts/account/src/query/migrations".into())
+        .await
+    {
+        println!("ERROR: {:?}", e);
+        std::process::exit(1)
+    }
+    let views = Arc::new(views);
+
+    // The event store lives in another process, so there are no commit
+    // notifications to wait for and the subscription polls instead.
+    let (_commits_tx, commits) = watch::channel(0);
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let projector = tokio::spawn(
+        CatchUpSubscription::new(
+            PROJECTOR_SUBSCRIPTION_ID.into(),
+            log,
+            views.clone(),
+            Arc::new(AccountProjector::new(views.clone())),
+            commits,
+            shutdown_rx,
+        )
+        .with_config(subscription_config())
+        .run(),
+    );
+
+    let service: Arc<dyn QueryServiceTrait<GraphQLAccountView> + Send + Sync> =
+        Arc::new(AccountQueryService::new(views));
+    let address = std::env::var("QUERY_ADDRESS").unwrap_or_else(|_| "0.0.0.0:3001".to_string());
+    let server = GraphQLAccountQueryAdapter::new(service)
+        .with_trusted_proxies(trusted_proxies()?)
+        .run(&address);
+    let mut sigterm = signal(SignalKind::terminate())?;
+
+    tokio::select! {
+        result = server => {
+            if let Err(e) = result {
+                println!("ERROR: {:?}", e);
+            }
+        }
+        _ = signal::ctrl_c() => {
+            println!("🎩 Ctrl-C received, shutting down");
+        }
+        _ = sigterm.recv() => {
+            println!("terminate signal received, shutting down");
+        }
+    }
+
+    let _ = shutdown_tx.send(true);
+    match projector.await {
+        Ok(Err(e)) => println!("ERROR: account projector stopped with {:?}", e),
+        Err(e) => println!("ERROR: account projector panicked {:?}", e),
+        _ => {}
+    }
+
+    opentelemetry::global::shutdown_tracer_provider();
+
+    Ok(())
+}
+
+async fn sqlite(url: &str) -> Result<Arc<SqliteConnector>, anyhow::Error> {
+    let conn: Result<Pool<Sqlite>, anyhow::Error> = sqlx::Pool::connect_with(
+        SqliteConnectOptions::from_str(url)?
+            .journal_mode(SqliteJournalMode::Wal)
+            .create_if_missing(true)
+            .foreign_keys(false)
+            .synchronous(SqliteSynchronous::Normal),
+    )
+    .await
+    .map_err(|e| anyhow!(e));
+    return Ok(SqliteConnector::new(conn).await.unwrap());
+}
+
+fn subscription_config() -> SubscriptionConfig {
+    let mut config = SubscriptionConfig::default();
+    if let Some(x) = std::env::var("PROJECTOR_POLL_INTERVAL_MS")
+        .ok()
+        .and_then(|x| x.parse().ok())
+    {
+        config.poll_interval = Duration::from_millis(x);
+    }
+    if let Some(x) = std::env::var("PROJECTOR_BATCH_SIZE")
+        .ok()
+        .and_then(|x| x.parse().ok())
+    {
+        config.batch_size = x;
+    }
+    return config;
+}
+
+/// `TRUSTED_PROXIES` lists the comma separated addresses of the proxies
+/// whose `x-authenticated-user` header is believed. `accountByEmail` is
+/// refused to everyone else.
+fn trusted_proxies() -> Result<TrustedProxies, anyhow::Error> {
+    let addresses = match std::env::var("TRUSTED_PROXIES") {
+        Ok(x) => x
+            .split(',')
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(|x| {
+                x.parse()
+                    .map_err(|e| anyhow!("invalid proxy `{}`: {}", x, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        Err(_) => vec![],
+    };
+    return Ok(TrustedProxies::new(addresses));
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use ulid::Ulid;
+
+pub const CORRELATION_ID: &str = "correlation_id";
+pub const CAUSATION_ID: &str = "causation_id";
+pub const ACTOR: &str = "actor";
+pub const CLIENT_IP: &str = "client_ip";
+pub const USER_AGENT: &str = "user_agent";
+pub const TRACEPARENT: &str = "traceparent";
+pub const IDEMPOTENCY_KEY: &str = "idempotency_key";
+
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub correlation_id: String,
+    pub causation_id: String,
+    pub actor: Option<String>,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub traceparent: Option<String>,
+    /// Only honored by `create_account`. Suspend and delete come from the
+    /// admin CLI, and a retry of either is refused by the state machine once
+    /// the first attempt landed; verifying credentials again is harmless, as
+    /// an outdated hash is only ever rehashed once.
+    pub idempotency_key: Option<String>,
+}
+
+impl RequestContext {
+    pub fn new() -> Self {
+        let id = Ulid::new().to_string();
+        return Self {
+            correlation_id: id.clone(),
+            causation_id: id,
+            actor: None,
+            client_ip: None,
+            user_agent: None,
+            traceparent: None,
+            idempotency_key: None,
+        };
+    }
+
+    pub fn metadata(&self) -> HashMap<String, String> {
+        let mut metadata = HashMap::new();
+        metadata.insert(CORRELATION_ID.into(), self.correlation_id.clone());
+        metadata.insert(CAUSATION_ID.into(), self.causation_id.clone());
+        let optional = [
+            (ACTOR, &self.actor),
+            (CLIENT_IP, &self.client_ip),
+            (USER_AGENT, &self.user_agent),
+            (TRACEPARENT, &self.traceparent),
+            (IDEMPOTENCY_KEY, &self.idempotency_key),
+        ];
+        for (key, value) in optional {
+            if let Some(x) = value {
+                metadata.insert(key.into(), x.clone());
+            }
+        }
+        return metadata;
+    }
+}
+
+impl Default for RequestContext {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
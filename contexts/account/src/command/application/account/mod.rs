@@ -1,2 +1,4 @@
+pub mod chain;
+pub mod context;
 pub mod ports;
-pub mod service;
\ No newline at end of file
+pub mod service;
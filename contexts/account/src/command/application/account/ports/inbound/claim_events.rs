@@ -0,0 +1,16 @@
+use crate::command::domain::account::entity::aggregate::AccountAggregate;
+
+use async_trait::async_trait;
+use cqrs_rs::domain::entity::event::EventEnvelope;
+
+#[async_trait]
+pub trait ClaimEvents<O>
+where
+    O: Into<EventEnvelope<AccountAggregate>>,
+{
+    async fn claim_events(&self, limit: i64) -> Result<Vec<O>, anyhow::Error>;
+    async fn release_events(&self) -> Result<(), anyhow::Error>;
+    /// Records a failed delivery. Returns `true` when the event exhausted its
+    /// attempts and was moved to the dead letters.
+    async fn fail_event(&self, sequence: String, error: String) -> Result<bool, anyhow::Error>;
+}
@@ -1,4 +1,7 @@
-use crate::command::domain::account::entity::{aggregate::AccountAggregate, command::CreateAccountCommand};
+use crate::command::{
+    application::account::context::RequestContext,
+    domain::account::entity::{aggregate::AccountAggregate, command::CreateAccountCommand},
+};
 
 use async_trait::async_trait;
 
@@ -10,6 +13,7 @@ where
     async fn create_account(
         &self,
         account: CreateAccountCommand,
+        context: RequestContext,
         fields: Vec<&str>
     ) -> Result<O, anyhow::Error>;
-}
\ No newline at end of file
+}
@@ -0,0 +1,18 @@
+use crate::command::{
+    application::account::context::RequestContext,
+    domain::account::entity::{aggregate::AccountAggregate, command::DeleteAccountCommand},
+};
+
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait DeleteAccountUseCase<O>
+where
+    O: From<AccountAggregate>,
+{
+    async fn delete_account(
+        &self,
+        command: DeleteAccountCommand,
+        context: RequestContext,
+    ) -> Result<O, anyhow::Error>;
+}
@@ -0,0 +1,9 @@
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait ForceSnapshotUseCase {
+    /// Snapshots the account's current state regardless of how many events
+    /// were applied since the last snapshot. Returns the snapshot id, or
+    /// `None` when the account has no history.
+    async fn force_snapshot(&self, aggregate_id: String) -> Result<Option<String>, anyhow::Error>;
+}
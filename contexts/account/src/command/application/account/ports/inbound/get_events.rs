@@ -8,5 +8,5 @@ pub trait GetEvents<O>
 where
     O: Into<EventEnvelope<AccountAggregate>>,
 {
-    async fn get_events(&self) -> Result<Vec<O>, anyhow::Error>;
-}
\ No newline at end of file
+    async fn get_events(&self, limit: i64) -> Result<Vec<O>, anyhow::Error>;
+}
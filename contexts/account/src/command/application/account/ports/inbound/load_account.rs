@@ -0,0 +1,13 @@
+use crate::command::domain::account::entity::aggregate::AccountAggregate;
+
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait LoadAccountUseCase<O>
+where
+    O: From<AccountAggregate>,
+{
+    /// Rebuilds the account from its latest snapshot and the events stored
+    /// after it. Returns `None` when the account has no history.
+    async fn load_account(&self, aggregate_id: String) -> Result<Option<O>, anyhow::Error>;
+}
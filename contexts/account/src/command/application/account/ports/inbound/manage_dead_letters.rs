@@ -0,0 +1,18 @@
+use crate::command::application::account::ports::outbound::dead_letter::OutboxDeadLetter;
+
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait ManageDeadLetters {
+    async fn list_dead_letters(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<OutboxDeadLetter>, anyhow::Error>;
+    async fn inspect_dead_letter(
+        &self,
+        sequence: String,
+    ) -> Result<Option<OutboxDeadLetter>, anyhow::Error>;
+    async fn requeue_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error>;
+    async fn discard_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error>;
+}
@@ -1,3 +1,12 @@
+pub mod claim_events;
 pub mod create_account;
+pub mod delete_account;
+pub mod force_snapshot;
 pub mod get_events;
-pub mod send_event;
\ No newline at end of file
+pub mod load_account;
+pub mod manage_dead_letters;
+pub mod purge_snapshots;
+pub mod send_event;
+pub mod suspend_account;
+pub mod verify_chain;
+pub mod verify_credentials;
@@ -0,0 +1,6 @@
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait PurgeStaleSnapshotsUseCase {
+    async fn purge_stale_snapshots(&self) -> Result<u64, anyhow::Error>;
+}
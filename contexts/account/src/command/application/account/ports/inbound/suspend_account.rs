@@ -0,0 +1,18 @@
+use crate::command::{
+    application::account::context::RequestContext,
+    domain::account::entity::{aggregate::AccountAggregate, command::SuspendAccountCommand},
+};
+
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait SuspendAccountUseCase<O>
+where
+    O: From<AccountAggregate>,
+{
+    async fn suspend_account(
+        &self,
+        command: SuspendAccountCommand,
+        context: RequestContext,
+    ) -> Result<O, anyhow::Error>;
+}
@@ -0,0 +1,47 @@
+use crate::command::application::account::chain::ChainAnchor;
+
+use async_trait::async_trait;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainBreakReason {
+    /// The event was stored without a hash.
+    Unhashed,
+    /// The event does not point at the hash of the event before it, so an
+    /// event was removed, inserted or reordered.
+    PrevHashMismatch {
+        expected: Option<String>,
+        found: Option<String>,
+    },
+    /// The event's contents no longer produce its recorded hash.
+    HashMismatch { expected: String, found: String },
+    /// The anchored head is gone from the chain, so its tail was cut off or
+    /// the aggregate deleted.
+    Truncated { anchored_events: i64, found_events: i64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainBreak {
+    pub aggregate_id: String,
+    pub sequence: String,
+    pub reason: ChainBreakReason,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChainReport {
+    pub aggregates: i64,
+    pub events: i64,
+    /// Verification stops at the first break.
+    pub first_break: Option<ChainBreak>,
+}
+
+#[async_trait]
+pub trait VerifyChainUseCase {
+    async fn verify_aggregate(&self, aggregate_id: String) -> Result<ChainReport, anyhow::Error>;
+    async fn verify_all(&self) -> Result<ChainReport, anyhow::Error>;
+    /// Verifies every chain and records its head, failing if any is broken.
+    async fn anchor(&self) -> Result<ChainAnchor, anyhow::Error>;
+    /// Verifies the chains of the anchored aggregates and that each still
+    /// holds its anchored head. Fails if the anchor itself was not signed
+    /// with this service's key.
+    async fn verify_anchor(&self, anchor: &ChainAnchor) -> Result<ChainReport, anyhow::Error>;
+}
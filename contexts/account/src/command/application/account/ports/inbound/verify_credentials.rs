@@ -0,0 +1,24 @@
+use crate::{
+    command::{
+        application::account::context::RequestContext,
+        domain::account::entity::aggregate::AccountAggregate,
+    },
+    common::domain::secret::Password,
+};
+
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait VerifyCredentialsUseCase<O>
+where
+    O: From<AccountAggregate>,
+{
+    /// Returns the account when the password matches an active account, and
+    /// `None` for an unknown email, a wrong password or an inactive account.
+    async fn verify_credentials(
+        &self,
+        email: String,
+        password: Password,
+        context: RequestContext,
+    ) -> Result<Option<O>, anyhow::Error>;
+}
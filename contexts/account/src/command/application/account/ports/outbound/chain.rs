@@ -0,0 +1,62 @@
+use crate::command::domain::account::entity::aggregate::AccountAggregate;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use cqrs_rs::domain::entity::event::{DomainEvent, EventEnvelope};
+use serde_json::json;
+
+/// An event as stored, with the JSON columns as the backend returns them
+/// except that encrypted payload fields are decrypted.
+#[derive(Debug, Clone)]
+pub struct ChainLink {
+    pub aggregate_type: String,
+    pub aggregate_id: String,
+    pub sequence: String,
+    pub event_type: String,
+    pub event_version: String,
+    pub payload: String,
+    pub metadata: String,
+    pub timestamp: DateTime<Utc>,
+    pub hash: Option<String>,
+    pub prev_hash: Option<String>,
+}
+
+impl ChainLink {
+    /// An event about to be appended, given its plaintext payload.
+    pub fn unhashed(event: &EventEnvelope<AccountAggregate>, payload: String) -> Self {
+        return Self {
+            aggregate_type: event.aggregate_type.clone(),
+            aggregate_id: event.aggregate_id.clone(),
+            sequence: event.sequence.clone(),
+            event_type: event.payload.event_type(),
+            event_version: event.payload.event_version(),
+            payload,
+            metadata: json!(event.metadata).to_string(),
+            timestamp: event.timestamp,
+            hash: None,
+            prev_hash: None,
+        };
+    }
+}
+
+#[async_trait]
+pub trait AccountEventChainRepository {
+    /// Returns at most `limit` aggregate ids after `after`, in ascending
+    /// order.
+    async fn retrieve_aggregate_ids(
+        &self,
+        after: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<String>, anyhow::Error>;
+    /// Returns at most `limit` stored events of the aggregate after `after`,
+    /// ordered by sequence.
+    async fn retrieve_chain_page(
+        &self,
+        aggregate_id: String,
+        after: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<ChainLink>, anyhow::Error>;
+    /// Records the hashes of events stored before the chain existed. Events
+    /// that already carry a hash are left alone. Returns the rows updated.
+    async fn backfill_chain(&self, links: Vec<ChainLink>) -> Result<u64, anyhow::Error>;
+}
@@ -0,0 +1,31 @@
+use crate::command::domain::account::entity::aggregate::AccountAggregate;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use cqrs_rs::domain::entity::event::EventEnvelope;
+
+#[derive(Debug)]
+pub struct OutboxDeadLetter {
+    pub event: EventEnvelope<AccountAggregate>,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub dead_lettered_at: DateTime<Utc>,
+}
+
+/// A dead-lettered event keeps its place in the outbox and holds back the
+/// later events of its aggregate until it is requeued, in its original
+/// position, or discarded.
+#[async_trait]
+pub trait AccountDeadLetterRepository {
+    async fn list_dead_letters(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<OutboxDeadLetter>, anyhow::Error>;
+    async fn retrieve_dead_letter(
+        &self,
+        sequence: String,
+    ) -> Result<Option<OutboxDeadLetter>, anyhow::Error>;
+    async fn requeue_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error>;
+    async fn discard_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error>;
+}
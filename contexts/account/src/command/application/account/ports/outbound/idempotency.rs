@@ -0,0 +1,37 @@
+use crate::command::domain::account::entity::aggregate::AccountAggregate;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone)]
+pub struct IdempotencyRecord {
+    pub idempotency_key: String,
+    pub command_hash: String,
+    pub outcome: Option<AccountAggregate>,
+}
+
+#[derive(Debug, Clone)]
+pub enum IdempotencyClaim {
+    Claimed,
+    Existing(Box<IdempotencyRecord>),
+}
+
+#[async_trait]
+pub trait IdempotencyRepository {
+    /// Claims the key for a new request. A key still pending since before
+    /// `stale_before` belongs to a request that died without releasing it,
+    /// and is taken over.
+    async fn claim_idempotency_key(
+        &self,
+        idempotency_key: String,
+        command_hash: String,
+        stale_before: DateTime<Utc>,
+    ) -> Result<IdempotencyClaim, anyhow::Error>;
+    async fn complete_idempotency_key(
+        &self,
+        idempotency_key: String,
+        outcome: AccountAggregate,
+    ) -> Result<(), anyhow::Error>;
+    async fn release_idempotency_key(&self, idempotency_key: String)
+        -> Result<(), anyhow::Error>;
+}
@@ -1 +1,6 @@
-pub mod repository;
\ No newline at end of file
+pub mod chain;
+pub mod dead_letter;
+pub mod idempotency;
+pub mod notifier;
+pub mod repository;
+pub mod subscription;
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Signals that new events have been committed to the store. Receivers only
+/// observe that something changed, never which events were written.
+#[derive(Clone)]
+pub struct CommitNotifier {
+    sender: Arc<watch::Sender<u64>>,
+}
+
+impl CommitNotifier {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(0);
+        return Self {
+            sender: Arc::new(sender),
+        };
+    }
+
+    pub fn notify(&self) {
+        self.sender.send_modify(|x| *x = x.wrapping_add(1));
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        return self.sender.subscribe();
+    }
+}
+
+impl Default for CommitNotifier {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
@@ -1,16 +1,133 @@
 use crate::command::domain::account::entity::aggregate::AccountAggregate;
 
+use super::{
+    chain::AccountEventChainRepository, dead_letter::AccountDeadLetterRepository,
+    idempotency::IdempotencyRepository, subscription::AccountEventLogRepository,
+};
+
+use std::time::Duration;
+
 use async_trait::async_trait;
 use cqrs_rs::{
     application::port::outbound::event_repository::EventRepository,
     domain::entity::event::{AggregateSnapshot, EventEnvelope},
 };
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
 
 #[async_trait]
 pub trait AccountRepository {
     async fn email_exists(&self, email: String) -> Result<bool, anyhow::Error>;
     async fn retrieve_aggregate_id_for_email(&self, email: String)
         -> Result<String, anyhow::Error>;
+    /// Appends the events of one aggregate, provided it still holds exactly
+    /// `expected_version` events. Otherwise nothing is written and
+    /// `AccountError::ConcurrentModification` is returned, so a command
+    /// decided on a stale aggregate cannot land after a conflicting one.
+    async fn append_events(
+        &self,
+        expected_version: i64,
+        events: Vec<EventEnvelope<AccountAggregate>>,
+    ) -> Result<(), anyhow::Error>;
+}
+
+/// Number of events fetched per round trip when streaming an aggregate.
+pub const EVENT_PAGE_SIZE: i64 = 500;
+
+pub type AccountEventStream<'a> =
+    BoxStream<'a, Result<EventEnvelope<AccountAggregate>, anyhow::Error>>;
+
+#[async_trait]
+pub trait AccountEventStreamRepository: Sync {
+    /// Returns at most `limit` events of the aggregate after `after`, ordered
+    /// by sequence.
+    async fn retrieve_event_page(
+        &self,
+        aggregate_id: String,
+        after: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error>;
+
+    /// Streams the aggregate's events after `after` in sequence order, holding
+    /// no more than one page in memory at a time.
+    fn stream_events(
+        &self,
+        aggregate_id: String,
+        after: Option<String>,
+    ) -> AccountEventStream<'_> {
+        let pages = stream::try_unfold((after, false), move |(after, done)| {
+            let aggregate_id = aggregate_id.clone();
+            async move {
+                if done {
+                    return Ok::<_, anyhow::Error>(None);
+                }
+                let page = self
+                    .retrieve_event_page(aggregate_id, after, EVENT_PAGE_SIZE)
+                    .await?;
+                if page.is_empty() {
+                    return Ok(None);
+                }
+                let done = (page.len() as i64) < EVENT_PAGE_SIZE;
+                let next = page.last().map(|x| x.sequence.clone());
+                let events = stream::iter(page.into_iter().map(Ok));
+                return Ok(Some((events, (next, done))));
+            }
+        });
+        return pages.try_flatten().boxed();
+    }
+}
+
+#[async_trait]
+pub trait AccountSnapshotRepository {
+    /// Deletes every snapshot whose schema version differs from the one the
+    /// current build writes, returning the number of rows removed.
+    async fn purge_stale_snapshots(&self) -> Result<u64, anyhow::Error>;
+}
+
+#[async_trait]
+pub trait AccountOutboxRepository {
+    async fn retrieve_outbox_batch(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error>;
+    /// Leases up to `limit` outbox events to `owner`, oldest first. An event
+    /// is only handed out when no earlier event of the same aggregate is
+    /// leased to someone else, so each aggregate is relayed in order.
+    async fn claim_outbox_events(
+        &self,
+        owner: String,
+        lease: Duration,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error>;
+    async fn release_outbox_leases(&self, owner: String) -> Result<(), anyhow::Error>;
+    /// Extends the lease `owner` holds on an outbox event. Returns `false`
+    /// when another relay took the event over after the lease expired.
+    async fn renew_outbox_lease(
+        &self,
+        owner: String,
+        sequence: String,
+        lease: Duration,
+    ) -> Result<bool, anyhow::Error>;
+    /// Deletes a relayed event, as long as `owner` still holds its lease.
+    async fn delete_leased_outbox_event(
+        &self,
+        owner: String,
+        sequence: String,
+    ) -> Result<bool, anyhow::Error>;
+    /// Counts a failed delivery against the event and schedules its next
+    /// attempt, or moves it to the dead letters once `max_attempts` is hit.
+    async fn record_outbox_failure(
+        &self,
+        sequence: String,
+        error: String,
+        max_attempts: i64,
+        retry_delay: Duration,
+    ) -> Result<bool, anyhow::Error>;
+    /// Clears the attempts and backoff of a pending outbox event so the next
+    /// relay batch picks it up. Returns `false` when no such event is pending.
+    async fn retry_outbox_event(&self, sequence: String) -> Result<bool, anyhow::Error>;
 }
 
 pub trait AccountEventRepository<T, Q>:
@@ -22,5 +139,12 @@ pub trait AccountEventRepository<T, Q>:
         AggregateSnapshot<AccountAggregate>,
         AggregateSnapshot<AccountAggregate>,
     > + AccountRepository
+    + AccountEventStreamRepository
+    + AccountEventLogRepository
+    + AccountSnapshotRepository
+    + AccountOutboxRepository
+    + AccountDeadLetterRepository
+    + IdempotencyRepository
+    + AccountEventChainRepository
 {
 }
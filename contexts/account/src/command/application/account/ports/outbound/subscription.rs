@@ -0,0 +1,45 @@
+use crate::command::domain::account::entity::aggregate::AccountAggregate;
+
+use async_trait::async_trait;
+use cqrs_rs::domain::entity::event::EventEnvelope;
+
+/// An event together with its place in the global, gap-free event log.
+#[derive(Debug)]
+pub struct PositionedEvent {
+    pub position: i64,
+    pub event: EventEnvelope<AccountAggregate>,
+}
+
+#[async_trait]
+pub trait AccountEventLogRepository {
+    /// Returns at most `limit` events whose position is greater than
+    /// `from_position`, in position order. Position `0`, or any before it,
+    /// reads from the start.
+    async fn read_all(
+        &self,
+        from_position: i64,
+        limit: i64,
+    ) -> Result<Vec<PositionedEvent>, anyhow::Error>;
+}
+
+#[async_trait]
+pub trait CheckpointStore {
+    /// Returns the last position handled by the subscription, or `0`.
+    async fn load_checkpoint(&self, subscription_id: String) -> Result<i64, anyhow::Error>;
+    /// Moves the checkpoint from `expected` to `position` and returns `true`,
+    /// or returns `false` and leaves it alone when it is no longer at
+    /// `expected`, such as after a projection replay rewound it.
+    async fn save_checkpoint(
+        &self,
+        subscription_id: String,
+        expected: i64,
+        position: i64,
+    ) -> Result<bool, anyhow::Error>;
+}
+
+#[async_trait]
+pub trait EventHandler {
+    /// Handles one event of the log. Events may be redelivered after a
+    /// restart, so handlers have to be idempotent.
+    async fn handle(&self, event: &PositionedEvent) -> Result<(), anyhow::Error>;
+}
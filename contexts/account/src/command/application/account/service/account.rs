@@ -1,29 +1,66 @@
 use crate::{
     command::{
-        application::account::ports::{
-            inbound::create_account::CreateAccountUseCase,
-            outbound::repository::AccountEventRepository,
+        application::account::{
+            context::RequestContext,
+            ports::{
+                inbound::{
+                    create_account::CreateAccountUseCase, delete_account::DeleteAccountUseCase,
+                    force_snapshot::ForceSnapshotUseCase, load_account::LoadAccountUseCase,
+                    purge_snapshots::PurgeStaleSnapshotsUseCase,
+                    suspend_account::SuspendAccountUseCase,
+                    verify_credentials::VerifyCredentialsUseCase,
+                },
+                outbound::{idempotency::IdempotencyClaim, repository::AccountEventRepository},
+            },
         },
         domain::account::entity::{
-            aggregate::AccountAggregate, command::CreateAccountCommand, error::AccountError,
+            aggregate::{AccountAggregate, ACCOUNT_STATUS_CREATED},
+            command::{
+                AccountCommand, CreateAccountCommand, DeleteAccountCommand, RehashPasswordCommand,
+                SuspendAccountCommand,
+            },
+            error::AccountError,
+            event::AccountEvent,
         },
     },
-    common::application::ports::outbound::account_services::AccountServices,
+    common::{
+        application::ports::outbound::account_services::{AccountServices, PasswordCheck},
+        domain::secret::{Password, PasswordHash, SecretToken},
+    },
 };
 
-use std::collections::HashMap;
-use std::{sync::Arc};
+use std::{sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use chrono::Utc;
-use cqrs_rs::domain::entity::{aggregate::Aggregate, event::DomainEvent, event::EventEnvelope};
-use tracing::span;
+use cqrs_rs::domain::entity::{
+    aggregate::Aggregate,
+    event::{AggregateSnapshot, DomainEvent, EventEnvelope},
+};
+use futures::TryStreamExt;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::sync::OnceCell;
+use tracing::{span, Instrument};
+use ulid::Ulid;
+
+pub trait ServiceTrait<O: From<AccountAggregate>>:
+    CreateAccountUseCase<O> + VerifyCredentialsUseCase<O>
+{
+}
 
-pub trait ServiceTrait<O: From<AccountAggregate>>: CreateAccountUseCase<O> {}
+/// How long an idempotency key may stay pending before another request is
+/// allowed to take it over.
+const IDEMPOTENCY_PENDING_TTL: Duration = Duration::from_secs(300);
 
 pub struct AccountService<T, Q> {
     services: Arc<dyn AccountServices + Sync + Send>,
     repository: Arc<dyn AccountEventRepository<T, Q> + Sync + Send>,
+    idempotency_secret: Option<SecretToken>,
+    idempotency_ttl: Duration,
+    /// Verified against when there is no stored hash, so unknown emails take
+    /// as long to reject as wrong passwords.
+    dummy_hash: OnceCell<PasswordHash>,
 }
 
 impl<T, Q> AccountService<T, Q> {
@@ -34,26 +71,72 @@ impl<T, Q> AccountService<T, Q> {
         return Self {
             services,
             repository,
+            idempotency_secret: None,
+            idempotency_ttl: IDEMPOTENCY_PENDING_TTL,
+            dummy_hash: OnceCell::new(),
         };
     }
-}
 
-#[async_trait]
-impl<O, T, Q> CreateAccountUseCase<O> for AccountService<T, Q>
-where
-    O: From<AccountAggregate>,
-{
-    async fn create_account(
+    /// Keys the HMAC that fingerprints idempotent commands. Requests that
+    /// carry an idempotency key are refused until one is set.
+    pub fn with_idempotency_secret(mut self, secret: SecretToken) -> Self {
+        self.idempotency_secret = Some(secret);
+        return self;
+    }
+
+    pub fn with_idempotency_ttl(mut self, ttl: Duration) -> Self {
+        self.idempotency_ttl = ttl;
+        return self;
+    }
+
+    /// The account registered with `email` and its password hash, if it can
+    /// sign in at all.
+    async fn credentials(
+        &self,
+        email: String,
+    ) -> Result<Option<(AccountAggregate, PasswordHash)>, anyhow::Error> {
+        if !self.repository.email_exists(email.clone()).await? {
+            return Ok(None);
+        }
+        let aggregate_id = self
+            .repository
+            .retrieve_aggregate_id_for_email(email)
+            .await?;
+        let aggregate = match self.load_aggregate(aggregate_id).await? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        if aggregate.status.as_deref() != Some(ACCOUNT_STATUS_CREATED) {
+            return Ok(None);
+        }
+        return Ok(aggregate.password_hash.clone().map(|x| (aggregate, x)));
+    }
+
+    /// Spends the same hashing work on a rejected sign in as a real check
+    /// would, so its timing does not reveal whether the account exists.
+    async fn reject_credentials(&self, password: Password) -> Result<(), anyhow::Error> {
+        let dummy_hash = self
+            .dummy_hash
+            .get_or_try_init(|| async {
+                return self
+                    .services
+                    .hash_password_async(Ulid::new().to_string().into())
+                    .await;
+            })
+            .await
+            .map_err(AccountError::from)?;
+        self.services
+            .verify_password_async(password, dummy_hash.clone())
+            .await
+            .map_err(AccountError::from)?;
+        return Ok(());
+    }
+
+    async fn execute_create_account(
         &self,
         command: CreateAccountCommand,
-        _fields: Vec<&str>,
-    ) -> Result<O, anyhow::Error> {
-        let root = span!(
-            tracing::Level::INFO,
-            "create_account",
-            target = "AccountService"
-        );
-        let _enter = root.enter();
+        context: &RequestContext,
+    ) -> Result<AccountAggregate, anyhow::Error> {
         let mut aggregate = AccountAggregate::default();
         let email = command.email.clone();
         let exists = self.repository.email_exists(email.clone()).await?;
@@ -61,12 +144,44 @@ where
             return Err(AccountError::AccountExists(email).into());
         }
         let events = aggregate.handle(command.into(), &self.services).await?;
+        self.commit_events(&mut aggregate, events, context).await?;
+        return Ok(aggregate);
+    }
+
+    /// Runs a command against an existing account.
+    async fn execute_command(
+        &self,
+        aggregate_id: String,
+        command: AccountCommand,
+        context: &RequestContext,
+    ) -> Result<AccountAggregate, anyhow::Error> {
+        let mut aggregate = match self.load_aggregate(aggregate_id.clone()).await? {
+            Some(x) => x,
+            None => return Err(AccountError::AccountNotFound(aggregate_id).into()),
+        };
+        let events = aggregate.handle(command, &self.services).await?;
+        self.commit_events(&mut aggregate, events, context).await?;
+        return Ok(aggregate);
+    }
+
+    /// Applies the events to the aggregate and stores them with the request
+    /// metadata, snapshotting the aggregate when it asks for it.
+    async fn commit_events(
+        &self,
+        aggregate: &mut AccountAggregate,
+        events: Vec<AccountEvent>,
+        context: &RequestContext,
+    ) -> Result<(), anyhow::Error> {
+        // The events were decided on this many, so the append is refused if
+        // another request has stored any since.
+        let expected_version = aggregate.applied_events as i64;
         events
             .iter()
             .for_each(|event| aggregate.apply(event.clone()));
         if aggregate.aggregate_id().is_none() {
             return Err(AccountError::UnknownError.into());
         }
+        let metadata = context.metadata();
         let wrapped_events = events
             .iter()
             .map(|x| EventEnvelope::<AccountAggregate> {
@@ -74,17 +189,318 @@ where
                 aggregate_type: "account".into(),
                 sequence: x.event_id(),
                 payload: x.clone(),
-                metadata: HashMap::new(),
+                metadata: metadata.clone(),
                 timestamp: Utc::now(),
             })
             .collect();
-        self.repository.store_events(wrapped_events).await?;
-        match aggregate.snapshot() {
-            Some(x) => self.repository.store_snapshot(x).await?,
-            _ => {}
+        self.repository
+            .append_events(expected_version, wrapped_events)
+            .await?;
+        if let Some(x) = aggregate.snapshot() {
+            self.repository.store_snapshot(x).await?;
+        }
+        return Ok(());
+    }
+
+    /// Hydrates the aggregate from its latest snapshot, streaming the events
+    /// after it so long histories are never buffered whole.
+    pub async fn load_aggregate(
+        &self,
+        aggregate_id: String,
+    ) -> Result<Option<AccountAggregate>, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "load_aggregate",
+            target = "AccountService",
+            aggregate_id = aggregate_id.as_str()
+        );
+        async move {
+            let snapshot = self
+                .repository
+                .retrieve_latest_snapshot(aggregate_id.clone())
+                .await?;
+            let mut aggregate = AccountAggregate::default();
+            let after = match snapshot {
+                Some(x) => {
+                    let after = x.last_sequence.clone();
+                    aggregate.apply_snapshot(x);
+                    Some(after)
+                }
+                None => None,
+            };
+            let mut found = after.is_some();
+            let mut events = self.repository.stream_events(aggregate_id, after);
+            while let Some(event) = events.try_next().await? {
+                aggregate.apply(event.payload);
+                found = true;
+            }
+            if !found {
+                return Ok(None);
+            }
+            return Ok(Some(aggregate));
+        }
+        .instrument(root)
+        .await
+    }
+}
+
+/// Fingerprints the command together with its idempotency key so a replay
+/// can be told apart from a reused key. It is an HMAC under the server
+/// secret, so the password is always covered while a stored fingerprint can
+/// never be used to test password guesses offline.
+fn command_hash(
+    secret: &SecretToken,
+    idempotency_key: &str,
+    command: &CreateAccountCommand,
+) -> Result<String, anyhow::Error> {
+    let fields = [
+        idempotency_key.as_bytes(),
+        b"CreateAccount",
+        command.email.as_bytes(),
+    ];
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret.expose().as_bytes())
+        .map_err(|_| anyhow::anyhow!("idempotency secret has an invalid length"))?;
+    for field in fields {
+        mac.update(field);
+        mac.update(&[0u8]);
+    }
+    mac.update(command.password.expose().as_bytes());
+    return Ok(hex::encode(mac.finalize().into_bytes()));
+}
+
+#[async_trait]
+impl<O, T, Q> CreateAccountUseCase<O> for AccountService<T, Q>
+where
+    O: From<AccountAggregate>,
+{
+    async fn create_account(
+        &self,
+        command: CreateAccountCommand,
+        context: RequestContext,
+        _fields: Vec<&str>,
+    ) -> Result<O, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "create_account",
+            target = "AccountService",
+            correlation_id = context.correlation_id.as_str()
+        );
+        let _enter = root.enter();
+        let idempotency_key = match &context.idempotency_key {
+            None => return Ok(self.execute_create_account(command, &context).await?.into()),
+            Some(x) => x.clone(),
+        };
+        let secret = match &self.idempotency_secret {
+            Some(x) => x,
+            None => return Err(anyhow::anyhow!("idempotency keys need an idempotency secret")),
+        };
+        let hash = command_hash(secret, &idempotency_key, &command)?;
+        let stale_before = Utc::now() - chrono::Duration::from_std(self.idempotency_ttl)?;
+        let claim = self
+            .repository
+            .claim_idempotency_key(idempotency_key.clone(), hash.clone(), stale_before)
+            .await?;
+        match claim {
+            IdempotencyClaim::Claimed => {}
+            IdempotencyClaim::Existing(record) => {
+                if record.command_hash != hash {
+                    return Err(AccountError::IdempotencyKeyReused(idempotency_key).into());
+                }
+                // The stored outcome only names the account, which is hydrated
+                // from its event stream so a replay sees its current state.
+                let aggregate_id = match record.outcome.and_then(|x| x.id) {
+                    Some(x) => x,
+                    None => {
+                        return Err(AccountError::IdempotencyKeyInProgress(idempotency_key).into())
+                    }
+                };
+                return match self.load_aggregate(aggregate_id.clone()).await? {
+                    Some(x) => Ok(x.into()),
+                    None => Err(AccountError::AccountNotFound(aggregate_id).into()),
+                };
+            }
         }
+        match self.execute_create_account(command, &context).await {
+            Ok(aggregate) => {
+                self.repository
+                    .complete_idempotency_key(idempotency_key, aggregate.clone())
+                    .await?;
+                return Ok(aggregate.into());
+            }
+            Err(e) => {
+                self.repository
+                    .release_idempotency_key(idempotency_key)
+                    .await?;
+                return Err(e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<O, T, Q> LoadAccountUseCase<O> for AccountService<T, Q>
+where
+    O: From<AccountAggregate>,
+{
+    async fn load_account(&self, aggregate_id: String) -> Result<Option<O>, anyhow::Error> {
+        return Ok(self.load_aggregate(aggregate_id).await?.map(|x| x.into()));
+    }
+}
+
+#[async_trait]
+impl<T, Q> PurgeStaleSnapshotsUseCase for AccountService<T, Q> {
+    async fn purge_stale_snapshots(&self) -> Result<u64, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "purge_stale_snapshots",
+            target = "AccountService"
+        );
+        let _enter = root.enter();
+        self.repository.purge_stale_snapshots().await
+    }
+}
+
+#[async_trait]
+impl<O, T, Q> SuspendAccountUseCase<O> for AccountService<T, Q>
+where
+    O: From<AccountAggregate>,
+{
+    async fn suspend_account(
+        &self,
+        command: SuspendAccountCommand,
+        context: RequestContext,
+    ) -> Result<O, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "suspend_account",
+            target = "AccountService",
+            correlation_id = context.correlation_id.as_str(),
+            aggregate_id = command.id.as_str()
+        );
+        let _enter = root.enter();
+        let aggregate_id = command.id.clone();
+        let aggregate = self
+            .execute_command(aggregate_id, command.into(), &context)
+            .await?;
         return Ok(aggregate.into());
     }
 }
 
+#[async_trait]
+impl<O, T, Q> DeleteAccountUseCase<O> for AccountService<T, Q>
+where
+    O: From<AccountAggregate>,
+{
+    async fn delete_account(
+        &self,
+        command: DeleteAccountCommand,
+        context: RequestContext,
+    ) -> Result<O, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "delete_account",
+            target = "AccountService",
+            correlation_id = context.correlation_id.as_str(),
+            aggregate_id = command.id.as_str()
+        );
+        let _enter = root.enter();
+        let aggregate_id = command.id.clone();
+        let aggregate = self
+            .execute_command(aggregate_id, command.into(), &context)
+            .await?;
+        return Ok(aggregate.into());
+    }
+}
+
+#[async_trait]
+impl<T, Q> ForceSnapshotUseCase for AccountService<T, Q> {
+    async fn force_snapshot(&self, aggregate_id: String) -> Result<Option<String>, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "force_snapshot",
+            target = "AccountService",
+            aggregate_id = aggregate_id.as_str()
+        );
+        let _enter = root.enter();
+        let aggregate = match self.load_aggregate(aggregate_id).await? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let last_sequence = match &aggregate.last_event {
+            Some(x) => x.event_id(),
+            None => return Ok(None),
+        };
+        let snapshot = AggregateSnapshot {
+            aggregate_id: aggregate.aggregate_id().unwrap(),
+            aggregate_type: AccountAggregate::aggregate_type(),
+            payload: aggregate,
+            last_sequence,
+            snapshot_id: Ulid::new().to_string(),
+            timestamp: Utc::now(),
+        };
+        let snapshot_id = snapshot.snapshot_id.clone();
+        self.repository.store_snapshot(snapshot).await?;
+        return Ok(Some(snapshot_id));
+    }
+}
+
+#[async_trait]
+impl<O, T, Q> VerifyCredentialsUseCase<O> for AccountService<T, Q>
+where
+    O: From<AccountAggregate>,
+{
+    async fn verify_credentials(
+        &self,
+        email: String,
+        password: Password,
+        context: RequestContext,
+    ) -> Result<Option<O>, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "verify_credentials",
+            target = "AccountService",
+            correlation_id = context.correlation_id.as_str()
+        );
+        let _enter = root.enter();
+        let (aggregate, password_hash) = match self.credentials(email).await? {
+            Some(x) => x,
+            None => {
+                self.reject_credentials(password).await?;
+                return Ok(None);
+            }
+        };
+        let aggregate_id = aggregate.aggregate_id().unwrap();
+        let check = self
+            .services
+            .verify_password_async(password, password_hash)
+            .await
+            .map_err(AccountError::from)?;
+        let password_hash = match check {
+            PasswordCheck::Mismatch => return Ok(None),
+            PasswordCheck::Match { rehashed: None } => return Ok(Some(aggregate.into())),
+            PasswordCheck::Match { rehashed: Some(x) } => x,
+        };
+        let command = RehashPasswordCommand {
+            id: aggregate_id.clone(),
+            password_hash,
+        };
+        // The password was already verified, so a failed rehash only means the
+        // old hash is kept until the next successful verification.
+        return match self
+            .execute_command(aggregate_id.clone(), command.into(), &context)
+            .await
+        {
+            Ok(x) => Ok(Some(x.into())),
+            Err(e) => {
+                tracing::warn!(
+                    aggregate_id = aggregate_id.as_str(),
+                    error = e.to_string().as_str(),
+                    "password rehash failed"
+                );
+                Ok(Some(aggregate.into()))
+            }
+        };
+    }
+}
+
 impl<O: From<AccountAggregate>, T, Q> ServiceTrait<O> for AccountService<T, Q> {}
@@ -0,0 +1,259 @@
+use crate::{
+    command::application::account::{
+        chain::{check_link, event_hash, ChainAnchor, ChainHead},
+        ports::{
+            inbound::verify_chain::{ChainBreak, ChainBreakReason, ChainReport, VerifyChainUseCase},
+            outbound::repository::AccountEventRepository,
+        },
+    },
+    common::domain::secret::SecretToken,
+};
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use tracing::{span, Instrument};
+
+/// Walks the per-aggregate hash chains of the event store, either to verify
+/// them or to hash events stored before the chain existed.
+pub struct AccountChainService<T, Q> {
+    repository: Arc<dyn AccountEventRepository<T, Q> + Sync + Send>,
+    key: Arc<SecretToken>,
+    page_size: i64,
+}
+
+impl<T, Q> AccountChainService<T, Q> {
+    pub fn new(repository: Arc<dyn AccountEventRepository<T, Q> + Sync + Send>) -> Self {
+        return Self {
+            repository,
+            key: Arc::new(SecretToken::default()),
+            page_size: 500,
+        };
+    }
+
+    /// The key the repository hashes new events with.
+    pub fn with_key(mut self, key: Arc<SecretToken>) -> Self {
+        self.key = key;
+        return self;
+    }
+
+    pub fn with_page_size(mut self, page_size: i64) -> Self {
+        self.page_size = page_size;
+        return self;
+    }
+
+    /// Verifies one aggregate's chain, adding to `report` and returning as
+    /// soon as a break is found. With `anchored`, the chain also has to
+    /// still hold that head. Returns the head of an intact chain.
+    async fn verify_into(
+        &self,
+        aggregate_id: String,
+        anchored: Option<&ChainHead>,
+        report: &mut ChainReport,
+    ) -> Result<Option<ChainHead>, anyhow::Error> {
+        report.aggregates += 1;
+        let mut head: Option<ChainHead> = None;
+        let mut reached = anchored.is_none();
+        loop {
+            let links = self
+                .repository
+                .retrieve_chain_page(
+                    aggregate_id.clone(),
+                    head.as_ref().map(|x| x.sequence.clone()),
+                    self.page_size,
+                )
+                .await?;
+            let done = (links.len() as i64) < self.page_size;
+            for link in links {
+                report.events += 1;
+                let prev_hash = head.as_ref().map(|x| x.hash.as_str());
+                if let Some(reason) = check_link(&self.key, &link, prev_hash)? {
+                    report.first_break = Some(ChainBreak {
+                        aggregate_id: link.aggregate_id,
+                        sequence: link.sequence,
+                        reason,
+                    });
+                    return Ok(None);
+                }
+                let next = ChainHead {
+                    aggregate_id: link.aggregate_id,
+                    sequence: link.sequence,
+                    hash: link.hash.unwrap_or_default(),
+                    events: head.as_ref().map_or(0, |x| x.events) + 1,
+                };
+                if anchored == Some(&next) {
+                    reached = true;
+                }
+                head = Some(next);
+            }
+            if done {
+                break;
+            }
+        }
+        if let (false, Some(x)) = (reached, anchored) {
+            report.first_break = Some(ChainBreak {
+                aggregate_id,
+                sequence: x.sequence.clone(),
+                reason: ChainBreakReason::Truncated {
+                    anchored_events: x.events,
+                    found_events: head.as_ref().map_or(0, |x| x.events),
+                },
+            });
+            return Ok(None);
+        }
+        return Ok(head);
+    }
+
+    /// Hashes every event that has no hash yet, chaining it to the event
+    /// before it. Meant to run once from the admin CLI before chaining is
+    /// turned on for a store that already holds events. Returns the number
+    /// of events hashed.
+    pub async fn backfill(&self) -> Result<u64, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "backfill",
+            target = "AccountChainService"
+        );
+        async {
+            let mut updated = 0;
+            let mut aggregates_after: Option<String> = None;
+            loop {
+                let aggregate_ids = self
+                    .repository
+                    .retrieve_aggregate_ids(aggregates_after, self.page_size)
+                    .await?;
+                if aggregate_ids.is_empty() {
+                    return Ok::<u64, anyhow::Error>(updated);
+                }
+                aggregates_after = aggregate_ids.last().cloned();
+                for aggregate_id in aggregate_ids {
+                    let mut prev_hash: Option<String> = None;
+                    let mut after: Option<String> = None;
+                    loop {
+                        let links = self
+                            .repository
+                            .retrieve_chain_page(aggregate_id.clone(), after, self.page_size)
+                            .await?;
+                        let done = (links.len() as i64) < self.page_size;
+                        after = links.last().map(|x| x.sequence.clone());
+                        let mut missing = vec![];
+                        for mut link in links {
+                            if link.hash.is_none() {
+                                link.hash =
+                                    Some(event_hash(&self.key, prev_hash.as_deref(), &link)?);
+                                link.prev_hash = prev_hash.clone();
+                                missing.push(link.clone());
+                            }
+                            prev_hash = link.hash;
+                        }
+                        if !missing.is_empty() {
+                            updated += self.repository.backfill_chain(missing).await?;
+                        }
+                        if done {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        .instrument(root)
+        .await
+    }
+}
+
+#[async_trait]
+impl<T, Q> VerifyChainUseCase for AccountChainService<T, Q> {
+    async fn verify_aggregate(&self, aggregate_id: String) -> Result<ChainReport, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "verify_aggregate",
+            target = "AccountChainService",
+            aggregate_id = aggregate_id.as_str()
+        );
+        let _enter = root.enter();
+        let mut report = ChainReport::default();
+        self.verify_into(aggregate_id, None, &mut report).await?;
+        return Ok(report);
+    }
+
+    async fn verify_all(&self) -> Result<ChainReport, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "verify_all",
+            target = "AccountChainService"
+        );
+        let _enter = root.enter();
+        let mut report = ChainReport::default();
+        let mut after: Option<String> = None;
+        loop {
+            let aggregate_ids = self
+                .repository
+                .retrieve_aggregate_ids(after, self.page_size)
+                .await?;
+            if aggregate_ids.is_empty() {
+                return Ok(report);
+            }
+            after = aggregate_ids.last().cloned();
+            for aggregate_id in aggregate_ids {
+                self.verify_into(aggregate_id, None, &mut report).await?;
+                if report.first_break.is_some() {
+                    return Ok(report);
+                }
+            }
+        }
+    }
+
+    async fn anchor(&self) -> Result<ChainAnchor, anyhow::Error> {
+        let root = span!(tracing::Level::INFO, "anchor", target = "AccountChainService");
+        let _enter = root.enter();
+        let mut report = ChainReport::default();
+        let mut heads: Vec<ChainHead> = vec![];
+        let mut after: Option<String> = None;
+        loop {
+            let aggregate_ids = self
+                .repository
+                .retrieve_aggregate_ids(after, self.page_size)
+                .await?;
+            if aggregate_ids.is_empty() {
+                return ChainAnchor::new(&self.key, heads);
+            }
+            after = aggregate_ids.last().cloned();
+            for aggregate_id in aggregate_ids {
+                let head = self.verify_into(aggregate_id, None, &mut report).await?;
+                if let Some(x) = report.first_break {
+                    return Err(anyhow!(
+                        "chain of aggregate `{}` breaks at event `{}`: {:?}, refusing to anchor it",
+                        x.aggregate_id,
+                        x.sequence,
+                        x.reason
+                    ));
+                }
+                heads.extend(head);
+            }
+        }
+    }
+
+    async fn verify_anchor(&self, anchor: &ChainAnchor) -> Result<ChainReport, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "verify_anchor",
+            target = "AccountChainService"
+        );
+        let _enter = root.enter();
+        if !anchor.is_authentic(&self.key)? {
+            return Err(anyhow!(
+                "chain anchor was altered or signed with another key"
+            ));
+        }
+        let mut report = ChainReport::default();
+        for head in anchor.heads.iter() {
+            self.verify_into(head.aggregate_id.clone(), Some(head), &mut report)
+                .await?;
+            if report.first_break.is_some() {
+                return Ok(report);
+            }
+        }
+        return Ok(report);
+    }
+}
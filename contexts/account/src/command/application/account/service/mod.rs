@@ -1,2 +1,5 @@
 pub mod account;
-pub mod outbox;
\ No newline at end of file
+pub mod chain;
+pub mod outbox;
+pub mod relay;
+pub mod subscription;
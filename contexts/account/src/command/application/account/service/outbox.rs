@@ -1,57 +1,132 @@
-use crate::command::{domain::account::entity::aggregate::AccountAggregate, application::account::ports::{inbound::{get_events::GetEvents, send_event::SendEvent}}};
+use crate::command::{
+    application::account::ports::{
+        inbound::{
+            claim_events::ClaimEvents, get_events::GetEvents,
+            manage_dead_letters::ManageDeadLetters, send_event::SendEvent,
+        },
+        outbound::{dead_letter::OutboxDeadLetter, repository::AccountEventRepository},
+    },
+    domain::account::entity::{aggregate::AccountAggregate, error::AccountError},
+};
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use cqrs_rs::{domain::entity::event::{EventEnvelope, AggregateSnapshot}, application::port::outbound::{event_bus::EventBus, event_repository::EventRepository}};
+use cqrs_rs::{
+    application::port::outbound::event_bus::EventBus, domain::entity::event::EventEnvelope,
+};
+use ulid::Ulid;
 
+pub trait OutboxServiceTrait:
+    GetEvents<EventEnvelope<AccountAggregate>>
+    + ClaimEvents<EventEnvelope<AccountAggregate>>
+    + SendEvent<EventEnvelope<AccountAggregate>>
+    + ManageDeadLetters
+{
+}
 
 pub struct AccountOutboxService<T, Q> {
-    repository: Arc<
-        dyn EventRepository<
-                EventEnvelope<AccountAggregate>,
-                T,
-                Q,
-                EventEnvelope<AccountAggregate>,
-                AggregateSnapshot<AccountAggregate>,
-                AggregateSnapshot<AccountAggregate>,
-            > + Sync
-            + Send,
-    >,
+    repository: Arc<dyn AccountEventRepository<T, Q> + Sync + Send>,
     bus: Arc<
         dyn EventBus<EventEnvelope<AccountAggregate>, T, Q, EventEnvelope<AccountAggregate>>
             + Sync
             + Send,
     >,
+    owner: String,
+    lease: Duration,
+    max_attempts: i64,
+    retry_delay: Duration,
 }
 
 impl<T, Q> AccountOutboxService<T, Q> {
     pub fn new(
-        repository: Arc<
-            dyn EventRepository<
-                    EventEnvelope<AccountAggregate>,
-                    T,
-                    Q,
-                    EventEnvelope<AccountAggregate>,
-                    AggregateSnapshot<AccountAggregate>,
-                    AggregateSnapshot<AccountAggregate>,
-                > + Sync
-                + Send,
-        >,
+        repository: Arc<dyn AccountEventRepository<T, Q> + Sync + Send>,
         bus: Arc<
             dyn EventBus<EventEnvelope<AccountAggregate>, T, Q, EventEnvelope<AccountAggregate>>
                 + Sync
                 + Send,
         >,
     ) -> Self {
-        return Self { repository, bus };
+        return Self {
+            repository,
+            bus,
+            owner: Ulid::new().to_string(),
+            lease: Duration::from_secs(30),
+            max_attempts: 10,
+            retry_delay: Duration::from_secs(1),
+        };
+    }
+
+    pub fn with_lease(mut self, owner: String, lease: Duration) -> Self {
+        self.owner = owner;
+        self.lease = lease;
+        return self;
+    }
+
+    pub fn with_retry_policy(mut self, max_attempts: i64, retry_delay: Duration) -> Self {
+        self.max_attempts = max_attempts;
+        self.retry_delay = retry_delay;
+        return self;
     }
 }
 
 #[async_trait]
 impl<T, Q> GetEvents<EventEnvelope<AccountAggregate>> for AccountOutboxService<T, Q> {
-    async fn get_events(&self) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
-        self.repository.retrieve_outbox_events().await
+    async fn get_events(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        self.repository.retrieve_outbox_batch(limit).await
+    }
+}
+
+#[async_trait]
+impl<T, Q> ClaimEvents<EventEnvelope<AccountAggregate>> for AccountOutboxService<T, Q> {
+    async fn claim_events(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        self.repository
+            .claim_outbox_events(self.owner.clone(), self.lease, limit)
+            .await
+    }
+
+    async fn release_events(&self) -> Result<(), anyhow::Error> {
+        self.repository
+            .release_outbox_leases(self.owner.clone())
+            .await
+    }
+
+    async fn fail_event(&self, sequence: String, error: String) -> Result<bool, anyhow::Error> {
+        self.repository
+            .record_outbox_failure(sequence, error, self.max_attempts, self.retry_delay)
+            .await
+    }
+}
+
+#[async_trait]
+impl<T, Q> ManageDeadLetters for AccountOutboxService<T, Q> {
+    async fn list_dead_letters(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<OutboxDeadLetter>, anyhow::Error> {
+        self.repository.list_dead_letters(limit, offset).await
+    }
+
+    async fn inspect_dead_letter(
+        &self,
+        sequence: String,
+    ) -> Result<Option<OutboxDeadLetter>, anyhow::Error> {
+        self.repository.retrieve_dead_letter(sequence).await
+    }
+
+    async fn requeue_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        self.repository.requeue_dead_letter(sequence).await
+    }
+
+    async fn discard_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        self.repository.discard_dead_letter(sequence).await
     }
 }
 
@@ -61,8 +136,26 @@ impl<T, Q> SendEvent<EventEnvelope<AccountAggregate>> for AccountOutboxService<T
         &self,
         event: EventEnvelope<AccountAggregate>,
     ) -> Result<(), anyhow::Error> {
-        self.repository
-            .send_and_delete_outbox_event(event, &self.bus)
-            .await
+        // The lease is renewed before every send so a slow batch keeps it,
+        // and the event is only deleted while it is still ours.
+        let sequence = event.sequence.clone();
+        if !self
+            .repository
+            .renew_outbox_lease(self.owner.clone(), sequence.clone(), self.lease)
+            .await?
+        {
+            return Err(AccountError::OutboxLeaseLost(sequence).into());
+        }
+        self.bus.send_event(event).await?;
+        if !self
+            .repository
+            .delete_leased_outbox_event(self.owner.clone(), sequence.clone())
+            .await?
+        {
+            return Err(AccountError::OutboxLeaseLost(sequence).into());
+        }
+        return Ok(());
     }
-}
\ No newline at end of file
+}
+
+impl<T, Q> OutboxServiceTrait for AccountOutboxService<T, Q> {}
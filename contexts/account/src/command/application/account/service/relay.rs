@@ -0,0 +1,177 @@
+use super::outbox::OutboxServiceTrait;
+use crate::command::domain::account::entity::{aggregate::AccountAggregate, error::AccountError};
+
+use std::{cmp::min, collections::HashMap, sync::Arc, time::Duration};
+
+use cqrs_rs::domain::entity::event::EventEnvelope;
+use futures::future::join_all;
+use tokio::sync::watch;
+use tracing::{span, Instrument};
+
+#[derive(Debug, Clone)]
+pub struct OutboxRelayConfig {
+    pub poll_interval: Duration,
+    pub batch_size: i64,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for OutboxRelayConfig {
+    fn default() -> Self {
+        return Self {
+            poll_interval: Duration::from_secs(5),
+            batch_size: 100,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(30),
+        };
+    }
+}
+
+/// Drains the outbox onto the event bus. Between batches it sleeps for the
+/// poll interval, or less when the repository signals a new commit, and it
+/// only observes shutdown between batches so an in-flight batch is finished.
+pub struct OutboxRelay {
+    service: Arc<dyn OutboxServiceTrait + Sync + Send>,
+    config: OutboxRelayConfig,
+    commits: watch::Receiver<u64>,
+    shutdown: watch::Receiver<bool>,
+}
+
+impl OutboxRelay {
+    pub fn new(
+        service: Arc<dyn OutboxServiceTrait + Sync + Send>,
+        config: OutboxRelayConfig,
+        commits: watch::Receiver<u64>,
+        shutdown: watch::Receiver<bool>,
+    ) -> Self {
+        return Self {
+            service,
+            config,
+            commits,
+            shutdown,
+        };
+    }
+
+    pub async fn run(mut self) -> Result<(), anyhow::Error> {
+        let mut backoff = self.config.initial_backoff;
+        loop {
+            if *self.shutdown.borrow() {
+                return Ok(());
+            }
+            let (delay, wake_on_commit) = match self.relay_batch().await {
+                Ok(x) if x >= self.config.batch_size => (Duration::ZERO, false),
+                Ok(_) => {
+                    backoff = self.config.initial_backoff;
+                    (self.config.poll_interval, true)
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "outbox relay batch failed");
+                    let delay = backoff;
+                    backoff = min(backoff * 2, self.config.max_backoff);
+                    (delay, false)
+                }
+            };
+            if delay.is_zero() {
+                continue;
+            }
+            if self.wait(delay, wake_on_commit).await {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Claims a batch and relays it, one task per aggregate so that events
+    /// of an aggregate stay in order while aggregates proceed in parallel.
+    /// A failed delivery is recorded against its event and ends the turn of
+    /// its aggregate, whose remaining events are released for a later batch.
+    /// Only repository errors fail the batch and back the relay off.
+    async fn relay_batch(&self) -> Result<i64, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "relay_batch",
+            target = "OutboxRelay"
+        );
+        async {
+            let events = self.service.claim_events(self.config.batch_size).await?;
+            let count = events.len() as i64;
+            let mut order: Vec<String> = vec![];
+            let mut groups: HashMap<String, Vec<EventEnvelope<AccountAggregate>>> =
+                HashMap::new();
+            for event in events {
+                if !groups.contains_key(&event.aggregate_id) {
+                    order.push(event.aggregate_id.clone());
+                }
+                groups
+                    .entry(event.aggregate_id.clone())
+                    .or_default()
+                    .push(event);
+            }
+            let deliveries = order.into_iter().map(|x| {
+                let events = groups.remove(&x).unwrap_or_default();
+                async move {
+                    for event in events {
+                        let sequence = event.sequence.clone();
+                        if let Err(e) = self.service.send_event(event).await {
+                            // The relay that took the aggregate over delivers the rest.
+                            if let Some(AccountError::OutboxLeaseLost(_)) = e.downcast_ref() {
+                                tracing::warn!(
+                                    sequence = sequence.as_str(),
+                                    "outbox lease lost to another relay"
+                                );
+                                break;
+                            }
+                            tracing::warn!(
+                                sequence = sequence.as_str(),
+                                error = %e,
+                                "outbox event delivery failed"
+                            );
+                            if self
+                                .service
+                                .fail_event(sequence.clone(), e.to_string())
+                                .await?
+                            {
+                                tracing::warn!(
+                                    sequence = sequence.as_str(),
+                                    "outbox event moved to dead letters"
+                                );
+                            }
+                            break;
+                        }
+                    }
+                    return Ok::<(), anyhow::Error>(());
+                }
+            });
+            let results = join_all(deliveries).await;
+            self.service.release_events().await?;
+            for result in results {
+                result?;
+            }
+            return Ok::<i64, anyhow::Error>(count);
+        }
+        .instrument(root)
+        .await
+    }
+
+    /// Returns `true` when shutdown was requested while waiting.
+    async fn wait(&mut self, delay: Duration, mut wake_on_commit: bool) -> bool {
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => return false,
+                x = self.shutdown.changed() => {
+                    if x.is_err() || *self.shutdown.borrow() {
+                        return true;
+                    }
+                }
+                x = self.commits.changed(), if wake_on_commit => {
+                    if x.is_ok() {
+                        return false;
+                    }
+                    // Without a notifier only the poll interval is left.
+                    wake_on_commit = false;
+                }
+            }
+        }
+    }
+}
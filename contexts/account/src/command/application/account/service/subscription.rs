@@ -0,0 +1,172 @@
+use crate::command::application::account::ports::outbound::subscription::{
+    AccountEventLogRepository, CheckpointStore, EventHandler,
+};
+
+use std::{cmp::min, sync::Arc, time::Duration};
+
+use tokio::sync::watch;
+use tracing::{span, Instrument};
+
+#[derive(Debug, Clone)]
+pub struct SubscriptionConfig {
+    pub poll_interval: Duration,
+    pub batch_size: i64,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        return Self {
+            poll_interval: Duration::from_secs(5),
+            batch_size: 500,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(30),
+        };
+    }
+}
+
+/// Feeds the global event log to a handler. It starts from the stored
+/// checkpoint and reads full batches back to back until it has caught up,
+/// then waits for commit notifications (or the poll interval) for new
+/// events. The checkpoint is saved after every batch, unless it was moved
+/// while the batch ran, and reloaded before the next one, so a projection
+/// replay can rewind it while the subscription runs.
+pub struct CatchUpSubscription {
+    subscription_id: String,
+    log: Arc<dyn AccountEventLogRepository + Sync + Send>,
+    checkpoints: Arc<dyn CheckpointStore + Sync + Send>,
+    handler: Arc<dyn EventHandler + Sync + Send>,
+    config: SubscriptionConfig,
+    commits: watch::Receiver<u64>,
+    shutdown: watch::Receiver<bool>,
+}
+
+impl CatchUpSubscription {
+    pub fn new(
+        subscription_id: String,
+        log: Arc<dyn AccountEventLogRepository + Sync + Send>,
+        checkpoints: Arc<dyn CheckpointStore + Sync + Send>,
+        handler: Arc<dyn EventHandler + Sync + Send>,
+        commits: watch::Receiver<u64>,
+        shutdown: watch::Receiver<bool>,
+    ) -> Self {
+        return Self {
+            subscription_id,
+            log,
+            checkpoints,
+            handler,
+            config: SubscriptionConfig::default(),
+            commits,
+            shutdown,
+        };
+    }
+
+    pub fn with_config(mut self, config: SubscriptionConfig) -> Self {
+        self.config = config;
+        return self;
+    }
+
+    pub async fn run(mut self) -> Result<(), anyhow::Error> {
+        let mut backoff = self.config.initial_backoff;
+        loop {
+            if *self.shutdown.borrow() {
+                return Ok(());
+            }
+            let (delay, wake_on_commit) = match self.handle_batch().await {
+                Ok(count) => {
+                    backoff = self.config.initial_backoff;
+                    if count >= self.config.batch_size {
+                        (Duration::ZERO, false)
+                    } else {
+                        (self.config.poll_interval, true)
+                    }
+                }
+                Err(e) => {
+                    tracing::error!(
+                        error = %e,
+                        subscription_id = self.subscription_id.as_str(),
+                        "subscription batch failed"
+                    );
+                    let delay = backoff;
+                    backoff = min(backoff * 2, self.config.max_backoff);
+                    (delay, false)
+                }
+            };
+            if delay.is_zero() {
+                continue;
+            }
+            if self.wait(delay, wake_on_commit).await {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Hands the events after the stored checkpoint to the handler and
+    /// returns the number of events read. A failing event stops the batch;
+    /// everything before it is checkpointed.
+    async fn handle_batch(&self) -> Result<i64, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "handle_batch",
+            target = "CatchUpSubscription",
+            subscription_id = self.subscription_id.as_str()
+        );
+        async {
+            let start = self
+                .checkpoints
+                .load_checkpoint(self.subscription_id.clone())
+                .await?;
+            let mut position = start;
+            let events = self.log.read_all(start, self.config.batch_size).await?;
+            let count = events.len() as i64;
+            let mut result = Ok(());
+            for event in events.iter() {
+                if let Err(e) = self.handler.handle(event).await {
+                    result = Err(e);
+                    break;
+                }
+                position = event.position;
+            }
+            if position != start {
+                let saved = self
+                    .checkpoints
+                    .save_checkpoint(self.subscription_id.clone(), start, position)
+                    .await?;
+                if !saved {
+                    tracing::info!(
+                        subscription_id = self.subscription_id.as_str(),
+                        "checkpoint moved during the batch, resuming from it"
+                    );
+                }
+            }
+            result?;
+            return Ok::<i64, anyhow::Error>(count);
+        }
+        .instrument(root)
+        .await
+    }
+
+    /// Returns `true` when shutdown was requested while waiting.
+    async fn wait(&mut self, delay: Duration, mut wake_on_commit: bool) -> bool {
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => return false,
+                x = self.shutdown.changed() => {
+                    if x.is_err() || *self.shutdown.borrow() {
+                        return true;
+                    }
+                }
+                x = self.commits.changed(), if wake_on_commit => {
+                    if x.is_ok() {
+                        return false;
+                    }
+                    // Without a notifier only the poll interval is left.
+                    wake_on_commit = false;
+                }
+            }
+        }
+    }
+}
@@ -2,7 +2,10 @@ use crate::{
     command::domain::account::machine::{
         context::AccountContext, create_account_machine, states::States,
     },
-    common::application::ports::outbound::account_services::AccountServices,
+    common::{
+        application::ports::outbound::account_services::AccountServices,
+        domain::secret::PasswordHash,
+    },
 };
 
 use std::sync::Arc;
@@ -14,20 +17,31 @@ use cqrs_rs::domain::entity::{
     event::{AggregateSnapshot, DomainEvent},
 };
 use struct_field_names_as_array::FieldNamesAsArray;
-use tracing::span;
+use tracing::{span, Instrument};
 use ulid::Ulid;
 
-use super::{command::AccountCommand, error::AccountError, event::AccountEvent};
+use super::{
+    command::{AccountCommand, CreateAccountCommand},
+    error::AccountError,
+    event::AccountEvent,
+};
+
+pub const ACCOUNT_STATUS_CREATED: &str = "Created";
+pub const ACCOUNT_STATUS_SUSPENDED: &str = "Suspended";
+pub const ACCOUNT_STATUS_DELETED: &str = "Deleted";
 
-#[derive(Clone, Debug, FieldNamesAsArray)]
+#[derive(Clone, Debug, Default, FieldNamesAsArray)]
 pub struct AccountAggregate {
     pub id: Option<String>,
     pub email: Option<String>,
     pub status: Option<String>,
-    pub password_hash: Option<String>,
+    pub password_hash: Option<PasswordHash>,
     pub created_at: Option<DateTime<Utc>>,
     pub last_event: Option<AccountEvent>,
     pub applied_events: i32,
+    /// `applied_events` as of the latest snapshot, so snapshots keep their
+    /// cadence once the aggregate is hydrated from one.
+    pub snapshotted_events: i32,
 }
 
 #[async_trait]
@@ -53,25 +67,32 @@ impl Aggregate for AccountAggregate {
         let root = span!(tracing::Level::INFO, "handle", target = "AccountAggregate");
         let _enter = root.enter();
         span!(tracing::Level::INFO, "aggregate has received command");
-        let mut context: AccountContext = match &self.last_event {
-            Some(_) => AccountContext::new(services.clone(), Some(self.clone())),
-            None => AccountContext::new(services.clone(), None),
-        };
-        span!(tracing::Level::INFO, "state machine context constructed");
-        let mut machine = match &self.last_event {
-            Some(x) => match x {
-                AccountEvent::AccountCreated { .. } => create_account_machine(States::Created),
-            },
-            None => create_account_machine(States::New),
-        };
-        span!(tracing::Level::INFO, "state machine reconstituted");
-        context.set_command(command.clone());
-        let machine_span = span!(tracing::Level::INFO, "machine executed").entered();
-        machine.decide(&mut context);
-        machine_span.exit();
+        let mut context = self.decide(command, services, None)?;
+        // The machine is synchronous, so a state that accepts a new account
+        // asks for the hash of its password, which is computed on the
+        // services' pool before the command is decided again. Refused
+        // commands never cost a hash, and the password is moved out of the
+        // command rather than copied.
+        if context.get_password_hash_required() {
+            let mut command = context.take_command().unwrap();
+            let password = match &mut command {
+                AccountCommand::CreateAccount(CreateAccountCommand { password, .. }) => {
+                    std::mem::take(password)
+                }
+                _ => return Err(AccountError::StateMachineTransitionFail(command)),
+            };
+            let hashing = span!(tracing::Level::INFO, "hashing password");
+            let password_hash = services
+                .hash_password_async(password)
+                .instrument(hashing)
+                .await?;
+            context = self.decide(command, services, Some(password_hash))?;
+        }
         return match context.get_event() {
             Some(x) => Ok(vec![x.clone()]),
-            None => Err(AccountError::StateMachineTransitionFail(command)),
+            None => Err(AccountError::StateMachineTransitionFail(
+                context.take_command().unwrap(),
+            )),
         };
     }
 
@@ -88,7 +109,20 @@ impl Aggregate for AccountAggregate {
                 self.id = Some(id.clone());
                 self.email = Some(email.clone());
                 self.password_hash = Some(password_hash.clone());
-                self.created_at = Some(created_at.clone());
+                self.created_at = Some(*created_at);
+                self.status = Some(ACCOUNT_STATUS_CREATED.into());
+                self.last_event = Some(event);
+            }
+            AccountEvent::AccountSuspended { .. } => {
+                self.status = Some(ACCOUNT_STATUS_SUSPENDED.into());
+                self.last_event = Some(event);
+            }
+            AccountEvent::AccountDeleted { .. } => {
+                self.status = Some(ACCOUNT_STATUS_DELETED.into());
+                self.last_event = Some(event);
+            }
+            AccountEvent::PasswordRehashed { password_hash, .. } => {
+                self.password_hash = Some(password_hash.clone());
                 self.last_event = Some(event);
             }
         }
@@ -102,10 +136,13 @@ impl Aggregate for AccountAggregate {
         self.created_at = payload.created_at;
         self.last_event = payload.last_event;
         self.status = payload.status;
+        self.applied_events = payload.applied_events;
+        self.snapshotted_events = payload.applied_events;
     }
 
     fn snapshot(&mut self) -> Option<AggregateSnapshot<Self>> {
-        if self.applied_events >= 10 {
+        if self.applied_events - self.snapshotted_events >= 10 {
+            self.snapshotted_events = self.applied_events;
             let snapshot: AggregateSnapshot<Self> = AggregateSnapshot {
                 aggregate_id: self.aggregate_id().unwrap(),
                 aggregate_type: Self::aggregate_type(),
@@ -120,16 +157,37 @@ impl Aggregate for AccountAggregate {
     }
 }
 
-impl Default for AccountAggregate {
-    fn default() -> Self {
-        AccountAggregate {
-            id: None,
-            email: None,
-            status: None,
-            password_hash: None,
-            created_at: None,
-            last_event: None,
-            applied_events: 0,
+impl AccountAggregate {
+    /// Runs `command` through a state machine reconstituted from this
+    /// aggregate, handing the states `password_hash` when it is known.
+    fn decide(
+        &self,
+        command: AccountCommand,
+        services: &Arc<dyn AccountServices + Sync + Send>,
+        password_hash: Option<PasswordHash>,
+    ) -> Result<AccountContext, AccountError> {
+        let mut context: AccountContext = match &self.last_event {
+            Some(_) => AccountContext::new(services.clone(), Some(self.clone())),
+            None => AccountContext::new(services.clone(), None),
+        };
+        if let Some(x) = password_hash {
+            context.set_password_hash(x);
         }
+        span!(tracing::Level::INFO, "state machine context constructed");
+        // Reconstituted from the status rather than the last event, which may
+        // be one that leaves the status as it was, like a rehash.
+        let mut machine = match self.status.as_deref() {
+            Some(ACCOUNT_STATUS_CREATED) => create_account_machine(States::Created),
+            Some(ACCOUNT_STATUS_SUSPENDED) => create_account_machine(States::Suspended),
+            Some(ACCOUNT_STATUS_DELETED) => create_account_machine(States::Deleted),
+            Some(_) => return Err(AccountError::StateMachineTransitionFail(command)),
+            None => create_account_machine(States::New),
+        };
+        span!(tracing::Level::INFO, "state machine reconstituted");
+        context.set_command(command);
+        let machine_span = span!(tracing::Level::INFO, "machine executed").entered();
+        machine.decide(&mut context);
+        machine_span.exit();
+        return Ok(context);
     }
 }
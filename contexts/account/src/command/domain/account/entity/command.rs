@@ -1,26 +1,72 @@
-use std::fmt::Debug;
+use crate::common::domain::secret::{Password, PasswordHash};
 
-#[derive(Debug, Clone)]
+use std::fmt::{Debug, Display};
+
+/// Not `Clone`, as commands may carry a password.
+#[derive(Debug)]
 pub enum AccountCommand {
-    CreateAccount(CreateAccountCommand)
+    CreateAccount(CreateAccountCommand),
+    SuspendAccount(SuspendAccountCommand),
+    DeleteAccount(DeleteAccountCommand),
+    RehashPassword(RehashPasswordCommand)
 }
 
-impl AccountCommand {
-    pub fn to_string(&self) -> String {
+impl Display for AccountCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::CreateAccount { .. } => "CreateAccount".into()
+            Self::CreateAccount { .. } => write!(f, "CreateAccount"),
+            Self::SuspendAccount { .. } => write!(f, "SuspendAccount"),
+            Self::DeleteAccount { .. } => write!(f, "DeleteAccount"),
+            Self::RehashPassword { .. } => write!(f, "RehashPassword")
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct CreateAccountCommand {
     pub email: String,
-    pub password: String
+    pub password: Password
+}
+
+impl From<CreateAccountCommand> for AccountCommand {
+    fn from(value: CreateAccountCommand) -> Self {
+        AccountCommand::CreateAccount(value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SuspendAccountCommand {
+    pub id: String,
+    pub reason: String
+}
+
+impl From<SuspendAccountCommand> for AccountCommand {
+    fn from(value: SuspendAccountCommand) -> Self {
+        AccountCommand::SuspendAccount(value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeleteAccountCommand {
+    pub id: String
+}
+
+impl From<DeleteAccountCommand> for AccountCommand {
+    fn from(value: DeleteAccountCommand) -> Self {
+        AccountCommand::DeleteAccount(value)
+    }
+}
+
+/// Replaces an outdated hash with one computed while the password was
+/// verified against it.
+#[derive(Debug, Clone)]
+pub struct RehashPasswordCommand {
+    pub id: String,
+    pub password_hash: PasswordHash
 }
 
-impl Into<AccountCommand> for CreateAccountCommand {
-    fn into(self) -> AccountCommand {
-        AccountCommand::CreateAccount(self)
+impl From<RehashPasswordCommand> for AccountCommand {
+    fn from(value: RehashPasswordCommand) -> Self {
+        AccountCommand::RehashPassword(value)
     }
 }
\ No newline at end of file
@@ -1,4 +1,7 @@
+use crate::common::application::ports::outbound::account_services::PasswordHashingError;
+
 use thiserror::Error;
+
 use super::command::AccountCommand;
 
 #[derive(Error, Debug)]
@@ -7,8 +10,45 @@ pub enum AccountError {
     AccountNotExists(String),
     #[error("account with email `{0}` already exists")]
     AccountExists(String),
+    #[error("account `{0}` does not exist")]
+    AccountNotFound(String),
     #[error("state machine failed to emit event for command `{0:?}`")]
     StateMachineTransitionFail(AccountCommand),
+    #[error("idempotency key `{0}` was already used with a different payload")]
+    IdempotencyKeyReused(String),
+    #[error("a request with idempotency key `{0}` is still being processed")]
+    IdempotencyKeyInProgress(String),
+    #[error("password hashing is at capacity, retry the request")]
+    PasswordHashingUnavailable,
+    #[error("password hashing failed: {0}")]
+    PasswordHashingFailed(String),
+    #[error("account `{0}` was changed by another request, retry against its current state")]
+    ConcurrentModification(String),
+    #[error("outbox event `{0}` was taken over by another relay")]
+    OutboxLeaseLost(String),
+    #[error("account `{0}` has unhashed events, run the chain backfill before appending")]
+    ChainNotBackfilled(String),
     #[error("unknown error occured")]
     UnknownError
+}
+
+impl AccountError {
+    /// Whether the same request may succeed if it is sent again later.
+    pub fn is_retryable(&self) -> bool {
+        return matches!(
+            self,
+            Self::PasswordHashingUnavailable
+                | Self::IdempotencyKeyInProgress(_)
+                | Self::ConcurrentModification(_)
+        );
+    }
+}
+
+impl From<PasswordHashingError> for AccountError {
+    fn from(e: PasswordHashingError) -> Self {
+        return match e {
+            PasswordHashingError::Saturated => Self::PasswordHashingUnavailable,
+            PasswordHashingError::Failed(e) => Self::PasswordHashingFailed(e.to_string()),
+        };
+    }
 }
\ No newline at end of file
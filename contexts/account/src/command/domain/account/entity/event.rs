@@ -1,34 +1,70 @@
+use crate::common::domain::secret::PasswordHash;
+
 use chrono::{DateTime, Utc};
 use cqrs_rs::domain::entity::event::DomainEvent;
 
+pub const ACCOUNT_CREATED_VERSION: &str = "0.0.1";
+pub const ACCOUNT_SUSPENDED_VERSION: &str = "0.0.1";
+pub const ACCOUNT_DELETED_VERSION: &str = "0.0.1";
+pub const PASSWORD_REHASHED_VERSION: &str = "0.0.1";
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum AccountEvent {
     AccountCreated {
         id: String,
         email: String,
-        password_hash: String,
+        password_hash: PasswordHash,
         created_at: DateTime<Utc>,
         event_version: String,
         event_id: String,
+    },
+    AccountSuspended {
+        id: String,
+        reason: String,
+        suspended_at: DateTime<Utc>,
+        event_version: String,
+        event_id: String,
+    },
+    AccountDeleted {
+        id: String,
+        deleted_at: DateTime<Utc>,
+        event_version: String,
+        event_id: String,
+    },
+    PasswordRehashed {
+        id: String,
+        password_hash: PasswordHash,
+        rehashed_at: DateTime<Utc>,
+        event_version: String,
+        event_id: String,
     }
 }
 
 impl DomainEvent for AccountEvent {
     fn event_type(&self) -> String {
         match self {
-            AccountEvent::AccountCreated { .. } => "AccountCreated".into()
+            AccountEvent::AccountCreated { .. } => "AccountCreated".into(),
+            AccountEvent::AccountSuspended { .. } => "AccountSuspended".into(),
+            AccountEvent::AccountDeleted { .. } => "AccountDeleted".into(),
+            AccountEvent::PasswordRehashed { .. } => "PasswordRehashed".into()
         }
     }
 
     fn event_version(&self) -> String {
         match self {
-            AccountEvent::AccountCreated { event_version, .. } => event_version.into()
+            AccountEvent::AccountCreated { event_version, .. }
+            | AccountEvent::AccountSuspended { event_version, .. }
+            | AccountEvent::AccountDeleted { event_version, .. }
+            | AccountEvent::PasswordRehashed { event_version, .. } => event_version.into()
         }
     }
 
     fn event_id(&self) -> String {
         match self {
-            AccountEvent::AccountCreated { event_id, .. } => event_id.into()
+            AccountEvent::AccountCreated { event_id, .. }
+            | AccountEvent::AccountSuspended { event_id, .. }
+            | AccountEvent::AccountDeleted { event_id, .. }
+            | AccountEvent::PasswordRehashed { event_id, .. } => event_id.into()
         }
     }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use crate::{command::domain::account::entity::{command::AccountCommand, event::AccountEvent, aggregate::AccountAggregate}, common::application::ports::outbound::account_services::AccountServices};
+use crate::{command::domain::account::entity::{command::AccountCommand, event::AccountEvent, aggregate::AccountAggregate}, common::{application::ports::outbound::account_services::AccountServices, domain::secret::PasswordHash}};
 
 use std::sync::Arc;
 
@@ -8,6 +8,8 @@ pub struct AccountContext {
     event: Option<AccountEvent>,
     error: Option<anyhow::Error>,
     current_state: Option<AccountAggregate>,
+    password_hash: Option<PasswordHash>,
+    password_hash_required: bool,
     services: Arc<dyn Send + Sync + AccountServices>,
 }
 
@@ -21,6 +23,8 @@ impl AccountContext {
             command: None,
             event: None,
             error: None,
+            password_hash: None,
+            password_hash_required: false,
             services
         };
     }
@@ -36,6 +40,9 @@ impl AccountContext {
     pub fn set_command(&mut self, command: AccountCommand) {
         self.command = Some(command);
     }
+    pub fn take_command(&mut self) -> Option<AccountCommand> {
+        return self.command.take();
+    }
     pub fn get_current_state(&self) -> &Option<AccountAggregate> {
         return &self.current_state;
     }
@@ -45,6 +52,22 @@ impl AccountContext {
     pub fn set_error(&mut self, error: anyhow::Error) {
         self.error = Some(error);
     }
+    /// The hash of the command's password, computed outside the machine so
+    /// that states never hash on the async executor.
+    pub fn get_password_hash(&self) -> &Option<PasswordHash> {
+        return &self.password_hash;
+    }
+    pub fn set_password_hash(&mut self, password_hash: PasswordHash) {
+        self.password_hash = Some(password_hash);
+    }
+    /// Set by a state that accepted the command but needs the hash of its
+    /// password to emit the event.
+    pub fn get_password_hash_required(&self) -> bool {
+        return self.password_hash_required;
+    }
+    pub fn require_password_hash(&mut self) {
+        self.password_hash_required = true;
+    }
     pub fn get_services(&self) -> Arc<dyn Send + Sync + AccountServices> {
         return self.services.clone();
     }
@@ -5,22 +5,53 @@ use machines_rs::{machine::Machine, state::State};
 
 use self::{
     context::AccountContext,
-    states::{new::New, States},
+    states::{created::Created, deleted::Deleted, new::New, suspended::Suspended, States},
 };
 
 pub type AccountMachine = Machine<States, AccountContext>;
 
 pub fn create_account_machine(initial_state: States) -> AccountMachine {
-    let fsm = Machine::new(initial_state).state(
-        States::New,
-        State::new(New).transition(
+    let fsm = Machine::new(initial_state)
+        .state(
+            States::New,
+            State::new(New).transition(
+                States::Created,
+                |data| is_command(data, "CreateAccount"),
+                vec![],
+            ),
+        )
+        .state(
             States::Created,
-            |data| {
-                return data.get_command().is_some()
-                    && data.get_command().as_ref().unwrap().to_string() == "CreateAccount";
-            },
-            vec![],
-        ),
-    );
+            State::new(Created)
+                .transition(
+                    States::Suspended,
+                    |data| is_command(data, "SuspendAccount"),
+                    vec![],
+                )
+                .transition(
+                    States::Deleted,
+                    |data| is_command(data, "DeleteAccount"),
+                    vec![],
+                )
+                .transition(
+                    States::Created,
+                    |data| is_command(data, "RehashPassword"),
+                    vec![],
+                ),
+        )
+        .state(
+            States::Suspended,
+            State::new(Suspended).transition(
+                States::Deleted,
+                |data| is_command(data, "DeleteAccount"),
+                vec![],
+            ),
+        )
+        .state(States::Deleted, State::new(Deleted));
     return fsm;
 }
+
+fn is_command(data: &AccountContext, name: &str) -> bool {
+    return data.get_command().is_some()
+        && data.get_command().as_ref().unwrap().to_string() == name;
+}
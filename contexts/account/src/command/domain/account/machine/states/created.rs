@@ -0,0 +1,76 @@
+use chrono::Utc;
+use machines_rs::traits::TState;
+use tracing::span;
+use ulid::Ulid;
+
+use crate::command::domain::account::{
+    entity::{
+        command::{
+            AccountCommand, DeleteAccountCommand, RehashPasswordCommand, SuspendAccountCommand,
+        },
+        event::{
+            AccountEvent, ACCOUNT_DELETED_VERSION, ACCOUNT_SUSPENDED_VERSION,
+            PASSWORD_REHASHED_VERSION,
+        },
+    },
+    machine::context::AccountContext,
+};
+
+pub struct Created;
+
+impl TState<AccountContext> for Created {
+    fn entry(&mut self, _context: &mut AccountContext) {
+        let root = span!(
+            tracing::Level::INFO,
+            "state entered",
+            target = "AccountStateMachine",
+            state = "Created"
+        );
+        let _enter = root.enter();
+    }
+
+    fn exit(&mut self, context: &mut AccountContext) {
+        let root = span!(
+            tracing::Level::INFO,
+            "state exited",
+            target = "AccountStateMachine",
+            state = "Created"
+        );
+        let _enter = root.enter();
+        let command: &AccountCommand = context.get_command().as_ref().unwrap();
+        match command {
+            AccountCommand::SuspendAccount(SuspendAccountCommand { id, reason }) => {
+                let event = AccountEvent::AccountSuspended {
+                    id: id.clone(),
+                    reason: reason.clone(),
+                    suspended_at: Utc::now(),
+                    event_version: ACCOUNT_SUSPENDED_VERSION.into(),
+                    event_id: Ulid::new().to_string(),
+                };
+                context.set_event(event);
+            }
+            AccountCommand::DeleteAccount(DeleteAccountCommand { id }) => {
+                let event = AccountEvent::AccountDeleted {
+                    id: id.clone(),
+                    deleted_at: Utc::now(),
+                    event_version: ACCOUNT_DELETED_VERSION.into(),
+                    event_id: Ulid::new().to_string(),
+                };
+                context.set_event(event);
+            }
+            AccountCommand::RehashPassword(RehashPasswordCommand { id, password_hash }) => {
+                let event = AccountEvent::PasswordRehashed {
+                    id: id.clone(),
+                    password_hash: password_hash.clone(),
+                    rehashed_at: Utc::now(),
+                    event_version: PASSWORD_REHASHED_VERSION.into(),
+                    event_id: Ulid::new().to_string(),
+                };
+                context.set_event(event);
+            }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self, _context: &mut AccountContext) {}
+}
@@ -0,0 +1,23 @@
+use machines_rs::traits::TState;
+use tracing::span;
+
+use crate::command::domain::account::machine::context::AccountContext;
+
+/// Terminal state, every command is rejected once an account is deleted.
+pub struct Deleted;
+
+impl TState<AccountContext> for Deleted {
+    fn entry(&mut self, _context: &mut AccountContext) {
+        let root = span!(
+            tracing::Level::INFO,
+            "state entered",
+            target = "AccountStateMachine",
+            state = "Deleted"
+        );
+        let _enter = root.enter();
+    }
+
+    fn exit(&mut self, _context: &mut AccountContext) {}
+
+    fn update(&mut self, _context: &mut AccountContext) {}
+}
@@ -1,8 +1,13 @@
+pub mod created;
+pub mod deleted;
 pub mod new;
+pub mod suspended;
 
 #[derive(Hash, PartialEq, Eq, Clone, Debug)]
 pub enum States {
     Created,
+    Deleted,
     New,
     PasswordReset,
+    Suspended,
 }
\ No newline at end of file
@@ -1,4 +1,3 @@
-use anyhow::anyhow;
 use chrono::Utc;
 use machines_rs::traits::TState;
 use tracing::span;
@@ -7,7 +6,7 @@ use ulid::Ulid;
 use crate::command::domain::account::{
     entity::{
         command::{AccountCommand, CreateAccountCommand},
-        event::AccountEvent,
+        event::{AccountEvent, ACCOUNT_CREATED_VERSION},
     },
     machine::context::AccountContext,
 };
@@ -27,30 +26,24 @@ impl TState<AccountContext> for New {
 
     fn exit(&mut self, context: &mut AccountContext) {
         let command: &AccountCommand = context.get_command().as_ref().unwrap();
-        match command {
-            AccountCommand::CreateAccount(CreateAccountCommand { email, password }) => {
-                let root = span!(
-                    tracing::Level::INFO,
-                    "state exited",
-                    target = "AccountStateMachine",
-                    state = "New"
-                );
-                let _enter = root.enter();
-                let span = span!(tracing::Level::INFO, "hashing password").entered();
-                match context.get_services().hash_password(password.clone()) {
-                    Ok(x) => {
-                        span.exit();
-                        context.set_event(AccountEvent::AccountCreated {
-                            id: Ulid::new().to_string(),
-                            email: email.clone(),
-                            password_hash: x,
-                            event_id: Ulid::new().to_string(),
-                            created_at: Utc::now(),
-                            event_version: "0.0.1".into(),
-                        })
-                    }
-                    Err(_e) => context.set_error(anyhow!("Failed to hash password")),
-                }
+        if let AccountCommand::CreateAccount(CreateAccountCommand { email, .. }) = command {
+            let root = span!(
+                tracing::Level::INFO,
+                "state exited",
+                target = "AccountStateMachine",
+                state = "New"
+            );
+            let _enter = root.enter();
+            match context.get_password_hash().clone() {
+                Some(x) => context.set_event(AccountEvent::AccountCreated {
+                    id: Ulid::new().to_string(),
+                    email: email.clone(),
+                    password_hash: x,
+                    event_id: Ulid::new().to_string(),
+                    created_at: Utc::now(),
+                    event_version: ACCOUNT_CREATED_VERSION.into(),
+                }),
+                None => context.require_password_hash(),
             }
         }
     }
@@ -0,0 +1,48 @@
+use chrono::Utc;
+use machines_rs::traits::TState;
+use tracing::span;
+use ulid::Ulid;
+
+use crate::command::domain::account::{
+    entity::{
+        command::{AccountCommand, DeleteAccountCommand},
+        event::{AccountEvent, ACCOUNT_DELETED_VERSION},
+    },
+    machine::context::AccountContext,
+};
+
+pub struct Suspended;
+
+impl TState<AccountContext> for Suspended {
+    fn entry(&mut self, _context: &mut AccountContext) {
+        let root = span!(
+            tracing::Level::INFO,
+            "state entered",
+            target = "AccountStateMachine",
+            state = "Suspended"
+        );
+        let _enter = root.enter();
+    }
+
+    fn exit(&mut self, context: &mut AccountContext) {
+        let root = span!(
+            tracing::Level::INFO,
+            "state exited",
+            target = "AccountStateMachine",
+            state = "Suspended"
+        );
+        let _enter = root.enter();
+        let command: &AccountCommand = context.get_command().as_ref().unwrap();
+        if let AccountCommand::DeleteAccount(DeleteAccountCommand { id }) = command {
+            let event = AccountEvent::AccountDeleted {
+                id: id.clone(),
+                deleted_at: Utc::now(),
+                event_version: ACCOUNT_DELETED_VERSION.into(),
+                event_id: Ulid::new().to_string(),
+            };
+            context.set_event(event);
+        }
+    }
+
+    fn update(&mut self, _context: &mut AccountContext) {}
+}
@@ -1,26 +1,32 @@
 use crate::command::{
-    application::account::service::account::ServiceTrait,
-    domain::account::entity::command::CreateAccountCommand,
-    infrastructure::dtos::transport::graphql::{GraphQLAccount, GraphQLCreateAccountInput},
+    application::account::{context::RequestContext, service::account::ServiceTrait},
+    domain::account::entity::{command::CreateAccountCommand, error::AccountError},
+    infrastructure::dtos::transport::graphql::{
+        GraphQLAccount, GraphQLCreateAccountInput, GraphQLVerifyCredentialsInput,
+    },
 };
 
-use std::sync::Arc;
+use std::{net::IpAddr, sync::Arc};
 
-use actix_web::{web::{self, Data}, App, HttpResponse, HttpServer, guard};
-use async_graphql::{http::GraphiQLSource, Context, EmptySubscription, Object, Result, Schema};
+use actix_web::{web::{self, Data}, App, HttpRequest, HttpResponse, HttpServer, guard};
+use async_graphql::{
+    http::GraphiQLSource, Context, EmptySubscription, ErrorExtensions, Object, Result, Schema,
+};
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
 use tracing::span;
 use validator::Validate;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+pub type AccountCommandSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
 pub struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
     #[graphql(visible = false)]
     async fn _version(&self) -> String {
-        return VERSION.into();
+        VERSION.into()
     }
 }
 
@@ -32,6 +38,7 @@ impl MutationRoot {
         &self,
         ctx: &Context<'_>,
         input: GraphQLCreateAccountInput,
+        idempotency_key: Option<String>,
     ) -> Result<GraphQLAccount> {
         let service = ctx
             .data::<Arc<dyn ServiceTrait<GraphQLAccount> + Sync + Send>>()
@@ -39,30 +46,158 @@ impl MutationRoot {
         if input.validate().is_err() {
             return Err(input.validate().unwrap_err().into());
         }
+        let mut context = match ctx.data_opt::<RequestContext>() {
+            Some(x) => x.clone(),
+            None => RequestContext::new(),
+        };
+        if idempotency_key.is_some() {
+            context.idempotency_key = idempotency_key;
+        }
         let command = CreateAccountCommand {
             email: input.email,
             password: input.password,
         };
-        let result = service.create_account(command.into(), vec![]).await;
+        let result = service.create_account(command, context, vec![]).await;
         match result {
             Ok(x) => return Ok(x),
-            Err(e) => return Err(e.into()),
+            Err(e) => return Err(graphql_error(e)),
         }
     }
+
+    /// A mutation because verifying against an outdated hash rehashes it.
+    async fn verify_credentials(
+        &self,
+        ctx: &Context<'_>,
+        input: GraphQLVerifyCredentialsInput,
+    ) -> Result<Option<GraphQLAccount>> {
+        let service = ctx
+            .data::<Arc<dyn ServiceTrait<GraphQLAccount> + Sync + Send>>()
+            .unwrap();
+        let context = match ctx.data_opt::<RequestContext>() {
+            Some(x) => x.clone(),
+            None => RequestContext::new(),
+        };
+        let result = service
+            .verify_credentials(input.email, input.password, context)
+            .await;
+        match result {
+            Ok(x) => return Ok(x),
+            Err(e) => return Err(graphql_error(e)),
+        }
+    }
+}
+
+/// Flags errors that may succeed on a later attempt, such as when password
+/// hashing is at capacity, with a `retryable` extension.
+fn graphql_error(e: anyhow::Error) -> async_graphql::Error {
+    let retryable = e
+        .downcast_ref::<AccountError>()
+        .is_some_and(|x| x.is_retryable());
+    let error: async_graphql::Error = e.into();
+    if !retryable {
+        return error;
+    }
+    return error.extend_with(|_, extensions| extensions.set("retryable", true));
 }
 
 #[derive(Clone)]
 pub struct GraphQLAccountCommandAdapter {
-    schema: Schema<QueryRoot, MutationRoot, EmptySubscription>
+    schema: AccountCommandSchema,
+    trusted_proxies: TrustedProxies,
+}
+
+fn header(http_req: &HttpRequest, name: &str) -> Option<String> {
+    return http_req
+        .headers()
+        .get(name)
+        .and_then(|x| x.to_str().ok())
+        .map(|x| x.to_string());
+}
+
+/// The proxies allowed to vouch for a request. `x-authenticated-user` and
+/// `x-forwarded-for` are client-controlled headers, so they are only read
+/// from connections that come from one of these addresses; otherwise the
+/// actor is left empty and the client ip is the peer address.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    addresses: Vec<IpAddr>,
+}
+
+impl TrustedProxies {
+    pub fn new(addresses: Vec<IpAddr>) -> Self {
+        return Self { addresses };
+    }
+
+    fn trusts(&self, http_req: &HttpRequest) -> bool {
+        return http_req
+            .peer_addr()
+            .is_some_and(|x| self.addresses.contains(&x.ip()));
+    }
+
+    /// Walks `x-forwarded-for` from the right, starting at the peer, for as
+    /// long as the hop that added an entry is trusted. Entries further left
+    /// were written by the client and can be anything.
+    fn client_ip(&self, http_req: &HttpRequest) -> Option<IpAddr> {
+        let mut client = http_req.peer_addr().map(|x| x.ip());
+        let hops: Vec<&str> = http_req
+            .headers()
+            .get_all("x-forwarded-for")
+            .filter_map(|x| x.to_str().ok())
+            .flat_map(|x| x.split(','))
+            .map(|x| x.trim())
+            .collect();
+        for hop in hops.into_iter().rev() {
+            if !client.is_some_and(|x| self.addresses.contains(&x)) {
+                break;
+            }
+            match hop.parse::<IpAddr>() {
+                Ok(x) => client = Some(x),
+                Err(_) => break,
+            }
+        }
+        return client;
+    }
+
+    pub fn request_context(&self, http_req: &HttpRequest) -> RequestContext {
+        let mut context = RequestContext::new();
+        if let Some(x) = header(http_req, "x-correlation-id") {
+            context.correlation_id = x;
+        }
+        if let Some(x) = header(http_req, "x-causation-id") {
+            context.causation_id = x;
+        }
+        if self.trusts(http_req) {
+            context.actor = header(http_req, "x-authenticated-user");
+        }
+        context.client_ip = self.client_ip(http_req).map(|x| x.to_string());
+        context.user_agent = header(http_req, "user-agent");
+        context.traceparent = header(http_req, "traceparent");
+        context.idempotency_key = header(http_req, "idempotency-key");
+        return context;
+    }
 }
 
 async fn index(
-    schema: web::Data<Schema<QueryRoot, MutationRoot, EmptySubscription>>,
+    schema: web::Data<AccountCommandSchema>,
+    trusted_proxies: web::Data<TrustedProxies>,
+    http_req: HttpRequest,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
-    let root = span!(tracing::Level::INFO, "graphql_request_received");
+    let context = trusted_proxies.request_context(&http_req);
+    let root = span!(
+        tracing::Level::INFO,
+        "graphql_request_received",
+        correlation_id = context.correlation_id.as_str()
+    );
     let _enter = root.enter();
-    schema.execute(req.into_inner()).await.into()
+    schema.execute(req.into_inner().data(context)).await.into()
+}
+
+pub fn schema(service: Arc<dyn ServiceTrait<GraphQLAccount> + Send + Sync>) -> AccountCommandSchema {
+    return Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .enable_federation()
+        .data(service)
+        .finish();
 }
 
 async fn gql_playgound() -> HttpResponse {
@@ -73,17 +208,22 @@ async fn gql_playgound() -> HttpResponse {
 
 impl GraphQLAccountCommandAdapter {
     pub fn new(service: Arc<dyn ServiceTrait<GraphQLAccount> + Send + Sync>) -> Self {
-        let schema = Schema::build(QueryRoot, MutationRoot, EmptySubscription).enable_federation()
-            .data(service)
-            .finish();
         return Self {
-            schema
-        }
+            schema: schema(service),
+            trusted_proxies: TrustedProxies::default(),
+        };
     }
+
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        return self;
+    }
+
     pub async fn run(self) -> Result<(), anyhow::Error> {
         HttpServer::new(move || {
             App::new()
                 .app_data(Data::new(self.schema.clone()))
+                .app_data(Data::new(self.trusted_proxies.clone()))
                 .service(web::resource("/").guard(guard::Post()).to(index))
                 .service(web::resource("/").guard(guard::Get()).to(gql_playgound))
         })
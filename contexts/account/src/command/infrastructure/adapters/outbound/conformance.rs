@@ -0,0 +1,838 @@
+use super::memory_bus::InMemoryEventBus;
+use crate::command::{
+    application::account::{
+        ports::{
+            inbound::verify_chain::{ChainBreakReason, VerifyChainUseCase},
+            outbound::repository::{AccountEventRepository, EVENT_PAGE_SIZE},
+        },
+        service::chain::AccountChainService,
+    },
+    domain::account::entity::{
+        aggregate::AccountAggregate, error::AccountError, event::AccountEvent,
+    },
+};
+
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, ensure};
+use chrono::{DateTime, SubsecRound, Utc};
+use cqrs_rs::{
+    application::port::outbound::event_bus::EventBus,
+    domain::entity::event::{AggregateSnapshot, EventEnvelope},
+};
+use futures::TryStreamExt;
+use ulid::Ulid;
+
+/// Key the repositories from a conformance factory have to be chained with.
+pub const CONFORMANCE_CHAIN_KEY: &str = "conformance chain key";
+
+pub type ConformanceRepository = Arc<
+    dyn AccountEventRepository<EventEnvelope<AccountAggregate>, EventEnvelope<AccountAggregate>>
+        + Send
+        + Sync,
+>;
+
+type ConformanceBus = Arc<
+    dyn EventBus<
+            EventEnvelope<AccountAggregate>,
+            EventEnvelope<AccountAggregate>,
+            EventEnvelope<AccountAggregate>,
+            EventEnvelope<AccountAggregate>,
+        > + Sync
+        + Send,
+>;
+
+/// Runs every check against a fresh repository from `factory`. Backends
+/// instantiate this from their own integration tests; a failing check
+/// returns an error naming the check.
+pub async fn run_conformance_suite<F, Fut>(factory: F) -> Result<(), anyhow::Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<ConformanceRepository, anyhow::Error>>,
+{
+    let checks: Vec<Check> = vec![
+        ("retrieve_events_in_order", |x| Box::pin(retrieve_events_in_order(x))),
+        ("retrieve_events_after", |x| Box::pin(retrieve_events_after(x))),
+        ("retrieve_events_unknown_aggregate", |x| {
+            Box::pin(retrieve_events_unknown_aggregate(x))
+        }),
+        ("duplicate_sequence_across_aggregates", |x| {
+            Box::pin(duplicate_sequence_across_aggregates(x))
+        }),
+        ("stream_events_in_order", |x| Box::pin(stream_events_in_order(x))),
+        ("snapshot_latest_wins", |x| Box::pin(snapshot_latest_wins(x))),
+        ("outbox_matches_stored_events", |x| {
+            Box::pin(outbox_matches_stored_events(x))
+        }),
+        ("outbox_skips_internal_events", |x| {
+            Box::pin(outbox_skips_internal_events(x))
+        }),
+        ("outbox_delete_after_send", |x| Box::pin(outbox_delete_after_send(x))),
+        ("outbox_kept_when_bus_fails", |x| Box::pin(outbox_kept_when_bus_fails(x))),
+        ("outbox_lease_renewal_keeps_the_event", |x| {
+            Box::pin(outbox_lease_renewal_keeps_the_event(x))
+        }),
+        ("outbox_delete_needs_the_lease", |x| {
+            Box::pin(outbox_delete_needs_the_lease(x))
+        }),
+        ("dead_letter_blocks_aggregate", |x| {
+            Box::pin(dead_letter_blocks_aggregate(x))
+        }),
+        ("discarded_dead_letter_unblocks_aggregate", |x| {
+            Box::pin(discarded_dead_letter_unblocks_aggregate(x))
+        }),
+        ("unknown_outbox_sequence_is_ignored", |x| {
+            Box::pin(unknown_outbox_sequence_is_ignored(x))
+        }),
+        ("store_events_is_atomic", |x| Box::pin(store_events_is_atomic(x))),
+        ("append_events_checks_the_version", |x| {
+            Box::pin(append_events_checks_the_version(x))
+        }),
+        ("read_all_is_gap_free", |x| Box::pin(read_all_is_gap_free(x))),
+        ("hash_chain_links_events", |x| Box::pin(hash_chain_links_events(x))),
+        ("email_lookups", |x| Box::pin(email_lookups(x))),
+        ("unknown_email_lookup_fails", |x| Box::pin(unknown_email_lookup_fails(x))),
+    ];
+    for (name, check) in checks {
+        let repository = factory().await?;
+        check(repository)
+            .await
+            .map_err(|e| anyhow!("conformance check `{}` failed: {}", name, e))?;
+    }
+    return Ok(());
+}
+
+type CheckFuture = std::pin::Pin<Box<dyn Future<Output = Result<(), anyhow::Error>> + Send>>;
+
+type Check = (&'static str, fn(ConformanceRepository) -> CheckFuture);
+
+fn sequences(count: usize) -> Vec<String> {
+    let mut sequences: Vec<String> = (0..count).map(|_| Ulid::new().to_string()).collect();
+    sequences.sort();
+    return sequences;
+}
+
+fn now() -> DateTime<Utc> {
+    return Utc::now().trunc_subsecs(0);
+}
+
+fn account_created(
+    aggregate_id: &str,
+    sequence: &str,
+    email: &str,
+) -> EventEnvelope<AccountAggregate> {
+    return EventEnvelope {
+        aggregate_id: aggregate_id.into(),
+        aggregate_type: "account".into(),
+        sequence: sequence.into(),
+        payload: AccountEvent::AccountCreated {
+            id: aggregate_id.into(),
+            email: email.into(),
+            password_hash: "hash".into(),
+            created_at: now(),
+            event_version: "0.0.1".into(),
+            event_id: sequence.into(),
+        },
+        metadata: HashMap::new(),
+        timestamp: now(),
+    };
+}
+
+fn stream(aggregate_id: &str, sequences: &[String]) -> Vec<EventEnvelope<AccountAggregate>> {
+    return sequences
+        .iter()
+        .map(|x| account_created(aggregate_id, x, &format!("{}@example.com", aggregate_id)))
+        .collect();
+}
+
+fn sequences_of(events: &[EventEnvelope<AccountAggregate>]) -> Vec<String> {
+    return events.iter().map(|x| x.sequence.clone()).collect();
+}
+
+pub async fn retrieve_events_in_order(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let aggregate_id = Ulid::new().to_string();
+    let other_id = Ulid::new().to_string();
+    let expected = sequences(3);
+    repository
+        .store_events(stream(&aggregate_id, &expected[..2]))
+        .await?;
+    repository
+        .store_events(stream(&other_id, &sequences(1)))
+        .await?;
+    repository
+        .store_events(stream(&aggregate_id, &expected[2..]))
+        .await?;
+    let events = repository.retrieve_events(aggregate_id, None).await?;
+    ensure!(
+        sequences_of(&events) == expected,
+        "expected {:?}, got {:?}",
+        expected,
+        sequences_of(&events)
+    );
+    return Ok(());
+}
+
+pub async fn retrieve_events_after(repository: ConformanceRepository) -> Result<(), anyhow::Error> {
+    let aggregate_id = Ulid::new().to_string();
+    let expected = sequences(4);
+    repository
+        .store_events(stream(&aggregate_id, &expected))
+        .await?;
+    let events = repository
+        .retrieve_events(aggregate_id.clone(), Some(expected[1].clone()))
+        .await?;
+    ensure!(
+        sequences_of(&events) == expected[2..].to_vec(),
+        "expected {:?}, got {:?}",
+        &expected[2..],
+        sequences_of(&events)
+    );
+    let events = repository
+        .retrieve_events(aggregate_id, Some(expected[3].clone()))
+        .await?;
+    ensure!(events.is_empty(), "expected no events after the last one");
+    return Ok(());
+}
+
+pub async fn retrieve_events_unknown_aggregate(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let aggregate_id = Ulid::new().to_string();
+    let events = repository
+        .retrieve_events(aggregate_id.clone(), None)
+        .await?;
+    ensure!(events.is_empty(), "expected no events for an unknown aggregate");
+    let events: Vec<EventEnvelope<AccountAggregate>> = repository
+        .stream_events(aggregate_id.clone(), None)
+        .try_collect()
+        .await?;
+    ensure!(events.is_empty(), "expected no streamed events for an unknown aggregate");
+    ensure!(
+        repository
+            .retrieve_latest_snapshot(aggregate_id)
+            .await?
+            .is_none(),
+        "expected no snapshot for an unknown aggregate"
+    );
+    return Ok(());
+}
+
+pub async fn duplicate_sequence_across_aggregates(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let stored = sequences(1);
+    repository
+        .store_events(stream(&Ulid::new().to_string(), &stored))
+        .await?;
+    let other = Ulid::new().to_string();
+    ensure!(
+        repository
+            .store_events(stream(&other, &stored))
+            .await
+            .is_err(),
+        "expected a sequence used by another aggregate to be rejected"
+    );
+    ensure!(
+        repository.retrieve_events(other, None).await?.is_empty(),
+        "expected the rejected append to leave no events behind"
+    );
+    return Ok(());
+}
+
+pub async fn unknown_outbox_sequence_is_ignored(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let sequence = Ulid::new().to_string();
+    ensure!(
+        !repository
+            .record_outbox_failure(sequence.clone(), "error".into(), 1, LEASE)
+            .await?,
+        "expected no dead letter for an unknown sequence"
+    );
+    ensure!(
+        !repository.retry_outbox_event(sequence.clone()).await?,
+        "expected retrying an unknown sequence to report false"
+    );
+    ensure!(
+        !repository.requeue_dead_letter(sequence.clone()).await?,
+        "expected requeueing an unknown sequence to report false"
+    );
+    ensure!(
+        !repository.discard_dead_letter(sequence.clone()).await?,
+        "expected discarding an unknown sequence to report false"
+    );
+    ensure!(
+        repository.retrieve_dead_letter(sequence).await?.is_none(),
+        "expected no dead letter for an unknown sequence"
+    );
+    return Ok(());
+}
+
+pub async fn stream_events_in_order(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let aggregate_id = Ulid::new().to_string();
+    let expected = sequences(EVENT_PAGE_SIZE as usize + 1);
+    let mut reversed = expected.clone();
+    reversed.reverse();
+    repository
+        .store_events(stream(&aggregate_id, &reversed))
+        .await?;
+    let events: Vec<EventEnvelope<AccountAggregate>> = repository
+        .stream_events(aggregate_id.clone(), None)
+        .try_collect()
+        .await?;
+    ensure!(
+        sequences_of(&events) == expected,
+        "expected {} streamed events in sequence order",
+        expected.len()
+    );
+    let events: Vec<EventEnvelope<AccountAggregate>> = repository
+        .stream_events(aggregate_id, Some(expected[1].clone()))
+        .try_collect()
+        .await?;
+    ensure!(
+        sequences_of(&events) == expected[2..].to_vec(),
+        "expected streaming after a sequence to skip earlier events"
+    );
+    return Ok(());
+}
+
+pub async fn snapshot_latest_wins(repository: ConformanceRepository) -> Result<(), anyhow::Error> {
+    let aggregate_id = Ulid::new().to_string();
+    let snapshot_ids = sequences(2);
+    let event_ids = sequences(2);
+    ensure!(
+        repository
+            .retrieve_latest_snapshot(aggregate_id.clone())
+            .await?
+            .is_none(),
+        "expected no snapshot before one is stored"
+    );
+    for (snapshot_id, event_id) in snapshot_ids.iter().zip(event_ids.iter()) {
+        let aggregate = AccountAggregate {
+            id: Some(aggregate_id.clone()),
+            email: Some(format!("{}@example.com", event_id)),
+            created_at: Some(now()),
+            ..Default::default()
+        };
+        repository
+            .store_snapshot(AggregateSnapshot {
+                aggregate_id: aggregate_id.clone(),
+                aggregate_type: "Account".into(),
+                payload: aggregate,
+                last_sequence: event_id.clone(),
+                snapshot_id: snapshot_id.clone(),
+                timestamp: now(),
+            })
+            .await?;
+    }
+    let snapshot = match repository.retrieve_latest_snapshot(aggregate_id).await? {
+        Some(x) => x,
+        None => return Err(anyhow!("expected a snapshot")),
+    };
+    ensure!(
+        snapshot.snapshot_id == snapshot_ids[1],
+        "expected snapshot {}, got {}",
+        snapshot_ids[1],
+        snapshot.snapshot_id
+    );
+    ensure!(
+        snapshot.last_sequence == event_ids[1],
+        "expected last sequence {}, got {}",
+        event_ids[1],
+        snapshot.last_sequence
+    );
+    ensure!(
+        snapshot.payload.email == Some(format!("{}@example.com", event_ids[1])),
+        "snapshot payload does not match the latest snapshot"
+    );
+    return Ok(());
+}
+
+pub async fn outbox_matches_stored_events(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let aggregate_id = Ulid::new().to_string();
+    let expected = sequences(3);
+    repository
+        .store_events(stream(&aggregate_id, &expected))
+        .await?;
+    let stored = repository.retrieve_events(aggregate_id, None).await?;
+    let outbox = repository.retrieve_outbox_events().await?;
+    ensure!(
+        sequences_of(&stored) == expected && sequences_of(&outbox) == expected,
+        "expected events and outbox to both hold {:?}, got {:?} and {:?}",
+        expected,
+        sequences_of(&stored),
+        sequences_of(&outbox)
+    );
+    return Ok(());
+}
+
+pub async fn outbox_skips_internal_events(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let aggregate_id = Ulid::new().to_string();
+    let expected = sequences(2);
+    let mut events = stream(&aggregate_id, &expected[..1]);
+    events.push(EventEnvelope {
+        aggregate_id: aggregate_id.clone(),
+        aggregate_type: "account".into(),
+        sequence: expected[1].clone(),
+        payload: AccountEvent::PasswordRehashed {
+            id: aggregate_id.clone(),
+            password_hash: "rehashed".into(),
+            rehashed_at: now(),
+            event_version: "0.0.1".into(),
+            event_id: expected[1].clone(),
+        },
+        metadata: HashMap::new(),
+        timestamp: now(),
+    });
+    repository.store_events(events).await?;
+    let stored = repository.retrieve_events(aggregate_id, None).await?;
+    let outbox = repository.retrieve_outbox_events().await?;
+    ensure!(
+        sequences_of(&stored) == expected && sequences_of(&outbox) == expected[..1],
+        "expected only {:?} in the outbox, got {:?}",
+        &expected[..1],
+        sequences_of(&outbox)
+    );
+    return Ok(());
+}
+
+pub async fn outbox_delete_after_send(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let bus = InMemoryEventBus::new();
+    let dyn_bus: ConformanceBus = Arc::new(bus.clone());
+    let aggregate_id = Ulid::new().to_string();
+    let expected = sequences(2);
+    repository
+        .store_events(stream(&aggregate_id, &expected))
+        .await?;
+    for event in repository.retrieve_outbox_events().await? {
+        repository
+            .send_and_delete_outbox_event(event, &dyn_bus)
+            .await?;
+    }
+    ensure!(
+        repository.retrieve_outbox_events().await?.is_empty(),
+        "expected the outbox to be empty after sending"
+    );
+    ensure!(
+        sequences_of(&bus.published()) == expected,
+        "expected {:?} to be published, got {:?}",
+        expected,
+        sequences_of(&bus.published())
+    );
+    ensure!(
+        repository.retrieve_events(aggregate_id, None).await?.len() == expected.len(),
+        "sending must not remove events from the store"
+    );
+    return Ok(());
+}
+
+pub async fn outbox_kept_when_bus_fails(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let bus = InMemoryEventBus::new();
+    bus.fail_next(1);
+    let dyn_bus: ConformanceBus = Arc::new(bus.clone());
+    let aggregate_id = Ulid::new().to_string();
+    let expected = sequences(1);
+    repository
+        .store_events(stream(&aggregate_id, &expected))
+        .await?;
+    let mut outbox = repository.retrieve_outbox_events().await?;
+    let event = match outbox.pop() {
+        Some(x) => x,
+        None => return Err(anyhow!("expected an outbox event")),
+    };
+    ensure!(
+        repository
+            .send_and_delete_outbox_event(event, &dyn_bus)
+            .await
+            .is_err(),
+        "expected the bus failure to be returned"
+    );
+    ensure!(
+        sequences_of(&repository.retrieve_outbox_events().await?) == expected,
+        "expected the event to stay in the outbox after a failed send"
+    );
+    ensure!(bus.published().is_empty(), "expected nothing to be published");
+    return Ok(());
+}
+
+const LEASE: Duration = Duration::from_secs(30);
+
+pub async fn outbox_lease_renewal_keeps_the_event(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let stored = sequences(1);
+    repository
+        .store_events(stream(&Ulid::new().to_string(), &stored))
+        .await?;
+    repository
+        .claim_outbox_events("relay-a".into(), Duration::ZERO, 10)
+        .await?;
+    ensure!(
+        repository
+            .renew_outbox_lease("relay-a".into(), stored[0].clone(), LEASE)
+            .await?,
+        "expected the owner to renew its lease"
+    );
+    ensure!(
+        !repository
+            .renew_outbox_lease("relay-b".into(), stored[0].clone(), LEASE)
+            .await?,
+        "expected another relay not to renew the lease"
+    );
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let claimed = repository
+        .claim_outbox_events("relay-b".into(), LEASE, 10)
+        .await?;
+    ensure!(
+        claimed.is_empty(),
+        "expected the renewed lease to keep the event, got {:?}",
+        sequences_of(&claimed)
+    );
+    ensure!(
+        repository
+            .delete_leased_outbox_event("relay-a".into(), stored[0].clone())
+            .await?,
+        "expected the owner to delete the event"
+    );
+    ensure!(
+        repository.retrieve_outbox_events().await?.is_empty(),
+        "expected the outbox to be empty after the delete"
+    );
+    return Ok(());
+}
+
+pub async fn outbox_delete_needs_the_lease(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let stored = sequences(1);
+    repository
+        .store_events(stream(&Ulid::new().to_string(), &stored))
+        .await?;
+    repository
+        .claim_outbox_events("relay-a".into(), Duration::ZERO, 10)
+        .await?;
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let claimed = repository
+        .claim_outbox_events("relay-b".into(), LEASE, 10)
+        .await?;
+    ensure!(
+        sequences_of(&claimed) == stored,
+        "expected the expired lease to be taken over, got {:?}",
+        sequences_of(&claimed)
+    );
+    ensure!(
+        !repository
+            .renew_outbox_lease("relay-a".into(), stored[0].clone(), LEASE)
+            .await?,
+        "expected the previous owner not to renew a lease it lost"
+    );
+    ensure!(
+        !repository
+            .delete_leased_outbox_event("relay-a".into(), stored[0].clone())
+            .await?,
+        "expected the previous owner not to delete the event"
+    );
+    ensure!(
+        sequences_of(&repository.retrieve_outbox_events().await?) == stored,
+        "expected the event to stay in the outbox for its new owner"
+    );
+    ensure!(
+        repository
+            .delete_leased_outbox_event("relay-b".into(), stored[0].clone())
+            .await?,
+        "expected the new owner to delete the event"
+    );
+    return Ok(());
+}
+
+/// Stores two events of one aggregate and one of another, then dead-letters
+/// the first, returning the blocked pair and the other aggregate's event.
+async fn dead_lettered_head(
+    repository: &ConformanceRepository,
+) -> Result<(Vec<String>, String), anyhow::Error> {
+    let stored = sequences(3);
+    repository
+        .store_events(stream(&Ulid::new().to_string(), &stored[..2]))
+        .await?;
+    repository
+        .store_events(stream(&Ulid::new().to_string(), &stored[2..]))
+        .await?;
+    let dead_lettered = repository
+        .record_outbox_failure(stored[0].clone(), "broker down".into(), 1, LEASE)
+        .await?;
+    ensure!(dead_lettered, "expected the event to be dead-lettered");
+    return Ok((stored[..2].to_vec(), stored[2].clone()));
+}
+
+pub async fn dead_letter_blocks_aggregate(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let (blocked, other) = dead_lettered_head(&repository).await?;
+    let claimed = repository
+        .claim_outbox_events("relay".into(), LEASE, 10)
+        .await?;
+    ensure!(
+        sequences_of(&claimed) == vec![other.clone()],
+        "expected only the other aggregate to be claimable, got {:?}",
+        sequences_of(&claimed)
+    );
+    ensure!(
+        sequences_of(&repository.retrieve_outbox_events().await?)
+            == vec![blocked[1].clone(), other.clone()],
+        "expected the dead letter to be left out of the outbox"
+    );
+    ensure!(
+        repository.requeue_dead_letter(blocked[0].clone()).await?,
+        "expected the dead letter to be requeued"
+    );
+    let claimed = repository
+        .claim_outbox_events("relay".into(), LEASE, 10)
+        .await?;
+    ensure!(
+        sequences_of(&claimed) == vec![blocked[0].clone(), blocked[1].clone(), other],
+        "expected the requeued event to keep its place, got {:?}",
+        sequences_of(&claimed)
+    );
+    return Ok(());
+}
+
+pub async fn discarded_dead_letter_unblocks_aggregate(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let (blocked, other) = dead_lettered_head(&repository).await?;
+    ensure!(
+        repository.discard_dead_letter(blocked[0].clone()).await?,
+        "expected the dead letter to be discarded"
+    );
+    ensure!(
+        repository
+            .retrieve_dead_letter(blocked[0].clone())
+            .await?
+            .is_none(),
+        "expected the dead letter to be gone"
+    );
+    let claimed = repository
+        .claim_outbox_events("relay".into(), LEASE, 10)
+        .await?;
+    ensure!(
+        sequences_of(&claimed) == vec![blocked[1].clone(), other],
+        "expected the rest of the aggregate to be claimable, got {:?}",
+        sequences_of(&claimed)
+    );
+    return Ok(());
+}
+
+pub async fn store_events_is_atomic(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let aggregate_id = Ulid::new().to_string();
+    let stored = sequences(2);
+    repository
+        .store_events(stream(&aggregate_id, &stored[..1]))
+        .await?;
+    let batch = vec![stored[1].clone(), stored[0].clone()];
+    ensure!(
+        repository
+            .store_events(stream(&aggregate_id, &batch))
+            .await
+            .is_err(),
+        "expected appending a duplicate sequence to fail"
+    );
+    let events = repository.retrieve_events(aggregate_id, None).await?;
+    ensure!(
+        sequences_of(&events) == stored[..1].to_vec(),
+        "expected the failed append to leave no events behind, got {:?}",
+        sequences_of(&events)
+    );
+    ensure!(
+        sequences_of(&repository.retrieve_outbox_events().await?) == stored[..1].to_vec(),
+        "expected the failed append to leave no outbox events behind"
+    );
+    return Ok(());
+}
+
+pub async fn append_events_checks_the_version(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let aggregate_id = Ulid::new().to_string();
+    let stored = sequences(3);
+    repository
+        .append_events(0, stream(&aggregate_id, &stored[..1]))
+        .await?;
+    repository
+        .append_events(1, stream(&aggregate_id, &stored[1..2]))
+        .await?;
+    // Decided on the aggregate as it was before the second append.
+    let stale = repository
+        .append_events(1, stream(&aggregate_id, &stored[2..]))
+        .await;
+    ensure!(
+        matches!(
+            stale.as_ref().map_err(|e| e.downcast_ref::<AccountError>()),
+            Err(Some(AccountError::ConcurrentModification(_)))
+        ),
+        "expected a stale version to be refused, got {:?}",
+        stale
+    );
+    let events = repository.retrieve_events(aggregate_id.clone(), None).await?;
+    ensure!(
+        sequences_of(&events) == stored[..2].to_vec(),
+        "expected the refused append to leave no events behind, got {:?}",
+        sequences_of(&events)
+    );
+    ensure!(
+        repository
+            .append_events(2, stream(&Ulid::new().to_string(), &sequences(1)))
+            .await
+            .is_err(),
+        "expected a new aggregate to be at version 0"
+    );
+    return Ok(());
+}
+
+pub async fn read_all_is_gap_free(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let first = Ulid::new().to_string();
+    let second = Ulid::new().to_string();
+    let first_sequences = sequences(2);
+    let second_sequences = sequences(2);
+    repository
+        .store_events(stream(&first, &first_sequences))
+        .await?;
+    let duplicate = vec![Ulid::new().to_string(), first_sequences[0].clone()];
+    ensure!(
+        repository
+            .store_events(stream(&second, &duplicate))
+            .await
+            .is_err(),
+        "expected appending a duplicate sequence to fail"
+    );
+    repository
+        .store_events(stream(&second, &second_sequences))
+        .await?;
+    let mut expected = first_sequences.clone();
+    expected.extend(second_sequences);
+    let events = repository.read_all(0, 100).await?;
+    let positions: Vec<i64> = events.iter().map(|x| x.position).collect();
+    ensure!(
+        positions == (1..=expected.len() as i64).collect::<Vec<i64>>(),
+        "expected gap-free positions, got {:?}",
+        positions
+    );
+    let read: Vec<String> = events.iter().map(|x| x.event.sequence.clone()).collect();
+    ensure!(
+        read == expected,
+        "expected events in append order, got {:?}",
+        read
+    );
+    let page = repository.read_all(2, 1).await?;
+    ensure!(
+        page.len() == 1 && page[0].position == 3,
+        "expected reading after position 2 to start at position 3"
+    );
+    return Ok(());
+}
+
+pub async fn hash_chain_links_events(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let aggregate_id = Ulid::new().to_string();
+    let stored = sequences(3);
+    repository
+        .store_events(stream(&aggregate_id, &stored[..2]))
+        .await?;
+    repository
+        .store_events(stream(&aggregate_id, &stored[2..]))
+        .await?;
+    let links = repository
+        .retrieve_chain_page(aggregate_id.clone(), None, 100)
+        .await?;
+    ensure!(
+        links.iter().all(|x| x.hash.is_some()),
+        "expected every stored event to be hashed"
+    );
+    ensure!(
+        links[0].prev_hash.is_none(),
+        "expected the first event to start the chain"
+    );
+    for pair in links.windows(2) {
+        ensure!(
+            pair[1].prev_hash == pair[0].hash,
+            "expected event {} to link to the hash of {}",
+            pair[1].sequence,
+            pair[0].sequence
+        );
+    }
+    let report = AccountChainService::new(repository.clone())
+        .with_key(Arc::new(CONFORMANCE_CHAIN_KEY.into()))
+        .verify_aggregate(aggregate_id.clone())
+        .await?;
+    ensure!(
+        report.events == 3 && report.first_break.is_none(),
+        "expected the chain to verify, got {:?}",
+        report
+    );
+    let report = AccountChainService::new(repository)
+        .with_key(Arc::new("another key".into()))
+        .verify_aggregate(aggregate_id)
+        .await?;
+    ensure!(
+        matches!(
+            report.first_break.map(|x| x.reason),
+            Some(ChainBreakReason::HashMismatch { .. })
+        ),
+        "expected the chain not to verify under another key"
+    );
+    return Ok(());
+}
+
+pub async fn email_lookups(repository: ConformanceRepository) -> Result<(), anyhow::Error> {
+    let aggregate_id = Ulid::new().to_string();
+    let email = format!("{}@example.com", aggregate_id);
+    ensure!(
+        !repository.email_exists(email.clone()).await?,
+        "expected the email to be unknown before storing"
+    );
+    repository
+        .store_events(stream(&aggregate_id, &sequences(1)))
+        .await?;
+    ensure!(
+        repository.email_exists(email.clone()).await?,
+        "expected the email to exist after storing"
+    );
+    let found = repository.retrieve_aggregate_id_for_email(email).await?;
+    ensure!(
+        found == aggregate_id,
+        "expected aggregate {}, got {}",
+        aggregate_id,
+        found
+    );
+    return Ok(());
+}
+
+pub async fn unknown_email_lookup_fails(
+    repository: ConformanceRepository,
+) -> Result<(), anyhow::Error> {
+    let email = format!("{}@example.com", Ulid::new());
+    ensure!(
+        repository
+            .retrieve_aggregate_id_for_email(email)
+            .await
+            .is_err(),
+        "expected looking up an unknown email to fail"
+    );
+    return Ok(());
+}
@@ -0,0 +1,113 @@
+use crate::command::{
+    domain::account::entity::aggregate::AccountAggregate,
+    infrastructure::dtos::transport::public::PublicEventEnvelope,
+};
+
+use std::time::Duration;
+
+use async_nats::jetstream::{self, context::Publish, stream};
+use async_trait::async_trait;
+use cqrs_rs::{
+    application::port::outbound::event_bus::EventBus,
+    domain::entity::event::{DomainEvent, EventEnvelope},
+};
+use tracing::{span, Instrument};
+
+const DEFAULT_SUBJECT_PREFIX: &str = "account.events";
+
+/// Publishes account events to JetStream on
+/// `<prefix>.<aggregate_id>.<event_type>`. The envelope sequence is sent as
+/// `Nats-Msg-Id`, so a relay retrying after a lost ack is deduplicated by the
+/// broker within the stream's duplicate window. Messages carry the public
+/// schema, `PublicEventEnvelope`, and internal-only events are dropped.
+#[derive(Clone)]
+pub struct JetStreamEventBus {
+    context: jetstream::Context,
+    subject_prefix: String,
+}
+
+impl JetStreamEventBus {
+    pub fn new(context: jetstream::Context) -> Self {
+        return Self {
+            context,
+            subject_prefix: DEFAULT_SUBJECT_PREFIX.into(),
+        };
+    }
+
+    pub async fn connect(url: &str) -> Result<Self, anyhow::Error> {
+        let client = async_nats::connect(url).await?;
+        return Ok(Self::new(jetstream::new(client)));
+    }
+
+    pub fn with_subject_prefix(mut self, subject_prefix: &str) -> Self {
+        self.subject_prefix = subject_prefix.into();
+        return self;
+    }
+
+    pub async fn ensure_stream(
+        &self,
+        name: &str,
+        duplicate_window: Duration,
+    ) -> Result<stream::Stream, anyhow::Error> {
+        let stream = self
+            .context
+            .get_or_create_stream(stream::Config {
+                name: name.into(),
+                subjects: vec![format!("{}.>", self.subject_prefix)],
+                duplicate_window,
+                ..Default::default()
+            })
+            .await?;
+        return Ok(stream);
+    }
+
+    pub fn subject(&self, event: &EventEnvelope<AccountAggregate>) -> String {
+        return format!(
+            "{}.{}.{}",
+            self.subject_prefix,
+            event.aggregate_id,
+            event.payload.event_type()
+        );
+    }
+}
+
+#[async_trait]
+impl<T, Q> EventBus<EventEnvelope<AccountAggregate>, T, Q, EventEnvelope<AccountAggregate>>
+    for JetStreamEventBus
+{
+    async fn send_event(&self, event: EventEnvelope<AccountAggregate>) -> Result<(), anyhow::Error> {
+        let subject = self.subject(&event);
+        let root = span!(
+            tracing::Level::INFO,
+            "send_event",
+            target = "EventBus",
+            implementation = "JetStreamEventBus",
+            subject = subject.as_str()
+        );
+        let message_id = event.sequence.clone();
+        let public = match PublicEventEnvelope::from_envelope(event) {
+            Some(x) => x,
+            None => {
+                tracing::warn!(
+                    sequence = message_id.as_str(),
+                    "internal event was not published"
+                );
+                return Ok(());
+            }
+        };
+        let payload = serde_json::to_vec(&public)?;
+        async {
+            let ack = self
+                .context
+                .send_publish(
+                    subject,
+                    Publish::build().payload(payload.into()).message_id(message_id),
+                )
+                .await?;
+            ack.await?;
+            return Ok::<(), anyhow::Error>(());
+        }
+        .instrument(root)
+        .await
+    }
+}
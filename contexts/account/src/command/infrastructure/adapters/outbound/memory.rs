@@ -0,0 +1,865 @@
+use crate::{
+    command::{
+        application::account::{
+            chain::event_hash,
+            ports::outbound::{
+                chain::{AccountEventChainRepository, ChainLink},
+                dead_letter::{AccountDeadLetterRepository, OutboxDeadLetter},
+                idempotency::{IdempotencyClaim, IdempotencyRecord, IdempotencyRepository},
+                notifier::CommitNotifier,
+                repository::{
+                    AccountEventRepository, AccountEventStreamRepository, AccountOutboxRepository,
+                    AccountRepository, AccountSnapshotRepository,
+                },
+                subscription::{AccountEventLogRepository, CheckpointStore, PositionedEvent},
+            },
+        },
+        domain::account::entity::{
+            aggregate::AccountAggregate, error::AccountError, event::AccountEvent,
+        },
+        infrastructure::dtos::{storage::sql::SQLAccountEvent, transport::public::is_published},
+    },
+    common::domain::secret::SecretToken,
+};
+
+use std::{
+    cmp::min,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use cqrs_rs::{
+    application::port::outbound::{event_bus::EventBus, event_repository::EventRepository},
+    domain::entity::event::{AggregateSnapshot, DomainEvent, EventEnvelope},
+};
+use serde_json::json;
+
+#[derive(Clone, Debug)]
+pub(crate) struct StoredEvent {
+    pub aggregate_type: String,
+    pub aggregate_id: String,
+    pub sequence: String,
+    pub payload: AccountEvent,
+    pub metadata: HashMap<String, String>,
+    pub timestamp: DateTime<Utc>,
+    pub hash: Option<String>,
+    pub prev_hash: Option<String>,
+}
+
+impl StoredEvent {
+    /// The event as the SQL backends would store it.
+    fn chain_link(&self) -> ChainLink {
+        let enum_sql: SQLAccountEvent = self.payload.clone().into();
+        return ChainLink {
+            aggregate_type: self.aggregate_type.clone(),
+            aggregate_id: self.aggregate_id.clone(),
+            sequence: self.sequence.clone(),
+            event_type: self.payload.event_type(),
+            event_version: self.payload.event_version(),
+            payload: json!(enum_sql).to_string(),
+            metadata: json!(self.metadata).to_string(),
+            timestamp: self.timestamp,
+            hash: self.hash.clone(),
+            prev_hash: self.prev_hash.clone(),
+        };
+    }
+}
+
+impl From<EventEnvelope<AccountAggregate>> for StoredEvent {
+    fn from(value: EventEnvelope<AccountAggregate>) -> Self {
+        return Self {
+            aggregate_type: value.aggregate_type,
+            aggregate_id: value.aggregate_id,
+            sequence: value.sequence,
+            payload: value.payload,
+            metadata: value.metadata,
+            timestamp: value.timestamp,
+            hash: None,
+            prev_hash: None,
+        };
+    }
+}
+
+impl From<StoredEvent> for EventEnvelope<AccountAggregate> {
+    fn from(value: StoredEvent) -> Self {
+        return EventEnvelope {
+            aggregate_type: value.aggregate_type,
+            aggregate_id: value.aggregate_id,
+            sequence: value.sequence,
+            payload: value.payload,
+            metadata: value.metadata,
+            timestamp: value.timestamp,
+        };
+    }
+}
+
+#[derive(Clone, Debug)]
+struct StoredSnapshot {
+    aggregate_type: String,
+    aggregate_id: String,
+    payload: AccountAggregate,
+    last_sequence: String,
+    snapshot_id: String,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug)]
+struct OutboxEntry {
+    event: StoredEvent,
+    lease_owner: Option<String>,
+    lease_expires_at: Option<i64>,
+    attempts: i64,
+    last_error: Option<String>,
+    next_attempt_at: Option<i64>,
+    dead_lettered: bool,
+}
+
+impl OutboxEntry {
+    fn is_held_by_other(&self, owner: &str, now: i64) -> bool {
+        return match (&self.lease_owner, self.lease_expires_at) {
+            (Some(x), Some(expires_at)) => x != owner && expires_at >= now,
+            _ => false,
+        };
+    }
+
+    fn is_waiting(&self, now: i64) -> bool {
+        return match self.next_attempt_at {
+            Some(x) => x > now,
+            None => false,
+        };
+    }
+}
+
+#[derive(Clone, Debug)]
+struct DeadLetterEntry {
+    event: StoredEvent,
+    attempts: i64,
+    last_error: Option<String>,
+    dead_lettered_at: DateTime<Utc>,
+}
+
+impl From<DeadLetterEntry> for OutboxDeadLetter {
+    fn from(value: DeadLetterEntry) -> Self {
+        return OutboxDeadLetter {
+            event: value.event.into(),
+            attempts: value.attempts,
+            last_error: value.last_error,
+            dead_lettered_at: value.dead_lettered_at,
+        };
+    }
+}
+
+#[derive(Clone, Debug)]
+struct IdempotencyEntry {
+    command_hash: String,
+    outcome: Option<AccountAggregate>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct State {
+    events: Vec<StoredEvent>,
+    snapshots: Vec<StoredSnapshot>,
+    outbox: Vec<OutboxEntry>,
+    dead_letters: Vec<DeadLetterEntry>,
+    idempotency: HashMap<String, IdempotencyEntry>,
+    checkpoints: HashMap<String, i64>,
+}
+
+/// Keeps the event store in process memory with the same observable
+/// behaviour as `SQLiteAccountRepository`. Intended for tests and local
+/// development; nothing survives a restart.
+#[derive(Clone)]
+pub struct InMemoryAccountRepository {
+    state: Arc<Mutex<State>>,
+    chain_key: Option<Arc<SecretToken>>,
+    pub notifier: CommitNotifier,
+}
+
+impl InMemoryAccountRepository {
+    pub fn new() -> Self {
+        return Self {
+            state: Arc::new(Mutex::new(State::default())),
+            chain_key: None,
+            notifier: CommitNotifier::new(),
+        };
+    }
+
+    pub fn with_chain_key(mut self, key: Arc<SecretToken>) -> Self {
+        self.chain_key = Some(key);
+        return self;
+    }
+
+    pub fn with_notifier(mut self, notifier: CommitNotifier) -> Self {
+        self.notifier = notifier;
+        return self;
+    }
+
+    /// Appends the events under one lock. With an `expected_version` they
+    /// have to belong to one aggregate that holds that many events.
+    fn insert_events(
+        &self,
+        events: Vec<EventEnvelope<AccountAggregate>>,
+        expected_version: Option<i64>,
+    ) -> Result<(), anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        if let (Some(expected), Some(first)) = (expected_version, events.first()) {
+            let aggregate_id = first.aggregate_id.clone();
+            if events.iter().any(|x| x.aggregate_id != aggregate_id) {
+                return Err(anyhow!("expected events of a single aggregate"));
+            }
+            let current = state
+                .events
+                .iter()
+                .filter(|x| x.aggregate_id == aggregate_id)
+                .count() as i64;
+            if current != expected {
+                return Err(AccountError::ConcurrentModification(aggregate_id).into());
+            }
+        }
+        let mut sequences: Vec<&str> = vec![];
+        for event in events.iter() {
+            if sequences.contains(&event.sequence.as_str())
+                || state.events.iter().any(|x| x.sequence == event.sequence)
+            {
+                return Err(anyhow!("duplicate event sequence {}", event.sequence));
+            }
+            sequences.push(&event.sequence);
+        }
+        let mut stored_events: Vec<StoredEvent> = vec![];
+        for event in events {
+            let mut stored: StoredEvent = event.into();
+            if let Some(key) = &self.chain_key {
+                let previous = stored_events
+                    .iter()
+                    .chain(state.events.iter())
+                    .filter(|x| x.aggregate_id == stored.aggregate_id)
+                    .max_by(|a, b| a.sequence.cmp(&b.sequence));
+                let prev_hash = match previous {
+                    None => None,
+                    Some(x) if x.hash.is_none() => {
+                        return Err(AccountError::ChainNotBackfilled(stored.aggregate_id).into());
+                    }
+                    Some(x) => x.hash.clone(),
+                };
+                stored.hash = Some(event_hash(key, prev_hash.as_deref(), &stored.chain_link())?);
+                stored.prev_hash = prev_hash;
+            }
+            stored_events.push(stored);
+        }
+        for stored in stored_events {
+            // As in the SQL backends, internal-only events are not queued.
+            if is_published(&stored.payload) {
+                state.outbox.push(OutboxEntry {
+                    event: stored.clone(),
+                    lease_owner: None,
+                    lease_expires_at: None,
+                    attempts: 0,
+                    last_error: None,
+                    next_attempt_at: None,
+                    dead_lettered: false,
+                });
+            }
+            state.events.push(stored);
+        }
+        drop(state);
+        self.notifier.notify();
+        return Ok(());
+    }
+}
+
+impl Default for InMemoryAccountRepository {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+fn event_email(event: &AccountEvent) -> Option<&String> {
+    return match event {
+        AccountEvent::AccountCreated { email, .. } => Some(email),
+        _ => None,
+    };
+}
+
+impl<
+        T: From<EventEnvelope<AccountAggregate>> + Into<EventEnvelope<AccountAggregate>> + Into<Q>,
+        Q,
+    > AccountEventRepository<T, Q> for InMemoryAccountRepository
+{
+}
+
+#[async_trait]
+impl AccountRepository for InMemoryAccountRepository {
+    async fn email_exists(&self, email: String) -> Result<bool, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        return Ok(state
+            .events
+            .iter()
+            .any(|x| event_email(&x.payload) == Some(&email)));
+    }
+
+    async fn retrieve_aggregate_id_for_email(
+        &self,
+        email: String,
+    ) -> Result<String, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        let found = state
+            .events
+            .iter()
+            .filter(|x| event_email(&x.payload) == Some(&email))
+            .max_by(|a, b| a.sequence.cmp(&b.sequence));
+        match found {
+            Some(x) => return Ok(x.aggregate_id.clone()),
+            None => return Err(sqlx::Error::RowNotFound.into()),
+        }
+    }
+
+    async fn append_events(
+        &self,
+        expected_version: i64,
+        events: Vec<EventEnvelope<AccountAggregate>>,
+    ) -> Result<(), anyhow::Error> {
+        return self.insert_events(events, Some(expected_version));
+    }
+}
+
+#[async_trait]
+impl AccountEventStreamRepository for InMemoryAccountRepository {
+    async fn retrieve_event_page(
+        &self,
+        aggregate_id: String,
+        after: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        let mut events: Vec<StoredEvent> = state
+            .events
+            .iter()
+            .filter(|x| x.aggregate_id == aggregate_id)
+            .filter(|x| after.as_ref().is_none_or(|after| &x.sequence > after))
+            .cloned()
+            .collect();
+        events.sort_by(|a, b| a.sequence.cmp(&b.sequence));
+        events.truncate(limit.max(0) as usize);
+        return Ok(events.into_iter().map(|x| x.into()).collect());
+    }
+}
+
+#[async_trait]
+impl AccountEventLogRepository for InMemoryAccountRepository {
+    async fn read_all(
+        &self,
+        from_position: i64,
+        limit: i64,
+    ) -> Result<Vec<PositionedEvent>, anyhow::Error> {
+        // Events are only ever appended, so an event's position is its index
+        // in the log plus one.
+        let state = self.state.lock().unwrap();
+        return Ok(state
+            .events
+            .iter()
+            .enumerate()
+            .skip(from_position.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .map(|(i, x)| PositionedEvent {
+                position: i as i64 + 1,
+                event: x.clone().into(),
+            })
+            .collect());
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for InMemoryAccountRepository {
+    async fn load_checkpoint(&self, subscription_id: String) -> Result<i64, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        return Ok(state
+            .checkpoints
+            .get(&subscription_id)
+            .cloned()
+            .unwrap_or(0));
+    }
+
+    async fn save_checkpoint(
+        &self,
+        subscription_id: String,
+        expected: i64,
+        position: i64,
+    ) -> Result<bool, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let current = state.checkpoints.get(&subscription_id).cloned().unwrap_or(0);
+        if current != expected {
+            return Ok(false);
+        }
+        state.checkpoints.insert(subscription_id, position);
+        return Ok(true);
+    }
+}
+
+#[async_trait]
+impl AccountSnapshotRepository for InMemoryAccountRepository {
+    async fn purge_stale_snapshots(&self) -> Result<u64, anyhow::Error> {
+        return Ok(0);
+    }
+}
+
+#[async_trait]
+impl AccountEventChainRepository for InMemoryAccountRepository {
+    async fn retrieve_aggregate_ids(
+        &self,
+        after: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        let mut ids: Vec<String> = state
+            .events
+            .iter()
+            .map(|x| x.aggregate_id.clone())
+            .filter(|x| after.as_ref().is_none_or(|after| x > after))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids.truncate(limit.max(0) as usize);
+        return Ok(ids);
+    }
+
+    async fn retrieve_chain_page(
+        &self,
+        aggregate_id: String,
+        after: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<ChainLink>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        let mut events: Vec<&StoredEvent> = state
+            .events
+            .iter()
+            .filter(|x| x.aggregate_id == aggregate_id)
+            .filter(|x| after.as_ref().is_none_or(|after| &x.sequence > after))
+            .collect();
+        events.sort_by(|a, b| a.sequence.cmp(&b.sequence));
+        events.truncate(limit.max(0) as usize);
+        return Ok(events.into_iter().map(|x| x.chain_link()).collect());
+    }
+
+    async fn backfill_chain(&self, links: Vec<ChainLink>) -> Result<u64, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let mut updated = 0;
+        for link in links {
+            let found = state
+                .events
+                .iter_mut()
+                .find(|x| x.sequence == link.sequence && x.hash.is_none());
+            if let Some(x) = found {
+                x.hash = link.hash;
+                x.prev_hash = link.prev_hash;
+                updated += 1;
+            }
+        }
+        return Ok(updated);
+    }
+}
+
+#[async_trait]
+impl AccountOutboxRepository for InMemoryAccountRepository {
+    async fn retrieve_outbox_batch(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        return Ok(state
+            .outbox
+            .iter()
+            .filter(|x| !x.dead_lettered)
+            .take(limit.max(0) as usize)
+            .map(|x| x.event.clone().into())
+            .collect());
+    }
+
+    async fn claim_outbox_events(
+        &self,
+        owner: String,
+        lease: Duration,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let now = Utc::now().timestamp_millis();
+        let expires_at = now + lease.as_millis() as i64;
+        let mut state = self.state.lock().unwrap();
+        let mut claimable: Vec<usize> = vec![];
+        for (index, entry) in state.outbox.iter().enumerate() {
+            if claimable.len() as i64 >= limit {
+                break;
+            }
+            if entry.dead_lettered || entry.is_held_by_other(&owner, now) || entry.is_waiting(now) {
+                continue;
+            }
+            let blocked = state.outbox[..index].iter().any(|x| {
+                x.event.aggregate_id == entry.event.aggregate_id
+                    && (x.dead_lettered || x.is_held_by_other(&owner, now) || x.is_waiting(now))
+            });
+            if !blocked {
+                claimable.push(index);
+            }
+        }
+        let mut resp: Vec<EventEnvelope<AccountAggregate>> = vec![];
+        for index in claimable {
+            let entry = &mut state.outbox[index];
+            entry.lease_owner = Some(owner.clone());
+            entry.lease_expires_at = Some(expires_at);
+            resp.push(entry.event.clone().into());
+        }
+        return Ok(resp);
+    }
+
+    async fn release_outbox_leases(&self, owner: String) -> Result<(), anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        for entry in state.outbox.iter_mut() {
+            if entry.lease_owner.as_ref() == Some(&owner) {
+                entry.lease_owner = None;
+                entry.lease_expires_at = None;
+            }
+        }
+        return Ok(());
+    }
+
+    async fn record_outbox_failure(
+        &self,
+        sequence: String,
+        error: String,
+        max_attempts: i64,
+        retry_delay: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let index = match state
+            .outbox
+            .iter()
+            .position(|x| x.event.sequence == sequence && !x.dead_lettered)
+        {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+        let attempts = state.outbox[index].attempts + 1;
+        if attempts >= max_attempts {
+            let entry = &mut state.outbox[index];
+            entry.attempts = attempts;
+            entry.last_error = Some(error.clone());
+            entry.lease_owner = None;
+            entry.lease_expires_at = None;
+            entry.dead_lettered = true;
+            let event = entry.event.clone();
+            state.dead_letters.push(DeadLetterEntry {
+                event,
+                attempts,
+                last_error: Some(error),
+                dead_lettered_at: Utc::now(),
+            });
+            return Ok(true);
+        }
+        let exponent = min(attempts - 1, 16) as u32;
+        let delay = retry_delay.as_millis() as i64 * 2_i64.pow(exponent);
+        let entry = &mut state.outbox[index];
+        entry.attempts = attempts;
+        entry.last_error = Some(error);
+        entry.next_attempt_at = Some(Utc::now().timestamp_millis() + delay);
+        entry.lease_owner = None;
+        entry.lease_expires_at = None;
+        return Ok(false);
+    }
+
+    async fn renew_outbox_lease(
+        &self,
+        owner: String,
+        sequence: String,
+        lease: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        let expires_at = Utc::now().timestamp_millis() + lease.as_millis() as i64;
+        let mut state = self.state.lock().unwrap();
+        return match state
+            .outbox
+            .iter_mut()
+            .find(|x| x.event.sequence == sequence && x.lease_owner.as_ref() == Some(&owner))
+        {
+            Some(entry) => {
+                entry.lease_expires_at = Some(expires_at);
+                Ok(true)
+            }
+            None => Ok(false),
+        };
+    }
+
+    async fn delete_leased_outbox_event(
+        &self,
+        owner: String,
+        sequence: String,
+    ) -> Result<bool, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let before = state.outbox.len();
+        state
+            .outbox
+            .retain(|x| x.event.sequence != sequence || x.lease_owner.as_ref() != Some(&owner));
+        return Ok(state.outbox.len() < before);
+    }
+
+    async fn retry_outbox_event(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        return match state
+            .outbox
+            .iter_mut()
+            .find(|x| x.event.sequence == sequence && !x.dead_lettered)
+        {
+            Some(entry) => {
+                entry.attempts = 0;
+                entry.last_error = None;
+                entry.next_attempt_at = None;
+                Ok(true)
+            }
+            None => Ok(false),
+        };
+    }
+}
+
+#[async_trait]
+impl AccountDeadLetterRepository for InMemoryAccountRepository {
+    async fn list_dead_letters(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<OutboxDeadLetter>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        let mut entries = state.dead_letters.clone();
+        entries.sort_by_key(|x| x.dead_lettered_at);
+        return Ok(entries
+            .into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .map(|x| x.into())
+            .collect());
+    }
+
+    async fn retrieve_dead_letter(
+        &self,
+        sequence: String,
+    ) -> Result<Option<OutboxDeadLetter>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        return Ok(state
+            .dead_letters
+            .iter()
+            .find(|x| x.event.sequence == sequence)
+            .map(|x| x.clone().into()));
+    }
+
+    async fn requeue_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let index = match state
+                .dead_letters
+                .iter()
+                .position(|x| x.event.sequence == sequence)
+            {
+                Some(x) => x,
+                None => return Ok(false),
+            };
+            state.dead_letters.remove(index);
+            if let Some(entry) = state
+                .outbox
+                .iter_mut()
+                .find(|x| x.event.sequence == sequence)
+            {
+                entry.attempts = 0;
+                entry.last_error = None;
+                entry.next_attempt_at = None;
+                entry.dead_lettered = false;
+            }
+        }
+        self.notifier.notify();
+        return Ok(true);
+    }
+
+    async fn discard_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let before = state.dead_letters.len();
+            state.dead_letters.retain(|x| x.event.sequence != sequence);
+            if state.dead_letters.len() == before {
+                return Ok(false);
+            }
+            state
+                .outbox
+                .retain(|x| !(x.dead_lettered && x.event.sequence == sequence));
+        }
+        self.notifier.notify();
+        return Ok(true);
+    }
+}
+
+#[async_trait]
+impl IdempotencyRepository for InMemoryAccountRepository {
+    async fn claim_idempotency_key(
+        &self,
+        idempotency_key: String,
+        command_hash: String,
+        stale_before: DateTime<Utc>,
+    ) -> Result<IdempotencyClaim, anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        match state.idempotency.get(&idempotency_key) {
+            Some(x) if x.outcome.is_some() || x.created_at >= stale_before => {
+                return Ok(IdempotencyClaim::Existing(Box::new(IdempotencyRecord {
+                    idempotency_key,
+                    command_hash: x.command_hash.clone(),
+                    outcome: x.outcome.clone(),
+                })))
+            }
+            _ => {
+                state.idempotency.insert(
+                    idempotency_key,
+                    IdempotencyEntry {
+                        command_hash,
+                        outcome: None,
+                        created_at: Utc::now(),
+                    },
+                );
+                return Ok(IdempotencyClaim::Claimed);
+            }
+        }
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        idempotency_key: String,
+        outcome: AccountAggregate,
+    ) -> Result<(), anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(x) = state.idempotency.get_mut(&idempotency_key) {
+            x.outcome = Some(outcome);
+        }
+        return Ok(());
+    }
+
+    async fn release_idempotency_key(
+        &self,
+        idempotency_key: String,
+    ) -> Result<(), anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        let pending = match state.idempotency.get(&idempotency_key) {
+            Some(x) => x.outcome.is_none(),
+            None => false,
+        };
+        if pending {
+            state.idempotency.remove(&idempotency_key);
+        }
+        return Ok(());
+    }
+}
+
+#[async_trait]
+impl<
+        T: From<EventEnvelope<AccountAggregate>> + Into<EventEnvelope<AccountAggregate>> + Into<Q>,
+        Q,
+    >
+    EventRepository<
+        EventEnvelope<AccountAggregate>,
+        T,
+        Q,
+        EventEnvelope<AccountAggregate>,
+        AggregateSnapshot<AccountAggregate>,
+        AggregateSnapshot<AccountAggregate>,
+    > for InMemoryAccountRepository
+{
+    async fn migrate(&self, _path: String) -> Result<(), anyhow::Error> {
+        return Ok(());
+    }
+
+    async fn store_events(
+        &self,
+        events: Vec<EventEnvelope<AccountAggregate>>,
+    ) -> Result<(), anyhow::Error> {
+        return self.insert_events(events, None);
+    }
+
+    async fn retrieve_events(
+        &self,
+        aggregate_id: String,
+        after: Option<String>,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        let mut events: Vec<StoredEvent> = state
+            .events
+            .iter()
+            .filter(|x| x.aggregate_id == aggregate_id)
+            .cloned()
+            .collect();
+        if let Some(after) = after {
+            events.retain(|x| x.sequence > after);
+        }
+        events.sort_by(|a, b| a.sequence.cmp(&b.sequence));
+        return Ok(events.into_iter().map(|x| x.into()).collect());
+    }
+
+    async fn store_snapshot(
+        &self,
+        snapshot: AggregateSnapshot<AccountAggregate>,
+    ) -> Result<(), anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        state.snapshots.push(StoredSnapshot {
+            aggregate_type: snapshot.aggregate_type,
+            aggregate_id: snapshot.aggregate_id,
+            payload: snapshot.payload,
+            last_sequence: snapshot.last_sequence,
+            snapshot_id: snapshot.snapshot_id,
+            timestamp: snapshot.timestamp,
+        });
+        return Ok(());
+    }
+
+    async fn retrieve_latest_snapshot(
+        &self,
+        aggregate_id: String,
+    ) -> Result<Option<AggregateSnapshot<AccountAggregate>>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        let latest = state
+            .snapshots
+            .iter()
+            .filter(|x| x.aggregate_id == aggregate_id)
+            .max_by(|a, b| a.snapshot_id.cmp(&b.snapshot_id))
+            .cloned();
+        return Ok(latest.map(|x| AggregateSnapshot {
+            aggregate_type: x.aggregate_type,
+            aggregate_id: x.aggregate_id,
+            payload: x.payload,
+            last_sequence: x.last_sequence,
+            snapshot_id: x.snapshot_id,
+            timestamp: x.timestamp,
+        }));
+    }
+
+    async fn send_and_delete_outbox_event(
+        &self,
+        event: EventEnvelope<AccountAggregate>,
+        bus: &Arc<
+            dyn EventBus<EventEnvelope<AccountAggregate>, T, Q, EventEnvelope<AccountAggregate>>
+                + Sync
+                + Send,
+        >,
+    ) -> Result<(), anyhow::Error> {
+        let sequence = event.sequence.clone();
+        bus.send_event(event).await?;
+        let mut state = self.state.lock().unwrap();
+        state.outbox.retain(|x| x.event.sequence != sequence);
+        return Ok(());
+    }
+
+    async fn retrieve_outbox_events(
+        &self,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let state = self.state.lock().unwrap();
+        return Ok(state
+            .outbox
+            .iter()
+            .filter(|x| !x.dead_lettered)
+            .map(|x| x.event.clone().into())
+            .collect());
+    }
+}
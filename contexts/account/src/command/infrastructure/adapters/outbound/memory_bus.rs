@@ -0,0 +1,86 @@
+use super::memory::StoredEvent;
+use crate::command::domain::account::entity::aggregate::AccountAggregate;
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use cqrs_rs::{
+    application::port::outbound::event_bus::EventBus, domain::entity::event::EventEnvelope,
+};
+
+type FailurePredicate = Box<dyn Fn(&EventEnvelope<AccountAggregate>) -> bool + Send + Sync>;
+
+#[derive(Default)]
+struct State {
+    published: Vec<StoredEvent>,
+    fail_next: usize,
+    fail_when: Option<FailurePredicate>,
+}
+
+/// Records every published envelope in memory. Failures can be scripted,
+/// either for the next `n` sends or for every event matching a predicate.
+#[derive(Clone, Default)]
+pub struct InMemoryEventBus {
+    state: Arc<Mutex<State>>,
+}
+
+impl InMemoryEventBus {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn fail_next(&self, count: usize) {
+        self.state.lock().unwrap().fail_next = count;
+    }
+
+    pub fn fail_when<F>(&self, predicate: F)
+    where
+        F: Fn(&EventEnvelope<AccountAggregate>) -> bool + Send + Sync + 'static,
+    {
+        self.state.lock().unwrap().fail_when = Some(Box::new(predicate));
+    }
+
+    pub fn clear_failures(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.fail_next = 0;
+        state.fail_when = None;
+    }
+
+    pub fn published(&self) -> Vec<EventEnvelope<AccountAggregate>> {
+        return self
+            .state
+            .lock()
+            .unwrap()
+            .published
+            .iter()
+            .map(|x| x.clone().into())
+            .collect();
+    }
+
+    pub fn clear(&self) {
+        self.state.lock().unwrap().published.clear();
+    }
+}
+
+#[async_trait]
+impl<T, Q> EventBus<EventEnvelope<AccountAggregate>, T, Q, EventEnvelope<AccountAggregate>>
+    for InMemoryEventBus
+{
+    async fn send_event(&self, event: EventEnvelope<AccountAggregate>) -> Result<(), anyhow::Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.fail_next > 0 {
+            state.fail_next -= 1;
+            return Err(anyhow!("scripted failure for event `{}`", event.sequence));
+        }
+        let fail = match &state.fail_when {
+            Some(x) => x(&event),
+            None => false,
+        };
+        if fail {
+            return Err(anyhow!("scripted failure for event `{}`", event.sequence));
+        }
+        state.published.push(event.into());
+        return Ok(());
+    }
+}
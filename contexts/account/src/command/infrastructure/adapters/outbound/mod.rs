@@ -1 +1,7 @@
-pub mod sqlite;
\ No newline at end of file
+#[cfg(feature = "testing")]
+pub mod conformance;
+pub mod jetstream;
+pub mod memory;
+pub mod memory_bus;
+pub mod postgres;
+pub mod sqlite;
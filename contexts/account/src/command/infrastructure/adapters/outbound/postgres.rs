@@ -0,0 +1,1118 @@
+use crate::{
+    command::{
+        application::account::{
+            chain::event_hash,
+            ports::outbound::{
+                chain::{AccountEventChainRepository, ChainLink},
+                dead_letter::{AccountDeadLetterRepository, OutboxDeadLetter},
+                idempotency::{IdempotencyClaim, IdempotencyRecord, IdempotencyRepository},
+                notifier::CommitNotifier,
+                repository::{
+                    AccountEventRepository, AccountEventStreamRepository, AccountOutboxRepository,
+                    AccountRepository, AccountSnapshotRepository,
+                },
+                subscription::{AccountEventLogRepository, CheckpointStore, PositionedEvent},
+            },
+        },
+        domain::account::entity::{aggregate::AccountAggregate, error::AccountError},
+        infrastructure::{
+            dtos::storage::sql::{
+                decode_aggregate, PostgresAccountEventRow, SQLAccountAggregate, SQLAccountEvent,
+                SQLAccountSnapshotRow, SNAPSHOT_SCHEMA_VERSION,
+            },
+            dtos::transport::public::is_published,
+            encryption::fields::{FieldBinding, FieldCipher},
+            upcasting::{account::account_upcasters, registry::UpcasterRegistry},
+        },
+    },
+    common::domain::secret::SecretToken,
+};
+
+use std::{cmp::min, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use cqrs_rs::{
+    application::port::outbound::{event_bus::EventBus, event_repository::EventRepository},
+    domain::entity::event::{AggregateSnapshot, DomainEvent, EventEnvelope},
+};
+use serde_json::json;
+use sqlx::{migrate::Migrator, postgres::PgRow, FromRow, PgPool, Postgres, Row, Transaction};
+use tracing::{span, Instrument};
+
+const EVENT_TABLE_NAME: &str = "account_events";
+const SNAPSHOT_TABLE_NAME: &str = "account_snapshots";
+const OUTBOX_TABLE_NAME: &str = "account_outbox_events";
+const IDEMPOTENCY_TABLE_NAME: &str = "account_idempotency_keys";
+const DEAD_LETTER_TABLE_NAME: &str = "account_outbox_dead_letters";
+const POSITION_TABLE_NAME: &str = "account_event_positions";
+const CHECKPOINT_TABLE_NAME: &str = "account_subscription_checkpoints";
+const EVENT_FIELDS: [&str; 8] = [
+    "aggregate_type",
+    "aggregate_id",
+    "sequence",
+    "event_type",
+    "event_version",
+    "payload",
+    "metadata",
+    "timestamp",
+];
+// JSONB columns are read back as text so they decode through the same DTOs
+// as the SQLite adapter.
+const EVENT_SELECT_FIELDS: &str = "aggregate_type, aggregate_id, sequence, event_type, event_version, payload::text AS payload, metadata::text AS metadata, timestamp";
+const DEAD_LETTER_SELECT_FIELDS: &str = "aggregate_type, aggregate_id, sequence, event_type, event_version, payload::text AS payload, metadata::text AS metadata, timestamp, attempts, last_error, dead_lettered_at";
+
+#[derive(Clone)]
+pub struct PostgresAccountRepository {
+    pub pool: PgPool,
+    pub upcasters: Arc<UpcasterRegistry>,
+    pub cipher: Arc<FieldCipher>,
+    pub chain_key: Option<Arc<SecretToken>>,
+    pub notifier: CommitNotifier,
+}
+
+impl PostgresAccountRepository {
+    pub fn new(pool: PgPool) -> Self {
+        return Self {
+            pool,
+            upcasters: Arc::new(account_upcasters()),
+            cipher: Arc::new(FieldCipher::default()),
+            chain_key: None,
+            notifier: CommitNotifier::new(),
+        };
+    }
+
+    pub fn with_upcasters(mut self, upcasters: UpcasterRegistry) -> Self {
+        self.upcasters = Arc::new(upcasters);
+        return self;
+    }
+
+    pub fn with_cipher(mut self, cipher: FieldCipher) -> Self {
+        self.cipher = Arc::new(cipher);
+        return self;
+    }
+
+    /// Key of the event hash chain, kept outside the database. Without one
+    /// events are stored unhashed.
+    pub fn with_chain_key(mut self, key: Arc<SecretToken>) -> Self {
+        self.chain_key = Some(key);
+        return self;
+    }
+
+    pub fn with_notifier(mut self, notifier: CommitNotifier) -> Self {
+        self.notifier = notifier;
+        return self;
+    }
+
+    fn dead_letter_from_row(&self, row: &PgRow) -> Result<OutboxDeadLetter, anyhow::Error> {
+        let event =
+            PostgresAccountEventRow::from_row(row)?.into_envelope(&self.upcasters, &self.cipher)?;
+        return Ok(OutboxDeadLetter {
+            event,
+            attempts: row.try_get("attempts")?,
+            last_error: row.try_get("last_error")?,
+            dead_lettered_at: row.try_get("dead_lettered_at")?,
+        });
+    }
+
+    fn decode_events(
+        &self,
+        rows: Vec<PostgresAccountEventRow>,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let mut resp: Vec<EventEnvelope<AccountAggregate>> = vec![];
+        for row in rows {
+            resp.push(row.into_envelope(&self.upcasters, &self.cipher)?);
+        }
+        return Ok(resp);
+    }
+
+    /// Appends the events in one transaction. With an `expected_version`
+    /// they have to belong to one aggregate that holds that many events.
+    async fn insert_events(
+        &self,
+        events: Vec<EventEnvelope<AccountAggregate>>,
+        expected_version: Option<i64>,
+    ) -> Result<(), anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "store_events",
+            target = "AccountEventRepository",
+            implementation = "PostgresAccountRepository"
+        );
+        let _enter = root.enter();
+        let values = "$1, $2, $3, $4, $5, $6::jsonb, $7::jsonb, $8";
+        // The counter row is locked until the transaction ends, which keeps
+        // positions gap-free and in commit order across concurrent writers.
+        // Taking the lock up front also means each hash chain is extended by
+        // one writer at a time.
+        // The cost is that appends to all aggregates run one transaction at a
+        // time, so write throughput is capped at about one append per commit
+        // round trip, a few hundred to a few thousand per second depending on
+        // the disk. Going past that needs positions from a sequence, with
+        // readers stopping at a watermark below the oldest open append.
+        let lock_query = format!("SELECT position FROM {} FOR UPDATE", POSITION_TABLE_NAME);
+        let query = format!(
+            "WITH next AS ( UPDATE {} SET position = position + 1 RETURNING position ) \
+            INSERT INTO {events} ({}, hash, prev_hash, position, version) SELECT {}, $9, $10, next.position, \
+            (SELECT COALESCE(MAX(version), 0) + 1 FROM {events} WHERE aggregate_id = $2) FROM next",
+            POSITION_TABLE_NAME,
+            EVENT_FIELDS.join(", "),
+            values,
+            events = EVENT_TABLE_NAME
+        );
+        let outbox_query = format!(
+            "INSERT INTO {} ({}) VALUES ( {} )",
+            OUTBOX_TABLE_NAME,
+            EVENT_FIELDS.join(", "),
+            values
+        );
+        let mut tx = self.pool.begin().await?;
+        sqlx::query::<Postgres>(&lock_query)
+            .execute(&mut tx)
+            .await?;
+        if let (Some(expected), Some(first)) = (expected_version, events.first()) {
+            let aggregate_id = first.aggregate_id.clone();
+            if events.iter().any(|x| x.aggregate_id != aggregate_id) {
+                return Err(anyhow::anyhow!("expected events of a single aggregate"));
+            }
+            let version_query = format!(
+                "SELECT COALESCE(MAX(version), 0) FROM {} WHERE aggregate_id = $1",
+                EVENT_TABLE_NAME
+            );
+            let current: i64 = sqlx::query::<Postgres>(&version_query)
+                .bind(&aggregate_id)
+                .fetch_one(&mut tx)
+                .await?
+                .get(0);
+            if current != expected {
+                return Err(AccountError::ConcurrentModification(aggregate_id).into());
+            }
+        }
+        for x in events {
+            let enum_sql: SQLAccountEvent = x.payload.clone().into();
+            // The chain covers the plaintext, so rotating keys leaves it intact.
+            let plaintext = json!(enum_sql).to_string();
+            let payload = self
+                .cipher
+                .encrypt(json!(enum_sql), FieldBinding::event(&x.aggregate_id, &x.sequence))?
+                .to_string();
+            let metadata = json!(x.metadata).to_string();
+            let (hash, prev_hash) = chain_link(
+                &mut tx,
+                self.chain_key.as_deref(),
+                ChainLink::unhashed(&x, plaintext),
+            )
+            .await?;
+            let mut statements = vec![&query];
+            // Only events with a public form are queued for the relay.
+            if is_published(&x.payload) {
+                statements.push(&outbox_query);
+            }
+            for statement in statements {
+                let insert_span = span!(
+                    tracing::Level::INFO,
+                    "insert event",
+                    aggregate_id = x.aggregate_id.as_str(),
+                    sequence = x.sequence.as_str(),
+                    event_type = x.payload.event_type().as_str()
+                );
+                let mut plan = sqlx::query::<Postgres>(statement)
+                    .bind(&x.aggregate_type)
+                    .bind(&x.aggregate_id)
+                    .bind(&x.sequence)
+                    .bind(x.payload.event_type())
+                    .bind(x.payload.event_version())
+                    .bind(&payload)
+                    .bind(&metadata)
+                    .bind(x.timestamp);
+                if statement == &query {
+                    plan = plan.bind(&hash).bind(&prev_hash);
+                }
+                plan.execute(&mut tx).instrument(insert_span).await?;
+            }
+        }
+        tx.commit().await?;
+        self.notifier.notify();
+        return Ok(());
+    }
+}
+
+/// Hash and previous hash for an event about to be appended inside `tx`,
+/// both empty without a chain key. Once chaining is on, an aggregate whose
+/// last stored event is still unhashed refuses appends until the chain
+/// backfill has linked it.
+async fn chain_link(
+    tx: &mut Transaction<'_, Postgres>,
+    key: Option<&SecretToken>,
+    link: ChainLink,
+) -> Result<(Option<String>, Option<String>), anyhow::Error> {
+    let key = match key {
+        None => return Ok((None, None)),
+        Some(x) => x,
+    };
+    let query = format!(
+        "SELECT hash FROM {} WHERE aggregate_id = $1 ORDER BY sequence DESC LIMIT 1",
+        EVENT_TABLE_NAME
+    );
+    let previous = sqlx::query::<Postgres>(&query)
+        .bind(&link.aggregate_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let prev_hash: Option<String> = match previous {
+        None => None,
+        Some(x) => match x.try_get::<Option<String>, _>(0)? {
+            None => return Err(AccountError::ChainNotBackfilled(link.aggregate_id).into()),
+            Some(x) => Some(x),
+        },
+    };
+    let hash = event_hash(key, prev_hash.as_deref(), &link)?;
+    return Ok((Some(hash), prev_hash));
+}
+
+impl<
+        T: From<EventEnvelope<AccountAggregate>> + Into<EventEnvelope<AccountAggregate>> + Into<Q>,
+        Q,
+    > AccountEventRepository<T, Q> for PostgresAccountRepository
+{
+}
+
+#[async_trait]
+impl AccountRepository for PostgresAccountRepository {
+    async fn email_exists(&self, email: String) -> Result<bool, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "email_exists",
+            target = "AccountEventRepository",
+            implementation = "PostgresAccountRepository"
+        );
+        let _enter = root.enter();
+        // Encrypted emails are matched on their blind index, plaintext ones
+        // written before encryption was enabled on the email itself.
+        let query = format!(
+            "SELECT EXISTS (SELECT 1 FROM {} WHERE payload->>'email_index' = $1 OR payload->>'email' = $2)",
+            EVENT_TABLE_NAME
+        );
+        let execute_span = span!(tracing::Level::INFO, "query execute");
+        let result = sqlx::query::<Postgres>(&query)
+            .bind(self.cipher.email_index(&email)?)
+            .bind(email)
+            .fetch_one(&self.pool)
+            .instrument(execute_span)
+            .await;
+        match result {
+            Err(e) => return Err(e.into()),
+            Ok(x) => return Ok(x.get(0)),
+        }
+    }
+
+    async fn retrieve_aggregate_id_for_email(
+        &self,
+        email: String,
+    ) -> Result<String, anyhow::Error> {
+        let query = format!(
+            "SELECT aggregate_id FROM {} WHERE payload->>'email_index' = $1 OR payload->>'email' = $2 ORDER BY sequence DESC LIMIT 1",
+            EVENT_TABLE_NAME
+        );
+        let result = sqlx::query::<Postgres>(&query)
+            .bind(self.cipher.email_index(&email)?)
+            .bind(email)
+            .fetch_one(&self.pool)
+            .await;
+        match result {
+            Err(e) => return Err(e.into()),
+            Ok(x) => return Ok(x.get(0)),
+        }
+    }
+
+    async fn append_events(
+        &self,
+        expected_version: i64,
+        events: Vec<EventEnvelope<AccountAggregate>>,
+    ) -> Result<(), anyhow::Error> {
+        return self.insert_events(events, Some(expected_version)).await;
+    }
+}
+
+#[async_trait]
+impl AccountEventStreamRepository for PostgresAccountRepository {
+    async fn retrieve_event_page(
+        &self,
+        aggregate_id: String,
+        after: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let query = format!(
+            "SELECT {} FROM {} WHERE aggregate_id = $1 AND ($2::text IS NULL OR sequence > $2) ORDER BY sequence ASC LIMIT $3",
+            EVENT_SELECT_FIELDS, EVENT_TABLE_NAME
+        );
+        let rows = sqlx::query_as::<Postgres, PostgresAccountEventRow>(&query)
+            .bind(aggregate_id)
+            .bind(after)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        return self.decode_events(rows);
+    }
+}
+
+#[async_trait]
+impl AccountEventLogRepository for PostgresAccountRepository {
+    async fn read_all(
+        &self,
+        from_position: i64,
+        limit: i64,
+    ) -> Result<Vec<PositionedEvent>, anyhow::Error> {
+        let query = format!(
+            "SELECT position, {} FROM {} WHERE position > $1 ORDER BY position ASC LIMIT $2",
+            EVENT_SELECT_FIELDS, EVENT_TABLE_NAME
+        );
+        let rows = sqlx::query::<Postgres>(&query)
+            .bind(from_position)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut resp: Vec<PositionedEvent> = vec![];
+        for row in rows {
+            resp.push(PositionedEvent {
+                position: row.try_get("position")?,
+                event: PostgresAccountEventRow::from_row(&row)?
+                    .into_envelope(&self.upcasters, &self.cipher)?,
+            });
+        }
+        return Ok(resp);
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for PostgresAccountRepository {
+    async fn load_checkpoint(&self, subscription_id: String) -> Result<i64, anyhow::Error> {
+        let query = format!(
+            "SELECT position FROM {} WHERE subscription_id = $1",
+            CHECKPOINT_TABLE_NAME
+        );
+        let row = sqlx::query::<Postgres>(&query)
+            .bind(subscription_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            None => return Ok(0),
+            Some(x) => return Ok(x.get(0)),
+        }
+    }
+
+    async fn save_checkpoint(
+        &self,
+        subscription_id: String,
+        expected: i64,
+        position: i64,
+    ) -> Result<bool, anyhow::Error> {
+        let update = format!(
+            "UPDATE {} SET position = $1, updated_at = $2 WHERE subscription_id = $3 AND position = $4",
+            CHECKPOINT_TABLE_NAME
+        );
+        let updated = sqlx::query::<Postgres>(&update)
+            .bind(position)
+            .bind(Utc::now())
+            .bind(&subscription_id)
+            .bind(expected)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if updated > 0 || expected != 0 {
+            return Ok(updated > 0);
+        }
+        // A missing checkpoint reads as 0, so it is created on the first save.
+        let insert = format!(
+            "INSERT INTO {} (subscription_id, position, updated_at) VALUES ( $1, $2, $3 ) ON CONFLICT (subscription_id) DO NOTHING",
+            CHECKPOINT_TABLE_NAME
+        );
+        let inserted = sqlx::query::<Postgres>(&insert)
+            .bind(subscription_id)
+            .bind(position)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        return Ok(inserted > 0);
+    }
+}
+
+#[async_trait]
+impl AccountSnapshotRepository for PostgresAccountRepository {
+    async fn purge_stale_snapshots(&self) -> Result<u64, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "purge_stale_snapshots",
+            target = "AccountEventRepository",
+            implementation = "PostgresAccountRepository"
+        );
+        let _enter = root.enter();
+        let query = format!(
+            "DELETE FROM {} WHERE schema_version != $1",
+            SNAPSHOT_TABLE_NAME
+        );
+        let result = sqlx::query::<Postgres>(&query)
+            .bind(SNAPSHOT_SCHEMA_VERSION)
+            .execute(&self.pool)
+            .await;
+        match result {
+            Err(e) => return Err(e.into()),
+            Ok(x) => return Ok(x.rows_affected()),
+        }
+    }
+}
+
+#[async_trait]
+impl AccountEventChainRepository for PostgresAccountRepository {
+    async fn retrieve_aggregate_ids(
+        &self,
+        after: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let query = format!(
+            "SELECT DISTINCT aggregate_id FROM {} WHERE $1::text IS NULL OR aggregate_id > $1 ORDER BY aggregate_id ASC LIMIT $2",
+            EVENT_TABLE_NAME
+        );
+        let rows = sqlx::query::<Postgres>(&query)
+            .bind(after)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        return Ok(rows.iter().map(|x| x.get(0)).collect());
+    }
+
+    async fn retrieve_chain_page(
+        &self,
+        aggregate_id: String,
+        after: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<ChainLink>, anyhow::Error> {
+        let query = format!(
+            "SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload::text AS payload, metadata::text AS metadata, timestamp, hash, prev_hash FROM {} WHERE aggregate_id = $1 AND ($2::text IS NULL OR sequence > $2) ORDER BY sequence ASC LIMIT $3",
+            EVENT_TABLE_NAME
+        );
+        let rows = sqlx::query::<Postgres>(&query)
+            .bind(aggregate_id)
+            .bind(after)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut resp: Vec<ChainLink> = vec![];
+        for row in rows {
+            let aggregate_id: String = row.try_get("aggregate_id")?;
+            let sequence: String = row.try_get("sequence")?;
+            let payload: String = row.try_get("payload")?;
+            let payload = self
+                .cipher
+                .decrypt(
+                    serde_json::from_str(&payload)?,
+                    FieldBinding::event(&aggregate_id, &sequence),
+                )?
+                .to_string();
+            resp.push(ChainLink {
+                aggregate_type: row.try_get("aggregate_type")?,
+                aggregate_id,
+                sequence,
+                event_type: row.try_get("event_type")?,
+                event_version: row.try_get("event_version")?,
+                payload,
+                metadata: row.try_get("metadata")?,
+                timestamp: row.try_get("timestamp")?,
+                hash: row.try_get("hash")?,
+                prev_hash: row.try_get("prev_hash")?,
+            });
+        }
+        return Ok(resp);
+    }
+
+    async fn backfill_chain(&self, links: Vec<ChainLink>) -> Result<u64, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "backfill_chain",
+            target = "AccountEventRepository",
+            implementation = "PostgresAccountRepository"
+        );
+        let _enter = root.enter();
+        let query = format!(
+            "UPDATE {} SET hash = $1, prev_hash = $2 WHERE sequence = $3 AND hash IS NULL",
+            EVENT_TABLE_NAME
+        );
+        let mut updated = 0;
+        let mut tx = self.pool.begin().await?;
+        for link in links {
+            let result = sqlx::query::<Postgres>(&query)
+                .bind(&link.hash)
+                .bind(&link.prev_hash)
+                .bind(&link.sequence)
+                .execute(&mut tx)
+                .await?;
+            updated += result.rows_affected();
+        }
+        tx.commit().await?;
+        return Ok(updated);
+    }
+}
+
+#[async_trait]
+impl AccountOutboxRepository for PostgresAccountRepository {
+    async fn retrieve_outbox_batch(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let query = format!(
+            "SELECT {} FROM {} WHERE dead_lettered_at IS NULL ORDER BY id ASC LIMIT $1",
+            EVENT_SELECT_FIELDS, OUTBOX_TABLE_NAME
+        );
+        let rows = sqlx::query_as::<Postgres, PostgresAccountEventRow>(&query)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+        return self.decode_events(rows);
+    }
+
+    async fn claim_outbox_events(
+        &self,
+        owner: String,
+        lease: Duration,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "claim_outbox_events",
+            target = "AccountEventRepository",
+            implementation = "PostgresAccountRepository"
+        );
+        let _enter = root.enter();
+        let now = Utc::now().timestamp_millis();
+        let expires_at = now + lease.as_millis() as i64;
+        let mut tx = self.pool.begin().await?;
+        // Lock the oldest outbox row of each aggregate first. A relay that
+        // cannot lock an aggregate's head skips the whole aggregate, so two
+        // relays never deliver events of the same aggregate concurrently. A
+        // dead-lettered head holds its aggregate back the same way.
+        let query = format!(
+            "SELECT o.aggregate_id FROM {table} o \
+            WHERE NOT EXISTS ( \
+                SELECT 1 FROM {table} p WHERE p.aggregate_id = o.aggregate_id AND p.id < o.id \
+            ) \
+            AND (o.lease_owner IS NULL OR o.lease_owner = $1 OR o.lease_expires_at < $2) \
+            AND (o.next_attempt_at IS NULL OR o.next_attempt_at <= $2) \
+            AND o.dead_lettered_at IS NULL \
+            ORDER BY o.id ASC LIMIT $3 \
+            FOR UPDATE SKIP LOCKED",
+            table = OUTBOX_TABLE_NAME
+        );
+        let heads = sqlx::query::<Postgres>(&query)
+            .bind(&owner)
+            .bind(now)
+            .bind(limit)
+            .fetch_all(&mut tx)
+            .await?;
+        let aggregate_ids: Vec<String> = heads.iter().map(|x| x.get::<String, _>(0)).collect();
+        if aggregate_ids.is_empty() {
+            tx.commit().await?;
+            return Ok(vec![]);
+        }
+        let query = format!(
+            "UPDATE {table} SET lease_owner = $1, lease_expires_at = $2 WHERE id IN ( \
+                SELECT o.id FROM {table} o \
+                WHERE o.aggregate_id = ANY($3) \
+                AND (o.lease_owner IS NULL OR o.lease_owner = $1 OR o.lease_expires_at < $4) \
+                AND (o.next_attempt_at IS NULL OR o.next_attempt_at <= $4) \
+                AND o.dead_lettered_at IS NULL \
+                AND NOT EXISTS ( \
+                    SELECT 1 FROM {table} p \
+                    WHERE p.aggregate_id = o.aggregate_id AND p.id < o.id \
+                    AND ( \
+                        (p.lease_owner IS NOT NULL AND p.lease_owner != $1 AND p.lease_expires_at >= $4) \
+                        OR p.next_attempt_at > $4 \
+                        OR p.dead_lettered_at IS NOT NULL \
+                    ) \
+                ) \
+                ORDER BY o.id ASC LIMIT $5 \
+            ) RETURNING id, {fields}",
+            table = OUTBOX_TABLE_NAME,
+            fields = EVENT_SELECT_FIELDS
+        );
+        let rows = sqlx::query::<Postgres>(&query)
+            .bind(&owner)
+            .bind(expires_at)
+            .bind(&aggregate_ids)
+            .bind(now)
+            .bind(limit)
+            .fetch_all(&mut tx)
+            .await?;
+        tx.commit().await?;
+        let mut claimed: Vec<(i64, PostgresAccountEventRow)> = vec![];
+        for row in rows {
+            claimed.push((row.get("id"), PostgresAccountEventRow::from_row(&row)?));
+        }
+        claimed.sort_by_key(|x| x.0);
+        return self.decode_events(claimed.into_iter().map(|x| x.1).collect());
+    }
+
+    async fn record_outbox_failure(
+        &self,
+        sequence: String,
+        error: String,
+        max_attempts: i64,
+        retry_delay: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "record_outbox_failure",
+            target = "AccountEventRepository",
+            implementation = "PostgresAccountRepository"
+        );
+        let _enter = root.enter();
+        let mut tx = self.pool.begin().await?;
+        let query = format!(
+            "SELECT attempts FROM {} WHERE sequence = $1 AND dead_lettered_at IS NULL FOR UPDATE",
+            OUTBOX_TABLE_NAME
+        );
+        let row = sqlx::query::<Postgres>(&query)
+            .bind(&sequence)
+            .fetch_optional(&mut tx)
+            .await?;
+        let attempts: i64 = match row {
+            None => return Ok(false),
+            Some(x) => x.get::<i64, _>(0) + 1,
+        };
+        if attempts >= max_attempts {
+            let query = format!(
+                "INSERT INTO {} ({fields}, attempts, last_error, dead_lettered_at) SELECT {fields}, $1, $2, $3 FROM {} WHERE sequence = $4",
+                DEAD_LETTER_TABLE_NAME,
+                OUTBOX_TABLE_NAME,
+                fields = EVENT_FIELDS.join(", ")
+            );
+            let dead_lettered_at = Utc::now();
+            sqlx::query::<Postgres>(&query)
+                .bind(attempts)
+                .bind(&error)
+                .bind(dead_lettered_at)
+                .bind(&sequence)
+                .execute(&mut tx)
+                .await?;
+            // Parked in place, see `SQLiteAccountRepository`.
+            let query = format!(
+                "UPDATE {} SET attempts = $1, last_error = $2, dead_lettered_at = $3, lease_owner = NULL, lease_expires_at = NULL WHERE sequence = $4",
+                OUTBOX_TABLE_NAME
+            );
+            sqlx::query::<Postgres>(&query)
+                .bind(attempts)
+                .bind(&error)
+                .bind(dead_lettered_at)
+                .bind(&sequence)
+                .execute(&mut tx)
+                .await?;
+            tx.commit().await?;
+            return Ok(true);
+        }
+        let exponent = min(attempts - 1, 16) as u32;
+        let delay = retry_delay.as_millis() as i64 * 2_i64.pow(exponent);
+        let query = format!(
+            "UPDATE {} SET attempts = $1, last_error = $2, next_attempt_at = $3, lease_owner = NULL, lease_expires_at = NULL WHERE sequence = $4",
+            OUTBOX_TABLE_NAME
+        );
+        sqlx::query::<Postgres>(&query)
+            .bind(attempts)
+            .bind(&error)
+            .bind(Utc::now().timestamp_millis() + delay)
+            .bind(&sequence)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        return Ok(false);
+    }
+
+    async fn release_outbox_leases(&self, owner: String) -> Result<(), anyhow::Error> {
+        let query = format!(
+            "UPDATE {} SET lease_owner = NULL, lease_expires_at = NULL WHERE lease_owner = $1",
+            OUTBOX_TABLE_NAME
+        );
+        let result = sqlx::query::<Postgres>(&query)
+            .bind(owner)
+            .execute(&self.pool)
+            .await;
+        match result {
+            Err(e) => return Err(e.into()),
+            _ => return Ok(()),
+        }
+    }
+
+    async fn renew_outbox_lease(
+        &self,
+        owner: String,
+        sequence: String,
+        lease: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        let query = format!(
+            "UPDATE {} SET lease_expires_at = $1 WHERE sequence = $2 AND lease_owner = $3",
+            OUTBOX_TABLE_NAME
+        );
+        let result = sqlx::query::<Postgres>(&query)
+            .bind(Utc::now().timestamp_millis() + lease.as_millis() as i64)
+            .bind(sequence)
+            .bind(owner)
+            .execute(&self.pool)
+            .await?;
+        return Ok(result.rows_affected() > 0);
+    }
+
+    async fn delete_leased_outbox_event(
+        &self,
+        owner: String,
+        sequence: String,
+    ) -> Result<bool, anyhow::Error> {
+        let query = format!(
+            "DELETE FROM {} WHERE sequence = $1 AND lease_owner = $2",
+            OUTBOX_TABLE_NAME
+        );
+        let result = sqlx::query::<Postgres>(&query)
+            .bind(sequence)
+            .bind(owner)
+            .execute(&self.pool)
+            .await?;
+        return Ok(result.rows_affected() > 0);
+    }
+
+    async fn retry_outbox_event(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "retry_outbox_event",
+            target = "AccountEventRepository",
+            implementation = "PostgresAccountRepository"
+        );
+        let _enter = root.enter();
+        let query = format!(
+            "UPDATE {} SET attempts = 0, last_error = NULL, next_attempt_at = NULL WHERE sequence = $1 AND dead_lettered_at IS NULL",
+            OUTBOX_TABLE_NAME
+        );
+        let result = sqlx::query::<Postgres>(&query)
+            .bind(sequence)
+            .execute(&self.pool)
+            .await?;
+        return Ok(result.rows_affected() > 0);
+    }
+}
+
+#[async_trait]
+impl AccountDeadLetterRepository for PostgresAccountRepository {
+    async fn list_dead_letters(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<OutboxDeadLetter>, anyhow::Error> {
+        let query = format!(
+            "SELECT {} FROM {} ORDER BY dead_lettered_at ASC LIMIT $1 OFFSET $2",
+            DEAD_LETTER_SELECT_FIELDS, DEAD_LETTER_TABLE_NAME
+        );
+        let rows = sqlx::query::<Postgres>(&query)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut resp: Vec<OutboxDeadLetter> = vec![];
+        for row in rows {
+            resp.push(self.dead_letter_from_row(&row)?);
+        }
+        return Ok(resp);
+    }
+
+    async fn retrieve_dead_letter(
+        &self,
+        sequence: String,
+    ) -> Result<Option<OutboxDeadLetter>, anyhow::Error> {
+        let query = format!(
+            "SELECT {} FROM {} WHERE sequence = $1",
+            DEAD_LETTER_SELECT_FIELDS, DEAD_LETTER_TABLE_NAME
+        );
+        let row = sqlx::query::<Postgres>(&query)
+            .bind(sequence)
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            None => return Ok(None),
+            Some(x) => return Ok(Some(self.dead_letter_from_row(&x)?)),
+        }
+    }
+
+    async fn requeue_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        let mut tx = self.pool.begin().await?;
+        let query = format!("DELETE FROM {} WHERE sequence = $1", DEAD_LETTER_TABLE_NAME);
+        let delete = sqlx::query::<Postgres>(&query)
+            .bind(&sequence)
+            .execute(&mut tx)
+            .await?;
+        if delete.rows_affected() == 0 {
+            return Ok(false);
+        }
+        let query = format!(
+            "UPDATE {} SET attempts = 0, last_error = NULL, next_attempt_at = NULL, dead_lettered_at = NULL WHERE sequence = $1",
+            OUTBOX_TABLE_NAME
+        );
+        sqlx::query::<Postgres>(&query)
+            .bind(&sequence)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        self.notifier.notify();
+        return Ok(true);
+    }
+
+    async fn discard_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        let mut tx = self.pool.begin().await?;
+        let query = format!("DELETE FROM {} WHERE sequence = $1", DEAD_LETTER_TABLE_NAME);
+        let delete = sqlx::query::<Postgres>(&query)
+            .bind(&sequence)
+            .execute(&mut tx)
+            .await?;
+        if delete.rows_affected() == 0 {
+            return Ok(false);
+        }
+        let query = format!(
+            "DELETE FROM {} WHERE sequence = $1 AND dead_lettered_at IS NOT NULL",
+            OUTBOX_TABLE_NAME
+        );
+        sqlx::query::<Postgres>(&query)
+            .bind(&sequence)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        self.notifier.notify();
+        return Ok(true);
+    }
+}
+
+#[async_trait]
+impl IdempotencyRepository for PostgresAccountRepository {
+    async fn claim_idempotency_key(
+        &self,
+        idempotency_key: String,
+        command_hash: String,
+        stale_before: DateTime<Utc>,
+    ) -> Result<IdempotencyClaim, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "claim_idempotency_key",
+            target = "AccountEventRepository",
+            implementation = "PostgresAccountRepository"
+        );
+        let _enter = root.enter();
+        let query = format!(
+            "INSERT INTO {} (idempotency_key, command_hash, status, created_at) VALUES ( $1, $2, 'pending', $3 ) ON CONFLICT (idempotency_key) DO UPDATE SET command_hash = excluded.command_hash, created_at = excluded.created_at WHERE {}.status = 'pending' AND {}.created_at < $4",
+            IDEMPOTENCY_TABLE_NAME, IDEMPOTENCY_TABLE_NAME, IDEMPOTENCY_TABLE_NAME
+        );
+        let insert = sqlx::query::<Postgres>(&query)
+            .bind(&idempotency_key)
+            .bind(&command_hash)
+            .bind(Utc::now())
+            .bind(stale_before)
+            .execute(&self.pool)
+            .await?;
+        if insert.rows_affected() > 0 {
+            return Ok(IdempotencyClaim::Claimed);
+        }
+        let query = format!(
+            "SELECT command_hash, outcome::text FROM {} WHERE idempotency_key = $1",
+            IDEMPOTENCY_TABLE_NAME
+        );
+        let row = sqlx::query::<Postgres>(&query)
+            .bind(&idempotency_key)
+            .fetch_one(&self.pool)
+            .await?;
+        let outcome: Option<String> = row.get(1);
+        let outcome = match outcome {
+            Some(x) => Some(decode_aggregate(
+                &x,
+                FieldBinding::outcome(&idempotency_key),
+                &self.upcasters,
+                &self.cipher,
+            )?),
+            None => None,
+        };
+        return Ok(IdempotencyClaim::Existing(Box::new(IdempotencyRecord {
+            idempotency_key,
+            command_hash: row.get(0),
+            outcome,
+        })));
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        idempotency_key: String,
+        outcome: AccountAggregate,
+    ) -> Result<(), anyhow::Error> {
+        let query = format!(
+            "UPDATE {} SET status = 'completed', outcome = $1::jsonb, completed_at = $2 WHERE idempotency_key = $3",
+            IDEMPOTENCY_TABLE_NAME
+        );
+        let enum_sql: SQLAccountAggregate = outcome.into();
+        let result = sqlx::query::<Postgres>(&query)
+            .bind(
+                self.cipher
+                    .encrypt(json!(enum_sql), FieldBinding::outcome(&idempotency_key))?
+                    .to_string(),
+            )
+            .bind(Utc::now())
+            .bind(&idempotency_key)
+            .execute(&self.pool)
+            .await;
+        match result {
+            Err(e) => return Err(e.into()),
+            _ => return Ok(()),
+        }
+    }
+
+    async fn release_idempotency_key(
+        &self,
+        idempotency_key: String,
+    ) -> Result<(), anyhow::Error> {
+        let query = format!(
+            "DELETE FROM {} WHERE idempotency_key = $1 AND status = 'pending'",
+            IDEMPOTENCY_TABLE_NAME
+        );
+        let result = sqlx::query::<Postgres>(&query)
+            .bind(idempotency_key)
+            .execute(&self.pool)
+            .await;
+        match result {
+            Err(e) => return Err(e.into()),
+            _ => return Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<
+        T: From<EventEnvelope<AccountAggregate>> + Into<EventEnvelope<AccountAggregate>> + Into<Q>,
+        Q,
+    >
+    EventRepository<
+        EventEnvelope<AccountAggregate>,
+        T,
+        Q,
+        EventEnvelope<AccountAggregate>,
+        AggregateSnapshot<AccountAggregate>,
+        AggregateSnapshot<AccountAggregate>,
+    > for PostgresAccountRepository
+{
+    async fn migrate(&self, path: String) -> Result<(), anyhow::Error> {
+        let m = Migrator::new(std::path::Path::new(&path)).await?;
+        m.run(&self.pool).await.map_err(|e| e.into())
+    }
+
+    async fn store_events(
+        &self,
+        events: Vec<EventEnvelope<AccountAggregate>>,
+    ) -> Result<(), anyhow::Error> {
+        return self.insert_events(events, None).await;
+    }
+
+    async fn retrieve_events(
+        &self,
+        aggregate_id: String,
+        after: Option<String>,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let query = match after {
+            None => format!(
+                "SELECT {} FROM {} WHERE aggregate_id = $1 ORDER BY sequence ASC",
+                EVENT_SELECT_FIELDS, EVENT_TABLE_NAME
+            ),
+            Some(_) => format!(
+                "SELECT {} FROM {} WHERE aggregate_id = $1 AND sequence > $2 ORDER BY sequence ASC",
+                EVENT_SELECT_FIELDS, EVENT_TABLE_NAME
+            ),
+        };
+        let mut plan = sqlx::query_as::<Postgres, PostgresAccountEventRow>(&query);
+        plan = match after {
+            None => plan.bind(aggregate_id),
+            Some(x) => plan.bind(aggregate_id).bind(x),
+        };
+        let rows = plan.fetch_all(&self.pool).await?;
+        return self.decode_events(rows);
+    }
+
+    async fn store_snapshot(
+        &self,
+        snapshot: AggregateSnapshot<AccountAggregate>,
+    ) -> Result<(), anyhow::Error> {
+        let query = format!(
+            "INSERT INTO {} (aggregate_type, aggregate_id, payload, last_sequence, snapshot_id, timestamp, schema_version) VALUES ( $1, $2, $3::jsonb, $4, $5, $6, $7 )",
+            SNAPSHOT_TABLE_NAME
+        );
+        let enum_sql: SQLAccountAggregate = snapshot.payload.into();
+        let payload = self.cipher.encrypt(
+            json!(enum_sql),
+            FieldBinding::snapshot(&snapshot.aggregate_id, &snapshot.snapshot_id),
+        )?;
+        let result = sqlx::query::<Postgres>(&query)
+            .bind(snapshot.aggregate_type)
+            .bind(snapshot.aggregate_id)
+            .bind(payload.to_string())
+            .bind(snapshot.last_sequence)
+            .bind(snapshot.snapshot_id)
+            .bind(snapshot.timestamp)
+            .bind(SNAPSHOT_SCHEMA_VERSION)
+            .execute(&self.pool)
+            .await;
+        match result {
+            Err(e) => return Err(e.into()),
+            _ => return Ok(()),
+        }
+    }
+
+    async fn retrieve_latest_snapshot(
+        &self,
+        aggregate_id: String,
+    ) -> Result<Option<AggregateSnapshot<AccountAggregate>>, anyhow::Error> {
+        let query = format!(
+            "SELECT aggregate_type, aggregate_id, payload::text AS payload, last_sequence, snapshot_id, timestamp FROM {} WHERE aggregate_id = $1 AND schema_version = $2 ORDER BY snapshot_id DESC LIMIT 1",
+            SNAPSHOT_TABLE_NAME
+        );
+        let result = sqlx::query_as::<Postgres, SQLAccountSnapshotRow>(&query)
+            .bind(aggregate_id)
+            .bind(SNAPSHOT_SCHEMA_VERSION)
+            .fetch_optional(&self.pool)
+            .await?;
+        match result {
+            None => Ok(None),
+            Some(x) => Ok(Some(x.into_snapshot(&self.upcasters, &self.cipher)?)),
+        }
+    }
+
+    async fn send_and_delete_outbox_event(
+        &self,
+        event: EventEnvelope<AccountAggregate>,
+        bus: &Arc<
+            dyn EventBus<EventEnvelope<AccountAggregate>, T, Q, EventEnvelope<AccountAggregate>>
+                + Sync
+                + Send,
+        >,
+    ) -> Result<(), anyhow::Error> {
+        let query = format!("DELETE FROM {} WHERE sequence = $1", OUTBOX_TABLE_NAME);
+        let sequence = event.sequence.clone();
+        bus.send_event(event).await?;
+        let result = sqlx::query::<Postgres>(&query)
+            .bind(sequence)
+            .execute(&self.pool)
+            .await;
+        match result {
+            Err(e) => return Err(e.into()),
+            _ => return Ok(()),
+        }
+    }
+
+    async fn retrieve_outbox_events(
+        &self,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let query = format!(
+            "SELECT {} FROM {} WHERE dead_lettered_at IS NULL ORDER BY id ASC",
+            EVENT_SELECT_FIELDS, OUTBOX_TABLE_NAME
+        );
+        let rows = sqlx::query_as::<Postgres, PostgresAccountEventRow>(&query)
+            .fetch_all(&self.pool)
+            .await?;
+        return self.decode_events(rows);
+    }
+}
@@ -1,33 +1,300 @@
-use crate::command::{
-    application::account::ports::outbound::repository::{
-        AccountEventRepository, AccountRepository,
+use crate::{
+    command::{
+        application::account::{
+            chain::event_hash,
+            ports::outbound::{
+                chain::{AccountEventChainRepository, ChainLink},
+                dead_letter::{AccountDeadLetterRepository, OutboxDeadLetter},
+                idempotency::{IdempotencyClaim, IdempotencyRecord, IdempotencyRepository},
+                notifier::CommitNotifier,
+                repository::{
+                    AccountEventRepository, AccountEventStreamRepository, AccountOutboxRepository,
+                    AccountRepository, AccountSnapshotRepository,
+                },
+                subscription::{AccountEventLogRepository, CheckpointStore, PositionedEvent},
+            },
+        },
+        domain::account::entity::{aggregate::AccountAggregate, error::AccountError},
+        infrastructure::{
+            dtos::storage::sql::{
+                decode_aggregate, SQLAccountAggregate, SQLAccountEvent, SQLAccountEventRow,
+                SQLAccountSnapshotRow, SNAPSHOT_SCHEMA_VERSION,
+            },
+            dtos::transport::public::is_published,
+            encryption::fields::{FieldBinding, FieldCipher},
+            upcasting::{account::account_upcasters, registry::UpcasterRegistry},
+        },
     },
-    domain::account::entity::{aggregate::AccountAggregate, error::AccountError},
-    infrastructure::dtos::storage::sql::{SQLAccountAggregate, SQLAccountEvent},
+    common::domain::secret::SecretToken,
 };
 
-use std::sync::Arc;
+use std::{cmp::min, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use cqrs_rs::{
     application::port::outbound::{event_bus::EventBus, event_repository::EventRepository},
     domain::entity::event::{AggregateSnapshot, DomainEvent, EventEnvelope},
-    infrastructure::{
-        adapter::secondary::storage::sqlite::SqliteConnector,
-        dto::storage::sql::{SQLAggregateSnapshot, SQLEventEnvelope},
-    },
+    infrastructure::adapter::secondary::storage::sqlite::SqliteConnector,
 };
 use serde_json::json;
-use sqlx::{migrate::Migrator, Row, Sqlite};
+use sqlx::{migrate::Migrator, sqlite::SqliteRow, FromRow, Row, Sqlite, Transaction};
 use tracing::{span, Instrument};
 
 const EVENT_TABLE_NAME: &str = "account_events";
 const SNAPSHOT_TABLE_NAME: &str = "account_snapshots";
 const OUTBOX_TABLE_NAME: &str = "account_outbox_events";
+const IDEMPOTENCY_TABLE_NAME: &str = "account_idempotency_keys";
+const DEAD_LETTER_TABLE_NAME: &str = "account_outbox_dead_letters";
+const CHECKPOINT_TABLE_NAME: &str = "account_subscription_checkpoints";
+const DEAD_LETTER_FIELDS: [&str; 11] = [
+    "aggregate_type",
+    "aggregate_id",
+    "sequence",
+    "event_type",
+    "event_version",
+    "payload",
+    "metadata",
+    "timestamp",
+    "attempts",
+    "last_error",
+    "dead_lettered_at",
+];
 
 #[derive(Clone)]
 pub struct SQLiteAccountRepository {
     pub connector: Arc<SqliteConnector>,
+    pub upcasters: Arc<UpcasterRegistry>,
+    pub cipher: Arc<FieldCipher>,
+    pub chain_key: Option<Arc<SecretToken>>,
+    pub notifier: CommitNotifier,
+}
+
+impl SQLiteAccountRepository {
+    pub fn new(connector: Arc<SqliteConnector>) -> Self {
+        return Self {
+            connector,
+            upcasters: Arc::new(account_upcasters()),
+            cipher: Arc::new(FieldCipher::default()),
+            chain_key: None,
+            notifier: CommitNotifier::new(),
+        };
+    }
+
+    pub fn with_upcasters(mut self, upcasters: UpcasterRegistry) -> Self {
+        self.upcasters = Arc::new(upcasters);
+        return self;
+    }
+
+    pub fn with_cipher(mut self, cipher: FieldCipher) -> Self {
+        self.cipher = Arc::new(cipher);
+        return self;
+    }
+
+    /// Key of the event hash chain, kept outside the database. Without one
+    /// events are stored unhashed.
+    pub fn with_chain_key(mut self, key: Arc<SecretToken>) -> Self {
+        self.chain_key = Some(key);
+        return self;
+    }
+
+    pub fn with_notifier(mut self, notifier: CommitNotifier) -> Self {
+        self.notifier = notifier;
+        return self;
+    }
+
+    fn dead_letter_from_row(&self, row: &SqliteRow) -> Result<OutboxDeadLetter, anyhow::Error> {
+        let event =
+            SQLAccountEventRow::from_row(row)?.into_envelope(&self.upcasters, &self.cipher)?;
+        return Ok(OutboxDeadLetter {
+            event,
+            attempts: row.try_get("attempts")?,
+            last_error: row.try_get("last_error")?,
+            dead_lettered_at: row.try_get("dead_lettered_at")?,
+        });
+    }
+
+    /// Appends the events in one transaction. With an `expected_version`
+    /// they have to belong to one aggregate that holds that many events.
+    async fn insert_events(
+        &self,
+        events: Vec<EventEnvelope<AccountAggregate>>,
+        expected_version: Option<i64>,
+    ) -> Result<(), anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "store_events",
+            target = "AccountEventRepository",
+            implementation = "SQLiteAccountRepository"
+        );
+        let _enter = root.enter();
+        let fields = [
+            "aggregate_type",
+            "aggregate_id",
+            "sequence",
+            "event_type",
+            "event_version",
+            "payload",
+            "metadata",
+            "timestamp",
+        ];
+        let placeholders: Vec<String> = (0..fields.len())
+            .map(|x| format!("?{}", x + 1))
+            .collect();
+        let placeholder_str = placeholders.join(", ");
+        // Positions and versions are assigned inside the write transaction, so
+        // they stay gap-free: a rolled back append never consumes one.
+        let query = format!(
+            "INSERT INTO {table} ({}, hash, prev_hash, position, version) VALUES ( {}, ?{}, ?{}, (SELECT COALESCE(MAX(position), 0) + 1 FROM {table}), (SELECT COALESCE(MAX(version), 0) + 1 FROM {table} WHERE aggregate_id = ?2) )",
+            fields.join(", "),
+            placeholder_str,
+            fields.len() + 1,
+            fields.len() + 2,
+            table = EVENT_TABLE_NAME
+        );
+        let outbox_query = format!(
+            "INSERT INTO {} ({}) VALUES ( {} )",
+            OUTBOX_TABLE_NAME,
+            fields.join(", "),
+            placeholder_str
+        );
+        let mut tx = self.connector.pool.begin().await?;
+        // The transaction starts as a reader, and a WAL reader whose snapshot
+        // went stale cannot become a writer. A no-op write takes the write
+        // lock before the version and chain reads, so they see the latest
+        // commit and the inserts cannot fail with a busy snapshot.
+        let lock_query = format!("UPDATE {} SET version = version WHERE 0", EVENT_TABLE_NAME);
+        if let Err(e) = sqlx::query::<Sqlite>(&lock_query).execute(&mut tx).await {
+            rollback(tx).await;
+            return Err(e.into());
+        }
+        if let (Some(expected), Some(first)) = (expected_version, events.first()) {
+            let aggregate_id = first.aggregate_id.clone();
+            if events.iter().any(|x| x.aggregate_id != aggregate_id) {
+                rollback(tx).await;
+                return Err(anyhow::anyhow!("expected events of a single aggregate"));
+            }
+            let version_query = format!(
+                "SELECT COALESCE(MAX(version), 0) FROM {} WHERE aggregate_id = ?1",
+                EVENT_TABLE_NAME
+            );
+            let current = sqlx::query::<Sqlite>(&version_query)
+                .bind(&aggregate_id)
+                .fetch_one(&mut tx)
+                .await;
+            let current: i64 = match current {
+                Ok(x) => x.get(0),
+                Err(e) => {
+                    rollback(tx).await;
+                    return Err(e.into());
+                }
+            };
+            if current != expected {
+                rollback(tx).await;
+                return Err(AccountError::ConcurrentModification(aggregate_id).into());
+            }
+        }
+        for x in events {
+            let enum_sql: SQLAccountEvent = x.payload.clone().into();
+            // The chain covers the plaintext, so rotating keys leaves it intact.
+            let plaintext = json!(enum_sql).to_string();
+            let payload = self
+                .cipher
+                .encrypt(json!(enum_sql), FieldBinding::event(&x.aggregate_id, &x.sequence))?
+                .to_string();
+            let metadata = json!(x.metadata).to_string();
+            let link = chain_link(
+                &mut tx,
+                EVENT_TABLE_NAME,
+                self.chain_key.as_deref(),
+                ChainLink::unhashed(&x, plaintext),
+            )
+            .await;
+            let (hash, prev_hash) = match link {
+                Ok(x) => x,
+                Err(e) => {
+                    rollback(tx).await;
+                    return Err(e);
+                }
+            };
+            let mut statements = vec![(&query, EVENT_TABLE_NAME)];
+            // Internal-only events stay out of the outbox, so they never reach the bus.
+            if is_published(&x.payload) {
+                statements.push((&outbox_query, OUTBOX_TABLE_NAME));
+            }
+            for (statement, table) in statements {
+                let insert_span = span!(
+                    tracing::Level::INFO,
+                    "insert event",
+                    table,
+                    aggregate_id = x.aggregate_id.as_str(),
+                    sequence = x.sequence.as_str(),
+                    event_type = x.payload.event_type().as_str()
+                );
+                let mut plan = sqlx::query::<Sqlite>(statement)
+                    .bind(&x.aggregate_type)
+                    .bind(&x.aggregate_id)
+                    .bind(&x.sequence)
+                    .bind(x.payload.event_type())
+                    .bind(x.payload.event_version())
+                    .bind(&payload)
+                    .bind(&metadata)
+                    .bind(x.timestamp.to_rfc3339());
+                if table == EVENT_TABLE_NAME {
+                    plan = plan.bind(&hash).bind(&prev_hash);
+                }
+                let insert = plan.execute(&mut tx).instrument(insert_span).await;
+                if let Err(e) = insert {
+                    rollback(tx).await;
+                    return Err(e.into());
+                }
+            }
+        }
+        tx.commit().await?;
+        self.notifier.notify();
+        return Ok(());
+    }
+}
+
+/// Rolls back after a failed statement. A failing rollback is only logged,
+/// so the caller still returns the error that caused it.
+async fn rollback(tx: Transaction<'_, Sqlite>) {
+    if let Err(e) = tx.rollback().await {
+        tracing::warn!(error = %e, "rollback failed");
+    }
+}
+
+/// Hash and previous hash for an event about to be appended to `table`
+/// inside `tx`, both empty without a chain key. Once chaining is on, an
+/// aggregate whose last stored event is still unhashed refuses appends until
+/// the chain backfill has linked it.
+pub(crate) async fn chain_link(
+    tx: &mut Transaction<'_, Sqlite>,
+    table: &str,
+    key: Option<&SecretToken>,
+    link: ChainLink,
+) -> Result<(Option<String>, Option<String>), anyhow::Error> {
+    let key = match key {
+        None => return Ok((None, None)),
+        Some(x) => x,
+    };
+    let query = format!(
+        "SELECT hash FROM {} WHERE aggregate_id = ?1 ORDER BY sequence DESC LIMIT 1",
+        table
+    );
+    let previous = sqlx::query::<Sqlite>(&query)
+        .bind(&link.aggregate_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let prev_hash: Option<String> = match previous {
+        None => None,
+        Some(x) => match x.try_get::<Option<String>, _>(0)? {
+            None => return Err(AccountError::ChainNotBackfilled(link.aggregate_id).into()),
+            Some(x) => Some(x),
+        },
+    };
+    let hash = event_hash(key, prev_hash.as_deref(), &link)?;
+    return Ok((Some(hash), prev_hash));
 }
 
 impl<
@@ -47,11 +314,15 @@ impl AccountRepository for SQLiteAccountRepository {
             implementation = "SQLiteAccountRepository"
         );
         let _enter = root.enter();
+        // Encrypted emails are matched on their blind index, plaintext ones
+        // written before encryption was enabled on the email itself.
         let query = format!(
-            "SELECT COUNT(*) FROM {} WHERE json_extract(payload, '$.email') = ?1",
+            "SELECT COUNT(*) FROM {} WHERE json_extract(payload, '$.email_index') = ?1 OR json_extract(payload, '$.email') = ?2",
             EVENT_TABLE_NAME
         );
-        let plan = sqlx::query::<Sqlite>(&query).bind(email);
+        let plan = sqlx::query::<Sqlite>(&query)
+            .bind(self.cipher.email_index(&email)?)
+            .bind(email);
         let execute_span = span!(tracing::Level::INFO, "query execute");
         let results = plan
             .fetch_one(&self.connector.pool)
@@ -73,11 +344,13 @@ impl AccountRepository for SQLiteAccountRepository {
         email: String,
     ) -> Result<String, anyhow::Error> {
         let query = format!(
-            "SELECT aggregate_id FROM {} WHERE json_extract(payload, '$.email') = ?1 ORDER BY sequence DESC LIMIT 1",
+            "SELECT aggregate_id FROM {} WHERE json_extract(payload, '$.email_index') = ?1 OR json_extract(payload, '$.email') = ?2 ORDER BY sequence DESC LIMIT 1",
             EVENT_TABLE_NAME
         );
 
-        let plan = sqlx::query::<Sqlite>(&query).bind(email);
+        let plan = sqlx::query::<Sqlite>(&query)
+            .bind(self.cipher.email_index(&email)?)
+            .bind(email);
         let results = plan.fetch_one(&self.connector.pool).await;
         match results {
             Err(e) => return Err(e.into()),
@@ -87,38 +360,274 @@ impl AccountRepository for SQLiteAccountRepository {
             }
         };
     }
+
+    async fn append_events(
+        &self,
+        expected_version: i64,
+        events: Vec<EventEnvelope<AccountAggregate>>,
+    ) -> Result<(), anyhow::Error> {
+        return self.insert_events(events, Some(expected_version)).await;
+    }
 }
 
 #[async_trait]
-impl<
-        T: From<EventEnvelope<AccountAggregate>> + Into<EventEnvelope<AccountAggregate>> + Into<Q>,
-        Q,
-    >
-    EventRepository<
-        EventEnvelope<AccountAggregate>,
-        T,
-        Q,
-        EventEnvelope<AccountAggregate>,
-        AggregateSnapshot<AccountAggregate>,
-        AggregateSnapshot<AccountAggregate>,
-    > for SQLiteAccountRepository
-{
-    async fn migrate(&self, path: String) -> Result<(), anyhow::Error> {
-        let m = Migrator::new(std::path::Path::new(&path)).await?;
-        m.run(&self.connector.pool).await.map_err(|e| e.into())
+impl AccountEventStreamRepository for SQLiteAccountRepository {
+    async fn retrieve_event_page(
+        &self,
+        aggregate_id: String,
+        after: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let fields = [
+            "aggregate_type",
+            "aggregate_id",
+            "sequence",
+            "event_type",
+            "event_version",
+            "payload",
+            "metadata",
+            "timestamp",
+        ];
+        let query = format!(
+            "SELECT {} FROM {} WHERE aggregate_id = ?1 AND (?2 IS NULL OR sequence > ?2) ORDER BY sequence ASC LIMIT ?3",
+            fields.join(", "),
+            EVENT_TABLE_NAME
+        );
+        let rows = sqlx::query_as::<Sqlite, SQLAccountEventRow>(&query)
+            .bind(aggregate_id)
+            .bind(after)
+            .bind(limit)
+            .fetch_all(&self.connector.pool)
+            .await?;
+        let mut resp: Vec<EventEnvelope<AccountAggregate>> = vec![];
+        for row in rows {
+            resp.push(row.into_envelope(&self.upcasters, &self.cipher)?);
+        }
+        return Ok(resp);
     }
-    async fn store_events(
+}
+
+#[async_trait]
+impl AccountEventLogRepository for SQLiteAccountRepository {
+    async fn read_all(
         &self,
-        events: Vec<EventEnvelope<AccountAggregate>>,
-    ) -> Result<(), anyhow::Error> {
+        from_position: i64,
+        limit: i64,
+    ) -> Result<Vec<PositionedEvent>, anyhow::Error> {
+        let fields = [
+            "aggregate_type",
+            "aggregate_id",
+            "sequence",
+            "event_type",
+            "event_version",
+            "payload",
+            "metadata",
+            "timestamp",
+        ];
+        let query = format!(
+            "SELECT position, {} FROM {} WHERE position > ?1 ORDER BY position ASC LIMIT ?2",
+            fields.join(", "),
+            EVENT_TABLE_NAME
+        );
+        let rows = sqlx::query::<Sqlite>(&query)
+            .bind(from_position)
+            .bind(limit)
+            .fetch_all(&self.connector.pool)
+            .await?;
+        let mut resp: Vec<PositionedEvent> = vec![];
+        for row in rows {
+            resp.push(PositionedEvent {
+                position: row.try_get("position")?,
+                event: SQLAccountEventRow::from_row(&row)?
+                    .into_envelope(&self.upcasters, &self.cipher)?,
+            });
+        }
+        return Ok(resp);
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for SQLiteAccountRepository {
+    async fn load_checkpoint(&self, subscription_id: String) -> Result<i64, anyhow::Error> {
+        let query = format!(
+            "SELECT position FROM {} WHERE subscription_id = ?1",
+            CHECKPOINT_TABLE_NAME
+        );
+        let row = sqlx::query::<Sqlite>(&query)
+            .bind(subscription_id)
+            .fetch_optional(&self.connector.pool)
+            .await?;
+        match row {
+            None => return Ok(0),
+            Some(x) => return Ok(x.get(0)),
+        }
+    }
+
+    async fn save_checkpoint(
+        &self,
+        subscription_id: String,
+        expected: i64,
+        position: i64,
+    ) -> Result<bool, anyhow::Error> {
+        let update = format!(
+            "UPDATE {} SET position = ?1, updated_at = ?2 WHERE subscription_id = ?3 AND position = ?4",
+            CHECKPOINT_TABLE_NAME
+        );
+        let updated = sqlx::query::<Sqlite>(&update)
+            .bind(position)
+            .bind(Utc::now())
+            .bind(&subscription_id)
+            .bind(expected)
+            .execute(&self.connector.pool)
+            .await?
+            .rows_affected();
+        if updated > 0 || expected != 0 {
+            return Ok(updated > 0);
+        }
+        // A missing checkpoint reads as 0, so it is created on the first save.
+        let insert = format!(
+            "INSERT INTO {} (subscription_id, position, updated_at) VALUES ( ?1, ?2, ?3 ) ON CONFLICT (subscription_id) DO NOTHING",
+            CHECKPOINT_TABLE_NAME
+        );
+        let inserted = sqlx::query::<Sqlite>(&insert)
+            .bind(subscription_id)
+            .bind(position)
+            .bind(Utc::now())
+            .execute(&self.connector.pool)
+            .await?
+            .rows_affected();
+        return Ok(inserted > 0);
+    }
+}
+
+#[async_trait]
+impl AccountSnapshotRepository for SQLiteAccountRepository {
+    async fn purge_stale_snapshots(&self) -> Result<u64, anyhow::Error> {
         let root = span!(
             tracing::Level::INFO,
-            "store_events",
+            "purge_stale_snapshots",
+            target = "AccountEventRepository",
+            implementation = "SQLiteAccountRepository"
+        );
+        let _enter = root.enter();
+        let query = format!(
+            "DELETE FROM {} WHERE schema_version != ?1",
+            SNAPSHOT_TABLE_NAME
+        );
+        let plan = sqlx::query::<Sqlite>(&query).bind(SNAPSHOT_SCHEMA_VERSION);
+        let result = plan.execute(&self.connector.pool).await;
+        match result {
+            Err(e) => return Err(e.into()),
+            Ok(x) => return Ok(x.rows_affected()),
+        }
+    }
+}
+
+#[async_trait]
+impl AccountEventChainRepository for SQLiteAccountRepository {
+    async fn retrieve_aggregate_ids(
+        &self,
+        after: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let query = format!(
+            "SELECT DISTINCT aggregate_id FROM {} WHERE ?1 IS NULL OR aggregate_id > ?1 ORDER BY aggregate_id ASC LIMIT ?2",
+            EVENT_TABLE_NAME
+        );
+        let rows = sqlx::query::<Sqlite>(&query)
+            .bind(after)
+            .bind(limit)
+            .fetch_all(&self.connector.pool)
+            .await?;
+        return Ok(rows.iter().map(|x| x.get(0)).collect());
+    }
+
+    async fn retrieve_chain_page(
+        &self,
+        aggregate_id: String,
+        after: Option<String>,
+        limit: i64,
+    ) -> Result<Vec<ChainLink>, anyhow::Error> {
+        let query = format!(
+            "SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata, timestamp, hash, prev_hash FROM {} WHERE aggregate_id = ?1 AND (?2 IS NULL OR sequence > ?2) ORDER BY sequence ASC LIMIT ?3",
+            EVENT_TABLE_NAME
+        );
+        let rows = sqlx::query::<Sqlite>(&query)
+            .bind(aggregate_id)
+            .bind(after)
+            .bind(limit)
+            .fetch_all(&self.connector.pool)
+            .await?;
+        let mut resp: Vec<ChainLink> = vec![];
+        for row in rows {
+            let aggregate_id: String = row.try_get("aggregate_id")?;
+            let sequence: String = row.try_get("sequence")?;
+            let payload: String = row.try_get("payload")?;
+            let payload = self
+                .cipher
+                .decrypt(
+                    serde_json::from_str(&payload)?,
+                    FieldBinding::event(&aggregate_id, &sequence),
+                )?
+                .to_string();
+            let timestamp: String = row.try_get("timestamp")?;
+            resp.push(ChainLink {
+                aggregate_type: row.try_get("aggregate_type")?,
+                aggregate_id,
+                sequence,
+                event_type: row.try_get("event_type")?,
+                event_version: row.try_get("event_version")?,
+                payload,
+                metadata: row.try_get("metadata")?,
+                timestamp: DateTime::parse_from_rfc3339(&timestamp)?.with_timezone(&Utc),
+                hash: row.try_get("hash")?,
+                prev_hash: row.try_get("prev_hash")?,
+            });
+        }
+        return Ok(resp);
+    }
+
+    async fn backfill_chain(&self, links: Vec<ChainLink>) -> Result<u64, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "backfill_chain",
             target = "AccountEventRepository",
             implementation = "SQLiteAccountRepository"
         );
         let _enter = root.enter();
-        let fields = vec![
+        let query = format!(
+            "UPDATE {} SET hash = ?1, prev_hash = ?2 WHERE sequence = ?3 AND hash IS NULL",
+            EVENT_TABLE_NAME
+        );
+        let mut updated = 0;
+        let mut tx = self.connector.pool.begin().await?;
+        for link in links {
+            let result = sqlx::query::<Sqlite>(&query)
+                .bind(&link.hash)
+                .bind(&link.prev_hash)
+                .bind(&link.sequence)
+                .execute(&mut tx)
+                .await;
+            match result {
+                Err(e) => {
+                    rollback(tx).await;
+                    return Err(e.into());
+                }
+                Ok(x) => updated += x.rows_affected(),
+            }
+        }
+        tx.commit().await?;
+        return Ok(updated);
+    }
+}
+
+#[async_trait]
+impl AccountOutboxRepository for SQLiteAccountRepository {
+    async fn retrieve_outbox_batch(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let fields = [
             "aggregate_type",
             "aggregate_id",
             "sequence",
@@ -128,79 +637,463 @@ impl<
             "metadata",
             "timestamp",
         ];
-        let placeholders: Vec<String> = (0..fields.len())
-            .map(|x| format!("?{}", (x + 1).to_string()))
-            .collect();
-        let placeholder_str = placeholders.join(", ");
         let query = format!(
-            "INSERT INTO {} ({}) VALUES ( {} )",
-            EVENT_TABLE_NAME,
+            "SELECT {} FROM {} WHERE dead_lettered_at IS NULL ORDER BY rowid ASC LIMIT ?1",
             fields.join(", "),
-            placeholder_str
+            OUTBOX_TABLE_NAME
         );
-        let outbox_query = format!(
-            "INSERT INTO {} ({}) VALUES ( {} )",
-            OUTBOX_TABLE_NAME,
-            fields.join(", "),
-            placeholder_str
+        let plan = sqlx::query_as::<Sqlite, SQLAccountEventRow>(&query).bind(limit);
+        let results = plan.fetch_all(&self.connector.pool).await;
+        if let Err(e) = results {
+            return Err(e.into());
+        }
+        let mut resp: Vec<EventEnvelope<AccountAggregate>> = vec![];
+        for row in results.unwrap() {
+            let x = row.into_envelope(&self.upcasters, &self.cipher)?;
+            resp.push(x)
+        }
+        return Ok(resp);
+    }
+
+    async fn claim_outbox_events(
+        &self,
+        owner: String,
+        lease: Duration,
+        limit: i64,
+    ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "claim_outbox_events",
+            target = "AccountEventRepository",
+            implementation = "SQLiteAccountRepository"
         );
-        let mut results = vec![];
-        for x in events {
-            let mut tx = self.connector.pool.begin().await?;
-            let plan = sqlx::query::<Sqlite>(&query);
-            let outbox_plan = sqlx::query::<Sqlite>(&outbox_query);
-            let insert_span = span!(
-                tracing::Level::INFO,
-                "insert event",
-                event = format!("{:?}", x)
+        let _enter = root.enter();
+        let fields = [
+            "aggregate_type",
+            "aggregate_id",
+            "sequence",
+            "event_type",
+            "event_version",
+            "payload",
+            "metadata",
+            "timestamp",
+        ];
+        let query = format!(
+            "UPDATE {table} SET lease_owner = ?1, lease_expires_at = ?2 WHERE rowid IN ( \
+                SELECT o.rowid FROM {table} o \
+                WHERE (o.lease_owner IS NULL OR o.lease_owner = ?1 OR o.lease_expires_at < ?3) \
+                AND (o.next_attempt_at IS NULL OR o.next_attempt_at <= ?3) \
+                AND o.dead_lettered_at IS NULL \
+                AND NOT EXISTS ( \
+                    SELECT 1 FROM {table} p \
+                    WHERE p.aggregate_id = o.aggregate_id AND p.rowid < o.rowid \
+                    AND ( \
+                        (p.lease_owner IS NOT NULL AND p.lease_owner != ?1 AND p.lease_expires_at >= ?3) \
+                        OR p.next_attempt_at > ?3 \
+                        OR p.dead_lettered_at IS NOT NULL \
+                    ) \
+                ) \
+                ORDER BY o.rowid ASC LIMIT ?4 \
+            ) RETURNING rowid, {fields}",
+            table = OUTBOX_TABLE_NAME,
+            fields = fields.join(", ")
+        );
+        let now = Utc::now().timestamp_millis();
+        let expires_at = now + lease.as_millis() as i64;
+        let results = sqlx::query::<Sqlite>(&query)
+            .bind(owner)
+            .bind(expires_at)
+            .bind(now)
+            .bind(limit)
+            .fetch_all(&self.connector.pool)
+            .await;
+        let rows = match results {
+            Err(e) => return Err(e.into()),
+            Ok(x) => x,
+        };
+        let mut claimed: Vec<(i64, SQLAccountEventRow)> = vec![];
+        for row in rows {
+            claimed.push((row.get("rowid"), SQLAccountEventRow::from_row(&row)?));
+        }
+        claimed.sort_by_key(|x| x.0);
+        let mut resp: Vec<EventEnvelope<AccountAggregate>> = vec![];
+        for (_, row) in claimed {
+            resp.push(row.into_envelope(&self.upcasters, &self.cipher)?);
+        }
+        return Ok(resp);
+    }
+
+    async fn record_outbox_failure(
+        &self,
+        sequence: String,
+        error: String,
+        max_attempts: i64,
+        retry_delay: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "record_outbox_failure",
+            target = "AccountEventRepository",
+            implementation = "SQLiteAccountRepository"
+        );
+        let _enter = root.enter();
+        let mut tx = self.connector.pool.begin().await?;
+        let query = format!(
+            "SELECT attempts FROM {} WHERE sequence = ?1 AND dead_lettered_at IS NULL",
+            OUTBOX_TABLE_NAME
+        );
+        let row = sqlx::query::<Sqlite>(&query)
+            .bind(&sequence)
+            .fetch_optional(&mut tx)
+            .await?;
+        let attempts: i64 = match row {
+            None => return Ok(false),
+            Some(x) => x.get::<i64, _>(0) + 1,
+        };
+        if attempts >= max_attempts {
+            let fields = [
+                "aggregate_type",
+                "aggregate_id",
+                "sequence",
+                "event_type",
+                "event_version",
+                "payload",
+                "metadata",
+                "timestamp",
+            ];
+            let query = format!(
+                "INSERT INTO {} ({fields}, attempts, last_error, dead_lettered_at) SELECT {fields}, ?1, ?2, ?3 FROM {} WHERE sequence = ?4",
+                DEAD_LETTER_TABLE_NAME,
+                OUTBOX_TABLE_NAME,
+                fields = fields.join(", ")
             );
-            let enum_sql: SQLAccountEvent = x.payload.clone().into();
-            let insert = plan
-                .bind(&x.aggregate_type)
-                .bind(&x.aggregate_id)
-                .bind(&x.sequence)
-                .bind(&x.payload.event_type())
-                .bind(&x.payload.event_version())
-                .bind(json!(enum_sql).to_string())
-                .bind(json!(x.metadata).to_string())
-                .bind(&x.timestamp.to_rfc3339())
+            let dead_lettered_at = Utc::now();
+            sqlx::query::<Sqlite>(&query)
+                .bind(attempts)
+                .bind(&error)
+                .bind(dead_lettered_at)
+                .bind(&sequence)
                 .execute(&mut tx)
-                .instrument(insert_span)
-                .await;
-            let insert_outbox_span = span!(
-                tracing::Level::INFO,
-                "insert outbox event",
-                event = format!("{:?}", x)
+                .await?;
+            // The row stays in the outbox, parked, so it keeps its place and
+            // holds back the aggregate's later events until it is requeued
+            // or discarded.
+            let query = format!(
+                "UPDATE {} SET attempts = ?1, last_error = ?2, dead_lettered_at = ?3, lease_owner = NULL, lease_expires_at = NULL WHERE sequence = ?4",
+                OUTBOX_TABLE_NAME
             );
-            let outbox_insert = outbox_plan
-                .bind(&x.aggregate_type)
-                .bind(&x.aggregate_id)
-                .bind(&x.sequence)
-                .bind(&x.payload.event_type())
-                .bind(&x.payload.event_version())
-                .bind(json!(enum_sql).to_string())
-                .bind(json!(x.metadata).to_string())
-                .bind(&x.timestamp.to_rfc3339())
+            sqlx::query::<Sqlite>(&query)
+                .bind(attempts)
+                .bind(&error)
+                .bind(dead_lettered_at)
+                .bind(&sequence)
                 .execute(&mut tx)
-                .instrument(insert_outbox_span)
-                .await;
-            if outbox_insert.is_err() {
-                results.push(outbox_insert)
-            }
+                .await?;
             tx.commit().await?;
-            results.push(insert);
+            return Ok(true);
         }
-        let mut err: Vec<anyhow::Error> = vec![];
-        for result in results {
-            match result {
-                Err(e) => err.push(e.into()),
-                _ => {}
-            }
+        let exponent = min(attempts - 1, 16) as u32;
+        let delay = retry_delay.as_millis() as i64 * 2_i64.pow(exponent);
+        let query = format!(
+            "UPDATE {} SET attempts = ?1, last_error = ?2, next_attempt_at = ?3, lease_owner = NULL, lease_expires_at = NULL WHERE sequence = ?4",
+            OUTBOX_TABLE_NAME
+        );
+        sqlx::query::<Sqlite>(&query)
+            .bind(attempts)
+            .bind(&error)
+            .bind(Utc::now().timestamp_millis() + delay)
+            .bind(&sequence)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        return Ok(false);
+    }
+
+    async fn release_outbox_leases(&self, owner: String) -> Result<(), anyhow::Error> {
+        let query = format!(
+            "UPDATE {} SET lease_owner = NULL, lease_expires_at = NULL WHERE lease_owner = ?1",
+            OUTBOX_TABLE_NAME
+        );
+        let result = sqlx::query::<Sqlite>(&query)
+            .bind(owner)
+            .execute(&self.connector.pool)
+            .await;
+        match result {
+            Err(e) => return Err(e.into()),
+            _ => return Ok(()),
         }
-        if err.len() > 0 {
-            return Err(AccountError::UnknownError.into());
+    }
+
+    async fn renew_outbox_lease(
+        &self,
+        owner: String,
+        sequence: String,
+        lease: Duration,
+    ) -> Result<bool, anyhow::Error> {
+        let query = format!(
+            "UPDATE {} SET lease_expires_at = ?1 WHERE sequence = ?2 AND lease_owner = ?3 RETURNING sequence",
+            OUTBOX_TABLE_NAME
+        );
+        // Checked through the returned rows, the affected row count is not
+        // reliable while other connections write to the outbox.
+        let rows = sqlx::query::<Sqlite>(&query)
+            .bind(Utc::now().timestamp_millis() + lease.as_millis() as i64)
+            .bind(sequence)
+            .bind(owner)
+            .fetch_all(&self.connector.pool)
+            .await?;
+        return Ok(!rows.is_empty());
+    }
+
+    async fn delete_leased_outbox_event(
+        &self,
+        owner: String,
+        sequence: String,
+    ) -> Result<bool, anyhow::Error> {
+        let query = format!(
+            "DELETE FROM {} WHERE sequence = ?1 AND lease_owner = ?2 RETURNING sequence",
+            OUTBOX_TABLE_NAME
+        );
+        let rows = sqlx::query::<Sqlite>(&query)
+            .bind(sequence)
+            .bind(owner)
+            .fetch_all(&self.connector.pool)
+            .await?;
+        return Ok(!rows.is_empty());
+    }
+
+    async fn retry_outbox_event(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "retry_outbox_event",
+            target = "AccountEventRepository",
+            implementation = "SQLiteAccountRepository"
+        );
+        let _enter = root.enter();
+        let query = format!(
+            "UPDATE {} SET attempts = 0, last_error = NULL, next_attempt_at = NULL WHERE sequence = ?1 AND dead_lettered_at IS NULL",
+            OUTBOX_TABLE_NAME
+        );
+        let result = sqlx::query::<Sqlite>(&query)
+            .bind(sequence)
+            .execute(&self.connector.pool)
+            .await?;
+        return Ok(result.rows_affected() > 0);
+    }
+}
+
+#[async_trait]
+impl AccountDeadLetterRepository for SQLiteAccountRepository {
+    async fn list_dead_letters(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<OutboxDeadLetter>, anyhow::Error> {
+        let query = format!(
+            "SELECT {} FROM {} ORDER BY dead_lettered_at ASC LIMIT ?1 OFFSET ?2",
+            DEAD_LETTER_FIELDS.join(", "),
+            DEAD_LETTER_TABLE_NAME
+        );
+        let rows = sqlx::query::<Sqlite>(&query)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.connector.pool)
+            .await?;
+        let mut resp: Vec<OutboxDeadLetter> = vec![];
+        for row in rows {
+            resp.push(self.dead_letter_from_row(&row)?);
         }
-        return Ok(());
+        return Ok(resp);
+    }
+
+    async fn retrieve_dead_letter(
+        &self,
+        sequence: String,
+    ) -> Result<Option<OutboxDeadLetter>, anyhow::Error> {
+        let query = format!(
+            "SELECT {} FROM {} WHERE sequence = ?1",
+            DEAD_LETTER_FIELDS.join(", "),
+            DEAD_LETTER_TABLE_NAME
+        );
+        let row = sqlx::query::<Sqlite>(&query)
+            .bind(sequence)
+            .fetch_optional(&self.connector.pool)
+            .await?;
+        match row {
+            None => return Ok(None),
+            Some(x) => return Ok(Some(self.dead_letter_from_row(&x)?)),
+        }
+    }
+
+    async fn requeue_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        let mut tx = self.connector.pool.begin().await?;
+        let query = format!("DELETE FROM {} WHERE sequence = ?1", DEAD_LETTER_TABLE_NAME);
+        let delete = sqlx::query::<Sqlite>(&query)
+            .bind(&sequence)
+            .execute(&mut tx)
+            .await?;
+        if delete.rows_affected() == 0 {
+            return Ok(false);
+        }
+        let query = format!(
+            "UPDATE {} SET attempts = 0, last_error = NULL, next_attempt_at = NULL, dead_lettered_at = NULL WHERE sequence = ?1",
+            OUTBOX_TABLE_NAME
+        );
+        sqlx::query::<Sqlite>(&query)
+            .bind(&sequence)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        self.notifier.notify();
+        return Ok(true);
+    }
+
+    async fn discard_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        let mut tx = self.connector.pool.begin().await?;
+        let query = format!("DELETE FROM {} WHERE sequence = ?1", DEAD_LETTER_TABLE_NAME);
+        let delete = sqlx::query::<Sqlite>(&query)
+            .bind(&sequence)
+            .execute(&mut tx)
+            .await?;
+        if delete.rows_affected() == 0 {
+            return Ok(false);
+        }
+        let query = format!(
+            "DELETE FROM {} WHERE sequence = ?1 AND dead_lettered_at IS NOT NULL",
+            OUTBOX_TABLE_NAME
+        );
+        sqlx::query::<Sqlite>(&query)
+            .bind(&sequence)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        self.notifier.notify();
+        return Ok(true);
+    }
+}
+
+#[async_trait]
+impl IdempotencyRepository for SQLiteAccountRepository {
+    async fn claim_idempotency_key(
+        &self,
+        idempotency_key: String,
+        command_hash: String,
+        stale_before: DateTime<Utc>,
+    ) -> Result<IdempotencyClaim, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "claim_idempotency_key",
+            target = "AccountEventRepository",
+            implementation = "SQLiteAccountRepository"
+        );
+        let _enter = root.enter();
+        let query = format!(
+            "INSERT INTO {} (idempotency_key, command_hash, status, created_at) VALUES ( ?1, ?2, 'pending', ?3 ) ON CONFLICT(idempotency_key) DO UPDATE SET command_hash = excluded.command_hash, created_at = excluded.created_at WHERE {}.status = 'pending' AND {}.created_at < ?4",
+            IDEMPOTENCY_TABLE_NAME, IDEMPOTENCY_TABLE_NAME, IDEMPOTENCY_TABLE_NAME
+        );
+        let insert = sqlx::query::<Sqlite>(&query)
+            .bind(&idempotency_key)
+            .bind(&command_hash)
+            .bind(Utc::now())
+            .bind(stale_before)
+            .execute(&self.connector.pool)
+            .await?;
+        if insert.rows_affected() > 0 {
+            return Ok(IdempotencyClaim::Claimed);
+        }
+        let query = format!(
+            "SELECT command_hash, outcome FROM {} WHERE idempotency_key = ?1",
+            IDEMPOTENCY_TABLE_NAME
+        );
+        let row = sqlx::query::<Sqlite>(&query)
+            .bind(&idempotency_key)
+            .fetch_one(&self.connector.pool)
+            .await?;
+        let outcome: Option<String> = row.get(1);
+        let outcome = match outcome {
+            Some(x) => Some(decode_aggregate(
+                &x,
+                FieldBinding::outcome(&idempotency_key),
+                &self.upcasters,
+                &self.cipher,
+            )?),
+            None => None,
+        };
+        return Ok(IdempotencyClaim::Existing(Box::new(IdempotencyRecord {
+            idempotency_key,
+            command_hash: row.get(0),
+            outcome,
+        })));
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        idempotency_key: String,
+        outcome: AccountAggregate,
+    ) -> Result<(), anyhow::Error> {
+        let query = format!(
+            "UPDATE {} SET status = 'completed', outcome = ?1, completed_at = ?2 WHERE idempotency_key = ?3",
+            IDEMPOTENCY_TABLE_NAME
+        );
+        let enum_sql: SQLAccountAggregate = outcome.into();
+        let result = sqlx::query::<Sqlite>(&query)
+            .bind(
+                self.cipher
+                    .encrypt(json!(enum_sql), FieldBinding::outcome(&idempotency_key))?
+                    .to_string(),
+            )
+            .bind(Utc::now())
+            .bind(&idempotency_key)
+            .execute(&self.connector.pool)
+            .await;
+        match result {
+            Err(e) => return Err(e.into()),
+            _ => return Ok(()),
+        }
+    }
+
+    async fn release_idempotency_key(
+        &self,
+        idempotency_key: String,
+    ) -> Result<(), anyhow::Error> {
+        let query = format!(
+            "DELETE FROM {} WHERE idempotency_key = ?1 AND status = 'pending'",
+            IDEMPOTENCY_TABLE_NAME
+        );
+        let result = sqlx::query::<Sqlite>(&query)
+            .bind(idempotency_key)
+            .execute(&self.connector.pool)
+            .await;
+        match result {
+            Err(e) => return Err(e.into()),
+            _ => return Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<
+        T: From<EventEnvelope<AccountAggregate>> + Into<EventEnvelope<AccountAggregate>> + Into<Q>,
+        Q,
+    >
+    EventRepository<
+        EventEnvelope<AccountAggregate>,
+        T,
+        Q,
+        EventEnvelope<AccountAggregate>,
+        AggregateSnapshot<AccountAggregate>,
+        AggregateSnapshot<AccountAggregate>,
+    > for SQLiteAccountRepository
+{
+    async fn migrate(&self, path: String) -> Result<(), anyhow::Error> {
+        let m = Migrator::new(std::path::Path::new(&path)).await?;
+        m.run(&self.connector.pool).await.map_err(|e| e.into())
+    }
+    async fn store_events(
+        &self,
+        events: Vec<EventEnvelope<AccountAggregate>>,
+    ) -> Result<(), anyhow::Error> {
+        return self.insert_events(events, None).await;
     }
 
     async fn retrieve_events(
@@ -208,7 +1101,7 @@ impl<
         aggregate_id: String,
         after: Option<String>,
     ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
-        let fields = vec![
+        let fields = [
             "aggregate_type",
             "aggregate_id",
             "sequence",
@@ -220,7 +1113,7 @@ impl<
         ];
         let query = match after {
             None => format!(
-                "SELECT {} FROM {} WHERE aggregate_id = ?1",
+                "SELECT {} FROM {} WHERE aggregate_id = ?1 ORDER BY sequence ASC",
                 fields.join(", "),
                 EVENT_TABLE_NAME
             ),
@@ -230,19 +1123,18 @@ impl<
                 EVENT_TABLE_NAME
             ),
         };
-        let mut plan = sqlx::query_as::<Sqlite, SQLEventEnvelope<SQLAccountEvent>>(&query);
+        let mut plan = sqlx::query_as::<Sqlite, SQLAccountEventRow>(&query);
         plan = match after {
             None => plan.bind(aggregate_id),
             Some(x) => plan.bind(aggregate_id).bind(x),
         };
         let results = plan.fetch_all(&self.connector.pool).await;
-        match results {
-            Err(e) => return Err(e.into()),
-            _ => {}
-        };
+        if let Err(e) = results {
+            return Err(e.into());
+        }
         let mut resp: Vec<EventEnvelope<AccountAggregate>> = vec![];
-        for env in results.unwrap() {
-            let x = env.into();
+        for row in results.unwrap() {
+            let x = row.into_envelope(&self.upcasters, &self.cipher)?;
             resp.push(x)
         }
         return Ok(resp);
@@ -253,16 +1145,17 @@ impl<
         snapshot: AggregateSnapshot<AccountAggregate>,
     ) -> Result<(), anyhow::Error> {
         println!("Storing snapshot");
-        let fields = vec![
+        let fields = [
             "aggregate_type",
             "aggregate_id",
             "payload",
             "last_sequence",
             "snapshot_id",
             "timestamp",
+            "schema_version",
         ];
         let placeholders: Vec<String> = (0..fields.len())
-            .map(|x| format!("?{}", (x + 1).to_string()))
+            .map(|x| format!("?{}", x + 1))
             .collect();
         let placeholder_str = placeholders.join(", ");
         let query = format!(
@@ -273,13 +1166,18 @@ impl<
         );
         let plan = sqlx::query::<Sqlite>(&query);
         let enum_sql: SQLAccountAggregate = snapshot.payload.clone().into();
+        let payload = self.cipher.encrypt(
+            json!(enum_sql),
+            FieldBinding::snapshot(&snapshot.aggregate_id, &snapshot.snapshot_id),
+        )?;
         let insert = plan
             .bind(snapshot.aggregate_type)
             .bind(snapshot.aggregate_id)
-            .bind(json!(enum_sql).to_string())
+            .bind(payload.to_string())
             .bind(snapshot.last_sequence)
             .bind(snapshot.snapshot_id)
             .bind(snapshot.timestamp)
+            .bind(SNAPSHOT_SCHEMA_VERSION)
             .fetch_optional(&self.connector.pool)
             .await;
         match insert {
@@ -295,7 +1193,7 @@ impl<
         &self,
         aggregate_id: String,
     ) -> Result<Option<AggregateSnapshot<AccountAggregate>>, anyhow::Error> {
-        let fields = vec![
+        let fields = [
             "aggregate_type",
             "aggregate_id",
             "payload",
@@ -304,20 +1202,20 @@ impl<
             "timestamp",
         ];
         let query = format!(
-            "SELECT {} FROM {} WHERE aggregate_id = ?1 ORDER BY snapshot_id DESC LIMIT 1",
+            "SELECT {} FROM {} WHERE aggregate_id = ?1 AND schema_version = ?2 ORDER BY snapshot_id DESC LIMIT 1",
             fields.join(", "),
             SNAPSHOT_TABLE_NAME
         );
-        let plan = sqlx::query_as::<Sqlite, SQLAggregateSnapshot<SQLAccountAggregate>>(&query)
-            .bind(aggregate_id);
+        let plan = sqlx::query_as::<Sqlite, SQLAccountSnapshotRow>(&query)
+            .bind(aggregate_id)
+            .bind(SNAPSHOT_SCHEMA_VERSION);
         let result = plan.fetch_optional(&self.connector.pool).await;
-        match result {
-            Err(e) => return Err(e.into()),
-            _ => {}
-        };
+        if let Err(e) = result {
+            return Err(e.into());
+        }
         match result.unwrap() {
             None => Ok(None),
-            Some(x) => Ok(Some(x.into())),
+            Some(x) => Ok(Some(x.into_snapshot(&self.upcasters, &self.cipher)?)),
         }
     }
 
@@ -338,7 +1236,7 @@ impl<
         let result = plan.execute(&mut tx).await;
         match result {
             Err(e) => {
-                tx.rollback().await?;
+                rollback(tx).await;
                 return Err(e.into());
             }
             _ => {
@@ -351,7 +1249,7 @@ impl<
     async fn retrieve_outbox_events(
         &self,
     ) -> Result<Vec<EventEnvelope<AccountAggregate>>, anyhow::Error> {
-        let fields = vec![
+        let fields = [
             "aggregate_type",
             "aggregate_id",
             "sequence",
@@ -361,16 +1259,19 @@ impl<
             "metadata",
             "timestamp",
         ];
-        let query = format!("SELECT {} FROM {}", fields.join(", "), OUTBOX_TABLE_NAME);
-        let plan = sqlx::query_as::<Sqlite, SQLEventEnvelope<SQLAccountEvent>>(&query);
+        let query = format!(
+            "SELECT {} FROM {} WHERE dead_lettered_at IS NULL ORDER BY rowid ASC",
+            fields.join(", "),
+            OUTBOX_TABLE_NAME
+        );
+        let plan = sqlx::query_as::<Sqlite, SQLAccountEventRow>(&query);
         let results = plan.fetch_all(&self.connector.pool).await;
-        match results {
-            Err(e) => return Err(e.into()),
-            _ => {}
-        };
+        if let Err(e) = results {
+            return Err(e.into());
+        }
         let mut resp: Vec<EventEnvelope<AccountAggregate>> = vec![];
-        for env in results.unwrap() {
-            let x = env.into();
+        for row in results.unwrap() {
+            let x = row.into_envelope(&self.upcasters, &self.cipher)?;
             resp.push(x)
         }
         return Ok(resp);
@@ -1,9 +1,25 @@
-use crate::command::domain::account::entity::{aggregate::AccountAggregate, event::AccountEvent};
+use crate::{
+    command::{
+        application::account::ports::outbound::chain::ChainLink,
+        domain::account::entity::{aggregate::AccountAggregate, event::AccountEvent},
+        infrastructure::{
+            encryption::fields::{FieldBinding, FieldCipher},
+            upcasting::registry::UpcasterRegistry,
+        },
+    },
+    common::domain::secret::PasswordHash,
+};
 
 use chrono::{serde::ts_seconds, serde::ts_seconds_option, DateTime, Utc};
+use cqrs_rs::domain::entity::event::{AggregateSnapshot, EventEnvelope};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use ulid::Ulid;
 
+/// Version of the `SQLAccountAggregate` shape written to `account_snapshots`.
+/// Bump it whenever that shape changes so older snapshots are skipped.
+pub const SNAPSHOT_SCHEMA_VERSION: i64 = 3;
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "event_type")]
 pub enum SQLAccountEvent {
@@ -12,9 +28,32 @@ pub enum SQLAccountEvent {
         event_id: String,
         event_version: String,
         email: String,
-        password_hash: String,
+        password_hash: PasswordHash,
         #[serde(with = "ts_seconds")]
         created_at: DateTime<Utc>
+    },
+    AccountSuspended {
+        id: String,
+        event_id: String,
+        event_version: String,
+        reason: String,
+        #[serde(with = "ts_seconds")]
+        suspended_at: DateTime<Utc>,
+    },
+    AccountDeleted {
+        id: String,
+        event_id: String,
+        event_version: String,
+        #[serde(with = "ts_seconds")]
+        deleted_at: DateTime<Utc>,
+    },
+    PasswordRehashed {
+        id: String,
+        event_id: String,
+        event_version: String,
+        password_hash: PasswordHash,
+        #[serde(with = "ts_seconds")]
+        rehashed_at: DateTime<Utc>,
     }
 }
 
@@ -31,10 +70,10 @@ impl Default for SQLAccountEvent {
     }
 }
 
-impl Into<Option<AccountEvent>> for SQLAccountEvent {
-    fn into(self) -> Option<AccountEvent> {
-        match self {
-            Self::AccountCreated {
+impl From<SQLAccountEvent> for Option<AccountEvent> {
+    fn from(value: SQLAccountEvent) -> Self {
+        match value {
+            SQLAccountEvent::AccountCreated {
                 id,
                 event_id,
                 event_version,
@@ -48,6 +87,43 @@ impl Into<Option<AccountEvent>> for SQLAccountEvent {
                 email,
                 password_hash,
                 created_at
+            }),
+            SQLAccountEvent::AccountSuspended {
+                id,
+                event_id,
+                event_version,
+                reason,
+                suspended_at,
+            } => Some(AccountEvent::AccountSuspended {
+                id,
+                event_id,
+                event_version,
+                reason,
+                suspended_at,
+            }),
+            SQLAccountEvent::AccountDeleted {
+                id,
+                event_id,
+                event_version,
+                deleted_at,
+            } => Some(AccountEvent::AccountDeleted {
+                id,
+                event_id,
+                event_version,
+                deleted_at,
+            }),
+            SQLAccountEvent::PasswordRehashed {
+                id,
+                event_id,
+                event_version,
+                password_hash,
+                rehashed_at,
+            } => Some(AccountEvent::PasswordRehashed {
+                id,
+                event_id,
+                event_version,
+                password_hash,
+                rehashed_at,
             })
         }
     }
@@ -70,15 +146,52 @@ impl From<AccountEvent> for SQLAccountEvent {
                 email,
                 password_hash,
                 created_at,
+            },
+            AccountEvent::AccountSuspended {
+                id,
+                event_id,
+                event_version,
+                reason,
+                suspended_at,
+            } => Self::AccountSuspended {
+                id,
+                event_id,
+                event_version,
+                reason,
+                suspended_at,
+            },
+            AccountEvent::AccountDeleted {
+                id,
+                event_id,
+                event_version,
+                deleted_at,
+            } => Self::AccountDeleted {
+                id,
+                event_id,
+                event_version,
+                deleted_at,
+            },
+            AccountEvent::PasswordRehashed {
+                id,
+                event_id,
+                event_version,
+                password_hash,
+                rehashed_at,
+            } => Self::PasswordRehashed {
+                id,
+                event_id,
+                event_version,
+                password_hash,
+                rehashed_at,
             }
         }
     }
 }
 
-impl Into<AccountEvent> for SQLAccountEvent {
-    fn into(self) -> AccountEvent {
-        match self {
-            Self::AccountCreated {
+impl From<SQLAccountEvent> for AccountEvent {
+    fn from(value: SQLAccountEvent) -> Self {
+        match value {
+            SQLAccountEvent::AccountCreated {
                 id,
                 event_id,
                 event_version,
@@ -93,6 +206,43 @@ impl Into<AccountEvent> for SQLAccountEvent {
                 password_hash,
                 created_at
             },
+            SQLAccountEvent::AccountSuspended {
+                id,
+                event_id,
+                event_version,
+                reason,
+                suspended_at,
+            } => AccountEvent::AccountSuspended {
+                id,
+                event_id,
+                event_version,
+                reason,
+                suspended_at,
+            },
+            SQLAccountEvent::AccountDeleted {
+                id,
+                event_id,
+                event_version,
+                deleted_at,
+            } => AccountEvent::AccountDeleted {
+                id,
+                event_id,
+                event_version,
+                deleted_at,
+            },
+            SQLAccountEvent::PasswordRehashed {
+                id,
+                event_id,
+                event_version,
+                password_hash,
+                rehashed_at,
+            } => AccountEvent::PasswordRehashed {
+                id,
+                event_id,
+                event_version,
+                password_hash,
+                rehashed_at,
+            }
         }
     }
 }
@@ -101,20 +251,26 @@ impl Into<AccountEvent> for SQLAccountEvent {
 pub struct SQLAccountAggregate {
     id: Option<String>,
     pub email: Option<String>,
-    pub password_hash: Option<String>,
+    #[serde(default)]
+    pub status: Option<String>,
+    pub password_hash: Option<PasswordHash>,
     #[serde(with = "ts_seconds_option")]
     pub created_at: Option<DateTime<Utc>>,
     last_event: Option<SQLAccountEvent>,
+    #[serde(default)]
+    applied_events: i32,
 }
 
-impl Into<AccountAggregate> for SQLAccountAggregate {
-    fn into(self) -> AccountAggregate {
+impl From<SQLAccountAggregate> for AccountAggregate {
+    fn from(value: SQLAccountAggregate) -> Self {
         return AccountAggregate {
-            id: self.id,
-            email: self.email,
-            password_hash: self.password_hash,
-            created_at: self.created_at,
-            last_event: self.last_event.map(|x| x.into()),
+            id: value.id,
+            email: value.email,
+            status: value.status,
+            password_hash: value.password_hash,
+            created_at: value.created_at,
+            last_event: value.last_event.map(|x| x.into()),
+            applied_events: value.applied_events,
             ..Default::default()
         };
     }
@@ -125,9 +281,206 @@ impl From<AccountAggregate> for SQLAccountAggregate {
         return SQLAccountAggregate {
             id: value.id,
             email: value.email,
+            status: value.status,
             password_hash: value.password_hash,
             created_at: value.created_at,
             last_event: value.last_event.map(|x| x.into()),
+            applied_events: value.applied_events,
+        };
+    }
+}
+#[derive(sqlx::FromRow, Debug)]
+pub struct SQLAccountEventRow {
+    pub aggregate_type: String,
+    pub aggregate_id: String,
+    pub sequence: String,
+    pub event_type: String,
+    pub event_version: String,
+    pub payload: String,
+    pub metadata: String,
+    pub timestamp: String,
+}
+
+impl SQLAccountEventRow {
+    /// Decrypts the payload, then upcasts it to the current event version.
+    pub fn into_envelope(
+        self,
+        upcasters: &UpcasterRegistry,
+        cipher: &FieldCipher,
+    ) -> Result<EventEnvelope<AccountAggregate>, anyhow::Error> {
+        let payload: Value = cipher.decrypt(
+            serde_json::from_str(&self.payload)?,
+            FieldBinding::event(&self.aggregate_id, &self.sequence),
+        )?;
+        let (_, payload) = upcasters.upcast(&self.event_type, &self.event_version, payload)?;
+        let event: SQLAccountEvent = serde_json::from_value(payload)?;
+        return Ok(EventEnvelope {
+            aggregate_id: self.aggregate_id,
+            aggregate_type: self.aggregate_type,
+            sequence: self.sequence,
+            payload: event.into(),
+            metadata: serde_json::from_str(&self.metadata)?,
+            timestamp: DateTime::parse_from_rfc3339(&self.timestamp)?.with_timezone(&Utc),
+        });
+    }
+
+    /// The row as the hash chain sees it, expecting a decrypted payload.
+    pub fn chain_link(&self) -> Result<ChainLink, anyhow::Error> {
+        return Ok(ChainLink {
+            aggregate_type: self.aggregate_type.clone(),
+            aggregate_id: self.aggregate_id.clone(),
+            sequence: self.sequence.clone(),
+            event_type: self.event_type.clone(),
+            event_version: self.event_version.clone(),
+            payload: self.payload.clone(),
+            metadata: self.metadata.clone(),
+            timestamp: DateTime::parse_from_rfc3339(&self.timestamp)?.with_timezone(&Utc),
+            hash: None,
+            prev_hash: None,
+        });
+    }
+}
+
+/// Portable form of an `account_events` row. The payload and metadata are
+/// kept as stored, without upcasting, so an exported stream replays exactly
+/// as it did at the source. The chain hashes travel with it, so the target
+/// can check the stream was not altered on the way.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SQLEventEnvelope {
+    pub aggregate_type: String,
+    pub aggregate_id: String,
+    pub sequence: String,
+    pub event_type: String,
+    pub event_version: String,
+    pub payload: Value,
+    pub metadata: Value,
+    pub timestamp: String,
+    pub hash: Option<String>,
+    pub prev_hash: Option<String>,
+}
+
+impl SQLEventEnvelope {
+    pub fn from_row(row: SQLAccountEventRow) -> Result<Self, anyhow::Error> {
+        return Ok(Self {
+            aggregate_type: row.aggregate_type,
+            aggregate_id: row.aggregate_id,
+            sequence: row.sequence,
+            event_type: row.event_type,
+            event_version: row.event_version,
+            payload: serde_json::from_str(&row.payload)?,
+            metadata: serde_json::from_str(&row.metadata)?,
+            timestamp: row.timestamp,
+            hash: None,
+            prev_hash: None,
+        });
+    }
+
+    pub fn into_row(self) -> SQLAccountEventRow {
+        return SQLAccountEventRow {
+            aggregate_type: self.aggregate_type,
+            aggregate_id: self.aggregate_id,
+            sequence: self.sequence,
+            event_type: self.event_type,
+            event_version: self.event_version,
+            payload: self.payload.to_string(),
+            metadata: self.metadata.to_string(),
+            timestamp: self.timestamp,
         };
     }
-}
\ No newline at end of file
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct SQLAccountSnapshotRow {
+    pub aggregate_type: String,
+    pub aggregate_id: String,
+    pub payload: String,
+    pub last_sequence: String,
+    pub snapshot_id: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl SQLAccountSnapshotRow {
+    pub fn into_snapshot(
+        self,
+        upcasters: &UpcasterRegistry,
+        cipher: &FieldCipher,
+    ) -> Result<AggregateSnapshot<AccountAggregate>, anyhow::Error> {
+        let payload = decode_aggregate(
+            &self.payload,
+            FieldBinding::snapshot(&self.aggregate_id, &self.snapshot_id),
+            upcasters,
+            cipher,
+        )?;
+        return Ok(AggregateSnapshot {
+            aggregate_id: self.aggregate_id,
+            aggregate_type: self.aggregate_type,
+            payload,
+            last_sequence: self.last_sequence,
+            snapshot_id: self.snapshot_id,
+            timestamp: self.timestamp,
+        });
+    }
+}
+
+/// Portable form of an `account_snapshots` row, see `SQLEventEnvelope`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SQLSnapshotEnvelope {
+    pub aggregate_type: String,
+    pub aggregate_id: String,
+    pub payload: Value,
+    pub last_sequence: String,
+    pub snapshot_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub schema_version: i64,
+}
+
+/// Decodes a stored `SQLAccountAggregate`, upcasting the embedded last event.
+pub fn decode_aggregate(
+    payload: &str,
+    binding: FieldBinding,
+    upcasters: &UpcasterRegistry,
+    cipher: &FieldCipher,
+) -> Result<AccountAggregate, anyhow::Error> {
+    let mut payload: Value = cipher.decrypt(serde_json::from_str(payload)?, binding)?;
+    if let Some(last_event) = payload.get_mut("last_event") {
+        if !last_event.is_null() {
+            *last_event = upcasters.upcast_tagged(last_event.take())?;
+        }
+    }
+    let aggregate: SQLAccountAggregate = serde_json::from_value(payload)?;
+    return Ok(aggregate.into());
+}
+
+/// Event row as read from Postgres, where the JSONB columns are selected as
+/// text and the timestamp is a native `TIMESTAMPTZ`.
+#[derive(sqlx::FromRow, Debug)]
+pub struct PostgresAccountEventRow {
+    pub aggregate_type: String,
+    pub aggregate_id: String,
+    pub sequence: String,
+    pub event_type: String,
+    pub event_version: String,
+    pub payload: String,
+    pub metadata: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl PostgresAccountEventRow {
+    pub fn into_envelope(
+        self,
+        upcasters: &UpcasterRegistry,
+        cipher: &FieldCipher,
+    ) -> Result<EventEnvelope<AccountAggregate>, anyhow::Error> {
+        let row = SQLAccountEventRow {
+            aggregate_type: self.aggregate_type,
+            aggregate_id: self.aggregate_id,
+            sequence: self.sequence,
+            event_type: self.event_type,
+            event_version: self.event_version,
+            payload: self.payload,
+            metadata: self.metadata,
+            timestamp: self.timestamp.to_rfc3339(),
+        };
+        return row.into_envelope(upcasters, cipher);
+    }
+}
@@ -1,6 +1,13 @@
-use crate::command::domain::account::entity::aggregate::AccountAggregate;
+use crate::{
+    command::domain::account::entity::aggregate::AccountAggregate, common::domain::secret::Password,
+};
 
-use async_graphql::{SimpleObject, InputObject};
+use std::borrow::Cow;
+
+use async_graphql::{
+    registry::Registry, InputObject, InputType, InputValueError, InputValueResult, OutputType,
+    SimpleObject, Value,
+};
 use chrono::{DateTime, Utc};
 use validator::Validate;
 
@@ -22,17 +29,55 @@ impl From<AccountAggregate> for GraphQLAccount {
     }
 }
 
-#[derive(Clone, InputObject, Validate)]
+#[derive(InputObject, Validate)]
 #[graphql(name = "CreateAccountInput")]
 pub struct GraphQLCreateAccountInput {
     #[validate(email)]
     pub email: String,
-    pub password: String
+    #[graphql(secret)]
+    pub password: Password
 }
 
 impl GraphQLCreateAccountInput {
-    pub fn new(email: String, password: String) -> Self {
+    pub fn new(email: String, password: Password) -> Self {
         return Self { email, password };
     }
 }
 
+#[derive(InputObject)]
+#[graphql(name = "VerifyCredentialsInput")]
+pub struct GraphQLVerifyCredentialsInput {
+    pub email: String,
+    #[graphql(secret)]
+    pub password: Password
+}
+
+/// Read straight into a `Password`, so the plain string never outlives the
+/// request parser. It is a `String` in the schema.
+impl InputType for Password {
+    type RawValueType = Self;
+
+    fn type_name() -> Cow<'static, str> {
+        return Cow::Borrowed("String");
+    }
+
+    fn create_type_info(registry: &mut Registry) -> String {
+        return <String as OutputType>::create_type_info(registry);
+    }
+
+    fn parse(value: Option<Value>) -> InputValueResult<Self> {
+        let value = value.unwrap_or_default();
+        return match value {
+            Value::String(x) => Ok(x.into()),
+            _ => Err(InputValueError::expected_type(value)),
+        };
+    }
+
+    fn to_value(&self) -> Value {
+        return Value::String(self.to_string());
+    }
+
+    fn as_raw_value(&self) -> Option<&Self::RawValueType> {
+        return Some(self);
+    }
+}
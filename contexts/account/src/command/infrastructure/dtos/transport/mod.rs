@@ -1,2 +1,2 @@
-pub mod nats;
-pub mod graphql;
\ No newline at end of file
+pub mod graphql;
+pub mod public;
\ No newline at end of file
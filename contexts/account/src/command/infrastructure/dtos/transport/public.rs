@@ -0,0 +1,128 @@
+use crate::command::{
+    application::account::context::{CAUSATION_ID, CORRELATION_ID, TRACEPARENT},
+    domain::account::entity::{aggregate::AccountAggregate, event::AccountEvent},
+};
+
+use std::collections::HashMap;
+
+use chrono::{serde::ts_seconds, DateTime, Utc};
+use cqrs_rs::domain::entity::event::EventEnvelope;
+use serde::{Deserialize, Serialize};
+
+/// Integration-event schema published to other services. It is mapped from
+/// `AccountEvent` field by field so that credential material never leaves
+/// the service, and internal-only events have no public form at all.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event_type")]
+pub enum PublicAccountEvent {
+    AccountCreated {
+        id: String,
+        event_id: String,
+        event_version: String,
+        email: String,
+        #[serde(with = "ts_seconds")]
+        created_at: DateTime<Utc>,
+    },
+    AccountSuspended {
+        id: String,
+        event_id: String,
+        event_version: String,
+        reason: String,
+        #[serde(with = "ts_seconds")]
+        suspended_at: DateTime<Utc>,
+    },
+    AccountDeleted {
+        id: String,
+        event_id: String,
+        event_version: String,
+        #[serde(with = "ts_seconds")]
+        deleted_at: DateTime<Utc>,
+    },
+}
+
+impl PublicAccountEvent {
+    /// Returns `None` for internal-only events. New events must be added
+    /// here, which forces a decision on what, if anything, they publish.
+    pub fn from_event(event: AccountEvent) -> Option<Self> {
+        return match event {
+            AccountEvent::AccountCreated {
+                id,
+                email,
+                created_at,
+                event_version,
+                event_id,
+                ..
+            } => Some(Self::AccountCreated {
+                id,
+                event_id,
+                event_version,
+                email,
+                created_at,
+            }),
+            AccountEvent::AccountSuspended {
+                id,
+                reason,
+                suspended_at,
+                event_version,
+                event_id,
+            } => Some(Self::AccountSuspended {
+                id,
+                event_id,
+                event_version,
+                reason,
+                suspended_at,
+            }),
+            AccountEvent::AccountDeleted {
+                id,
+                deleted_at,
+                event_version,
+                event_id,
+            } => Some(Self::AccountDeleted {
+                id,
+                event_id,
+                event_version,
+                deleted_at,
+            }),
+            AccountEvent::PasswordRehashed { .. } => None,
+        };
+    }
+}
+
+/// Whether the event has a public form, and so belongs in the outbox.
+pub fn is_published(event: &AccountEvent) -> bool {
+    return PublicAccountEvent::from_event(event.clone()).is_some();
+}
+
+/// The metadata keys other services may see. The rest, such as the actor,
+/// client ip, user agent and idempotency key, stays in the event store.
+pub const PUBLIC_METADATA: [&str; 3] = [CORRELATION_ID, CAUSATION_ID, TRACEPARENT];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PublicEventEnvelope {
+    pub aggregate_id: String,
+    pub aggregate_type: String,
+    pub sequence: String,
+    pub payload: PublicAccountEvent,
+    pub metadata: HashMap<String, String>,
+    #[serde(with = "ts_seconds")]
+    pub timestamp: DateTime<Utc>,
+}
+
+impl PublicEventEnvelope {
+    pub fn from_envelope(event: EventEnvelope<AccountAggregate>) -> Option<Self> {
+        let payload = PublicAccountEvent::from_event(event.payload)?;
+        let metadata = event
+            .metadata
+            .into_iter()
+            .filter(|(key, _)| PUBLIC_METADATA.contains(&key.as_str()))
+            .collect();
+        return Some(Self {
+            aggregate_id: event.aggregate_id,
+            aggregate_type: event.aggregate_type,
+            sequence: event.sequence,
+            payload,
+            metadata,
+            timestamp: event.timestamp,
+        });
+    }
+}
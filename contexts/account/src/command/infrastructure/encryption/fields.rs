@@ -0,0 +1,403 @@
+use crate::common::application::ports::outbound::key_provider::KeyProvider;
+
+use std::sync::Arc;
+
+use aes_gcm::{
+    aead::{generic_array::GenericArray, Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm,
+};
+use anyhow::anyhow;
+use hmac::{Hmac, Mac};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+use thiserror::Error;
+
+/// Payload fields that are encrypted wherever they appear, including inside
+/// a snapshot's `last_event`.
+pub const ENCRYPTED_FIELDS: [&str; 2] = ["email", "password_hash"];
+/// Written next to an encrypted `email` so it can still be looked up.
+pub const EMAIL_INDEX_FIELD: &str = "email_index";
+const ENVELOPE_PREFIX: &str = "enc:";
+/// Values sealed before they were bound to their record. They still open,
+/// and rotation reseals them bound.
+const UNBOUND_VERSION: &str = "v1";
+const BOUND_VERSION: &str = "v2";
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordKind {
+    /// An event, or its copy in the outbox.
+    Event,
+    Snapshot,
+    /// The aggregate recorded for an idempotency key.
+    Outcome,
+}
+
+impl RecordKind {
+    fn tag(&self) -> &'static str {
+        return match self {
+            Self::Event => "event",
+            Self::Snapshot => "snapshot",
+            Self::Outcome => "outcome",
+        };
+    }
+}
+
+/// The stored record a payload belongs to. Encrypted fields are bound to
+/// it, so a value copied into another record, even of the same account, no
+/// longer decrypts.
+#[derive(Debug, Clone, Copy)]
+pub struct FieldBinding<'a> {
+    pub kind: RecordKind,
+    pub aggregate_id: &'a str,
+    pub record_id: &'a str,
+}
+
+impl<'a> FieldBinding<'a> {
+    pub fn new(kind: RecordKind, aggregate_id: &'a str, record_id: &'a str) -> Self {
+        return Self {
+            kind,
+            aggregate_id,
+            record_id,
+        };
+    }
+
+    pub fn event(aggregate_id: &'a str, sequence: &'a str) -> Self {
+        return Self::new(RecordKind::Event, aggregate_id, sequence);
+    }
+
+    pub fn snapshot(aggregate_id: &'a str, snapshot_id: &'a str) -> Self {
+        return Self::new(RecordKind::Snapshot, aggregate_id, snapshot_id);
+    }
+
+    /// Outcomes are looked up by key alone, before their aggregate is known.
+    pub fn outcome(idempotency_key: &'a str) -> Self {
+        return Self::new(RecordKind::Outcome, "", idempotency_key);
+    }
+
+    /// Associated data of a value sealed in `field`, each part length
+    /// prefixed so no two bindings share it.
+    fn aad(&self, field: &str) -> Vec<u8> {
+        let mut aad = vec![];
+        for part in [field, self.kind.tag(), self.aggregate_id, self.record_id] {
+            aad.extend((part.len() as u64).to_be_bytes());
+            aad.extend(part.as_bytes());
+        }
+        return aad;
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum FieldCipherError {
+    #[error("field `{0}` is encrypted but no key provider is configured")]
+    NoKeyProvider(String),
+    #[error("field `{0}` does not hold a valid envelope")]
+    Malformed(String),
+    #[error("field `{0}` does not decrypt under key `{1}`")]
+    Decrypt(String, String),
+    #[error("encrypting field `{0}` failed")]
+    Encrypt(String),
+}
+
+/// Envelope encryption of the `ENCRYPTED_FIELDS` of stored payloads. Every
+/// value gets its own data key, which is sealed under a key of the
+/// `KeyProvider` and stored next to that key's id:
+///
+/// `enc:v2:<key id>:<sealed data key>:<sealed value>`
+///
+/// The value is sealed with its field and `FieldBinding` as associated data.
+/// Rotation only reseals the data keys. Without a key provider, payloads are
+/// written in plaintext; plaintext values are always read back as they are.
+#[derive(Debug, Clone, Default)]
+pub struct FieldCipher {
+    keys: Option<Arc<dyn KeyProvider + Sync + Send>>,
+}
+
+impl FieldCipher {
+    pub fn new(keys: Arc<dyn KeyProvider + Sync + Send>) -> Self {
+        return Self { keys: Some(keys) };
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.keys.is_some();
+    }
+
+    /// Encrypts plaintext fields under the current key and indexes emails.
+    pub fn encrypt(
+        &self,
+        mut payload: Value,
+        binding: FieldBinding,
+    ) -> Result<Value, anyhow::Error> {
+        let keys = match &self.keys {
+            Some(x) => x.as_ref(),
+            None => return Ok(payload),
+        };
+        let index_key = keys.index_key()?;
+        walk(&mut payload, &mut |map| {
+            for field in ENCRYPTED_FIELDS {
+                let plaintext = match map.get(field) {
+                    Some(Value::String(x)) if !is_envelope(x) => x.clone(),
+                    _ => continue,
+                };
+                encrypt_field(map, keys, &index_key, field, &plaintext, binding)?;
+            }
+            return Ok(());
+        })?;
+        return Ok(payload);
+    }
+
+    /// Returns the payload as it was before `encrypt`, dropping blind indexes.
+    /// Fails if it was encrypted for another record.
+    pub fn decrypt(
+        &self,
+        mut payload: Value,
+        binding: FieldBinding,
+    ) -> Result<Value, anyhow::Error> {
+        walk(&mut payload, &mut |map| {
+            map.remove(EMAIL_INDEX_FIELD);
+            for field in ENCRYPTED_FIELDS {
+                let value = match map.get(field) {
+                    Some(Value::String(x)) if is_envelope(x) => x.clone(),
+                    _ => continue,
+                };
+                let keys = match &self.keys {
+                    Some(x) => x.as_ref(),
+                    None => return Err(FieldCipherError::NoKeyProvider(field.into()).into()),
+                };
+                let plaintext = open(keys, field, &value, binding)?;
+                map.insert(field.into(), Value::String(plaintext));
+            }
+            return Ok(());
+        })?;
+        return Ok(payload);
+    }
+
+    /// Reseals values whose data key is sealed under a key other than the
+    /// current one, and encrypts values still in plaintext or not yet bound
+    /// to their record. Returns `None` when the payload is already up to date.
+    pub fn rotate(
+        &self,
+        mut payload: Value,
+        binding: FieldBinding,
+    ) -> Result<Option<Value>, anyhow::Error> {
+        let keys = match &self.keys {
+            Some(x) => x.as_ref(),
+            None => return Err(anyhow!("rotating keys needs a key provider")),
+        };
+        let current = keys.current_key_id();
+        let index_key = keys.index_key()?;
+        let mut changed = false;
+        walk(&mut payload, &mut |map| {
+            for field in ENCRYPTED_FIELDS {
+                let value = match map.get(field) {
+                    Some(Value::String(x)) => x.clone(),
+                    _ => continue,
+                };
+                if !is_envelope(&value) {
+                    encrypt_field(map, keys, &index_key, field, &value, binding)?;
+                    changed = true;
+                    continue;
+                }
+                let envelope = Envelope::parse(field, &value)?;
+                if !envelope.bound {
+                    let plaintext = open(keys, field, &value, binding)?;
+                    encrypt_field(map, keys, &index_key, field, &plaintext, binding)?;
+                    changed = true;
+                    continue;
+                }
+                if envelope.key_id != current {
+                    let data_key = envelope.open_key(keys, field)?;
+                    let resealed = Envelope::seal(keys, field, &data_key, envelope.sealed_value)?;
+                    map.insert(field.into(), Value::String(resealed.encode()));
+                    changed = true;
+                }
+            }
+            return Ok(());
+        })?;
+        if !changed {
+            return Ok(None);
+        }
+        return Ok(Some(payload));
+    }
+
+    /// Blind index of the email as stored next to it, or `None` when
+    /// encryption is off.
+    pub fn email_index(&self, email: &str) -> Result<Option<String>, anyhow::Error> {
+        return match &self.keys {
+            Some(x) => Ok(Some(blind_index(&x.index_key()?, email)?)),
+            None => Ok(None),
+        };
+    }
+}
+
+struct Envelope {
+    bound: bool,
+    key_id: String,
+    sealed_key: Vec<u8>,
+    sealed_value: Vec<u8>,
+}
+
+impl Envelope {
+    fn parse(field: &str, value: &str) -> Result<Self, FieldCipherError> {
+        let malformed = || FieldCipherError::Malformed(field.into());
+        let parts: Vec<&str> = value[ENVELOPE_PREFIX.len()..].split(':').collect();
+        if parts.len() != 4 || parts[1].is_empty() {
+            return Err(malformed());
+        }
+        let bound = match parts[0] {
+            BOUND_VERSION => true,
+            UNBOUND_VERSION => false,
+            _ => return Err(malformed()),
+        };
+        return Ok(Self {
+            bound,
+            key_id: parts[1].into(),
+            sealed_key: hex::decode(parts[2]).map_err(|_| malformed())?,
+            sealed_value: hex::decode(parts[3]).map_err(|_| malformed())?,
+        });
+    }
+
+    /// Seals `data_key` under the current key.
+    fn seal(
+        keys: &dyn KeyProvider,
+        field: &str,
+        data_key: &[u8],
+        sealed_value: Vec<u8>,
+    ) -> Result<Self, anyhow::Error> {
+        let key_id = keys.current_key_id();
+        let sealed_key = seal_bytes(&aead(&keys.key(&key_id)?)?, data_key, key_id.as_bytes())
+            .map_err(|_| FieldCipherError::Encrypt(field.into()))?;
+        return Ok(Self {
+            bound: true,
+            key_id,
+            sealed_key,
+            sealed_value,
+        });
+    }
+
+    fn open_key(&self, keys: &dyn KeyProvider, field: &str) -> Result<Vec<u8>, anyhow::Error> {
+        let key = aead(&keys.key(&self.key_id)?)?;
+        return open_bytes(&key, &self.sealed_key, self.key_id.as_bytes())
+            .map_err(|_| FieldCipherError::Decrypt(field.into(), self.key_id.clone()).into());
+    }
+
+    /// Only bound envelopes are ever written.
+    fn encode(&self) -> String {
+        return format!(
+            "{}{}:{}:{}:{}",
+            ENVELOPE_PREFIX,
+            BOUND_VERSION,
+            self.key_id,
+            hex::encode(&self.sealed_key),
+            hex::encode(&self.sealed_value)
+        );
+    }
+}
+
+fn encrypt_field(
+    map: &mut Map<String, Value>,
+    keys: &dyn KeyProvider,
+    index_key: &[u8],
+    field: &str,
+    plaintext: &str,
+    binding: FieldBinding,
+) -> Result<(), anyhow::Error> {
+    if field == "email" {
+        map.insert(
+            EMAIL_INDEX_FIELD.into(),
+            Value::String(blind_index(index_key, plaintext)?),
+        );
+    }
+    let data_key = Aes256Gcm::generate_key(&mut OsRng);
+    let sealed_value = seal_bytes(&aead(&data_key)?, plaintext.as_bytes(), &binding.aad(field))
+        .map_err(|_| FieldCipherError::Encrypt(field.into()))?;
+    let envelope = Envelope::seal(keys, field, &data_key, sealed_value)?;
+    map.insert(field.into(), Value::String(envelope.encode()));
+    return Ok(());
+}
+
+fn open(
+    keys: &dyn KeyProvider,
+    field: &str,
+    value: &str,
+    binding: FieldBinding,
+) -> Result<String, anyhow::Error> {
+    let envelope = Envelope::parse(field, value)?;
+    let data_key = envelope.open_key(keys, field)?;
+    let aad = match envelope.bound {
+        true => binding.aad(field),
+        false => field.as_bytes().to_vec(),
+    };
+    let plaintext = open_bytes(&aead(&data_key)?, &envelope.sealed_value, &aad)
+        .map_err(|_| FieldCipherError::Decrypt(field.into(), envelope.key_id.clone()))?;
+    let plaintext =
+        String::from_utf8(plaintext).map_err(|_| FieldCipherError::Malformed(field.into()))?;
+    return Ok(plaintext);
+}
+
+/// Calls `f` on every object in `value`, outermost first.
+fn walk<F>(value: &mut Value, f: &mut F) -> Result<(), anyhow::Error>
+where
+    F: FnMut(&mut Map<String, Value>) -> Result<(), anyhow::Error>,
+{
+    match value {
+        Value::Object(map) => {
+            f(map)?;
+            for (_, x) in map.iter_mut() {
+                walk(x, f)?;
+            }
+        }
+        Value::Array(items) => {
+            for x in items.iter_mut() {
+                walk(x, f)?;
+            }
+        }
+        _ => {}
+    }
+    return Ok(());
+}
+
+fn is_envelope(value: &str) -> bool {
+    return [BOUND_VERSION, UNBOUND_VERSION]
+        .iter()
+        .any(|x| value.starts_with(&format!("{}{}:", ENVELOPE_PREFIX, x)));
+}
+
+fn blind_index(key: &[u8], value: &str) -> Result<String, anyhow::Error> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key)
+        .map_err(|_| anyhow!("index key has an invalid length"))?;
+    mac.update(value.as_bytes());
+    return Ok(hex::encode(mac.finalize().into_bytes()));
+}
+
+fn aead(key: &[u8]) -> Result<Aes256Gcm, anyhow::Error> {
+    return Aes256Gcm::new_from_slice(key).map_err(|_| anyhow!("key has an invalid length"));
+}
+
+/// Encrypts under a fresh nonce, which is prepended to the ciphertext.
+fn seal_bytes(key: &Aes256Gcm, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, aes_gcm::Error> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let mut sealed = nonce.to_vec();
+    sealed.extend(key.encrypt(
+        &nonce,
+        Payload {
+            msg: plaintext,
+            aad,
+        },
+    )?);
+    return Ok(sealed);
+}
+
+fn open_bytes(key: &Aes256Gcm, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, aes_gcm::Error> {
+    if sealed.len() < NONCE_LENGTH {
+        return Err(aes_gcm::Error);
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+    return key.decrypt(
+        GenericArray::from_slice(nonce),
+        Payload {
+            msg: ciphertext,
+            aad,
+        },
+    );
+}
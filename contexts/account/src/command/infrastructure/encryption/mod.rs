@@ -0,0 +1,4 @@
+pub mod fields;
+pub mod postgres;
+pub mod rotation;
+pub mod sqlite;
\ No newline at end of file
@@ -0,0 +1,107 @@
+use crate::command::infrastructure::encryption::{
+    fields::{FieldBinding, FieldCipher},
+    rotation::{EncryptedColumn, KeyRotation, RotationReport, ENCRYPTED_COLUMNS},
+};
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use sqlx::{PgPool, Postgres, Row};
+use tracing::{span, Instrument};
+
+/// `KeyRotation` of a Postgres store, paging through each table by the id
+/// its fields are bound to.
+pub struct PostgresKeyRotation {
+    pool: PgPool,
+    cipher: Arc<FieldCipher>,
+    batch_size: i64,
+}
+
+impl PostgresKeyRotation {
+    pub fn new(pool: PgPool, cipher: FieldCipher) -> Self {
+        return Self {
+            pool,
+            cipher: Arc::new(cipher),
+            batch_size: 500,
+        };
+    }
+
+    pub fn with_batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        return self;
+    }
+
+    async fn rotate_column(
+        &self,
+        encrypted: &EncryptedColumn,
+        report: &mut RotationReport,
+    ) -> Result<(), anyhow::Error> {
+        let select = format!(
+            "SELECT {column}::text, {aggregate_id}, {record_id} FROM {table} WHERE {record_id} > $1 AND {column} IS NOT NULL ORDER BY {record_id} ASC LIMIT $2",
+            column = encrypted.column,
+            aggregate_id = encrypted.aggregate_id_expression(),
+            record_id = encrypted.record_id,
+            table = encrypted.table
+        );
+        let update = format!(
+            "UPDATE {table} SET {column} = $1::jsonb WHERE {record_id} = $2 AND {column} = $3::jsonb",
+            column = encrypted.column,
+            record_id = encrypted.record_id,
+            table = encrypted.table
+        );
+        let mut last_record_id = String::new();
+        loop {
+            let rows = sqlx::query::<Postgres>(&select)
+                .bind(&last_record_id)
+                .bind(self.batch_size)
+                .fetch_all(&self.pool)
+                .await?;
+            if rows.is_empty() {
+                return Ok(());
+            }
+            let mut tx = self.pool.begin().await?;
+            for row in rows {
+                let stored: String = row.get(0);
+                let aggregate_id: String = row.get(1);
+                last_record_id = row.get(2);
+                report.scanned += 1;
+                let binding = FieldBinding::new(encrypted.kind, &aggregate_id, &last_record_id);
+                let rotated = match self
+                    .cipher
+                    .rotate(serde_json::from_str(&stored)?, binding)?
+                {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let result = sqlx::query::<Postgres>(&update)
+                    .bind(rotated.to_string())
+                    .bind(&last_record_id)
+                    .bind(&stored)
+                    .execute(&mut tx)
+                    .await?;
+                report.rotated += result.rows_affected() as i64;
+            }
+            tx.commit().await?;
+        }
+    }
+}
+
+#[async_trait]
+impl KeyRotation for PostgresKeyRotation {
+    async fn run(&self) -> Result<RotationReport, anyhow::Error> {
+        let root = span!(tracing::Level::INFO, "run", target = "PostgresKeyRotation");
+        async {
+            if !self.cipher.is_enabled() {
+                return Err(anyhow!("rotating keys needs a key provider"));
+            }
+            let mut report = RotationReport::default();
+            for encrypted in ENCRYPTED_COLUMNS.iter() {
+                self.rotate_column(encrypted, &mut report).await?;
+            }
+            return Ok(report);
+        }
+        .instrument(root)
+        .await
+    }
+}
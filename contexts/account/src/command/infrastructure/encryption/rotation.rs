@@ -0,0 +1,76 @@
+use crate::command::infrastructure::encryption::fields::RecordKind;
+
+use async_trait::async_trait;
+
+/// A column holding payloads with encrypted fields, along with the columns
+/// naming the record those fields are bound to.
+pub(crate) struct EncryptedColumn {
+    pub table: &'static str,
+    pub column: &'static str,
+    pub kind: RecordKind,
+    /// `None` for records that do not belong to an aggregate.
+    pub aggregate_id: Option<&'static str>,
+    /// Unique per row.
+    pub record_id: &'static str,
+}
+
+impl EncryptedColumn {
+    /// Selects the aggregate id, or an empty string for records without one.
+    pub fn aggregate_id_expression(&self) -> &'static str {
+        return self.aggregate_id.unwrap_or("''");
+    }
+}
+
+pub(crate) const ENCRYPTED_COLUMNS: [EncryptedColumn; 5] = [
+    EncryptedColumn {
+        table: "account_events",
+        column: "payload",
+        kind: RecordKind::Event,
+        aggregate_id: Some("aggregate_id"),
+        record_id: "sequence",
+    },
+    EncryptedColumn {
+        table: "account_outbox_events",
+        column: "payload",
+        kind: RecordKind::Event,
+        aggregate_id: Some("aggregate_id"),
+        record_id: "sequence",
+    },
+    EncryptedColumn {
+        table: "account_outbox_dead_letters",
+        column: "payload",
+        kind: RecordKind::Event,
+        aggregate_id: Some("aggregate_id"),
+        record_id: "sequence",
+    },
+    EncryptedColumn {
+        table: "account_snapshots",
+        column: "payload",
+        kind: RecordKind::Snapshot,
+        aggregate_id: Some("aggregate_id"),
+        record_id: "snapshot_id",
+    },
+    EncryptedColumn {
+        table: "account_idempotency_keys",
+        column: "outcome",
+        kind: RecordKind::Outcome,
+        aggregate_id: None,
+        record_id: "idempotency_key",
+    },
+];
+
+#[derive(Debug, Default)]
+pub struct RotationReport {
+    pub scanned: i64,
+    pub rotated: i64,
+}
+
+/// Re-encrypts every payload onto the key provider's current key, including
+/// fields still stored in plaintext or not yet bound to their record. Rows
+/// that are up to date are left alone and rows are only replaced if they did
+/// not change in the meantime, so the job can run next to live writers and
+/// be restarted at any point.
+#[async_trait]
+pub trait KeyRotation {
+    async fn run(&self) -> Result<RotationReport, anyhow::Error>;
+}
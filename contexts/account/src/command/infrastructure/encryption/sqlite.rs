@@ -0,0 +1,107 @@
+use crate::command::infrastructure::encryption::{
+    fields::{FieldBinding, FieldCipher},
+    rotation::{EncryptedColumn, KeyRotation, RotationReport, ENCRYPTED_COLUMNS},
+};
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use cqrs_rs::infrastructure::adapter::secondary::storage::sqlite::SqliteConnector;
+use sqlx::{Row, Sqlite};
+use tracing::{span, Instrument};
+
+/// `KeyRotation` of a SQLite store, paging through each table by rowid.
+pub struct SQLiteKeyRotation {
+    connector: Arc<SqliteConnector>,
+    cipher: Arc<FieldCipher>,
+    batch_size: i64,
+}
+
+impl SQLiteKeyRotation {
+    pub fn new(connector: Arc<SqliteConnector>, cipher: FieldCipher) -> Self {
+        return Self {
+            connector,
+            cipher: Arc::new(cipher),
+            batch_size: 500,
+        };
+    }
+
+    pub fn with_batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        return self;
+    }
+
+    async fn rotate_column(
+        &self,
+        encrypted: &EncryptedColumn,
+        report: &mut RotationReport,
+    ) -> Result<(), anyhow::Error> {
+        let select = format!(
+            "SELECT rowid, {column}, {aggregate_id}, {record_id} FROM {table} WHERE rowid > ?1 AND {column} IS NOT NULL ORDER BY rowid ASC LIMIT ?2",
+            column = encrypted.column,
+            aggregate_id = encrypted.aggregate_id_expression(),
+            record_id = encrypted.record_id,
+            table = encrypted.table
+        );
+        let update = format!(
+            "UPDATE {table} SET {column} = ?1 WHERE rowid = ?2 AND {column} = ?3",
+            column = encrypted.column,
+            table = encrypted.table
+        );
+        let mut last_rowid: i64 = 0;
+        loop {
+            let rows = sqlx::query::<Sqlite>(&select)
+                .bind(last_rowid)
+                .bind(self.batch_size)
+                .fetch_all(&self.connector.pool)
+                .await?;
+            if rows.is_empty() {
+                return Ok(());
+            }
+            let mut tx = self.connector.pool.begin().await?;
+            for row in rows {
+                last_rowid = row.get(0);
+                let stored: String = row.get(1);
+                let aggregate_id: String = row.get(2);
+                let record_id: String = row.get(3);
+                report.scanned += 1;
+                let binding = FieldBinding::new(encrypted.kind, &aggregate_id, &record_id);
+                let rotated = match self
+                    .cipher
+                    .rotate(serde_json::from_str(&stored)?, binding)?
+                {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let result = sqlx::query::<Sqlite>(&update)
+                    .bind(rotated.to_string())
+                    .bind(last_rowid)
+                    .bind(&stored)
+                    .execute(&mut tx)
+                    .await?;
+                report.rotated += result.rows_affected() as i64;
+            }
+            tx.commit().await?;
+        }
+    }
+}
+
+#[async_trait]
+impl KeyRotation for SQLiteKeyRotation {
+    async fn run(&self) -> Result<RotationReport, anyhow::Error> {
+        let root = span!(tracing::Level::INFO, "run", target = "SQLiteKeyRotation");
+        async {
+            if !self.cipher.is_enabled() {
+                return Err(anyhow!("rotating keys needs a key provider"));
+            }
+            let mut report = RotationReport::default();
+            for encrypted in ENCRYPTED_COLUMNS.iter() {
+                self.rotate_column(encrypted, &mut report).await?;
+            }
+            return Ok(report);
+        }
+        .instrument(root)
+        .await
+    }
+}
@@ -1,2 +1,6 @@
 pub mod adapters;
-pub mod dtos;
\ No newline at end of file
+pub mod dtos;
+pub mod encryption;
+pub mod migration;
+pub mod transfer;
+pub mod upcasting;
\ No newline at end of file
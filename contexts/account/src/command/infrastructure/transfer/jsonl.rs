@@ -0,0 +1,120 @@
+use crate::command::infrastructure::dtos::storage::sql::{SQLEventEnvelope, SQLSnapshotEnvelope};
+
+use std::io::{BufRead, Write};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const EXPORT_FORMAT: &str = "account-events";
+/// Version of the export layout. Bump it whenever a record's shape changes.
+pub const EXPORT_VERSION: i64 = 2;
+
+/// First line of every export.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportHeader {
+    pub format: String,
+    pub version: i64,
+    pub exported_at: DateTime<Utc>,
+    pub filter: ExportFilter,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ExportFilter {
+    pub aggregate_id: Option<String>,
+    /// Inclusive lower bound on the event or snapshot timestamp.
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound on the event or snapshot timestamp.
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl ExportFilter {
+    pub fn matches(&self, timestamp: &DateTime<Utc>) -> bool {
+        if let Some(x) = &self.from {
+            if timestamp < x {
+                return false;
+            }
+        }
+        if let Some(x) = &self.to {
+            if timestamp >= x {
+                return false;
+            }
+        }
+        return true;
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "record", rename_all = "snake_case")]
+pub enum ExportRecord {
+    Header(ExportHeader),
+    Event(SQLEventEnvelope),
+    Snapshot(SQLSnapshotEnvelope),
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct TransferReport {
+    pub events: i64,
+    pub snapshots: i64,
+    pub aggregates: i64,
+    pub dry_run: bool,
+}
+
+pub fn write_record<W: Write>(writer: &mut W, record: &ExportRecord) -> Result<(), anyhow::Error> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")?;
+    return Ok(());
+}
+
+/// Parsed export, with the line number of every record for error reporting.
+pub struct ExportContents {
+    pub header: ExportHeader,
+    pub events: Vec<(usize, SQLEventEnvelope)>,
+    pub snapshots: Vec<(usize, SQLSnapshotEnvelope)>,
+}
+
+/// Reads a whole export, rejecting unknown formats and versions and any
+/// header that is not on the first line.
+pub fn read_export<R: BufRead>(reader: R) -> Result<ExportContents, anyhow::Error> {
+    let mut header: Option<ExportHeader> = None;
+    let mut events = vec![];
+    let mut snapshots = vec![];
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: ExportRecord = serde_json::from_str(&line)
+            .map_err(|e| anyhow!("line {}: invalid record: {}", line_number, e))?;
+        match (record, &header) {
+            (ExportRecord::Header(x), None) => {
+                if x.format != EXPORT_FORMAT {
+                    return Err(anyhow!("unknown export format `{}`", x.format));
+                }
+                if x.version != EXPORT_VERSION {
+                    return Err(anyhow!(
+                        "unsupported export version {}, expected {}",
+                        x.version,
+                        EXPORT_VERSION
+                    ));
+                }
+                header = Some(x);
+            }
+            (_, None) => return Err(anyhow!("line {}: export header missing", line_number)),
+            (ExportRecord::Header(_), Some(_)) => {
+                return Err(anyhow!("line {}: unexpected second header", line_number))
+            }
+            (ExportRecord::Event(x), Some(_)) => events.push((line_number, x)),
+            (ExportRecord::Snapshot(x), Some(_)) => snapshots.push((line_number, x)),
+        }
+    }
+    return match header {
+        Some(header) => Ok(ExportContents {
+            header,
+            events,
+            snapshots,
+        }),
+        None => Err(anyhow!("export is empty")),
+    };
+}
@@ -0,0 +1,464 @@
+use crate::{
+    command::{
+        application::account::{chain::check_link, ports::outbound::chain::ChainLink},
+        domain::account::entity::event::AccountEvent,
+        infrastructure::{
+            dtos::storage::sql::{SQLAccountEventRow, SQLEventEnvelope, SQLSnapshotEnvelope},
+            encryption::fields::{FieldBinding, FieldCipher},
+            transfer::jsonl::{
+                read_export, write_record, ExportFilter, ExportHeader, ExportRecord,
+                TransferReport, EXPORT_FORMAT, EXPORT_VERSION,
+            },
+            upcasting::{account::account_upcasters, registry::UpcasterRegistry},
+        },
+    },
+    common::domain::secret::SecretToken,
+};
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufRead, Write},
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use cqrs_rs::infrastructure::adapter::secondary::storage::sqlite::SqliteConnector;
+use sqlx::{FromRow, Row, Sqlite};
+use tracing::span;
+
+const EVENT_TABLE_NAME: &str = "account_events";
+const SNAPSHOT_TABLE_NAME: &str = "account_snapshots";
+const EVENT_FIELDS: [&str; 8] = [
+    "aggregate_type",
+    "aggregate_id",
+    "sequence",
+    "event_type",
+    "event_version",
+    "payload",
+    "metadata",
+    "timestamp",
+];
+const SNAPSHOT_FIELDS: [&str; 7] = [
+    "aggregate_type",
+    "aggregate_id",
+    "payload",
+    "last_sequence",
+    "snapshot_id",
+    "timestamp",
+    "schema_version",
+];
+
+/// Writes `account_events` in global order, followed by `account_snapshots`,
+/// as JSON Lines behind an `ExportHeader`. Payloads are written as stored, so
+/// encrypted fields stay encrypted, along with each event's chain hashes.
+pub struct SQLiteEventExport {
+    connector: Arc<SqliteConnector>,
+    filter: ExportFilter,
+    batch_size: i64,
+}
+
+impl SQLiteEventExport {
+    pub fn new(connector: Arc<SqliteConnector>) -> Self {
+        return Self {
+            connector,
+            filter: ExportFilter::default(),
+            batch_size: 500,
+        };
+    }
+
+    pub fn with_filter(mut self, filter: ExportFilter) -> Self {
+        self.filter = filter;
+        return self;
+    }
+
+    pub fn with_batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        return self;
+    }
+
+    pub async fn run<W: Write + Send>(
+        &self,
+        writer: &mut W,
+    ) -> Result<TransferReport, anyhow::Error> {
+        let root = span!(tracing::Level::INFO, "run", target = "SQLiteEventExport");
+        let _enter = root.enter();
+        let mut report = TransferReport::default();
+        write_record(
+            writer,
+            &ExportRecord::Header(ExportHeader {
+                format: EXPORT_FORMAT.into(),
+                version: EXPORT_VERSION,
+                exported_at: Utc::now(),
+                filter: self.filter.clone(),
+            }),
+        )?;
+        let select = format!(
+            "SELECT position, hash, prev_hash, {} FROM {} WHERE position > ?1 AND (?2 IS NULL OR aggregate_id = ?2) ORDER BY position ASC LIMIT ?3",
+            EVENT_FIELDS.join(", "),
+            EVENT_TABLE_NAME
+        );
+        let mut aggregates: HashSet<String> = HashSet::new();
+        let mut position: i64 = 0;
+        loop {
+            let rows = sqlx::query::<Sqlite>(&select)
+                .bind(position)
+                .bind(&self.filter.aggregate_id)
+                .bind(self.batch_size)
+                .fetch_all(&self.connector.pool)
+                .await?;
+            if rows.is_empty() {
+                break;
+            }
+            for row in rows {
+                position = row.get("position");
+                let mut event = SQLEventEnvelope::from_row(SQLAccountEventRow::from_row(&row)?)?;
+                event.hash = row.get("hash");
+                event.prev_hash = row.get("prev_hash");
+                let timestamp =
+                    DateTime::parse_from_rfc3339(&event.timestamp)?.with_timezone(&Utc);
+                if !self.filter.matches(&timestamp) {
+                    continue;
+                }
+                aggregates.insert(event.aggregate_id.clone());
+                write_record(writer, &ExportRecord::Event(event))?;
+                report.events += 1;
+            }
+        }
+        let select = format!(
+            "SELECT {} FROM {} WHERE (?1 IS NULL OR aggregate_id = ?1) ORDER BY snapshot_id ASC",
+            SNAPSHOT_FIELDS.join(", "),
+            SNAPSHOT_TABLE_NAME
+        );
+        let rows = sqlx::query::<Sqlite>(&select)
+            .bind(&self.filter.aggregate_id)
+            .fetch_all(&self.connector.pool)
+            .await?;
+        for row in rows {
+            let payload: String = row.get("payload");
+            let snapshot = SQLSnapshotEnvelope {
+                aggregate_type: row.get("aggregate_type"),
+                aggregate_id: row.get("aggregate_id"),
+                payload: serde_json::from_str(&payload)?,
+                last_sequence: row.get("last_sequence"),
+                snapshot_id: row.get("snapshot_id"),
+                timestamp: row.get("timestamp"),
+                schema_version: row.get("schema_version"),
+            };
+            if !self.filter.matches(&snapshot.timestamp) {
+                continue;
+            }
+            write_record(writer, &ExportRecord::Snapshot(snapshot))?;
+            report.snapshots += 1;
+        }
+        writer.flush()?;
+        report.aggregates = aggregates.len() as i64;
+        return Ok(report);
+    }
+}
+
+/// Appends an export to `account_events` and `account_snapshots`. The whole
+/// file is validated first: every event has to decode, sequences have to be
+/// unique and ascending per aggregate, and each stream has to continue after
+/// whatever the store already holds for that aggregate, or start with its
+/// `AccountCreated` event. The exported hash chains have to verify from the
+/// stored head of each stream, so an unhashed source has to backfill its
+/// chain before exporting. Nothing is written unless all of it passes, and
+/// nothing at all in dry-run mode.
+///
+/// Imported events are history, so they are not queued in the outbox.
+/// Encrypted fields are imported as they are, so the cipher needs the keys
+/// they were sealed under.
+pub struct SQLiteEventImport {
+    connector: Arc<SqliteConnector>,
+    upcasters: Arc<UpcasterRegistry>,
+    cipher: Arc<FieldCipher>,
+    chain_key: Arc<SecretToken>,
+    dry_run: bool,
+}
+
+impl SQLiteEventImport {
+    pub fn new(connector: Arc<SqliteConnector>) -> Self {
+        return Self {
+            connector,
+            upcasters: Arc::new(account_upcasters()),
+            cipher: Arc::new(FieldCipher::default()),
+            chain_key: Arc::new(SecretToken::default()),
+            dry_run: false,
+        };
+    }
+
+    pub fn with_upcasters(mut self, upcasters: UpcasterRegistry) -> Self {
+        self.upcasters = Arc::new(upcasters);
+        return self;
+    }
+
+    pub fn with_cipher(mut self, cipher: FieldCipher) -> Self {
+        self.cipher = Arc::new(cipher);
+        return self;
+    }
+
+    /// Key the source chained its events with, checked on every event.
+    pub fn with_chain_key(mut self, key: Arc<SecretToken>) -> Self {
+        self.chain_key = key;
+        return self;
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        return self;
+    }
+
+    pub async fn run<R: BufRead + Send>(
+        &self,
+        reader: R,
+    ) -> Result<TransferReport, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "run",
+            target = "SQLiteEventImport",
+            dry_run = self.dry_run
+        );
+        let _enter = root.enter();
+        let contents = read_export(reader)?;
+        let aggregates = self.validate_events(&contents.events).await?;
+        self.validate_snapshots(&contents.events, &contents.snapshots)
+            .await?;
+        let report = TransferReport {
+            events: contents.events.len() as i64,
+            snapshots: contents.snapshots.len() as i64,
+            aggregates,
+            dry_run: self.dry_run,
+        };
+        if self.dry_run {
+            return Ok(report);
+        }
+        let placeholders: Vec<String> = (0..EVENT_FIELDS.len())
+            .map(|x| format!("?{}", x + 1))
+            .collect();
+        // The exported hashes are kept as they are, having been verified
+        // against the target's streams.
+        let insert_event = format!(
+            "INSERT INTO {table} ({}, hash, prev_hash, position, version) VALUES ( {}, ?{}, ?{}, (SELECT COALESCE(MAX(position), 0) + 1 FROM {table}), (SELECT COALESCE(MAX(version), 0) + 1 FROM {table} WHERE aggregate_id = ?2) )",
+            EVENT_FIELDS.join(", "),
+            placeholders.join(", "),
+            EVENT_FIELDS.len() + 1,
+            EVENT_FIELDS.len() + 2,
+            table = EVENT_TABLE_NAME
+        );
+        let placeholders: Vec<String> = (0..SNAPSHOT_FIELDS.len())
+            .map(|x| format!("?{}", x + 1))
+            .collect();
+        let insert_snapshot = format!(
+            "INSERT INTO {} ({}) VALUES ( {} )",
+            SNAPSHOT_TABLE_NAME,
+            SNAPSHOT_FIELDS.join(", "),
+            placeholders.join(", ")
+        );
+        let mut tx = self.connector.pool.begin().await?;
+        for (_, event) in contents.events {
+            let (hash, prev_hash) = (event.hash.clone(), event.prev_hash.clone());
+            let row = event.into_row();
+            sqlx::query::<Sqlite>(&insert_event)
+                .bind(row.aggregate_type)
+                .bind(row.aggregate_id)
+                .bind(row.sequence)
+                .bind(row.event_type)
+                .bind(row.event_version)
+                .bind(row.payload)
+                .bind(row.metadata)
+                .bind(row.timestamp)
+                .bind(hash)
+                .bind(prev_hash)
+                .execute(&mut tx)
+                .await?;
+        }
+        for (_, snapshot) in contents.snapshots {
+            sqlx::query::<Sqlite>(&insert_snapshot)
+                .bind(snapshot.aggregate_type)
+                .bind(snapshot.aggregate_id)
+                .bind(snapshot.payload.to_string())
+                .bind(snapshot.last_sequence)
+                .bind(snapshot.snapshot_id)
+                .bind(snapshot.timestamp)
+                .bind(snapshot.schema_version)
+                .execute(&mut tx)
+                .await?;
+        }
+        tx.commit().await?;
+        return Ok(report);
+    }
+
+    /// Returns the number of aggregates the events belong to.
+    async fn validate_events(
+        &self,
+        events: &[(usize, SQLEventEnvelope)],
+    ) -> Result<i64, anyhow::Error> {
+        let mut sequences: HashSet<&String> = HashSet::new();
+        // Last sequence and hash of every aggregate seen so far.
+        let mut heads: HashMap<&String, (&String, Option<String>)> = HashMap::new();
+        let exists_query = format!("SELECT 1 FROM {} WHERE sequence = ?1", EVENT_TABLE_NAME);
+        let head_query = format!(
+            "SELECT sequence, hash FROM {} WHERE aggregate_id = ?1 ORDER BY sequence DESC LIMIT 1",
+            EVENT_TABLE_NAME
+        );
+        for (line, event) in events.iter() {
+            let decoded = event
+                .clone()
+                .into_row()
+                .into_envelope(&self.upcasters, &self.cipher)
+                .map_err(|e| {
+                    anyhow!(
+                        "line {}: event `{}` does not decode: {}",
+                        line,
+                        event.sequence,
+                        e
+                    )
+                })?;
+            if !sequences.insert(&event.sequence) {
+                return Err(anyhow!(
+                    "line {}: duplicate event `{}`",
+                    line,
+                    event.sequence
+                ));
+            }
+            let exists = sqlx::query::<Sqlite>(&exists_query)
+                .bind(&event.sequence)
+                .fetch_optional(&self.connector.pool)
+                .await?;
+            if exists.is_some() {
+                return Err(anyhow!(
+                    "line {}: event `{}` is already stored",
+                    line,
+                    event.sequence
+                ));
+            }
+            let prev_hash = match heads.get(&event.aggregate_id) {
+                Some((x, _)) if *x >= &event.sequence => {
+                    return Err(anyhow!(
+                        "line {}: event `{}` of aggregate `{}` is out of order",
+                        line,
+                        event.sequence,
+                        event.aggregate_id
+                    ))
+                }
+                Some((_, hash)) => hash.clone(),
+                None => {
+                    let stored = sqlx::query::<Sqlite>(&head_query)
+                        .bind(&event.aggregate_id)
+                        .fetch_optional(&self.connector.pool)
+                        .await?;
+                    match stored {
+                        Some(x) => {
+                            let sequence: String = x.get("sequence");
+                            if sequence >= event.sequence {
+                                return Err(anyhow!(
+                                    "line {}: event `{}` of aggregate `{}` does not follow the stored event `{}`",
+                                    line,
+                                    event.sequence,
+                                    event.aggregate_id,
+                                    sequence
+                                ));
+                            }
+                            x.get("hash")
+                        }
+                        // A partial stream, such as one cut by a time filter,
+                        // can only continue one the store already holds.
+                        None if !matches!(decoded.payload, AccountEvent::AccountCreated { .. }) => {
+                            return Err(anyhow!(
+                                "line {}: aggregate `{}` is not stored and its stream starts at `{}` rather than its creation",
+                                line,
+                                event.aggregate_id,
+                                event.sequence
+                            ))
+                        }
+                        None => None,
+                    }
+                }
+            };
+            let link = ChainLink {
+                aggregate_type: event.aggregate_type.clone(),
+                aggregate_id: event.aggregate_id.clone(),
+                sequence: event.sequence.clone(),
+                event_type: event.event_type.clone(),
+                event_version: event.event_version.clone(),
+                payload: self
+                    .cipher
+                    .decrypt(
+                        event.payload.clone(),
+                        FieldBinding::event(&event.aggregate_id, &event.sequence),
+                    )?
+                    .to_string(),
+                metadata: event.metadata.to_string(),
+                timestamp: DateTime::parse_from_rfc3339(&event.timestamp)?.with_timezone(&Utc),
+                hash: event.hash.clone(),
+                prev_hash: event.prev_hash.clone(),
+            };
+            if let Some(reason) = check_link(&self.chain_key, &link, prev_hash.as_deref())? {
+                return Err(anyhow!(
+                    "line {}: event `{}` breaks the hash chain of aggregate `{}`: {:?}",
+                    line,
+                    event.sequence,
+                    event.aggregate_id,
+                    reason
+                ));
+            }
+            heads.insert(&event.aggregate_id, (&event.sequence, event.hash.clone()));
+        }
+        return Ok(heads.len() as i64);
+    }
+
+    async fn validate_snapshots(
+        &self,
+        events: &[(usize, SQLEventEnvelope)],
+        snapshots: &[(usize, SQLSnapshotEnvelope)],
+    ) -> Result<(), anyhow::Error> {
+        let sequences: HashSet<&String> = events.iter().map(|(_, x)| &x.sequence).collect();
+        let mut snapshot_ids: HashSet<&String> = HashSet::new();
+        let exists_query = format!(
+            "SELECT 1 FROM {} WHERE snapshot_id = ?1",
+            SNAPSHOT_TABLE_NAME
+        );
+        let event_query = format!(
+            "SELECT 1 FROM {} WHERE sequence = ?1 AND aggregate_id = ?2",
+            EVENT_TABLE_NAME
+        );
+        for (line, snapshot) in snapshots.iter() {
+            if !snapshot_ids.insert(&snapshot.snapshot_id) {
+                return Err(anyhow!(
+                    "line {}: duplicate snapshot `{}`",
+                    line,
+                    snapshot.snapshot_id
+                ));
+            }
+            let exists = sqlx::query::<Sqlite>(&exists_query)
+                .bind(&snapshot.snapshot_id)
+                .fetch_optional(&self.connector.pool)
+                .await?;
+            if exists.is_some() {
+                return Err(anyhow!(
+                    "line {}: snapshot `{}` is already stored",
+                    line,
+                    snapshot.snapshot_id
+                ));
+            }
+            if sequences.contains(&snapshot.last_sequence) {
+                continue;
+            }
+            let stored = sqlx::query::<Sqlite>(&event_query)
+                .bind(&snapshot.last_sequence)
+                .bind(&snapshot.aggregate_id)
+                .fetch_optional(&self.connector.pool)
+                .await?;
+            if stored.is_none() {
+                return Err(anyhow!(
+                    "line {}: snapshot `{}` refers to unknown event `{}`",
+                    line,
+                    snapshot.snapshot_id,
+                    snapshot.last_sequence
+                ));
+            }
+        }
+        return Ok(());
+    }
+}
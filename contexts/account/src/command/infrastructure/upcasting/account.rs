@@ -0,0 +1,14 @@
+use crate::command::domain::account::entity::event::{
+    ACCOUNT_CREATED_VERSION, ACCOUNT_DELETED_VERSION, ACCOUNT_SUSPENDED_VERSION,
+    PASSWORD_REHASHED_VERSION,
+};
+
+use super::registry::UpcasterRegistry;
+
+pub fn account_upcasters() -> UpcasterRegistry {
+    return UpcasterRegistry::new()
+        .current("AccountCreated", ACCOUNT_CREATED_VERSION)
+        .current("AccountSuspended", ACCOUNT_SUSPENDED_VERSION)
+        .current("AccountDeleted", ACCOUNT_DELETED_VERSION)
+        .current("PasswordRehashed", PASSWORD_REHASHED_VERSION);
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+use thiserror::Error;
+
+pub type UpcastFn = Box<dyn Fn(Value) -> Result<Value, anyhow::Error> + Send + Sync>;
+
+#[derive(Error, Debug)]
+pub enum UpcastError {
+    #[error("no current version is registered for event type `{0}`")]
+    UnknownEventType(String),
+    #[error("no upcaster is registered for event `{event_type}` at version `{event_version}`")]
+    UnknownVersion {
+        event_type: String,
+        event_version: String,
+    },
+    #[error("upcasting event `{event_type}` from version `{event_version}` failed: {error}")]
+    Failed {
+        event_type: String,
+        event_version: String,
+        error: anyhow::Error,
+    },
+    #[error("upcaster chain for event `{0}` does not terminate")]
+    Cycle(String),
+    #[error("stored payload is missing the `{0}` field")]
+    MissingField(String),
+}
+
+struct Upcaster {
+    to_version: String,
+    upcast: UpcastFn,
+}
+
+/// Upgrades stored event payloads to the version the domain currently
+/// understands, one registered step at a time.
+pub struct UpcasterRegistry {
+    current_versions: HashMap<String, String>,
+    upcasters: HashMap<(String, String), Upcaster>,
+}
+
+impl UpcasterRegistry {
+    pub fn new() -> Self {
+        return Self {
+            current_versions: HashMap::new(),
+            upcasters: HashMap::new(),
+        };
+    }
+
+    pub fn current(mut self, event_type: &str, event_version: &str) -> Self {
+        self.current_versions
+            .insert(event_type.into(), event_version.into());
+        return self;
+    }
+
+    pub fn register<F>(
+        mut self,
+        event_type: &str,
+        from_version: &str,
+        to_version: &str,
+        upcast: F,
+    ) -> Self
+    where
+        F: Fn(Value) -> Result<Value, anyhow::Error> + Send + Sync + 'static,
+    {
+        self.upcasters.insert(
+            (event_type.into(), from_version.into()),
+            Upcaster {
+                to_version: to_version.into(),
+                upcast: Box::new(upcast),
+            },
+        );
+        return self;
+    }
+
+    pub fn current_version(&self, event_type: &str) -> Option<&str> {
+        return self.current_versions.get(event_type).map(|x| x.as_str());
+    }
+
+    pub fn upcast(
+        &self,
+        event_type: &str,
+        event_version: &str,
+        payload: Value,
+    ) -> Result<(String, Value), UpcastError> {
+        let current = match self.current_versions.get(event_type) {
+            Some(x) => x,
+            None => return Err(UpcastError::UnknownEventType(event_type.into())),
+        };
+        let mut version = event_version.to_string();
+        let mut payload = payload;
+        let mut steps = 0;
+        while &version != current {
+            if steps > self.upcasters.len() {
+                return Err(UpcastError::Cycle(event_type.into()));
+            }
+            let upcaster = match self.upcasters.get(&(event_type.into(), version.clone())) {
+                Some(x) => x,
+                None => {
+                    return Err(UpcastError::UnknownVersion {
+                        event_type: event_type.into(),
+                        event_version: version,
+                    })
+                }
+            };
+            payload = match (upcaster.upcast)(payload) {
+                Ok(x) => x,
+                Err(e) => {
+                    return Err(UpcastError::Failed {
+                        event_type: event_type.into(),
+                        event_version: version,
+                        error: e,
+                    })
+                }
+            };
+            version = upcaster.to_version.clone();
+            if let Value::Object(map) = &mut payload {
+                map.insert("event_version".into(), Value::String(version.clone()));
+            }
+            steps += 1;
+        }
+        return Ok((version, payload));
+    }
+
+    /// Upcasts a payload that carries its own `event_type` and
+    /// `event_version` fields, such as the last event embedded in a snapshot.
+    pub fn upcast_tagged(&self, payload: Value) -> Result<Value, UpcastError> {
+        let event_type = match payload.get("event_type").and_then(|x| x.as_str()) {
+            Some(x) => x.to_string(),
+            None => return Err(UpcastError::MissingField("event_type".into())),
+        };
+        let event_version = match payload.get("event_version").and_then(|x| x.as_str()) {
+            Some(x) => x.to_string(),
+            None => return Err(UpcastError::MissingField("event_version".into())),
+        };
+        let (_, payload) = self.upcast(&event_type, &event_version, payload)?;
+        return Ok(payload);
+    }
+}
+
+impl Default for UpcasterRegistry {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
@@ -0,0 +1 @@
+ALTER TABLE account_snapshots ADD COLUMN schema_version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,8 @@
+CREATE TABLE account_event_migrations(
+    migration_id TEXT PRIMARY KEY,
+    target_table TEXT NOT NULL,
+    last_rowid INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL,
+    started_at DATETIME,
+    completed_at DATETIME
+);
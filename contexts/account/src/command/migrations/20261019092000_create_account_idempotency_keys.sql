@@ -0,0 +1,8 @@
+CREATE TABLE account_idempotency_keys(
+    idempotency_key TEXT PRIMARY KEY,
+    command_hash TEXT NOT NULL,
+    status TEXT NOT NULL,
+    outcome JSON,
+    created_at DATETIME,
+    completed_at DATETIME
+);
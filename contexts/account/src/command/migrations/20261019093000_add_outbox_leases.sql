@@ -0,0 +1,3 @@
+ALTER TABLE account_outbox_events ADD COLUMN lease_owner TEXT;
+ALTER TABLE account_outbox_events ADD COLUMN lease_expires_at INTEGER;
+CREATE INDEX account_outbox_events_aggregate_id ON account_outbox_events(aggregate_id);
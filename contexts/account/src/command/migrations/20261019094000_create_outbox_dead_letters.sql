@@ -0,0 +1,17 @@
+ALTER TABLE account_outbox_events ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE account_outbox_events ADD COLUMN last_error TEXT;
+ALTER TABLE account_outbox_events ADD COLUMN next_attempt_at INTEGER;
+
+CREATE TABLE account_outbox_dead_letters(
+    aggregate_type TEXT,
+    aggregate_id TEXT,
+    sequence TEXT PRIMARY KEY,
+    event_type TEXT,
+    event_version TEXT,
+    payload JSON,
+    metadata JSON,
+    timestamp DATETIME,
+    attempts INTEGER NOT NULL,
+    last_error TEXT,
+    dead_lettered_at DATETIME
+);
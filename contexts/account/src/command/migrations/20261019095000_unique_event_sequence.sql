@@ -0,0 +1,2 @@
+CREATE UNIQUE INDEX account_events_sequence ON account_events(sequence);
+CREATE UNIQUE INDEX account_outbox_events_sequence ON account_outbox_events(sequence);
@@ -0,0 +1,11 @@
+ALTER TABLE account_events ADD COLUMN position INTEGER;
+UPDATE account_events SET position = (
+    SELECT COUNT(*) FROM account_events e WHERE e.rowid <= account_events.rowid
+);
+CREATE UNIQUE INDEX account_events_position ON account_events(position);
+
+CREATE TABLE account_subscription_checkpoints(
+    subscription_id TEXT PRIMARY KEY,
+    position INTEGER NOT NULL,
+    updated_at DATETIME
+);
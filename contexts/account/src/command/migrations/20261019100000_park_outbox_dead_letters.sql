@@ -0,0 +1,3 @@
+ALTER TABLE account_outbox_events ADD COLUMN dead_lettered_at DATETIME;
+INSERT INTO account_outbox_events (aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata, timestamp, attempts, last_error, dead_lettered_at)
+SELECT aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata, timestamp, attempts, last_error, dead_lettered_at FROM account_outbox_dead_letters;
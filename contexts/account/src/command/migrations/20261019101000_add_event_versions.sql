@@ -0,0 +1,8 @@
+-- The version of an event is its 1-based place in its aggregate's stream.
+-- Two writers appending the same version to an aggregate conflict.
+ALTER TABLE account_events ADD COLUMN version INTEGER;
+UPDATE account_events SET version = (
+    SELECT COUNT(*) FROM account_events e
+    WHERE e.aggregate_id = account_events.aggregate_id AND e.sequence <= account_events.sequence
+);
+CREATE UNIQUE INDEX account_events_aggregate_version ON account_events(aggregate_id, version);
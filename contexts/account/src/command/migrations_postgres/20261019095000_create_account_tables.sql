@@ -0,0 +1,67 @@
+CREATE TABLE account_events(
+    id BIGSERIAL PRIMARY KEY,
+    aggregate_type TEXT NOT NULL,
+    aggregate_id TEXT NOT NULL,
+    sequence TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    event_version TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    metadata JSONB NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL
+);
+CREATE UNIQUE INDEX account_events_sequence ON account_events(sequence);
+CREATE INDEX account_events_aggregate_id_sequence ON account_events(aggregate_id, sequence);
+CREATE INDEX account_events_email ON account_events((payload->>'email'));
+
+CREATE TABLE account_snapshots(
+    aggregate_type TEXT NOT NULL,
+    aggregate_id TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    last_sequence TEXT NOT NULL,
+    snapshot_id TEXT PRIMARY KEY,
+    timestamp TIMESTAMPTZ NOT NULL,
+    schema_version BIGINT NOT NULL DEFAULT 0
+);
+CREATE INDEX account_snapshots_aggregate_id_snapshot_id ON account_snapshots(aggregate_id, snapshot_id);
+
+CREATE TABLE account_outbox_events(
+    id BIGSERIAL PRIMARY KEY,
+    aggregate_type TEXT NOT NULL,
+    aggregate_id TEXT NOT NULL,
+    sequence TEXT NOT NULL UNIQUE,
+    event_type TEXT NOT NULL,
+    event_version TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    metadata JSONB NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    lease_owner TEXT,
+    lease_expires_at BIGINT,
+    attempts BIGINT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at BIGINT
+);
+CREATE INDEX account_outbox_events_aggregate_id ON account_outbox_events(aggregate_id, id);
+
+CREATE TABLE account_outbox_dead_letters(
+    aggregate_type TEXT NOT NULL,
+    aggregate_id TEXT NOT NULL,
+    sequence TEXT PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    event_version TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    metadata JSONB NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    attempts BIGINT NOT NULL,
+    last_error TEXT,
+    dead_lettered_at TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX account_outbox_dead_letters_dead_lettered_at ON account_outbox_dead_letters(dead_lettered_at);
+
+CREATE TABLE account_idempotency_keys(
+    idempotency_key TEXT PRIMARY KEY,
+    command_hash TEXT NOT NULL,
+    status TEXT NOT NULL,
+    outcome JSONB,
+    created_at TIMESTAMPTZ NOT NULL,
+    completed_at TIMESTAMPTZ
+);
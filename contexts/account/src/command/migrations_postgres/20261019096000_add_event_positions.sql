@@ -0,0 +1,19 @@
+ALTER TABLE account_events ADD COLUMN position BIGINT;
+UPDATE account_events SET position = ordered.position
+FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY id) AS position FROM account_events) ordered
+WHERE account_events.id = ordered.id;
+ALTER TABLE account_events ALTER COLUMN position SET NOT NULL;
+CREATE UNIQUE INDEX account_events_position ON account_events(position);
+
+CREATE TABLE account_event_positions(
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    position BIGINT NOT NULL
+);
+INSERT INTO account_event_positions (id, position)
+SELECT TRUE, COALESCE(MAX(position), 0) FROM account_events;
+
+CREATE TABLE account_subscription_checkpoints(
+    subscription_id TEXT PRIMARY KEY,
+    position BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ
+);
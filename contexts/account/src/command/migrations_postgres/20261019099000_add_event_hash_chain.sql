@@ -0,0 +1,2 @@
+ALTER TABLE account_events ADD COLUMN hash TEXT;
+ALTER TABLE account_events ADD COLUMN prev_hash TEXT;
@@ -0,0 +1 @@
+ALTER TABLE account_outbox_events ADD COLUMN dead_lettered_at TIMESTAMPTZ;
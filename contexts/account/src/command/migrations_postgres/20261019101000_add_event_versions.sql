@@ -0,0 +1,11 @@
+-- The version of an event is its 1-based place in its aggregate's stream.
+-- Two writers appending the same version to an aggregate conflict.
+ALTER TABLE account_events ADD COLUMN version BIGINT;
+UPDATE account_events SET version = ordered.version
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY aggregate_id ORDER BY sequence) AS version
+    FROM account_events
+) ordered
+WHERE account_events.id = ordered.id;
+ALTER TABLE account_events ALTER COLUMN version SET NOT NULL;
+CREATE UNIQUE INDEX account_events_aggregate_version ON account_events(aggregate_id, version);
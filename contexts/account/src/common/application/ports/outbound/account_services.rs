@@ -1,7 +1,50 @@
+use crate::common::domain::secret::{Password, PasswordHash};
+
 use std::fmt::Debug;
 
+use async_trait::async_trait;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PasswordCheck {
+    Mismatch,
+    /// `rehashed` holds a hash of the password under the current algorithm,
+    /// parameters and pepper when the stored hash was not produced with
+    /// them. It is computed while the password is at hand, so the password
+    /// never has to be kept around for a later rehash.
+    Match {
+        rehashed: Option<PasswordHash>,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum PasswordHashingError {
+    /// Every hashing slot stayed busy for the whole queue timeout.
+    #[error("password hashing is at capacity")]
+    Saturated,
+    #[error(transparent)]
+    Failed(#[from] anyhow::Error),
+}
+
+#[async_trait]
 pub trait TAccountServices {
-    fn hash_password(&self, password: String) -> Result<String, anyhow::Error>;
+    fn hash_password(&self, password: Password) -> Result<PasswordHash, anyhow::Error>;
+    fn verify_password(
+        &self,
+        password: Password,
+        password_hash: PasswordHash,
+    ) -> Result<PasswordCheck, anyhow::Error>;
+    /// Hashes off the async executor, so it is safe to await from a handler.
+    async fn hash_password_async(
+        &self,
+        password: Password,
+    ) -> Result<PasswordHash, PasswordHashingError>;
+    /// Verifies off the async executor, so it is safe to await from a handler.
+    async fn verify_password_async(
+        &self,
+        password: Password,
+        password_hash: PasswordHash,
+    ) -> Result<PasswordCheck, PasswordHashingError>;
 }
 
-pub trait AccountServices: TAccountServices + Debug {}
\ No newline at end of file
+pub trait AccountServices: TAccountServices + Debug {}
@@ -0,0 +1,13 @@
+use std::fmt::Debug;
+
+/// Source of the 256-bit keys that protect encrypted payload fields. Keys are
+/// addressed by id, so values written under a retired key stay readable until
+/// they are rotated onto the current one.
+pub trait KeyProvider: Debug {
+    /// Id of the key new values are encrypted under.
+    fn current_key_id(&self) -> String;
+    fn key(&self, key_id: &str) -> Result<Vec<u8>, anyhow::Error>;
+    /// Key for blind indexes, which let encrypted values be matched by
+    /// equality. It is never rotated; changing it invalidates every index.
+    fn index_key(&self) -> Result<Vec<u8>, anyhow::Error>;
+}
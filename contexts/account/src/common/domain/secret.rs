@@ -0,0 +1,88 @@
+use std::fmt::{Debug, Display};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroize;
+
+const REDACTED: &str = "<redacted>";
+
+/// Declares a string secret whose `Debug` and `Display` never print the
+/// value, and whose memory is wiped when it is dropped. The value is only
+/// reachable through `expose`, which keeps every read greppable. Secrets are
+/// not `Clone`, so every copy of one has to be made on purpose.
+macro_rules! secret {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(PartialEq, Eq, Default)]
+        pub struct $name(String);
+
+        impl $name {
+            pub fn new(value: impl Into<String>) -> Self {
+                return Self(value.into());
+            }
+
+            pub fn expose(&self) -> &str {
+                return &self.0;
+            }
+        }
+
+        impl Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                return write!(f, "{}({})", stringify!($name), REDACTED);
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                return f.write_str(REDACTED);
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                self.0.zeroize();
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(value: String) -> Self {
+                return Self(value);
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(value: &str) -> Self {
+                return Self(value.into());
+            }
+        }
+    };
+}
+
+secret!(
+    /// A plaintext password, as received from the client.
+    Password
+);
+
+secret!(
+    /// A password hash in PHC or modular crypt format. Unlike the other
+    /// secrets it serializes as a plain string and can be cloned, as it is
+    /// stored with the events and aggregates that carry it.
+    #[derive(Clone)]
+    PasswordHash
+);
+
+secret!(
+    /// Any other credential, such as a pepper or an API token.
+    SecretToken
+);
+
+impl Serialize for PasswordHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        return serializer.serialize_str(&self.0);
+    }
+}
+
+impl<'de> Deserialize<'de> for PasswordHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        return String::deserialize(deserializer).map(Self);
+    }
+}
@@ -1,41 +1,230 @@
-use crate::{common::application::ports::outbound::account_services};
+use crate::common::{
+    application::ports::outbound::account_services::{self, PasswordCheck, PasswordHashingError},
+    domain::secret::{Password, PasswordHash, SecretToken},
+};
 
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::Arc};
 
 use anyhow::anyhow;
 use argon2::{
-    password_hash::{rand_core::OsRng, SaltString},
-    Argon2, PasswordHasher,
+    password_hash::{
+        self, rand_core::OsRng, PasswordHash as PhcHash, PasswordVerifier, SaltString,
+    },
+    Algorithm, Argon2, Params, PasswordHasher, Version,
 };
+use async_trait::async_trait;
+use pbkdf2::Pbkdf2;
+use scrypt::Scrypt;
+
+use super::pool::{HashingPool, HashingPoolConfig};
 
-pub struct AccountServices<'a> {
-    argon: Argon2<'a>,
+/// Hashes passwords with argon2id. Verification also accepts the bcrypt,
+/// scrypt and PBKDF2 hashes imported from the legacy system, and reports
+/// those, like argon2 hashes with outdated parameters, as needing a rehash.
+pub struct AccountServices {
+    hasher: Hasher,
+    pool: HashingPool,
 }
 
-impl<'a> AccountServices<'a> {
-    pub fn new() -> Self {
-        return Self {
-            argon: Argon2::default(),
-        };
+impl AccountServices {
+    /// Starts the threads of the hashing pool, which stop once the services
+    /// are dropped.
+    pub fn new(pool: HashingPoolConfig) -> Result<Self, anyhow::Error> {
+        return Ok(Self {
+            hasher: Hasher {
+                params: Params::default(),
+                pepper: None,
+            },
+            pool: HashingPool::new(pool)?,
+        });
+    }
+
+    /// Sets the argon2 memory cost, in KiB, used for new hashes.
+    pub fn with_memory_cost(self, memory_kib: u32) -> Result<Self, anyhow::Error> {
+        let params = &self.hasher.params;
+        let (t_cost, p_cost) = (params.t_cost(), params.p_cost());
+        return self.with_costs(memory_kib, t_cost, p_cost);
+    }
+
+    /// Sets the number of argon2 iterations used for new hashes.
+    pub fn with_iterations(self, iterations: u32) -> Result<Self, anyhow::Error> {
+        let params = &self.hasher.params;
+        let (m_cost, p_cost) = (params.m_cost(), params.p_cost());
+        return self.with_costs(m_cost, iterations, p_cost);
+    }
+
+    /// Sets the degree of argon2 parallelism used for new hashes.
+    pub fn with_parallelism(self, parallelism: u32) -> Result<Self, anyhow::Error> {
+        let params = &self.hasher.params;
+        let (m_cost, t_cost) = (params.m_cost(), params.t_cost());
+        return self.with_costs(m_cost, t_cost, parallelism);
+    }
+
+    fn with_costs(mut self, m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Self, anyhow::Error> {
+        self.hasher.params = Params::new(m_cost, t_cost, p_cost, None).map_err(|e| anyhow!(e))?;
+        return Ok(self);
+    }
+
+    /// Mixes a secret into every new argon2 hash, so stolen hashes cannot be
+    /// cracked without it.
+    pub fn with_pepper(mut self, pepper: SecretToken) -> Self {
+        self.hasher.pepper = Some(Arc::new(pepper));
+        return self;
     }
 }
 
-impl<'a> Debug for AccountServices<'a> {
+impl Debug for AccountServices {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AccountServices")
             .field("argon", &"Argon2")
+            .field("params", &self.hasher.params)
+            .field("pepper", &self.hasher.pepper)
+            .field("pool", &self.pool)
             .finish()
     }
 }
 
-impl<'a> account_services::TAccountServices for AccountServices<'a> {
-    fn hash_password(&self, password: String) -> Result<String, anyhow::Error> {
+/// The hashing configuration, cloned into each pool job. The pepper is
+/// shared rather than copied.
+#[derive(Clone)]
+struct Hasher {
+    params: Params,
+    pepper: Option<Arc<SecretToken>>,
+}
+
+impl Hasher {
+    fn argon<'a>(&self, pepper: Option<&'a SecretToken>) -> Result<Argon2<'a>, anyhow::Error> {
+        let params = self.params.clone();
+        return match pepper {
+            Some(x) => Argon2::new_with_secret(
+                x.expose().as_bytes(),
+                Algorithm::Argon2id,
+                Version::V0x13,
+                params,
+            )
+            .map_err(|e| anyhow!(e)),
+            None => Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params)),
+        };
+    }
+
+    fn hash(&self, password: &Password) -> Result<PasswordHash, anyhow::Error> {
         let salt = SaltString::generate(&mut OsRng);
-        match self.argon.hash_password(password.as_bytes(), &salt) {
-            Ok(x) => Ok(x.to_string()),
+        match self
+            .argon(self.pepper.as_deref())?
+            .hash_password(password.expose().as_bytes(), &salt)
+        {
+            Ok(x) => Ok(PasswordHash::new(x.to_string())),
             Err(e) => return Err(anyhow!(e)),
         }
     }
+
+    /// Verifies the password, hashing it anew when the stored hash is
+    /// outdated.
+    fn verify(
+        &self,
+        password: &Password,
+        password_hash: &PasswordHash,
+    ) -> Result<PasswordCheck, anyhow::Error> {
+        return match self.compare(password.expose(), password_hash.expose())? {
+            Comparison::Mismatch => Ok(PasswordCheck::Mismatch),
+            Comparison::Current => Ok(PasswordCheck::Match { rehashed: None }),
+            Comparison::Outdated => Ok(PasswordCheck::Match {
+                rehashed: Some(self.hash(password)?),
+            }),
+        };
+    }
+
+    fn compare(&self, password: &str, password_hash: &str) -> Result<Comparison, anyhow::Error> {
+        // bcrypt hashes use the modular crypt format rather than PHC strings.
+        if password_hash.starts_with("$2") {
+            return match bcrypt::verify(password.as_bytes(), password_hash) {
+                Ok(true) => Ok(Comparison::Outdated),
+                Ok(false) => Ok(Comparison::Mismatch),
+                Err(e) => Err(anyhow!(e)),
+            };
+        }
+        let hash = PhcHash::new(password_hash).map_err(|e| anyhow!(e))?;
+        if Algorithm::try_from(hash.algorithm).is_ok() {
+            return self.compare_argon2(password.as_bytes(), &hash);
+        }
+        let legacy = hash.verify_password(&[&Scrypt, &Pbkdf2], password.as_bytes());
+        return match verified(legacy)? {
+            true => Ok(Comparison::Outdated),
+            false => Ok(Comparison::Mismatch),
+        };
+    }
+
+    fn compare_argon2(&self, password: &[u8], hash: &PhcHash) -> Result<Comparison, anyhow::Error> {
+        let current = hash.algorithm == Algorithm::Argon2id.ident()
+            && hash.version == Some(Version::V0x13 as u32)
+            && Params::try_from(hash).is_ok_and(|x| {
+                x.m_cost() == self.params.m_cost()
+                    && x.t_cost() == self.params.t_cost()
+                    && x.p_cost() == self.params.p_cost()
+            });
+        let argon = self.argon(self.pepper.as_deref())?;
+        if verified(argon.verify_password(password, hash))? {
+            return match current {
+                true => Ok(Comparison::Current),
+                false => Ok(Comparison::Outdated),
+            };
+        }
+        // Hashes written before a pepper was configured only verify without it.
+        if self.pepper.is_some() && verified(self.argon(None)?.verify_password(password, hash))? {
+            return Ok(Comparison::Outdated);
+        }
+        return Ok(Comparison::Mismatch);
+    }
+}
+
+enum Comparison {
+    Mismatch,
+    /// Matches a hash made with the current algorithm, parameters and pepper.
+    Current,
+    Outdated,
+}
+
+fn verified(result: Result<(), password_hash::Error>) -> Result<bool, anyhow::Error> {
+    return match result {
+        Ok(_) => Ok(true),
+        Err(password_hash::Error::Password) => Ok(false),
+        Err(e) => Err(anyhow!(e)),
+    };
+}
+
+#[async_trait]
+impl account_services::TAccountServices for AccountServices {
+    fn hash_password(&self, password: Password) -> Result<PasswordHash, anyhow::Error> {
+        return self.hasher.hash(&password);
+    }
+
+    fn verify_password(
+        &self,
+        password: Password,
+        password_hash: PasswordHash,
+    ) -> Result<PasswordCheck, anyhow::Error> {
+        return self.hasher.verify(&password, &password_hash);
+    }
+
+    async fn hash_password_async(
+        &self,
+        password: Password,
+    ) -> Result<PasswordHash, PasswordHashingError> {
+        let hasher = self.hasher.clone();
+        return self.pool.run(move || hasher.hash(&password)).await;
+    }
+
+    async fn verify_password_async(
+        &self,
+        password: Password,
+        password_hash: PasswordHash,
+    ) -> Result<PasswordCheck, PasswordHashingError> {
+        let hasher = self.hasher.clone();
+        return self
+            .pool
+            .run(move || hasher.verify(&password, &password_hash))
+            .await;
+    }
 }
 
-impl<'a> account_services::AccountServices for AccountServices<'a> {}
\ No newline at end of file
+impl account_services::AccountServices for AccountServices {}
@@ -1 +1,2 @@
-pub mod argon2;
\ No newline at end of file
+pub mod argon2;
+pub mod pool;
\ No newline at end of file
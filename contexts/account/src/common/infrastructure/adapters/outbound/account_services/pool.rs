@@ -0,0 +1,105 @@
+use crate::common::application::ports::outbound::account_services::PasswordHashingError;
+
+use std::{
+    fmt::Debug,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use crossbeam_channel::{unbounded, Sender};
+use tokio::sync::{oneshot, Semaphore};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug, Clone)]
+pub struct HashingPoolConfig {
+    /// The most jobs that run at once, each on its own thread.
+    pub concurrency: usize,
+    /// How long a job may wait for a free thread before it is refused.
+    pub queue_timeout: Duration,
+}
+
+impl Default for HashingPoolConfig {
+    fn default() -> Self {
+        return Self {
+            concurrency: thread::available_parallelism()
+                .map(|x| x.get())
+                .unwrap_or(1),
+            queue_timeout: Duration::from_secs(2),
+        };
+    }
+}
+
+/// Runs password hashing on its own threads so it never blocks the async
+/// executor. At most `concurrency` jobs run at once; a job that cannot start
+/// within `queue_timeout` fails with `PasswordHashingError::Saturated`.
+pub struct HashingPool {
+    jobs: Sender<Job>,
+    permits: Arc<Semaphore>,
+    config: HashingPoolConfig,
+}
+
+impl HashingPool {
+    pub fn new(config: HashingPoolConfig) -> Result<Self, anyhow::Error> {
+        if config.concurrency == 0 {
+            return Err(anyhow!("password hashing concurrency must be at least 1"));
+        }
+        let (jobs, receiver) = unbounded::<Job>();
+        for i in 0..config.concurrency {
+            let receiver = receiver.clone();
+            // Workers exit once the pool, and with it the sender, is dropped.
+            thread::Builder::new()
+                .name(format!("password-hasher-{}", i))
+                .spawn(move || {
+                    while let Ok(job) = receiver.recv() {
+                        let _ = catch_unwind(AssertUnwindSafe(job));
+                    }
+                })?;
+        }
+        return Ok(Self {
+            jobs,
+            permits: Arc::new(Semaphore::new(config.concurrency)),
+            config,
+        });
+    }
+
+    pub async fn run<F, R>(&self, f: F) -> Result<R, PasswordHashingError>
+    where
+        F: FnOnce() -> Result<R, anyhow::Error> + Send + 'static,
+        R: Send + 'static,
+    {
+        let permit = match tokio::time::timeout(
+            self.config.queue_timeout,
+            self.permits.clone().acquire_owned(),
+        )
+        .await
+        {
+            Ok(Ok(x)) => x,
+            Ok(Err(e)) => return Err(anyhow!(e).into()),
+            Err(_) => return Err(PasswordHashingError::Saturated),
+        };
+        let (sender, receiver) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            let _ = sender.send(f());
+            drop(permit);
+        });
+        if self.jobs.send(job).is_err() {
+            return Err(anyhow!("password hashing pool has stopped").into());
+        }
+        return match receiver.await {
+            Ok(x) => x.map_err(|e| e.into()),
+            Err(_) => Err(anyhow!("password hashing job panicked").into()),
+        };
+    }
+}
+
+impl Debug for HashingPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HashingPool")
+            .field("config", &self.config)
+            .finish()
+    }
+}
@@ -0,0 +1,105 @@
+use crate::common::application::ports::outbound::key_provider::KeyProvider;
+
+use std::{collections::HashMap, fmt::Debug, path::Path};
+
+use anyhow::anyhow;
+use serde::Deserialize;
+
+const KEY_LENGTH: usize = 32;
+
+/// On-disk layout of a keyfile. Keys are hex encoded.
+///
+/// ```json
+/// {
+///   "current": "2026-10",
+///   "keys": { "2026-04": "…", "2026-10": "…" },
+///   "index_key": "…"
+/// }
+/// ```
+#[derive(Deserialize)]
+struct Keyfile {
+    current: String,
+    keys: HashMap<String, String>,
+    index_key: String,
+}
+
+/// Reads keys from a local JSON keyfile. To rotate, add a key, point
+/// `current` at it, restart, and drop the old key once the re-encryption job
+/// has run.
+pub struct KeyfileKeyProvider {
+    current: String,
+    keys: HashMap<String, Vec<u8>>,
+    index_key: Vec<u8>,
+}
+
+impl KeyfileKeyProvider {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, anyhow::Error> {
+        let contents = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| anyhow!("cannot read keyfile {}: {}", path.as_ref().display(), e))?;
+        return Self::from_json(&contents);
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, anyhow::Error> {
+        let keyfile: Keyfile = serde_json::from_str(contents)?;
+        let mut keys: HashMap<String, Vec<u8>> = HashMap::new();
+        for (key_id, key) in keyfile.keys {
+            if key_id.is_empty() || key_id.contains(':') {
+                return Err(anyhow!("key id `{}` must be non-empty without `:`", key_id));
+            }
+            let key = decode_key(&key).map_err(|e| anyhow!("key `{}`: {}", key_id, e))?;
+            keys.insert(key_id, key);
+        }
+        if !keys.contains_key(&keyfile.current) {
+            return Err(anyhow!(
+                "current key `{}` is not in the keyfile",
+                keyfile.current
+            ));
+        }
+        let index_key = decode_key(&keyfile.index_key).map_err(|e| anyhow!("index key: {}", e))?;
+        return Ok(Self {
+            current: keyfile.current,
+            keys,
+            index_key,
+        });
+    }
+}
+
+fn decode_key(value: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let key = hex::decode(value.trim())?;
+    if key.len() != KEY_LENGTH {
+        return Err(anyhow!(
+            "expected {} bytes, found {}",
+            KEY_LENGTH,
+            key.len()
+        ));
+    }
+    return Ok(key);
+}
+
+impl Debug for KeyfileKeyProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut key_ids: Vec<&String> = self.keys.keys().collect();
+        key_ids.sort();
+        f.debug_struct("KeyfileKeyProvider")
+            .field("current", &self.current)
+            .field("keys", &key_ids)
+            .finish()
+    }
+}
+
+impl KeyProvider for KeyfileKeyProvider {
+    fn current_key_id(&self) -> String {
+        return self.current.clone();
+    }
+
+    fn key(&self, key_id: &str) -> Result<Vec<u8>, anyhow::Error> {
+        return match self.keys.get(key_id) {
+            Some(x) => Ok(x.clone()),
+            None => Err(anyhow!("key `{}` is not in the keyfile", key_id)),
+        };
+    }
+
+    fn index_key(&self) -> Result<Vec<u8>, anyhow::Error> {
+        return Ok(self.index_key.clone());
+    }
+}
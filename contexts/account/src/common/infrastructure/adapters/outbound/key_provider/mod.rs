@@ -0,0 +1 @@
+pub mod keyfile;
\ No newline at end of file
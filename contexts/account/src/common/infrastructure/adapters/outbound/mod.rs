@@ -1 +1,2 @@
-pub mod account_services;
\ No newline at end of file
+pub mod account_services;
+pub mod key_provider;
\ No newline at end of file
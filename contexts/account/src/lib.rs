@@ -1,2 +1,3 @@
 pub mod common;
-pub mod command;
\ No newline at end of file
+pub mod command;
+pub mod query;
\ No newline at end of file
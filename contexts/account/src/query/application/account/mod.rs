@@ -0,0 +1,2 @@
+pub mod ports;
+pub mod service;
\ No newline at end of file
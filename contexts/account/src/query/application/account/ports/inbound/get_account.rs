@@ -0,0 +1,12 @@
+use crate::query::domain::account::entity::view::AccountView;
+
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait GetAccountUseCase<O>
+where
+    O: From<AccountView>,
+{
+    async fn get_account(&self, id: String) -> Result<Option<O>, anyhow::Error>;
+    async fn get_account_by_email(&self, email: String) -> Result<Option<O>, anyhow::Error>;
+}
@@ -0,0 +1,22 @@
+use crate::query::domain::account::entity::view::AccountView;
+
+use async_trait::async_trait;
+
+pub struct AccountPage<O> {
+    pub items: Vec<O>,
+    /// Cursor to pass as `after` to fetch the next page.
+    pub end_cursor: Option<String>,
+    pub has_next_page: bool,
+}
+
+#[async_trait]
+pub trait ListAccountsUseCase<O>
+where
+    O: From<AccountView>,
+{
+    async fn list_accounts(
+        &self,
+        first: i64,
+        after: Option<String>,
+    ) -> Result<AccountPage<O>, anyhow::Error>;
+}
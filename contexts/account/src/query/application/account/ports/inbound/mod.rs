@@ -0,0 +1,2 @@
+pub mod get_account;
+pub mod list_accounts;
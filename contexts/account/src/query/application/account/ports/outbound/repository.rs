@@ -0,0 +1,22 @@
+use crate::query::domain::account::entity::view::AccountView;
+
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait AccountViewRepository {
+    async fn find_account(&self, id: String) -> Result<Option<AccountView>, anyhow::Error>;
+    async fn find_account_by_email(
+        &self,
+        email: String,
+    ) -> Result<Option<AccountView>, anyhow::Error>;
+    /// Returns at most `limit` accounts ordered by id, starting after the
+    /// account with id `after`.
+    async fn list_accounts(
+        &self,
+        limit: i64,
+        after: Option<String>,
+    ) -> Result<Vec<AccountView>, anyhow::Error>;
+    /// Inserts or replaces the view, unless the stored view already reflects
+    /// an event at the same or a later log position.
+    async fn save_account(&self, view: AccountView) -> Result<(), anyhow::Error>;
+}
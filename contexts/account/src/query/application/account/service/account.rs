@@ -0,0 +1,94 @@
+use crate::query::{
+    application::account::ports::{
+        inbound::{
+            get_account::GetAccountUseCase,
+            list_accounts::{AccountPage, ListAccountsUseCase},
+        },
+        outbound::repository::AccountViewRepository,
+    },
+    domain::account::entity::view::AccountView,
+};
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::span;
+
+/// Largest page `list_accounts` hands out, whatever the caller asks for.
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+pub trait QueryServiceTrait<O: From<AccountView>>:
+    GetAccountUseCase<O> + ListAccountsUseCase<O>
+{
+}
+
+pub struct AccountQueryService {
+    repository: Arc<dyn AccountViewRepository + Sync + Send>,
+}
+
+impl AccountQueryService {
+    pub fn new(repository: Arc<dyn AccountViewRepository + Sync + Send>) -> Self {
+        return Self { repository };
+    }
+}
+
+#[async_trait]
+impl<O> GetAccountUseCase<O> for AccountQueryService
+where
+    O: From<AccountView>,
+{
+    async fn get_account(&self, id: String) -> Result<Option<O>, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "get_account",
+            target = "AccountQueryService"
+        );
+        let _enter = root.enter();
+        return Ok(self.repository.find_account(id).await?.map(|x| x.into()));
+    }
+
+    async fn get_account_by_email(&self, email: String) -> Result<Option<O>, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "get_account_by_email",
+            target = "AccountQueryService"
+        );
+        let _enter = root.enter();
+        return Ok(self
+            .repository
+            .find_account_by_email(email)
+            .await?
+            .map(|x| x.into()));
+    }
+}
+
+#[async_trait]
+impl<O> ListAccountsUseCase<O> for AccountQueryService
+where
+    O: From<AccountView> + Send,
+{
+    async fn list_accounts(
+        &self,
+        first: i64,
+        after: Option<String>,
+    ) -> Result<AccountPage<O>, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "list_accounts",
+            target = "AccountQueryService"
+        );
+        let _enter = root.enter();
+        let limit = first.clamp(1, MAX_PAGE_SIZE);
+        let mut accounts = self.repository.list_accounts(limit + 1, after).await?;
+        let has_next_page = accounts.len() as i64 > limit;
+        accounts.truncate(limit as usize);
+        let end_cursor = accounts.last().map(|x| x.id.clone());
+        return Ok(AccountPage {
+            items: accounts.into_iter().map(|x| x.into()).collect(),
+            end_cursor,
+            has_next_page,
+        });
+    }
+}
+
+impl<O: From<AccountView> + Send> QueryServiceTrait<O> for AccountQueryService {}
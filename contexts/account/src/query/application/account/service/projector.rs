@@ -0,0 +1,116 @@
+use crate::{
+    command::{
+        application::account::ports::outbound::subscription::{EventHandler, PositionedEvent},
+        domain::account::entity::{aggregate::AccountAggregate, event::AccountEvent},
+    },
+    query::{
+        application::account::ports::outbound::repository::AccountViewRepository,
+        domain::account::entity::view::{
+            AccountView, ACCOUNT_STATUS_CREATED, ACCOUNT_STATUS_DELETED, ACCOUNT_STATUS_SUSPENDED,
+        },
+    },
+};
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use cqrs_rs::domain::entity::event::EventEnvelope;
+use tracing::{span, Instrument};
+
+/// Folds account events into the `AccountView` read model, driven by a
+/// `CatchUpSubscription` over the event store.
+pub struct AccountProjector {
+    repository: Arc<dyn AccountViewRepository + Sync + Send>,
+}
+
+impl AccountProjector {
+    pub fn new(repository: Arc<dyn AccountViewRepository + Sync + Send>) -> Self {
+        return Self { repository };
+    }
+
+    pub async fn project(
+        &self,
+        event: &EventEnvelope<AccountAggregate>,
+        position: i64,
+    ) -> Result<(), anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "project",
+            target = "AccountProjector",
+            aggregate_id = event.aggregate_id.as_str(),
+            sequence = event.sequence.as_str()
+        );
+        async {
+            match &event.payload {
+                AccountEvent::AccountCreated {
+                    id,
+                    email,
+                    created_at,
+                    ..
+                } => {
+                    self.repository
+                        .save_account(AccountView {
+                            id: id.clone(),
+                            email: email.clone(),
+                            status: ACCOUNT_STATUS_CREATED.into(),
+                            created_at: *created_at,
+                            updated_at: event.timestamp,
+                            last_sequence: event.sequence.clone(),
+                            last_position: position,
+                        })
+                        .await?;
+                }
+                AccountEvent::AccountSuspended { id, .. } => {
+                    self.update_status(id, ACCOUNT_STATUS_SUSPENDED, event, position)
+                        .await?;
+                }
+                AccountEvent::AccountDeleted { id, .. } => {
+                    self.update_status(id, ACCOUNT_STATUS_DELETED, event, position)
+                        .await?;
+                }
+                // The view holds nothing derived from the password hash.
+                AccountEvent::PasswordRehashed { .. } => {}
+            }
+            return Ok::<(), anyhow::Error>(());
+        }
+        .instrument(root)
+        .await
+    }
+
+    async fn update_status(
+        &self,
+        id: &str,
+        status: &str,
+        event: &EventEnvelope<AccountAggregate>,
+        position: i64,
+    ) -> Result<(), anyhow::Error> {
+        let view = match self.repository.find_account(id.into()).await? {
+            Some(x) => x,
+            None => {
+                tracing::warn!(
+                    aggregate_id = id,
+                    sequence = event.sequence.as_str(),
+                    "status change for an account without a view"
+                );
+                return Ok(());
+            }
+        };
+        return self
+            .repository
+            .save_account(AccountView {
+                status: status.into(),
+                updated_at: event.timestamp,
+                last_sequence: event.sequence.clone(),
+                last_position: position,
+                ..view
+            })
+            .await;
+    }
+}
+
+#[async_trait]
+impl EventHandler for AccountProjector {
+    async fn handle(&self, event: &PositionedEvent) -> Result<(), anyhow::Error> {
+        return self.project(&event.event, event.position).await;
+    }
+}
@@ -0,0 +1 @@
+pub mod account;
\ No newline at end of file
@@ -0,0 +1 @@
+pub mod view;
\ No newline at end of file
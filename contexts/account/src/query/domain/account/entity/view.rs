@@ -0,0 +1,21 @@
+use chrono::{DateTime, Utc};
+
+pub const ACCOUNT_STATUS_CREATED: &str = "Created";
+pub const ACCOUNT_STATUS_SUSPENDED: &str = "Suspended";
+pub const ACCOUNT_STATUS_DELETED: &str = "Deleted";
+
+/// Read model of an account as projected from its events. It deliberately
+/// carries no credentials.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountView {
+    pub id: String,
+    pub email: String,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Sequence of the last event applied to the view.
+    pub last_sequence: String,
+    /// Global log position of the last event applied to the view. Events at
+    /// or before it are ignored so redelivered events cannot roll it back.
+    pub last_position: i64,
+}
@@ -0,0 +1 @@
+pub mod entity;
\ No newline at end of file
@@ -0,0 +1,153 @@
+use crate::{
+    command::{
+        application::account::context::RequestContext,
+        infrastructure::adapters::inbound::graphql::TrustedProxies,
+    },
+    query::{
+        application::account::service::account::QueryServiceTrait,
+        infrastructure::dtos::transport::graphql::{GraphQLAccountView, GraphQLAccountViewPage},
+    },
+};
+
+use std::sync::Arc;
+
+use actix_web::{web::{self, Data}, App, HttpRequest, HttpResponse, HttpServer, guard};
+use async_graphql::{
+    http::GraphiQLSource, Context, EmptyMutation, EmptySubscription, Object, Result, Schema,
+};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use tracing::span;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const DEFAULT_PAGE_SIZE: i32 = 20;
+
+pub type AccountQuerySchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    #[graphql(visible = false)]
+    async fn _version(&self) -> String {
+        VERSION.into()
+    }
+
+    async fn account(&self, ctx: &Context<'_>, id: String) -> Result<Option<GraphQLAccountView>> {
+        let service = ctx
+            .data::<Arc<dyn QueryServiceTrait<GraphQLAccountView> + Sync + Send>>()
+            .unwrap();
+        match service.get_account(id).await {
+            Ok(x) => return Ok(x),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    /// Only answers callers authenticated by a trusted proxy, so the query
+    /// cannot be used to probe which emails have an account.
+    async fn account_by_email(
+        &self,
+        ctx: &Context<'_>,
+        email: String,
+    ) -> Result<Option<GraphQLAccountView>> {
+        let authenticated = ctx
+            .data_opt::<RequestContext>()
+            .is_some_and(|x| x.actor.is_some());
+        if !authenticated {
+            return Err("accountByEmail needs an authenticated caller".into());
+        }
+        let service = ctx
+            .data::<Arc<dyn QueryServiceTrait<GraphQLAccountView> + Sync + Send>>()
+            .unwrap();
+        match service.get_account_by_email(email).await {
+            Ok(x) => return Ok(x),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    async fn accounts(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<GraphQLAccountViewPage> {
+        let service = ctx
+            .data::<Arc<dyn QueryServiceTrait<GraphQLAccountView> + Sync + Send>>()
+            .unwrap();
+        let first = first.unwrap_or(DEFAULT_PAGE_SIZE) as i64;
+        match service.list_accounts(first, after).await {
+            Ok(x) => {
+                return Ok(GraphQLAccountViewPage {
+                    nodes: x.items,
+                    end_cursor: x.end_cursor,
+                    has_next_page: x.has_next_page,
+                })
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GraphQLAccountQueryAdapter {
+    schema: AccountQuerySchema,
+    trusted_proxies: TrustedProxies,
+}
+
+async fn index(
+    schema: web::Data<AccountQuerySchema>,
+    trusted_proxies: web::Data<TrustedProxies>,
+    http_req: HttpRequest,
+    req: GraphQLRequest,
+) -> GraphQLResponse {
+    let context = trusted_proxies.request_context(&http_req);
+    let root = span!(
+        tracing::Level::INFO,
+        "graphql_query_received",
+        correlation_id = context.correlation_id.as_str()
+    );
+    let _enter = root.enter();
+    schema.execute(req.into_inner().data(context)).await.into()
+}
+
+pub fn schema(
+    service: Arc<dyn QueryServiceTrait<GraphQLAccountView> + Send + Sync>,
+) -> AccountQuerySchema {
+    return Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .enable_federation()
+        .data(service)
+        .finish();
+}
+
+async fn gql_playgound() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(GraphiQLSource::build().endpoint("/").finish())
+}
+
+impl GraphQLAccountQueryAdapter {
+    pub fn new(service: Arc<dyn QueryServiceTrait<GraphQLAccountView> + Send + Sync>) -> Self {
+        return Self {
+            schema: schema(service),
+            trusted_proxies: TrustedProxies::default(),
+        };
+    }
+
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        return self;
+    }
+
+    pub async fn run(self, address: &str) -> Result<(), anyhow::Error> {
+        HttpServer::new(move || {
+            App::new()
+                .app_data(Data::new(self.schema.clone()))
+                .app_data(Data::new(self.trusted_proxies.clone()))
+                .service(web::resource("/").guard(guard::Post()).to(index))
+                .service(web::resource("/").guard(guard::Get()).to(gql_playgound))
+        })
+        .bind(address)?
+        .run()
+        .await
+        .map_err(|e| e.into())
+    }
+}
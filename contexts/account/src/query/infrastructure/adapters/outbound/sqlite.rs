@@ -0,0 +1,192 @@
+use crate::{
+    command::application::account::ports::outbound::subscription::CheckpointStore,
+    query::{
+        application::account::ports::outbound::repository::AccountViewRepository,
+        domain::account::entity::view::AccountView,
+        infrastructure::dtos::storage::sql::SQLAccountViewRow,
+    },
+};
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use cqrs_rs::infrastructure::adapter::secondary::storage::sqlite::SqliteConnector;
+use sqlx::{migrate::Migrator, Row, Sqlite};
+use tracing::span;
+
+pub const ACCOUNT_VIEW_TABLE_NAME: &str = "accounts";
+pub const CHECKPOINT_TABLE_NAME: &str = "account_query_checkpoints";
+const VIEW_FIELDS: [&str; 7] = [
+    "id",
+    "email",
+    "status",
+    "created_at",
+    "updated_at",
+    "last_sequence",
+    "last_position",
+];
+
+/// SQLite read model for accounts. The table name is configurable so a
+/// replay can build a shadow table next to the live one.
+#[derive(Clone)]
+pub struct SQLiteAccountViewRepository {
+    pub connector: Arc<SqliteConnector>,
+    pub table: String,
+}
+
+impl SQLiteAccountViewRepository {
+    pub fn new(connector: Arc<SqliteConnector>) -> Self {
+        return Self {
+            connector,
+            table: ACCOUNT_VIEW_TABLE_NAME.into(),
+        };
+    }
+
+    pub fn with_table(mut self, table: &str) -> Self {
+        self.table = table.into();
+        return self;
+    }
+
+    pub async fn migrate(&self, path: String) -> Result<(), anyhow::Error> {
+        let m = Migrator::new(std::path::Path::new(&path)).await?;
+        m.run(&self.connector.pool).await.map_err(|e| e.into())
+    }
+
+    async fn find_one(
+        &self,
+        column: &str,
+        value: String,
+    ) -> Result<Option<AccountView>, anyhow::Error> {
+        let query = format!(
+            "SELECT {} FROM {} WHERE {} = ?1",
+            VIEW_FIELDS.join(", "),
+            self.table,
+            column
+        );
+        let row = sqlx::query_as::<Sqlite, SQLAccountViewRow>(&query)
+            .bind(value)
+            .fetch_optional(&self.connector.pool)
+            .await?;
+        return Ok(row.map(|x| x.into()));
+    }
+}
+
+#[async_trait]
+impl AccountViewRepository for SQLiteAccountViewRepository {
+    async fn find_account(&self, id: String) -> Result<Option<AccountView>, anyhow::Error> {
+        return self.find_one("id", id).await;
+    }
+
+    async fn find_account_by_email(
+        &self,
+        email: String,
+    ) -> Result<Option<AccountView>, anyhow::Error> {
+        return self.find_one("email", email).await;
+    }
+
+    async fn list_accounts(
+        &self,
+        limit: i64,
+        after: Option<String>,
+    ) -> Result<Vec<AccountView>, anyhow::Error> {
+        let query = format!(
+            "SELECT {} FROM {} WHERE (?1 IS NULL OR id > ?1) ORDER BY id ASC LIMIT ?2",
+            VIEW_FIELDS.join(", "),
+            self.table
+        );
+        let rows = sqlx::query_as::<Sqlite, SQLAccountViewRow>(&query)
+            .bind(after)
+            .bind(limit)
+            .fetch_all(&self.connector.pool)
+            .await?;
+        return Ok(rows.into_iter().map(|x| x.into()).collect());
+    }
+
+    async fn save_account(&self, view: AccountView) -> Result<(), anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "save_account",
+            target = "AccountViewRepository",
+            implementation = "SQLiteAccountViewRepository"
+        );
+        let _enter = root.enter();
+        let query = format!(
+            "INSERT INTO {table} ({fields}) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 ) \
+            ON CONFLICT(id) DO UPDATE SET email = excluded.email, status = excluded.status, \
+            created_at = excluded.created_at, updated_at = excluded.updated_at, \
+            last_sequence = excluded.last_sequence, last_position = excluded.last_position \
+            WHERE excluded.last_position > {table}.last_position",
+            table = self.table,
+            fields = VIEW_FIELDS.join(", ")
+        );
+        let result = sqlx::query::<Sqlite>(&query)
+            .bind(view.id)
+            .bind(view.email)
+            .bind(view.status)
+            .bind(view.created_at)
+            .bind(view.updated_at)
+            .bind(view.last_sequence)
+            .bind(view.last_position)
+            .execute(&self.connector.pool)
+            .await;
+        match result {
+            Err(e) => return Err(e.into()),
+            _ => return Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for SQLiteAccountViewRepository {
+    async fn load_checkpoint(&self, subscription_id: String) -> Result<i64, anyhow::Error> {
+        let query = format!(
+            "SELECT position FROM {} WHERE subscription_id = ?1",
+            CHECKPOINT_TABLE_NAME
+        );
+        let row = sqlx::query::<Sqlite>(&query)
+            .bind(subscription_id)
+            .fetch_optional(&self.connector.pool)
+            .await?;
+        match row {
+            None => return Ok(0),
+            Some(x) => return Ok(x.get(0)),
+        }
+    }
+
+    async fn save_checkpoint(
+        &self,
+        subscription_id: String,
+        expected: i64,
+        position: i64,
+    ) -> Result<bool, anyhow::Error> {
+        let update = format!(
+            "UPDATE {} SET position = ?1, updated_at = ?2 WHERE subscription_id = ?3 AND position = ?4",
+            CHECKPOINT_TABLE_NAME
+        );
+        let updated = sqlx::query::<Sqlite>(&update)
+            .bind(position)
+            .bind(Utc::now())
+            .bind(&subscription_id)
+            .bind(expected)
+            .execute(&self.connector.pool)
+            .await?
+            .rows_affected();
+        if updated > 0 || expected != 0 {
+            return Ok(updated > 0);
+        }
+        // A missing checkpoint reads as 0, so it is created on the first save.
+        let insert = format!(
+            "INSERT INTO {} (subscription_id, position, updated_at) VALUES ( ?1, ?2, ?3 ) ON CONFLICT (subscription_id) DO NOTHING",
+            CHECKPOINT_TABLE_NAME
+        );
+        let inserted = sqlx::query::<Sqlite>(&insert)
+            .bind(subscription_id)
+            .bind(position)
+            .bind(Utc::now())
+            .execute(&self.connector.pool)
+            .await?
+            .rows_affected();
+        return Ok(inserted > 0);
+    }
+}
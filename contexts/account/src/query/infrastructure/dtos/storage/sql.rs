@@ -0,0 +1,28 @@
+use crate::query::domain::account::entity::view::AccountView;
+
+use chrono::{DateTime, Utc};
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct SQLAccountViewRow {
+    pub id: String,
+    pub email: String,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_sequence: String,
+    pub last_position: i64,
+}
+
+impl From<SQLAccountViewRow> for AccountView {
+    fn from(value: SQLAccountViewRow) -> Self {
+        return AccountView {
+            id: value.id,
+            email: value.email,
+            status: value.status,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+            last_sequence: value.last_sequence,
+            last_position: value.last_position,
+        };
+    }
+}
@@ -0,0 +1,34 @@
+use crate::query::domain::account::entity::view::AccountView;
+
+use async_graphql::SimpleObject;
+use chrono::{DateTime, Utc};
+
+#[derive(Clone, SimpleObject)]
+#[graphql(name = "AccountView")]
+pub struct GraphQLAccountView {
+    pub id: String,
+    pub email: String,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<AccountView> for GraphQLAccountView {
+    fn from(value: AccountView) -> Self {
+        return GraphQLAccountView {
+            id: value.id,
+            email: value.email,
+            status: value.status,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+        };
+    }
+}
+
+#[derive(Clone, SimpleObject)]
+#[graphql(name = "AccountViewPage")]
+pub struct GraphQLAccountViewPage {
+    pub nodes: Vec<GraphQLAccountView>,
+    pub end_cursor: Option<String>,
+    pub has_next_page: bool,
+}
@@ -0,0 +1,3 @@
+pub mod adapters;
+pub mod dtos;
+pub mod replay;
\ No newline at end of file
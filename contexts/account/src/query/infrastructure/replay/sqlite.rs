@@ -0,0 +1,343 @@
+use crate::{
+    command::application::account::ports::outbound::subscription::AccountEventLogRepository,
+    query::{
+        application::account::service::projector::AccountProjector,
+        infrastructure::adapters::outbound::sqlite::{
+            SQLiteAccountViewRepository, ACCOUNT_VIEW_TABLE_NAME, CHECKPOINT_TABLE_NAME,
+        },
+    },
+};
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use chrono::Utc;
+use cqrs_rs::infrastructure::adapter::secondary::storage::sqlite::SqliteConnector;
+use sqlx::{Row, Sqlite};
+use tracing::span;
+
+const REPLAY_TABLE_NAME: &str = "account_query_replays";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayMode {
+    /// Wipes the live table and rebuilds it in place. The projector has to
+    /// be stopped while the replay runs.
+    InPlace,
+    /// Builds into a new table and swaps it in once it has caught up, so the
+    /// live table keeps serving reads meanwhile.
+    Shadow,
+}
+
+impl ReplayMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::InPlace => "in_place",
+            Self::Shadow => "shadow",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayStatus {
+    Replaying,
+    Completed,
+}
+
+impl ReplayStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Replaying => "replaying",
+            Self::Completed => "completed",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, anyhow::Error> {
+        match value {
+            "replaying" => Ok(Self::Replaying),
+            "completed" => Ok(Self::Completed),
+            x => Err(anyhow!("unknown replay status `{}`", x)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ReplayReport {
+    pub replay_id: String,
+    pub position: i64,
+    pub status: ReplayStatus,
+}
+
+/// Rebuilds the `accounts` projection by re-applying every event of the
+/// global log in position order. Progress is recorded per batch, so running
+/// the same replay id again resumes it. On completion the projector's
+/// checkpoint is moved to the replayed position; in shadow mode it is always
+/// moved back, and the projector re-applies anything it had already written
+/// to the old table.
+pub struct SQLiteProjectionReplay {
+    connector: Arc<SqliteConnector>,
+    log: Arc<dyn AccountEventLogRepository + Sync + Send>,
+    replay_id: String,
+    subscription_id: String,
+    mode: ReplayMode,
+    batch_size: i64,
+}
+
+impl SQLiteProjectionReplay {
+    pub fn new(
+        connector: Arc<SqliteConnector>,
+        log: Arc<dyn AccountEventLogRepository + Sync + Send>,
+        replay_id: &str,
+        subscription_id: &str,
+        mode: ReplayMode,
+    ) -> Result<Self, anyhow::Error> {
+        if replay_id.is_empty()
+            || !replay_id
+                .chars()
+                .all(|x| x.is_ascii_alphanumeric() || x == '_')
+        {
+            return Err(anyhow!(
+                "replay id `{}` may only contain ascii letters, digits and underscores",
+                replay_id
+            ));
+        }
+        return Ok(Self {
+            connector,
+            log,
+            replay_id: replay_id.into(),
+            subscription_id: subscription_id.into(),
+            mode,
+            batch_size: 500,
+        });
+    }
+
+    pub fn with_batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        return self;
+    }
+
+    fn target_table(&self) -> String {
+        return match self.mode {
+            ReplayMode::InPlace => ACCOUNT_VIEW_TABLE_NAME.into(),
+            ReplayMode::Shadow => format!("{}_{}", ACCOUNT_VIEW_TABLE_NAME, self.replay_id),
+        };
+    }
+
+    /// Name of the shadow table's copy of the live index `name`.
+    fn shadow_index(&self, name: &str) -> String {
+        return format!("{}_{}", self.target_table(), name);
+    }
+
+    pub async fn run(&self) -> Result<ReplayReport, anyhow::Error> {
+        let root = span!(
+            tracing::Level::INFO,
+            "run",
+            target = "SQLiteProjectionReplay",
+            replay_id = self.replay_id.as_str()
+        );
+        let _enter = root.enter();
+        let (status, mut position) = self.prepare().await?;
+        if status == ReplayStatus::Replaying {
+            position = self.replay(position).await?;
+            self.complete(position).await?;
+        }
+        return Ok(ReplayReport {
+            replay_id: self.replay_id.clone(),
+            position,
+            status: ReplayStatus::Completed,
+        });
+    }
+
+    async fn prepare(&self) -> Result<(ReplayStatus, i64), anyhow::Error> {
+        let query = format!(
+            "SELECT status, position FROM {} WHERE replay_id = ?1",
+            REPLAY_TABLE_NAME
+        );
+        let existing = sqlx::query::<Sqlite>(&query)
+            .bind(&self.replay_id)
+            .fetch_optional(&self.connector.pool)
+            .await?;
+        if let Some(x) = existing {
+            let status: String = x.get(0);
+            return Ok((ReplayStatus::parse(&status)?, x.get(1)));
+        }
+        let mut tx = self.connector.pool.begin().await?;
+        match self.mode {
+            ReplayMode::InPlace => {
+                sqlx::query::<Sqlite>(&format!("DELETE FROM {}", ACCOUNT_VIEW_TABLE_NAME))
+                    .execute(&mut tx)
+                    .await?;
+            }
+            ReplayMode::Shadow => {
+                let schema_query =
+                    "SELECT type, name, sql FROM sqlite_master WHERE tbl_name = ?1 AND sql IS NOT NULL ORDER BY type DESC";
+                let statements = sqlx::query::<Sqlite>(schema_query)
+                    .bind(ACCOUNT_VIEW_TABLE_NAME)
+                    .fetch_all(&mut tx)
+                    .await?;
+                sqlx::query::<Sqlite>(&format!("DROP TABLE IF EXISTS {}", self.target_table()))
+                    .execute(&mut tx)
+                    .await?;
+                // Table first, then its indexes, each renamed after the
+                // shadow table so they do not collide with the live ones.
+                for statement in statements {
+                    let kind: String = statement.get(0);
+                    let name: String = statement.get(1);
+                    let mut sql = replace_identifier(
+                        statement.get(2),
+                        ACCOUNT_VIEW_TABLE_NAME,
+                        &self.target_table(),
+                    );
+                    if kind == "index" {
+                        sql = replace_identifier(&sql, &name, &self.shadow_index(&name));
+                    }
+                    sqlx::query::<Sqlite>(&sql).execute(&mut tx).await?;
+                }
+            }
+        }
+        let insert = format!(
+            "INSERT INTO {} (replay_id, target_table, mode, position, status, started_at) VALUES ( ?1, ?2, ?3, 0, ?4, ?5 )",
+            REPLAY_TABLE_NAME
+        );
+        sqlx::query::<Sqlite>(&insert)
+            .bind(&self.replay_id)
+            .bind(self.target_table())
+            .bind(self.mode.as_str())
+            .bind(ReplayStatus::Replaying.as_str())
+            .bind(Utc::now())
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        return Ok((ReplayStatus::Replaying, 0));
+    }
+
+    /// Applies every event after `position` to the target table and returns
+    /// the position of the last one.
+    async fn replay(&self, mut position: i64) -> Result<i64, anyhow::Error> {
+        let views = SQLiteAccountViewRepository::new(self.connector.clone())
+            .with_table(&self.target_table());
+        let projector = AccountProjector::new(Arc::new(views));
+        let progress = format!(
+            "UPDATE {} SET position = ?1 WHERE replay_id = ?2",
+            REPLAY_TABLE_NAME
+        );
+        loop {
+            let events = self.log.read_all(position, self.batch_size).await?;
+            if events.is_empty() {
+                return Ok(position);
+            }
+            for event in events.iter() {
+                projector.project(&event.event, event.position).await?;
+                position = event.position;
+            }
+            sqlx::query::<Sqlite>(&progress)
+                .bind(position)
+                .bind(&self.replay_id)
+                .execute(&self.connector.pool)
+                .await?;
+            tracing::info!(
+                replay_id = self.replay_id.as_str(),
+                position,
+                "projection replay progressed"
+            );
+        }
+    }
+
+    async fn complete(&self, position: i64) -> Result<(), anyhow::Error> {
+        let index_query =
+            "SELECT name, sql FROM sqlite_master WHERE type = 'index' AND tbl_name = ?1 AND sql IS NOT NULL";
+        let indexes = sqlx::query::<Sqlite>(index_query)
+            .bind(ACCOUNT_VIEW_TABLE_NAME)
+            .fetch_all(&self.connector.pool)
+            .await?;
+        let mut tx = self.connector.pool.begin().await?;
+        let checkpoint_query = format!(
+            "SELECT position FROM {} WHERE subscription_id = ?1",
+            CHECKPOINT_TABLE_NAME
+        );
+        let current: Option<i64> = sqlx::query::<Sqlite>(&checkpoint_query)
+            .bind(&self.subscription_id)
+            .fetch_optional(&mut tx)
+            .await?
+            .map(|x| x.get(0));
+        // In shadow mode the projector may have a batch in flight that wrote
+        // to the old table. Moving the checkpoint strictly back makes that
+        // batch's compare-and-set fail, so it re-reads from here and writes
+        // to the new table; re-applying one event is a no-op.
+        let checkpoint = match self.mode {
+            ReplayMode::Shadow => {
+                let current = current.unwrap_or(0);
+                if position < current {
+                    position
+                } else {
+                    (current - 1).max(0)
+                }
+            }
+            ReplayMode::InPlace => position,
+        };
+        if self.mode == ReplayMode::Shadow {
+            sqlx::query::<Sqlite>(&format!("DROP TABLE {}", ACCOUNT_VIEW_TABLE_NAME))
+                .execute(&mut tx)
+                .await?;
+            sqlx::query::<Sqlite>(&format!(
+                "ALTER TABLE {} RENAME TO {}",
+                self.target_table(),
+                ACCOUNT_VIEW_TABLE_NAME
+            ))
+            .execute(&mut tx)
+            .await?;
+            for index in indexes {
+                let name: String = index.get(0);
+                let sql: String = index.get(1);
+                sqlx::query::<Sqlite>(&format!(
+                    "DROP INDEX IF EXISTS {}",
+                    self.shadow_index(&name)
+                ))
+                .execute(&mut tx)
+                .await?;
+                sqlx::query::<Sqlite>(&sql).execute(&mut tx).await?;
+            }
+        }
+        let save_checkpoint = format!(
+            "INSERT INTO {} (subscription_id, position, updated_at) VALUES ( ?1, ?2, ?3 ) ON CONFLICT(subscription_id) DO UPDATE SET position = excluded.position, updated_at = excluded.updated_at",
+            CHECKPOINT_TABLE_NAME
+        );
+        sqlx::query::<Sqlite>(&save_checkpoint)
+            .bind(&self.subscription_id)
+            .bind(checkpoint)
+            .bind(Utc::now())
+            .execute(&mut tx)
+            .await?;
+        let query = format!(
+            "UPDATE {} SET status = ?1, position = ?2, completed_at = ?3 WHERE replay_id = ?4",
+            REPLAY_TABLE_NAME
+        );
+        sqlx::query::<Sqlite>(&query)
+            .bind(ReplayStatus::Completed.as_str())
+            .bind(position)
+            .bind(Utc::now())
+            .bind(&self.replay_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        return Ok(());
+    }
+}
+
+/// Replaces `from` in `sql` only where it is a whole identifier, so names
+/// that merely contain it are copied as they are.
+fn replace_identifier(sql: &str, from: &str, to: &str) -> String {
+    let is_identifier = |x: char| x.is_ascii_alphanumeric() || x == '_';
+    let mut replaced = String::with_capacity(sql.len());
+    let mut copied = 0;
+    for (start, _) in sql.match_indices(from) {
+        let end = start + from.len();
+        if sql[..start].ends_with(is_identifier) || sql[end..].starts_with(is_identifier) {
+            continue;
+        }
+        replaced.push_str(&sql[copied..start]);
+        replaced.push_str(to);
+        copied = end;
+    }
+    replaced.push_str(&sql[copied..]);
+    return replaced;
+}
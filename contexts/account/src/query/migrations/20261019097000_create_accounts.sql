@@ -0,0 +1,15 @@
+CREATE TABLE accounts(
+    id TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    status TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    last_sequence TEXT NOT NULL
+);
+CREATE INDEX accounts_email ON accounts(email);
+
+CREATE TABLE account_query_checkpoints(
+    subscription_id TEXT PRIMARY KEY,
+    position INTEGER NOT NULL,
+    updated_at DATETIME
+);
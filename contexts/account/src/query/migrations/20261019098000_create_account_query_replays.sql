@@ -0,0 +1,9 @@
+CREATE TABLE account_query_replays(
+    replay_id TEXT PRIMARY KEY,
+    target_table TEXT NOT NULL,
+    mode TEXT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL,
+    started_at DATETIME,
+    completed_at DATETIME
+);
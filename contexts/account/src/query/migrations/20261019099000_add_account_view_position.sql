@@ -0,0 +1,3 @@
+-- Views are guarded by the global log position of their last event. Views
+-- written before this column start at 0, so the next event always applies.
+ALTER TABLE accounts ADD COLUMN last_position INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+pub mod application;
+pub mod domain;
+pub mod infrastructure;
\ No newline at end of file
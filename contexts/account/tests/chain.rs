@@ -0,0 +1,311 @@
+mod common;
+
+use std::{collections::HashMap, sync::Arc};
+
+use account::command::{
+    application::account::{
+        chain::ChainAnchor,
+        context::RequestContext,
+        ports::inbound::{
+            suspend_account::SuspendAccountUseCase,
+            verify_chain::{ChainBreakReason, ChainReport, VerifyChainUseCase},
+        },
+        service::chain::AccountChainService,
+    },
+    domain::account::entity::{
+        aggregate::AccountAggregate, command::SuspendAccountCommand, error::AccountError,
+        event::AccountEvent,
+    },
+    infrastructure::adapters::outbound::{
+        memory::InMemoryAccountRepository, sqlite::SQLiteAccountRepository,
+    },
+};
+use chrono::{Duration, Utc};
+use cqrs_rs::domain::entity::event::EventEnvelope;
+use sqlx::Sqlite;
+use ulid::Ulid;
+
+use common::{chain_key, create_account, store, Envelope, Repository, Store};
+
+fn chain(store: &Store) -> AccountChainService<Envelope, Envelope> {
+    return AccountChainService::new(store.repository.clone()).with_key(chain_key());
+}
+
+fn account_suspended(aggregate_id: &str, sequence: &str) -> Envelope {
+    return EventEnvelope {
+        aggregate_id: aggregate_id.into(),
+        aggregate_type: "account".into(),
+        sequence: sequence.into(),
+        payload: AccountEvent::AccountSuspended {
+            id: aggregate_id.into(),
+            reason: "abuse".into(),
+            suspended_at: Utc::now(),
+            event_version: "0.0.1".into(),
+            event_id: sequence.into(),
+        },
+        metadata: HashMap::new(),
+        timestamp: Utc::now(),
+    };
+}
+
+/// Appends to `aggregate_id` through a store written with and without the
+/// chain key: once chaining is on, the unhashed account is refused until the
+/// backfill has linked it.
+async fn refuses_appends_until_backfilled(
+    chained: Repository,
+    unchained: Repository,
+) -> Result<(), anyhow::Error> {
+    let aggregate_id = Ulid::new().to_string();
+    // ULIDs minted in the same millisecond are not ordered.
+    let mut sequences: Vec<String> = (0..2).map(|_| Ulid::new().to_string()).collect();
+    sequences.sort();
+    unchained
+        .store_events(vec![account_suspended(&aggregate_id, &sequences[0])])
+        .await?;
+    let chain: AccountChainService<Envelope, Envelope> =
+        AccountChainService::new(chained.clone()).with_key(chain_key());
+    assert_eq!(
+        reason(chain.verify_aggregate(aggregate_id.clone()).await?),
+        Some(ChainBreakReason::Unhashed)
+    );
+
+    let refused = chained
+        .store_events(vec![account_suspended(&aggregate_id, &sequences[1])])
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        refused.downcast_ref::<AccountError>(),
+        Some(AccountError::ChainNotBackfilled(x)) if x == &aggregate_id
+    ));
+
+    assert_eq!(chain.backfill().await?, 1);
+    chained
+        .store_events(vec![account_suspended(&aggregate_id, &sequences[1])])
+        .await?;
+    let report = chain.verify_aggregate(aggregate_id).await?;
+    assert_eq!(report.events, 2);
+    assert_eq!(report.first_break, None);
+    return Ok(());
+}
+
+async fn execute(store: &Store, query: &str, id: &str) -> Result<(), anyhow::Error> {
+    sqlx::query::<Sqlite>(query)
+        .bind(id)
+        .execute(&store.connector.pool)
+        .await?;
+    return Ok(());
+}
+
+fn reason(report: ChainReport) -> Option<ChainBreakReason> {
+    return report.first_break.map(|x| x.reason);
+}
+
+/// Seeds a suspended account, so its chain holds two events.
+async fn seed(store: &Store, email: &str) -> Result<String, anyhow::Error> {
+    let id = create_account(store, email).await?;
+    let _: AccountAggregate = store
+        .service
+        .suspend_account(
+            SuspendAccountCommand {
+                id: id.clone(),
+                reason: "abuse".into(),
+            },
+            RequestContext::new(),
+        )
+        .await?;
+    return Ok(id);
+}
+
+#[tokio::test]
+async fn intact_chains_verify() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    seed(&store, "intact@example.com").await?;
+    let report = chain(&store).verify_all().await?;
+    assert_eq!(report.aggregates, 1);
+    assert_eq!(report.events, 2);
+    assert_eq!(report.first_break, None);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn sqlite_refuses_appends_until_backfilled() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let unchained: Repository = Arc::new(SQLiteAccountRepository::new(store.connector.clone()));
+    return refuses_appends_until_backfilled(store.repository.clone(), unchained).await;
+}
+
+#[tokio::test]
+async fn memory_refuses_appends_until_backfilled() -> Result<(), anyhow::Error> {
+    let unchained = InMemoryAccountRepository::new();
+    let chained: Repository = Arc::new(unchained.clone().with_chain_key(chain_key()));
+    return refuses_appends_until_backfilled(chained, Arc::new(unchained)).await;
+}
+
+#[tokio::test]
+async fn tampered_payloads_break_the_chain() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = seed(&store, "payload@example.com").await?;
+    execute(
+        &store,
+        "UPDATE account_events SET payload = replace(payload, 'payload@', 'attacker@') WHERE aggregate_id = ?1",
+        &id,
+    )
+    .await?;
+    let report = chain(&store).verify_aggregate(id).await?;
+    assert!(matches!(
+        reason(report),
+        Some(ChainBreakReason::HashMismatch { .. })
+    ));
+    return Ok(());
+}
+
+#[tokio::test]
+async fn tampered_timestamps_break_the_chain() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = seed(&store, "timestamp@example.com").await?;
+    sqlx::query::<Sqlite>("UPDATE account_events SET timestamp = ?1 WHERE aggregate_id = ?2")
+        .bind((Utc::now() - Duration::days(30)).to_rfc3339())
+        .bind(&id)
+        .execute(&store.connector.pool)
+        .await?;
+    let report = chain(&store).verify_aggregate(id).await?;
+    assert!(matches!(
+        reason(report),
+        Some(ChainBreakReason::HashMismatch { .. })
+    ));
+    return Ok(());
+}
+
+#[tokio::test]
+async fn tampered_event_versions_break_the_chain() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = seed(&store, "type@example.com").await?;
+    execute(
+        &store,
+        "UPDATE account_events SET event_version = '9.9.9' WHERE aggregate_id = ?1",
+        &id,
+    )
+    .await?;
+    let report = chain(&store).verify_aggregate(id).await?;
+    assert!(matches!(
+        reason(report),
+        Some(ChainBreakReason::HashMismatch { .. })
+    ));
+    return Ok(());
+}
+
+#[tokio::test]
+async fn chains_hashed_with_another_key_do_not_verify() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = seed(&store, "key@example.com").await?;
+    let report = AccountChainService::new(store.repository.clone())
+        .with_key(Arc::new("another key".into()))
+        .verify_aggregate(id)
+        .await?;
+    assert!(matches!(
+        reason(report),
+        Some(ChainBreakReason::HashMismatch { .. })
+    ));
+    return Ok(());
+}
+
+#[tokio::test]
+async fn anchors_catch_truncated_chains() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = seed(&store, "truncated@example.com").await?;
+    let anchor = chain(&store).anchor().await?;
+    assert_eq!(anchor.heads.len(), 1);
+    assert_eq!(
+        chain(&store).verify_anchor(&anchor).await?.first_break,
+        None
+    );
+
+    // Dropping the newest event leaves a chain that verifies on its own.
+    execute(
+        &store,
+        "DELETE FROM account_events WHERE aggregate_id = ?1 AND sequence = (SELECT MAX(sequence) FROM account_events WHERE aggregate_id = ?1)",
+        &id,
+    )
+    .await?;
+    assert_eq!(chain(&store).verify_all().await?.first_break, None);
+    let report = chain(&store).verify_anchor(&anchor).await?;
+    assert_eq!(
+        reason(report),
+        Some(ChainBreakReason::Truncated {
+            anchored_events: 2,
+            found_events: 1
+        })
+    );
+    return Ok(());
+}
+
+#[tokio::test]
+async fn anchors_catch_deleted_aggregates() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    seed(&store, "kept@example.com").await?;
+    let deleted = seed(&store, "deleted@example.com").await?;
+    let anchor = chain(&store).anchor().await?;
+    execute(
+        &store,
+        "DELETE FROM account_events WHERE aggregate_id = ?1",
+        &deleted,
+    )
+    .await?;
+    assert_eq!(chain(&store).verify_all().await?.first_break, None);
+    let report = chain(&store).verify_anchor(&anchor).await?;
+    let truncated = report.first_break.unwrap();
+    assert_eq!(truncated.aggregate_id, deleted);
+    assert_eq!(
+        truncated.reason,
+        ChainBreakReason::Truncated {
+            anchored_events: 2,
+            found_events: 0
+        }
+    );
+    return Ok(());
+}
+
+#[tokio::test]
+async fn anchors_survive_appends() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    create_account(&store, "before@example.com").await?;
+    let anchor = chain(&store).anchor().await?;
+    create_account(&store, "after@example.com").await?;
+    let report = chain(&store).verify_anchor(&anchor).await?;
+    assert_eq!(report.first_break, None);
+    assert_eq!(report.aggregates, 1);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn altered_anchors_are_rejected() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    create_account(&store, "anchored@example.com").await?;
+    let mut anchor = chain(&store).anchor().await?;
+    anchor.heads.clear();
+    assert!(chain(&store).verify_anchor(&anchor).await.is_err());
+
+    let anchor: ChainAnchor =
+        serde_json::from_str(&serde_json::to_string(&chain(&store).anchor().await?)?)?;
+    assert!(chain(&store).verify_anchor(&anchor).await.is_ok());
+    let signed_elsewhere = AccountChainService::new(store.repository.clone())
+        .with_key(Arc::new("another key".into()))
+        .verify_anchor(&anchor)
+        .await;
+    assert!(signed_elsewhere.is_err());
+    return Ok(());
+}
+
+#[tokio::test]
+async fn broken_chains_are_not_anchored() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = create_account(&store, "broken@example.com").await?;
+    execute(
+        &store,
+        "UPDATE account_events SET aggregate_type = 'other' WHERE aggregate_id = ?1",
+        &id,
+    )
+    .await?;
+    assert!(chain(&store).anchor().await.is_err());
+    return Ok(());
+}
@@ -0,0 +1,129 @@
+//! Setup shared by the integration tests. Not every test file uses all of it.
+#![allow(dead_code)]
+
+use std::{path::PathBuf, str::FromStr, sync::Arc};
+
+use account::{
+    command::{
+        application::account::{
+            context::RequestContext,
+            ports::{
+                inbound::create_account::CreateAccountUseCase,
+                outbound::repository::AccountEventRepository,
+            },
+            service::account::AccountService,
+        },
+        domain::account::entity::{aggregate::AccountAggregate, command::CreateAccountCommand},
+        infrastructure::{
+            adapters::outbound::sqlite::SQLiteAccountRepository, encryption::fields::FieldCipher,
+        },
+    },
+    common::{
+        application::ports::outbound::account_services,
+        domain::secret::SecretToken,
+        infrastructure::adapters::outbound::account_services::{
+            argon2::AccountServices, pool::HashingPoolConfig,
+        },
+    },
+};
+use anyhow::anyhow;
+use cqrs_rs::{
+    domain::entity::event::EventEnvelope,
+    infrastructure::adapter::secondary::storage::sqlite::SqliteConnector,
+};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode},
+    Pool, Sqlite,
+};
+use tempfile::TempDir;
+
+pub type Envelope = EventEnvelope<AccountAggregate>;
+pub type Repository = Arc<dyn AccountEventRepository<Envelope, Envelope> + Send + Sync>;
+pub type Service = AccountService<Envelope, Envelope>;
+pub type Services = Arc<dyn account_services::AccountServices + Sync + Send>;
+
+pub const COMMAND_MIGRATIONS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/command/migrations");
+pub const QUERY_MIGRATIONS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/query/migrations");
+pub const CHAIN_KEY: &str = "test chain key";
+
+pub fn chain_key() -> Arc<SecretToken> {
+    return Arc::new(CHAIN_KEY.into());
+}
+
+/// A directory of SQLite files that is removed, with the files, on drop.
+pub struct TempDatabases {
+    dir: TempDir,
+}
+
+impl TempDatabases {
+    pub fn new() -> Result<Self, anyhow::Error> {
+        let dir = tempfile::Builder::new().prefix("account-test-").tempdir()?;
+        return Ok(Self { dir });
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        return self.dir.path().join(format!("{}.db", name));
+    }
+
+    pub async fn connect(&self, name: &str) -> Result<Arc<SqliteConnector>, anyhow::Error> {
+        let conn: Result<Pool<Sqlite>, anyhow::Error> = sqlx::Pool::connect_with(
+            SqliteConnectOptions::from_str(&format!("sqlite:{}", self.path(name).display()))?
+                .journal_mode(SqliteJournalMode::Wal)
+                .create_if_missing(true),
+        )
+        .await
+        .map_err(|e| anyhow!(e));
+        return SqliteConnector::new(conn).await;
+    }
+}
+
+/// A migrated SQLite event store with a service on top of it.
+pub struct Store {
+    pub connector: Arc<SqliteConnector>,
+    pub repository: Repository,
+    pub service: Service,
+    _databases: TempDatabases,
+}
+
+pub async fn store() -> Result<Store, anyhow::Error> {
+    return store_with_cipher(FieldCipher::default()).await;
+}
+
+pub async fn store_with_cipher(cipher: FieldCipher) -> Result<Store, anyhow::Error> {
+    let databases = TempDatabases::new()?;
+    let connector = databases.connect("events").await?;
+    let repository: Repository = Arc::new(
+        SQLiteAccountRepository::new(connector.clone())
+            .with_cipher(cipher)
+            .with_chain_key(chain_key()),
+    );
+    repository.migrate(COMMAND_MIGRATIONS.into()).await?;
+    return Ok(Store {
+        service: AccountService::new(services()?, repository.clone())
+            .with_idempotency_secret("idempotency secret".into()),
+        connector,
+        repository,
+        _databases: databases,
+    });
+}
+
+pub fn services() -> Result<Services, anyhow::Error> {
+    return Ok(Arc::new(
+        AccountServices::new(HashingPoolConfig::default())?,
+    ));
+}
+
+pub async fn create_account(store: &Store, email: &str) -> Result<String, anyhow::Error> {
+    let account: AccountAggregate = store
+        .service
+        .create_account(
+            CreateAccountCommand {
+                email: email.into(),
+                password: "correct horse battery staple".into(),
+            },
+            RequestContext::new(),
+            vec![],
+        )
+        .await?;
+    return Ok(account.id.unwrap());
+}
@@ -0,0 +1,235 @@
+mod common;
+
+use std::sync::Arc;
+
+use account::{
+    command::infrastructure::{
+        adapters::outbound::sqlite::SQLiteAccountRepository,
+        encryption::{
+            fields::{FieldBinding, FieldCipher, FieldCipherError, EMAIL_INDEX_FIELD},
+            rotation::KeyRotation,
+            sqlite::SQLiteKeyRotation,
+        },
+    },
+    common::infrastructure::adapters::outbound::key_provider::keyfile::KeyfileKeyProvider,
+};
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm,
+};
+use serde_json::{json, Value};
+use sqlx::{Row, Sqlite};
+
+use common::{chain_key, create_account, store_with_cipher, Repository};
+
+const EMAIL: &str = "sealed@example.com";
+
+fn key(seed: u8) -> String {
+    return format!("{:02x}", seed).repeat(32);
+}
+
+/// A cipher over `keys`, given as id and seed, encrypting under `current`.
+fn cipher(current: &str, keys: &[(&str, u8)]) -> FieldCipher {
+    let keys: serde_json::Map<String, Value> = keys
+        .iter()
+        .map(|(id, seed)| (id.to_string(), Value::String(key(*seed))))
+        .collect();
+    let keyfile = json!({ "current": current, "keys": keys, "index_key": key(0) });
+    return FieldCipher::new(Arc::new(
+        KeyfileKeyProvider::from_json(&keyfile.to_string()).unwrap(),
+    ));
+}
+
+fn payload() -> Value {
+    return json!({
+        "id": "01ACCOUNT",
+        "email": EMAIL,
+        "password_hash": "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA",
+        "last_event": { "email": EMAIL },
+    });
+}
+
+fn binding() -> FieldBinding<'static> {
+    return FieldBinding::event("01ACCOUNT", "01EVENT");
+}
+
+fn field_error(result: Result<Value, anyhow::Error>) -> FieldCipherError {
+    return result.unwrap_err().downcast::<FieldCipherError>().unwrap();
+}
+
+/// An envelope as written before values were bound to their record.
+fn unbound_envelope(key_id: &str, seed: u8, field: &str, plaintext: &str) -> String {
+    let seal = |key: &[u8], msg: &[u8], aad: &[u8]| {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            Aes256Gcm::new_from_slice(key)
+                .unwrap()
+                .encrypt(&nonce, Payload { msg, aad })
+                .unwrap(),
+        );
+        return hex::encode(sealed);
+    };
+    let data_key = Aes256Gcm::generate_key(&mut OsRng);
+    let sealed_value = seal(&data_key, plaintext.as_bytes(), field.as_bytes());
+    let sealed_key = seal(
+        &hex::decode(key(seed)).unwrap(),
+        &data_key,
+        key_id.as_bytes(),
+    );
+    return format!("enc:v1:{}:{}:{}", key_id, sealed_key, sealed_value);
+}
+
+#[test]
+fn fields_round_trip() -> Result<(), anyhow::Error> {
+    let cipher = cipher("k1", &[("k1", 1)]);
+    let encrypted = cipher.encrypt(payload(), binding())?;
+    for field in ["email", "password_hash"] {
+        assert!(encrypted[field].as_str().unwrap().starts_with("enc:v2:k1:"));
+    }
+    assert!(encrypted["last_event"]["email"]
+        .as_str()
+        .unwrap()
+        .starts_with("enc:v2:k1:"));
+    assert_eq!(
+        encrypted[EMAIL_INDEX_FIELD].as_str(),
+        cipher.email_index(EMAIL)?.as_deref()
+    );
+    assert_eq!(encrypted["id"], payload()["id"]);
+    assert_eq!(cipher.decrypt(encrypted, binding())?, payload());
+    return Ok(());
+}
+
+#[test]
+fn fields_only_decrypt_under_their_key() -> Result<(), anyhow::Error> {
+    let encrypted = cipher("k1", &[("k1", 1)]).encrypt(payload(), binding())?;
+    let wrong = cipher("k1", &[("k1", 2)]).decrypt(encrypted.clone(), binding());
+    assert!(matches!(field_error(wrong), FieldCipherError::Decrypt(_, x) if x == "k1"));
+    let missing = cipher("k2", &[("k2", 1)]).decrypt(encrypted.clone(), binding());
+    assert!(missing.is_err());
+    let disabled = FieldCipher::default().decrypt(encrypted, binding());
+    assert!(matches!(
+        field_error(disabled),
+        FieldCipherError::NoKeyProvider(_)
+    ));
+    return Ok(());
+}
+
+#[test]
+fn fields_only_decrypt_in_their_record() -> Result<(), anyhow::Error> {
+    let cipher = cipher("k1", &[("k1", 1)]);
+    let encrypted = cipher.encrypt(payload(), binding())?;
+    for other in [
+        FieldBinding::event("01ACCOUNT", "01OTHER"),
+        FieldBinding::event("01OTHER", "01EVENT"),
+        FieldBinding::snapshot("01ACCOUNT", "01EVENT"),
+        FieldBinding::outcome("01EVENT"),
+    ] {
+        let moved = cipher.decrypt(encrypted.clone(), other);
+        assert!(matches!(field_error(moved), FieldCipherError::Decrypt(..)));
+    }
+    return Ok(());
+}
+
+#[test]
+fn malformed_envelopes_are_rejected() -> Result<(), anyhow::Error> {
+    let cipher = cipher("k1", &[("k1", 1)]);
+    let sealed = cipher.encrypt(payload(), binding())?["email"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let parts: Vec<&str> = sealed.split(':').collect();
+    for envelope in [
+        "enc:v2:k1".to_string(),
+        "enc:v2:k1:00:00:00".to_string(),
+        format!("enc:v2::{}:{}", parts[3], parts[4]),
+        format!("enc:v2:k1:zz:{}", parts[4]),
+        format!("enc:v2:k1:{}:{}", parts[3], &parts[4][..20]),
+    ] {
+        let result = cipher.decrypt(json!({ "email": envelope }), binding());
+        assert!(result.is_err(), "{} was accepted", envelope);
+    }
+    let short = cipher.decrypt(json!({ "email": "enc:v2:k1" }), binding());
+    assert!(matches!(field_error(short), FieldCipherError::Malformed(_)));
+    // Values that only look like an envelope's prefix are plaintext.
+    let plain = json!({ "email": "enc:someone@example.com" });
+    assert_eq!(cipher.decrypt(plain.clone(), binding())?, plain);
+    return Ok(());
+}
+
+#[test]
+fn rotation_reseals_onto_the_current_key() -> Result<(), anyhow::Error> {
+    let old = cipher("k1", &[("k1", 1)]);
+    let both = cipher("k2", &[("k1", 1), ("k2", 2)]);
+    let new = cipher("k2", &[("k2", 2)]);
+    let encrypted = old.encrypt(payload(), binding())?;
+    assert!(new.decrypt(encrypted.clone(), binding()).is_err());
+
+    let rotated = both.rotate(encrypted, binding())?.unwrap();
+    assert!(rotated["email"].as_str().unwrap().starts_with("enc:v2:k2:"));
+    assert_eq!(rotated[EMAIL_INDEX_FIELD], json!(old.email_index(EMAIL)?));
+    assert_eq!(new.decrypt(rotated.clone(), binding())?, payload());
+    assert!(both.rotate(rotated, binding())?.is_none());
+
+    let plaintext = both.rotate(payload(), binding())?.unwrap();
+    assert_eq!(new.decrypt(plaintext, binding())?, payload());
+    return Ok(());
+}
+
+#[test]
+fn rotation_binds_unbound_envelopes() -> Result<(), anyhow::Error> {
+    let cipher = cipher("k1", &[("k1", 1)]);
+    let legacy = json!({ "email": unbound_envelope("k1", 1, "email", EMAIL) });
+    assert_eq!(
+        cipher.decrypt(legacy.clone(), binding())?,
+        json!({ "email": EMAIL })
+    );
+    let rotated = cipher.rotate(legacy, binding())?.unwrap();
+    assert!(rotated["email"].as_str().unwrap().starts_with("enc:v2:k1:"));
+    assert!(cipher
+        .decrypt(rotated.clone(), FieldBinding::event("01ACCOUNT", "01OTHER"))
+        .is_err());
+    assert_eq!(
+        cipher.decrypt(rotated, binding())?,
+        json!({ "email": EMAIL })
+    );
+    return Ok(());
+}
+
+#[tokio::test]
+async fn emails_are_found_after_a_store_rotation() -> Result<(), anyhow::Error> {
+    let store = store_with_cipher(cipher("k1", &[("k1", 1)])).await?;
+    let id = create_account(&store, EMAIL).await?;
+    let report = SQLiteKeyRotation::new(
+        store.connector.clone(),
+        cipher("k2", &[("k1", 1), ("k2", 2)]),
+    )
+    .run()
+    .await?;
+    assert_eq!(report.rotated, report.scanned);
+    assert!(report.rotated > 0);
+
+    let stored: Vec<String> = sqlx::query::<Sqlite>("SELECT payload FROM account_events")
+        .fetch_all(&store.connector.pool)
+        .await?
+        .into_iter()
+        .map(|x| x.get(0))
+        .collect();
+    assert!(stored.iter().all(|x| !x.contains("enc:v2:k1:")));
+
+    // The old key is gone once every payload was rotated.
+    let repository: Repository = Arc::new(
+        SQLiteAccountRepository::new(store.connector.clone())
+            .with_cipher(cipher("k2", &[("k2", 2)]))
+            .with_chain_key(chain_key()),
+    );
+    assert!(repository.email_exists(EMAIL.into()).await?);
+    assert_eq!(
+        repository
+            .retrieve_aggregate_id_for_email(EMAIL.into())
+            .await?,
+        id
+    );
+    assert_eq!(repository.retrieve_events(id, None).await?.len(), 1);
+    return Ok(());
+}
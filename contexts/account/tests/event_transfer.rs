@@ -0,0 +1,267 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use account::command::{
+    application::account::{
+        context::RequestContext,
+        ports::inbound::{
+            force_snapshot::ForceSnapshotUseCase, suspend_account::SuspendAccountUseCase,
+        },
+    },
+    domain::account::entity::{aggregate::AccountAggregate, command::SuspendAccountCommand},
+    infrastructure::transfer::{
+        jsonl::ExportFilter,
+        sqlite::{SQLiteEventExport, SQLiteEventImport},
+    },
+};
+use chrono::{DateTime, Utc};
+
+use common::{chain_key, create_account, store, Store};
+
+/// Seeds two accounts, one suspended and snapshotted, and returns their ids.
+async fn seed(store: &Store) -> Result<(String, String), anyhow::Error> {
+    let first = create_account(store, "first@example.com").await?;
+    let second = create_account(store, "second@example.com").await?;
+    let mut context = RequestContext::new();
+    context.actor = Some("operator:test".into());
+    let _: AccountAggregate = store
+        .service
+        .suspend_account(
+            SuspendAccountCommand {
+                id: first.clone(),
+                reason: "chargeback".into(),
+            },
+            context,
+        )
+        .await?;
+    store.service.force_snapshot(first.clone()).await?;
+    return Ok((first, second));
+}
+
+async fn export(store: &Store, filter: ExportFilter) -> Result<Vec<u8>, anyhow::Error> {
+    let mut buffer: Vec<u8> = vec![];
+    SQLiteEventExport::new(store.connector.clone())
+        .with_filter(filter)
+        .with_batch_size(2)
+        .run(&mut buffer)
+        .await?;
+    return Ok(buffer);
+}
+
+/// Every event in the global log: position, aggregate, sequence, payload,
+/// metadata in key order and timestamp.
+type Replayed = (
+    i64,
+    String,
+    String,
+    String,
+    BTreeMap<String, String>,
+    DateTime<Utc>,
+);
+
+async fn replay(store: &Store) -> Result<Vec<Replayed>, anyhow::Error> {
+    let events = store.repository.read_all(0, 1000).await?;
+    return Ok(events
+        .into_iter()
+        .map(|x| {
+            (
+                x.position,
+                x.event.aggregate_id,
+                x.event.sequence,
+                format!("{:?}", x.event.payload),
+                x.event.metadata.into_iter().collect(),
+                x.event.timestamp,
+            )
+        })
+        .collect());
+}
+
+#[tokio::test]
+async fn export_then_import_replays_identically() {
+    let source = store().await.unwrap();
+    let (first, second) = seed(&source).await.unwrap();
+    let exported = export(&source, ExportFilter::default()).await.unwrap();
+
+    let target = store().await.unwrap();
+    let dry_run = SQLiteEventImport::new(target.connector.clone())
+        .with_chain_key(chain_key())
+        .with_dry_run(true)
+        .run(exported.as_slice())
+        .await
+        .unwrap();
+    assert_eq!(dry_run.events, 3);
+    assert_eq!(dry_run.snapshots, 1);
+    assert_eq!(dry_run.aggregates, 2);
+    assert!(replay(&target).await.unwrap().is_empty());
+
+    SQLiteEventImport::new(target.connector.clone())
+        .with_chain_key(chain_key())
+        .run(exported.as_slice())
+        .await
+        .unwrap();
+    assert_eq!(
+        replay(&source).await.unwrap(),
+        replay(&target).await.unwrap()
+    );
+    for id in [first.clone(), second] {
+        let expected = source.service.load_aggregate(id.clone()).await.unwrap();
+        let actual = target.service.load_aggregate(id).await.unwrap();
+        assert_eq!(format!("{:?}", expected), format!("{:?}", actual));
+    }
+    let expected = source
+        .repository
+        .retrieve_latest_snapshot(first.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    let actual = target
+        .repository
+        .retrieve_latest_snapshot(first)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(expected.snapshot_id, actual.snapshot_id);
+    assert_eq!(expected.last_sequence, actual.last_sequence);
+    assert!(target
+        .repository
+        .retrieve_outbox_batch(100)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn import_rejects_duplicates_without_writing() {
+    let source = store().await.unwrap();
+    seed(&source).await.unwrap();
+    let exported = export(&source, ExportFilter::default()).await.unwrap();
+
+    let target = store().await.unwrap();
+    SQLiteEventImport::new(target.connector.clone())
+        .with_chain_key(chain_key())
+        .run(exported.as_slice())
+        .await
+        .unwrap();
+    let before = replay(&target).await.unwrap();
+    let result = SQLiteEventImport::new(target.connector.clone())
+        .with_chain_key(chain_key())
+        .run(exported.as_slice())
+        .await;
+    assert!(result.is_err());
+    assert_eq!(before, replay(&target).await.unwrap());
+}
+
+#[tokio::test]
+async fn import_rejects_out_of_order_streams() {
+    let source = store().await.unwrap();
+    let (first, _) = seed(&source).await.unwrap();
+    let exported =
+        String::from_utf8(export(&source, ExportFilter::default()).await.unwrap()).unwrap();
+    let mut lines: Vec<&str> = exported.lines().collect();
+    let events: Vec<usize> = (1..lines.len())
+        .filter(|x| lines[*x].contains(&first) && lines[*x].contains("\"record\":\"event\""))
+        .collect();
+    lines.swap(events[0], events[1]);
+    let reordered = lines.join("\n");
+
+    let target = store().await.unwrap();
+    let result = SQLiteEventImport::new(target.connector.clone())
+        .with_chain_key(chain_key())
+        .with_dry_run(true)
+        .run(reordered.as_bytes())
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn export_filters_by_aggregate() {
+    let source = store().await.unwrap();
+    let (_, second) = seed(&source).await.unwrap();
+    let exported = export(
+        &source,
+        ExportFilter {
+            aggregate_id: Some(second.clone()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let target = store().await.unwrap();
+    let report = SQLiteEventImport::new(target.connector.clone())
+        .with_chain_key(chain_key())
+        .run(exported.as_slice())
+        .await
+        .unwrap();
+    assert_eq!(report.events, 1);
+    assert_eq!(report.snapshots, 0);
+    let events = replay(&target).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].1, second);
+}
+
+#[tokio::test]
+async fn import_rejects_tampered_events() {
+    let source = store().await.unwrap();
+    seed(&source).await.unwrap();
+    let exported =
+        String::from_utf8(export(&source, ExportFilter::default()).await.unwrap()).unwrap();
+    assert!(exported.contains("chargeback"));
+    let tampered = exported.replace("chargeback", "fraud");
+
+    let target = store().await.unwrap();
+    let result = SQLiteEventImport::new(target.connector.clone())
+        .with_chain_key(chain_key())
+        .run(tampered.as_bytes())
+        .await;
+    assert!(result.unwrap_err().to_string().contains("hash chain"));
+    assert!(replay(&target).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn partial_streams_only_continue_stored_ones() {
+    let source = store().await.unwrap();
+    let (first, _) = seed(&source).await.unwrap();
+    let events = replay(&source).await.unwrap();
+    // Only the suspension of the first account falls in the window.
+    let tail = export(
+        &source,
+        ExportFilter {
+            aggregate_id: Some(first.clone()),
+            from: Some(events[2].5),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let target = store().await.unwrap();
+    let result = SQLiteEventImport::new(target.connector.clone())
+        .with_chain_key(chain_key())
+        .with_dry_run(true)
+        .run(tail.as_slice())
+        .await;
+    assert!(result.unwrap_err().to_string().contains("creation"));
+
+    let head = export(
+        &source,
+        ExportFilter {
+            aggregate_id: Some(first.clone()),
+            to: Some(events[2].5),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    for part in [head, tail] {
+        SQLiteEventImport::new(target.connector.clone())
+            .with_chain_key(chain_key())
+            .run(part.as_slice())
+            .await
+            .unwrap();
+    }
+    let expected = source.service.load_aggregate(first.clone()).await.unwrap();
+    let actual = target.service.load_aggregate(first).await.unwrap();
+    assert_eq!(format!("{:?}", expected), format!("{:?}", actual));
+}
@@ -0,0 +1,248 @@
+mod common;
+
+use std::{
+    sync::{mpsc, Arc, Barrier},
+    time::{Duration, Instant},
+};
+
+use account::{
+    command::{
+        application::account::{
+            context::RequestContext, ports::inbound::suspend_account::SuspendAccountUseCase,
+            service::account::AccountService,
+        },
+        domain::account::entity::{
+            aggregate::AccountAggregate,
+            command::{CreateAccountCommand, SuspendAccountCommand},
+            error::AccountError,
+        },
+        infrastructure::adapters::inbound::graphql::schema,
+    },
+    common::{
+        application::ports::outbound::account_services::{
+            self, PasswordCheck, PasswordHashingError, TAccountServices,
+        },
+        domain::secret::{Password, PasswordHash},
+        infrastructure::adapters::outbound::account_services::{
+            argon2::AccountServices,
+            pool::{HashingPool, HashingPoolConfig},
+        },
+    },
+};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use cqrs_rs::domain::entity::aggregate::Aggregate;
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+use common::{create_account, store, Service, Services};
+
+fn pool(concurrency: usize, queue_timeout: Duration) -> Result<Arc<HashingPool>, anyhow::Error> {
+    return Ok(Arc::new(HashingPool::new(HashingPoolConfig {
+        concurrency,
+        queue_timeout,
+    })?));
+}
+
+/// Occupies a slot of `pool` until the returned sender is used or dropped.
+async fn occupy(
+    pool: &Arc<HashingPool>,
+) -> (
+    mpsc::Sender<()>,
+    tokio::task::JoinHandle<Result<i32, PasswordHashingError>>,
+) {
+    let (release, released) = mpsc::channel::<()>();
+    let (started, running) = oneshot::channel::<()>();
+    let pool = pool.clone();
+    let job = tokio::spawn(async move {
+        return pool
+            .run(move || {
+                let _ = started.send(());
+                let _ = released.recv();
+                return Ok(1);
+            })
+            .await;
+    });
+    running.await.unwrap();
+    return (release, job);
+}
+
+#[test]
+fn pools_need_at_least_one_thread() {
+    assert!(pool(0, Duration::from_secs(1)).is_err());
+    let services = AccountServices::new(HashingPoolConfig {
+        concurrency: 0,
+        queue_timeout: Duration::from_secs(1),
+    });
+    assert!(services.is_err());
+}
+
+#[tokio::test]
+async fn saturated_pools_refuse_jobs_after_the_queue_timeout() -> Result<(), anyhow::Error> {
+    let pool = pool(1, Duration::from_millis(50))?;
+    let (release, busy) = occupy(&pool).await;
+
+    let started = Instant::now();
+    let refused = pool.run(|| Ok(2)).await;
+    assert!(matches!(refused, Err(PasswordHashingError::Saturated)));
+    assert!(started.elapsed() >= Duration::from_millis(50));
+
+    release.send(())?;
+    assert_eq!(busy.await??, 1);
+    assert_eq!(pool.run(|| Ok(3)).await?, 3);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn queued_jobs_run_once_a_thread_frees_up_in_time() -> Result<(), anyhow::Error> {
+    let pool = pool(1, Duration::from_secs(10))?;
+    let (release, busy) = occupy(&pool).await;
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let _ = release.send(());
+    });
+    assert_eq!(pool.run(|| Ok(2)).await?, 2);
+    assert_eq!(busy.await??, 1);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn jobs_run_side_by_side_up_to_the_concurrency() -> Result<(), anyhow::Error> {
+    let pool = pool(2, Duration::from_secs(10))?;
+    // Each job waits for the other, so they only finish if both run at once.
+    let barrier = Arc::new(Barrier::new(2));
+    let jobs = (0..2).map(|x| {
+        let barrier = barrier.clone();
+        return pool.run(move || {
+            barrier.wait();
+            return Ok(x);
+        });
+    });
+    let results = futures::future::join_all(jobs).await;
+    assert!(results.iter().all(|x| x.is_ok()));
+    return Ok(());
+}
+
+#[tokio::test]
+async fn failing_jobs_leave_the_pool_running() -> Result<(), anyhow::Error> {
+    let pool = pool(1, Duration::from_secs(1))?;
+    let failed = pool.run::<_, i32>(|| Err(anyhow!("bad hash"))).await;
+    assert!(matches!(failed, Err(PasswordHashingError::Failed(_))));
+    let panicked = pool.run::<_, i32>(|| panic!("hasher panicked")).await;
+    assert!(matches!(panicked, Err(PasswordHashingError::Failed(_))));
+    assert_eq!(pool.run(|| Ok(1)).await?, 1);
+    return Ok(());
+}
+
+#[test]
+fn only_saturation_is_retryable() {
+    let saturated = AccountError::from(PasswordHashingError::Saturated);
+    assert!(matches!(
+        saturated,
+        AccountError::PasswordHashingUnavailable
+    ));
+    assert!(saturated.is_retryable());
+    let failed = AccountError::from(PasswordHashingError::Failed(anyhow!("bad hash")));
+    assert!(matches!(failed, AccountError::PasswordHashingFailed(_)));
+    assert!(!failed.is_retryable());
+}
+
+/// Services whose hashing pool never has a free slot.
+#[derive(Debug)]
+struct SaturatedServices;
+
+#[async_trait]
+impl TAccountServices for SaturatedServices {
+    fn hash_password(&self, _password: Password) -> Result<PasswordHash, anyhow::Error> {
+        return Err(anyhow!("hashing on the caller's thread"));
+    }
+
+    fn verify_password(
+        &self,
+        _password: Password,
+        _password_hash: PasswordHash,
+    ) -> Result<PasswordCheck, anyhow::Error> {
+        return Err(anyhow!("hashing on the caller's thread"));
+    }
+
+    async fn hash_password_async(
+        &self,
+        _password: Password,
+    ) -> Result<PasswordHash, PasswordHashingError> {
+        return Err(PasswordHashingError::Saturated);
+    }
+
+    async fn verify_password_async(
+        &self,
+        _password: Password,
+        _password_hash: PasswordHash,
+    ) -> Result<PasswordCheck, PasswordHashingError> {
+        return Err(PasswordHashingError::Saturated);
+    }
+}
+
+impl account_services::AccountServices for SaturatedServices {}
+
+fn create_account_mutation(email: &str) -> String {
+    return format!(
+        r#"mutation {{ createAccount(input: {{ email: "{}", password: "correct horse battery staple" }}) {{ id }} }}"#,
+        email
+    );
+}
+
+#[tokio::test]
+async fn saturation_reaches_graphql_clients_as_retryable() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    create_account(&store, "taken@example.com").await?;
+    let service: Service =
+        AccountService::new(Arc::new(SaturatedServices), store.repository.clone());
+    let schema = schema(Arc::new(service));
+
+    let response = schema
+        .execute(create_account_mutation("new@example.com"))
+        .await;
+    let errors = serde_json::to_value(&response.errors)?;
+    assert_eq!(errors[0]["extensions"], json!({ "retryable": true }));
+
+    // Refused before any hashing, and no retry would change that.
+    let response = schema
+        .execute(create_account_mutation("taken@example.com"))
+        .await;
+    let errors = serde_json::to_value(&response.errors)?;
+    assert_eq!(errors.as_array().map(|x| x.len()), Some(1));
+    assert_eq!(errors[0]["extensions"], Value::Null);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn refused_commands_are_never_hashed() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = create_account(&store, "suspended@example.com").await?;
+    let suspended: AccountAggregate = store
+        .service
+        .suspend_account(
+            SuspendAccountCommand {
+                id,
+                reason: "abuse".into(),
+            },
+            RequestContext::new(),
+        )
+        .await?;
+    // Hashing would fail as saturated, so only the machine can refuse it.
+    let saturated: Services = Arc::new(SaturatedServices);
+    let refused = suspended
+        .handle(
+            CreateAccountCommand {
+                email: "suspended@example.com".into(),
+                password: "correct horse battery staple".into(),
+            }
+            .into(),
+            &saturated,
+        )
+        .await;
+    assert!(matches!(
+        refused,
+        Err(AccountError::StateMachineTransitionFail(_))
+    ));
+    return Ok(());
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use std::time::Duration;
+
+use account::command::{
+    application::account::{
+        context::RequestContext, ports::inbound::create_account::CreateAccountUseCase,
+        service::account::AccountService,
+    },
+    domain::account::entity::{
+        aggregate::AccountAggregate, command::CreateAccountCommand, error::AccountError,
+    },
+};
+use chrono::Utc;
+use sqlx::{Row, Sqlite};
+
+use common::{services, store, Service, Store};
+
+const PASSWORD: &str = "correct horse battery staple";
+
+fn command(email: &str, password: &str) -> CreateAccountCommand {
+    return CreateAccountCommand {
+        email: email.into(),
+        password: password.into(),
+    };
+}
+
+fn context(key: &str) -> RequestContext {
+    let mut context = RequestContext::new();
+    context.idempotency_key = Some(key.into());
+    return context;
+}
+
+async fn create(
+    service: &Service,
+    key: &str,
+    command: CreateAccountCommand,
+) -> Result<AccountAggregate, anyhow::Error> {
+    return service.create_account(command, context(key), vec![]).await;
+}
+
+async fn stored_hash(store: &Store, key: &str) -> Result<String, anyhow::Error> {
+    let row = sqlx::query::<Sqlite>(
+        "SELECT command_hash FROM account_idempotency_keys WHERE idempotency_key = ?1",
+    )
+    .bind(key)
+    .fetch_one(&store.connector.pool)
+    .await?;
+    return Ok(row.get(0));
+}
+
+async fn insert_abandoned(store: &Store, key: &str, age: Duration) -> Result<(), anyhow::Error> {
+    sqlx::query::<Sqlite>(
+        "INSERT INTO account_idempotency_keys (idempotency_key, command_hash, status, created_at) VALUES ( ?1, 'abandoned', 'pending', ?2 )",
+    )
+    .bind(key)
+    .bind(Utc::now() - chrono::Duration::from_std(age)?)
+    .execute(&store.connector.pool)
+    .await?;
+    return Ok(());
+}
+
+#[tokio::test]
+async fn replays_return_the_first_outcome() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let first = create(
+        &store.service,
+        "replay",
+        command("replay@example.com", PASSWORD),
+    )
+    .await?;
+    let second = create(
+        &store.service,
+        "replay",
+        command("replay@example.com", PASSWORD),
+    )
+    .await?;
+    assert_eq!(first.id, second.id);
+    let error = create(
+        &store.service,
+        "replay",
+        command("other@example.com", PASSWORD),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<AccountError>(),
+        Some(AccountError::IdempotencyKeyReused(_))
+    ));
+    return Ok(());
+}
+
+#[tokio::test]
+async fn idempotency_keys_need_a_secret() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let service: Service = AccountService::new(services()?, store.repository.clone());
+    let keyed = create(&service, "plain", command("plain@example.com", PASSWORD)).await;
+    assert!(keyed.is_err());
+    let unkeyed: Result<AccountAggregate, anyhow::Error> = service
+        .create_account(
+            command("plain@example.com", PASSWORD),
+            RequestContext::new(),
+            vec![],
+        )
+        .await;
+    assert!(unkeyed.is_ok());
+    return Ok(());
+}
+
+#[tokio::test]
+async fn keyed_fingerprints_cover_the_password() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let services = services()?;
+    let keyed = |secret: &str| {
+        return AccountService::new(services.clone(), store.repository.clone())
+            .with_idempotency_secret(secret.into());
+    };
+    let service: Service = keyed("server secret");
+    create(&service, "keyed", command("keyed@example.com", PASSWORD)).await?;
+    let error = create(&service, "keyed", command("keyed@example.com", "other"))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<AccountError>(),
+        Some(AccountError::IdempotencyKeyReused(_))
+    ));
+
+    let hash = stored_hash(&store, "keyed").await?;
+    let rotated: Service = keyed("another secret");
+    create(
+        &rotated,
+        "rekeyed",
+        command("rekeyed@example.com", PASSWORD),
+    )
+    .await?;
+    assert_ne!(hash, stored_hash(&store, "rekeyed").await?);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn fresh_pending_keys_are_in_progress() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    create(
+        &store.service,
+        "busy",
+        command("busy@example.com", PASSWORD),
+    )
+    .await?;
+    sqlx::query::<Sqlite>(
+        "UPDATE account_idempotency_keys SET status = 'pending', outcome = NULL, created_at = ?1 WHERE idempotency_key = 'busy'",
+    )
+    .bind(Utc::now())
+    .execute(&store.connector.pool)
+    .await?;
+    let error = create(
+        &store.service,
+        "busy",
+        command("busy@example.com", PASSWORD),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<AccountError>(),
+        Some(AccountError::IdempotencyKeyInProgress(_))
+    ));
+    return Ok(());
+}
+
+#[tokio::test]
+async fn stale_pending_keys_are_taken_over() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    insert_abandoned(&store, "stale", Duration::from_secs(60)).await?;
+    let service: Service = AccountService::new(services()?, store.repository.clone())
+        .with_idempotency_secret("idempotency secret".into())
+        .with_idempotency_ttl(Duration::from_secs(30));
+    let account = create(&service, "stale", command("stale@example.com", PASSWORD)).await?;
+    let replay = create(&service, "stale", command("stale@example.com", PASSWORD)).await?;
+    assert_eq!(account.id, replay.id);
+    assert_ne!(stored_hash(&store, "stale").await?, "abandoned");
+    return Ok(());
+}
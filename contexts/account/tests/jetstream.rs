@@ -0,0 +1,114 @@
+use std::{
+    collections::HashMap,
+    net::TcpListener,
+    process::{Child, Command},
+    sync::Arc,
+    time::Duration,
+};
+
+use account::command::{
+    domain::account::entity::{aggregate::AccountAggregate, event::AccountEvent},
+    infrastructure::adapters::outbound::jetstream::JetStreamEventBus,
+};
+use chrono::Utc;
+use cqrs_rs::{
+    application::port::outbound::event_bus::EventBus,
+    domain::entity::event::EventEnvelope,
+};
+use tempfile::TempDir;
+use ulid::Ulid;
+
+struct NatsServer {
+    child: Child,
+    url: String,
+    _store: TempDir,
+}
+
+impl Drop for NatsServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Needs `nats-server` on the PATH; run with `cargo test -- --ignored`.
+fn spawn_nats_server() -> NatsServer {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let store = tempfile::Builder::new().prefix("nats-").tempdir().unwrap();
+    let child = Command::new("nats-server")
+        .args(["-js", "-a", "127.0.0.1", "-p", &port.to_string()])
+        .arg("-sd")
+        .arg(store.path())
+        .spawn()
+        .expect("nats-server must be on PATH to run the jetstream tests");
+    return NatsServer {
+        child,
+        url: format!("nats://127.0.0.1:{}", port),
+        _store: store,
+    };
+}
+
+async fn connect(url: &str) -> JetStreamEventBus {
+    for _ in 0..50 {
+        if let Ok(x) = JetStreamEventBus::connect(url).await {
+            return x;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("nats-server at {} did not come up", url);
+}
+
+fn envelope(id: &str, event_id: &str) -> EventEnvelope<AccountAggregate> {
+    let id = id.to_string();
+    let event_id = event_id.to_string();
+    return EventEnvelope {
+        aggregate_id: id.clone(),
+        aggregate_type: "account".into(),
+        sequence: event_id.clone(),
+        payload: AccountEvent::AccountCreated {
+            id,
+            email: "jetstream@example.com".into(),
+            password_hash: "hash".into(),
+            created_at: Utc::now(),
+            event_version: "0.0.1".into(),
+            event_id,
+        },
+        metadata: HashMap::new(),
+        timestamp: Utc::now(),
+    };
+}
+
+#[tokio::test]
+#[ignore = "needs nats-server on PATH"]
+async fn publishes_to_aggregate_subject_and_deduplicates_by_sequence() {
+    let server = spawn_nats_server();
+    let bus = connect(&server.url).await;
+    let mut stream = bus
+        .ensure_stream("ACCOUNT_EVENTS", Duration::from_secs(60))
+        .await
+        .unwrap();
+    let id = Ulid::new().to_string();
+    let event_id = Ulid::new().to_string();
+    let subject = bus.subject(&envelope(&id, &event_id));
+    assert_eq!(subject, format!("account.events.{}.AccountCreated", id));
+    let bus: Arc<
+        dyn EventBus<
+                EventEnvelope<AccountAggregate>,
+                EventEnvelope<AccountAggregate>,
+                EventEnvelope<AccountAggregate>,
+                EventEnvelope<AccountAggregate>,
+            > + Send
+            + Sync,
+    > = Arc::new(bus);
+    bus.send_event(envelope(&id, &event_id)).await.unwrap();
+    bus.send_event(envelope(&id, &event_id)).await.unwrap();
+
+    let info = stream.info().await.unwrap();
+    assert_eq!(info.state.messages, 1);
+    let message = stream.get_last_raw_message_by_subject(&subject).await.unwrap();
+    assert_eq!(message.subject, subject);
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use account::command::{
+    application::account::{
+        ports::inbound::{load_account::LoadAccountUseCase, verify_chain::VerifyChainUseCase},
+        service::chain::AccountChainService,
+    },
+    domain::account::entity::aggregate::AccountAggregate,
+    infrastructure::migration::sqlite::{MigrationStatus, SQLiteEventStreamMigration},
+};
+use anyhow::anyhow;
+use sqlx::{Row, Sqlite};
+
+use common::{chain_key, create_account, store, Store};
+
+async fn seed() -> Result<(Store, Vec<String>), anyhow::Error> {
+    let store = store().await?;
+    let mut ids = vec![];
+    for i in 0..3 {
+        ids.push(create_account(&store, &format!("migrate{}@example.com", i)).await?);
+    }
+    return Ok((store, ids));
+}
+
+async fn column(store: &Store, query: &str) -> Result<Vec<String>, anyhow::Error> {
+    let rows = sqlx::query::<Sqlite>(query)
+        .fetch_all(&store.connector.pool)
+        .await?;
+    return Ok(rows.into_iter().map(|x| x.get(0)).collect());
+}
+
+async fn status(store: &Store, migration_id: &str) -> Result<(String, i64), anyhow::Error> {
+    let row = sqlx::query::<Sqlite>(
+        "SELECT status, last_rowid FROM account_event_migrations WHERE migration_id = ?1",
+    )
+    .bind(migration_id)
+    .fetch_one(&store.connector.pool)
+    .await?;
+    return Ok((row.get(0), row.get(1)));
+}
+
+#[tokio::test]
+async fn interrupted_migrations_resume_from_their_checkpoint() -> Result<(), anyhow::Error> {
+    let (store, ids) = seed().await?;
+    let copied = Arc::new(AtomicUsize::new(0));
+    let counter = copied.clone();
+    let interrupted =
+        SQLiteEventStreamMigration::new(store.connector.clone(), "resume", move |x| {
+            if counter.fetch_add(1, Ordering::SeqCst) == 1 {
+                return Err(anyhow!("interrupted"));
+            }
+            return Ok(x);
+        })?
+        .with_chain_key(chain_key())
+        .with_batch_size(1);
+    assert!(interrupted.run().await.is_err());
+    let (status_before, last_rowid) = status(&store, "resume").await?;
+    assert_eq!(status_before, "copying");
+    assert_eq!(last_rowid, 1);
+
+    let transformed = Arc::new(AtomicUsize::new(0));
+    let counter = transformed.clone();
+    let resumed = SQLiteEventStreamMigration::new(store.connector.clone(), "resume", move |x| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        return Ok(x);
+    })?
+    .with_chain_key(chain_key())
+    .with_batch_size(1);
+    let report = resumed.run().await?;
+    assert_eq!(report.status, MigrationStatus::Completed);
+    assert_eq!(report.events, ids.len() as i64);
+    assert_eq!(
+        transformed.load(Ordering::SeqCst),
+        ids.len() - 1,
+        "the committed batch was copied again"
+    );
+    let chain = AccountChainService::new(store.repository.clone())
+        .with_key(chain_key())
+        .verify_all()
+        .await?;
+    assert_eq!(chain.events, ids.len() as i64);
+    assert_eq!(chain.first_break, None);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn migrations_that_change_replayed_state_are_not_swapped_in() -> Result<(), anyhow::Error> {
+    let (store, _) = seed().await?;
+    let before = column(&store, "SELECT payload FROM account_events ORDER BY rowid").await?;
+    let migration = SQLiteEventStreamMigration::new(store.connector.clone(), "lossy", |mut x| {
+        x.payload = x.payload.replace("migrate1@", "changed@");
+        return Ok(x);
+    })?;
+    let error = migration.run().await.unwrap_err();
+    assert!(
+        error.to_string().contains("changed the replayed state"),
+        "{}",
+        error
+    );
+    assert_eq!(status(&store, "lossy").await?.0, "copying");
+    assert_eq!(
+        column(&store, "SELECT payload FROM account_events ORDER BY rowid").await?,
+        before
+    );
+    let tables = column(
+        &store,
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'account_events_pre_lossy'",
+    )
+    .await?;
+    assert!(tables.is_empty());
+    return Ok(());
+}
+
+#[tokio::test]
+async fn verified_migrations_swap_tables_and_keep_positions() -> Result<(), anyhow::Error> {
+    let (store, ids) = seed().await?;
+    let positions = column(
+        &store,
+        "SELECT aggregate_id || ':' || position FROM account_events ORDER BY position",
+    )
+    .await?;
+    let migration = SQLiteEventStreamMigration::new(store.connector.clone(), "tagged", |mut x| {
+        x.metadata = r#"{"migrated":"tagged"}"#.into();
+        return Ok(x);
+    })?;
+    let report = migration.run().await?;
+    assert_eq!(report.status, MigrationStatus::Completed);
+    assert_eq!(report.aggregates, ids.len() as i64);
+
+    let metadata = column(&store, "SELECT metadata FROM account_events").await?;
+    assert!(metadata.iter().all(|x| x == r#"{"migrated":"tagged"}"#));
+    let previous = column(&store, "SELECT metadata FROM account_events_pre_tagged").await?;
+    assert_eq!(previous.len(), ids.len());
+    assert!(previous.iter().all(|x| x != r#"{"migrated":"tagged"}"#));
+    assert_eq!(
+        column(
+            &store,
+            "SELECT aggregate_id || ':' || position FROM account_events ORDER BY position",
+        )
+        .await?,
+        positions
+    );
+    let indexes = column(
+        &store,
+        "SELECT name FROM sqlite_master WHERE type = 'index' AND tbl_name = 'account_events' AND sql IS NOT NULL",
+    )
+    .await?;
+    assert!(!indexes.is_empty());
+    for id in &ids {
+        let account: Option<AccountAggregate> = store.service.load_account(id.clone()).await?;
+        assert_eq!(account.and_then(|x| x.id).as_ref(), Some(id));
+    }
+
+    let again = migration.run().await?;
+    assert_eq!(again.status, MigrationStatus::Completed);
+    assert_eq!(again.events, ids.len() as i64);
+    return Ok(());
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use account::command::{
+    application::account::{
+        ports::inbound::{claim_events::ClaimEvents, get_events::GetEvents, send_event::SendEvent},
+        service::outbox::AccountOutboxService,
+    },
+    domain::account::entity::event::AccountEvent,
+    infrastructure::adapters::outbound::memory_bus::InMemoryEventBus,
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use cqrs_rs::domain::entity::event::EventEnvelope;
+use ulid::Ulid;
+
+use common::{store, Envelope};
+
+const RELAYS: usize = 4;
+const AGGREGATES: usize = 10;
+const EVENTS_PER_AGGREGATE: usize = 5;
+
+fn account_suspended(aggregate_id: &str, sequence: &str) -> Envelope {
+    return EventEnvelope {
+        aggregate_id: aggregate_id.into(),
+        aggregate_type: "account".into(),
+        sequence: sequence.into(),
+        payload: AccountEvent::AccountSuspended {
+            id: aggregate_id.into(),
+            reason: "abuse".into(),
+            suspended_at: Utc::now(),
+            event_version: "0.0.1".into(),
+            event_id: sequence.into(),
+        },
+        metadata: HashMap::new(),
+        timestamp: Utc::now(),
+    };
+}
+
+/// Several relays drain one SQLite outbox at once, each claiming small
+/// batches. No aggregate may be leased to two of them at the same time, and
+/// every aggregate has to reach the bus in the order it was stored.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_relays_never_share_or_reorder_an_aggregate() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let mut expected: HashMap<String, Vec<String>> = HashMap::new();
+    let mut events = vec![];
+    for _ in 0..AGGREGATES {
+        let aggregate_id = Ulid::new().to_string();
+        let mut sequences: Vec<String> = (0..EVENTS_PER_AGGREGATE)
+            .map(|_| Ulid::new().to_string())
+            .collect();
+        sequences.sort();
+        for sequence in sequences.iter() {
+            events.push(account_suspended(&aggregate_id, sequence));
+        }
+        expected.insert(aggregate_id, sequences);
+    }
+    store.repository.store_events(events).await?;
+
+    let bus = InMemoryEventBus::new();
+    let held: Arc<Mutex<HashMap<String, String>>> = Arc::new(Mutex::new(HashMap::new()));
+    let relays = (0..RELAYS).map(|x| {
+        let owner = format!("relay-{}", x);
+        let outbox: AccountOutboxService<Envelope, Envelope> =
+            AccountOutboxService::new(store.repository.clone(), Arc::new(bus.clone()))
+                .with_lease(owner.clone(), Duration::from_secs(30));
+        let held = held.clone();
+        return tokio::spawn(async move {
+            loop {
+                let claimed = outbox.claim_events(3).await?;
+                if claimed.is_empty() {
+                    if outbox.get_events(1).await?.is_empty() {
+                        return Ok::<(), anyhow::Error>(());
+                    }
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    continue;
+                }
+                let aggregates: HashSet<String> =
+                    claimed.iter().map(|x| x.aggregate_id.clone()).collect();
+                for aggregate_id in aggregates.iter() {
+                    let previous = held
+                        .lock()
+                        .unwrap()
+                        .insert(aggregate_id.clone(), owner.clone());
+                    if let Some(other) = previous {
+                        return Err(anyhow!(
+                            "aggregate {} was leased to {} and {}",
+                            aggregate_id,
+                            other,
+                            owner
+                        ));
+                    }
+                }
+                let mut last: HashMap<String, String> = HashMap::new();
+                for event in claimed.iter() {
+                    last.insert(event.aggregate_id.clone(), event.sequence.clone());
+                }
+                for event in claimed {
+                    // Deleting the last leased event lets another relay claim
+                    // the rest of the aggregate, so it is handed back first.
+                    if last.get(&event.aggregate_id) == Some(&event.sequence) {
+                        held.lock().unwrap().remove(&event.aggregate_id);
+                    }
+                    outbox.send_event(event).await?;
+                }
+                outbox.release_events().await?;
+            }
+        });
+    });
+    for relay in futures::future::join_all(relays).await {
+        relay??;
+    }
+
+    let mut published: HashMap<String, Vec<String>> = HashMap::new();
+    for event in bus.published() {
+        published
+            .entry(event.aggregate_id)
+            .or_default()
+            .push(event.sequence);
+    }
+    assert_eq!(published, expected);
+    return Ok(());
+}
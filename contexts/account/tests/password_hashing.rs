@@ -0,0 +1,275 @@
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use account::{
+    command::{
+        application::account::{
+            context::RequestContext,
+            ports::inbound::{
+                suspend_account::SuspendAccountUseCase,
+                verify_credentials::VerifyCredentialsUseCase,
+            },
+            service::account::AccountService,
+        },
+        domain::account::entity::{
+            aggregate::AccountAggregate, command::SuspendAccountCommand, event::AccountEvent,
+        },
+    },
+    common::{
+        application::ports::outbound::account_services::{
+            self, PasswordCheck, PasswordHashingError, TAccountServices,
+        },
+        domain::secret::{Password, PasswordHash, SecretToken},
+        infrastructure::adapters::outbound::account_services::{
+            argon2::AccountServices, pool::HashingPoolConfig,
+        },
+    },
+};
+use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
+use async_trait::async_trait;
+
+use common::{create_account, store, Service, Store};
+
+const PASSWORD: &str = "correct horse battery staple";
+
+fn argon() -> Result<AccountServices, anyhow::Error> {
+    return AccountServices::new(HashingPoolConfig::default());
+}
+
+fn check(
+    services: &AccountServices,
+    password: &str,
+    password_hash: &str,
+) -> Result<PasswordCheck, anyhow::Error> {
+    return services.verify_password(password.into(), password_hash.into());
+}
+
+/// Whether `check` matched and came with a replacement hash.
+fn was_rehashed(check: PasswordCheck) -> bool {
+    return matches!(check, PasswordCheck::Match { rehashed: Some(_) });
+}
+
+fn is_current(check: PasswordCheck) -> bool {
+    return matches!(check, PasswordCheck::Match { rehashed: None });
+}
+
+#[test]
+fn bcrypt_hashes_verify_and_need_a_rehash() -> Result<(), anyhow::Error> {
+    let services = argon()?;
+    let legacy = bcrypt::hash(PASSWORD, 4)?;
+    assert!(was_rehashed(check(&services, PASSWORD, &legacy)?));
+    assert_eq!(check(&services, "wrong", &legacy)?, PasswordCheck::Mismatch);
+    return Ok(());
+}
+
+#[test]
+fn scrypt_hashes_verify_and_need_a_rehash() -> Result<(), anyhow::Error> {
+    let services = argon()?;
+    let salt = SaltString::generate(&mut OsRng);
+    let legacy = scrypt::Scrypt
+        .hash_password_customized(
+            PASSWORD.as_bytes(),
+            None,
+            None,
+            scrypt::Params::new(4, 8, 1)?,
+            &salt,
+        )?
+        .to_string();
+    assert!(was_rehashed(check(&services, PASSWORD, &legacy)?));
+    assert_eq!(check(&services, "wrong", &legacy)?, PasswordCheck::Mismatch);
+    return Ok(());
+}
+
+#[test]
+fn pbkdf2_hashes_verify_and_need_a_rehash() -> Result<(), anyhow::Error> {
+    let services = argon()?;
+    let salt = SaltString::generate(&mut OsRng);
+    let legacy = pbkdf2::Pbkdf2
+        .hash_password_customized(
+            PASSWORD.as_bytes(),
+            Some(pbkdf2::Algorithm::Pbkdf2Sha256.ident()),
+            None,
+            pbkdf2::Params {
+                rounds: 1000,
+                output_length: 32,
+            },
+            &salt,
+        )?
+        .to_string();
+    assert!(was_rehashed(check(&services, PASSWORD, &legacy)?));
+    assert_eq!(check(&services, "wrong", &legacy)?, PasswordCheck::Mismatch);
+    return Ok(());
+}
+
+#[test]
+fn argon2_hashes_need_a_rehash_when_the_parameters_change() -> Result<(), anyhow::Error> {
+    let services = argon()?;
+    let current = services.hash_password(PASSWORD.into())?;
+    assert!(is_current(check(&services, PASSWORD, current.expose())?));
+    assert_eq!(
+        check(&services, "wrong", current.expose())?,
+        PasswordCheck::Mismatch
+    );
+    let stronger = argon()?.with_iterations(4)?;
+    assert!(was_rehashed(check(&stronger, PASSWORD, current.expose())?));
+    return Ok(());
+}
+
+#[test]
+fn hashes_from_before_the_pepper_still_verify() -> Result<(), anyhow::Error> {
+    let plain = argon()?;
+    let peppered = argon()?.with_pepper(SecretToken::new("pepper"));
+    let unpeppered = plain.hash_password(PASSWORD.into())?;
+    assert!(was_rehashed(check(
+        &peppered,
+        PASSWORD,
+        unpeppered.expose()
+    )?));
+    assert_eq!(
+        check(&peppered, "wrong", unpeppered.expose())?,
+        PasswordCheck::Mismatch
+    );
+
+    let hash = peppered.hash_password(PASSWORD.into())?;
+    assert!(is_current(check(&peppered, PASSWORD, hash.expose())?));
+    assert_eq!(
+        check(&plain, PASSWORD, hash.expose())?,
+        PasswordCheck::Mismatch
+    );
+    let other = argon()?.with_pepper(SecretToken::new("other"));
+    assert_eq!(
+        check(&other, PASSWORD, hash.expose())?,
+        PasswordCheck::Mismatch
+    );
+    return Ok(());
+}
+
+fn service(store: &Store, services: AccountServices) -> Service {
+    return AccountService::new(Arc::new(services), store.repository.clone());
+}
+
+async fn verify(service: &Service, email: &str, password: &str) -> Option<AccountAggregate> {
+    return service
+        .verify_credentials(email.into(), password.into(), RequestContext::new())
+        .await
+        .unwrap();
+}
+
+async fn rehashes(store: &Store, id: &str) -> Result<usize, anyhow::Error> {
+    let events = store.repository.retrieve_events(id.into(), None).await?;
+    return Ok(events
+        .iter()
+        .filter(|x| matches!(x.payload, AccountEvent::PasswordRehashed { .. }))
+        .count());
+}
+
+#[tokio::test]
+async fn outdated_hashes_are_rehashed_on_sign_in() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = create_account(&store, "rehash@example.com").await?;
+    let stronger = service(&store, argon()?.with_iterations(4)?);
+
+    assert!(verify(&stronger, "rehash@example.com", "wrong")
+        .await
+        .is_none());
+    assert_eq!(rehashes(&store, &id).await?, 0);
+
+    let account = verify(&stronger, "rehash@example.com", PASSWORD)
+        .await
+        .unwrap();
+    assert_eq!(rehashes(&store, &id).await?, 1);
+    let rehashed = account.password_hash.unwrap();
+    assert!(rehashed.expose().contains("t=4"));
+    assert!(is_current(check(
+        &argon()?.with_iterations(4)?,
+        PASSWORD,
+        rehashed.expose()
+    )?));
+
+    // The new hash is current, so later sign ins leave it alone.
+    assert!(verify(&stronger, "rehash@example.com", PASSWORD)
+        .await
+        .is_some());
+    assert_eq!(rehashes(&store, &id).await?, 1);
+    return Ok(());
+}
+
+/// Counts the passwords verified through the wrapped services.
+#[derive(Debug)]
+struct CountingServices {
+    inner: AccountServices,
+    verified: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl TAccountServices for CountingServices {
+    fn hash_password(&self, password: Password) -> Result<PasswordHash, anyhow::Error> {
+        return self.inner.hash_password(password);
+    }
+
+    fn verify_password(
+        &self,
+        password: Password,
+        password_hash: PasswordHash,
+    ) -> Result<PasswordCheck, anyhow::Error> {
+        self.verified.fetch_add(1, Ordering::SeqCst);
+        return self.inner.verify_password(password, password_hash);
+    }
+
+    async fn hash_password_async(
+        &self,
+        password: Password,
+    ) -> Result<PasswordHash, PasswordHashingError> {
+        return self.inner.hash_password_async(password).await;
+    }
+
+    async fn verify_password_async(
+        &self,
+        password: Password,
+        password_hash: PasswordHash,
+    ) -> Result<PasswordCheck, PasswordHashingError> {
+        self.verified.fetch_add(1, Ordering::SeqCst);
+        return self
+            .inner
+            .verify_password_async(password, password_hash)
+            .await;
+    }
+}
+
+impl account_services::AccountServices for CountingServices {}
+
+#[tokio::test]
+async fn rejected_sign_ins_still_verify_a_password() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = create_account(&store, "suspended@example.com").await?;
+    let _: AccountAggregate = store
+        .service
+        .suspend_account(
+            SuspendAccountCommand {
+                id,
+                reason: "abuse".into(),
+            },
+            RequestContext::new(),
+        )
+        .await?;
+    let verified = Arc::new(AtomicUsize::new(0));
+    let service: Service = AccountService::new(
+        Arc::new(CountingServices {
+            inner: argon()?,
+            verified: verified.clone(),
+        }),
+        store.repository.clone(),
+    );
+
+    // Neither an unknown email nor an account that cannot sign in skips the
+    // hashing work a wrong password costs.
+    for (email, expected) in [("unknown@example.com", 1), ("suspended@example.com", 2)] {
+        assert!(verify(&service, email, PASSWORD).await.is_none());
+        assert_eq!(verified.load(Ordering::SeqCst), expected);
+    }
+    return Ok(());
+}
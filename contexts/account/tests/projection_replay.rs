@@ -0,0 +1,270 @@
+mod common;
+
+use std::sync::Arc;
+
+use account::{
+    command::{
+        application::account::{
+            context::RequestContext,
+            ports::{
+                inbound::suspend_account::SuspendAccountUseCase,
+                outbound::subscription::{
+                    AccountEventLogRepository, CheckpointStore, EventHandler,
+                },
+            },
+        },
+        domain::account::entity::{aggregate::AccountAggregate, command::SuspendAccountCommand},
+        infrastructure::adapters::outbound::sqlite::SQLiteAccountRepository,
+    },
+    query::{
+        application::account::{
+            ports::outbound::repository::AccountViewRepository,
+            service::projector::AccountProjector,
+        },
+        domain::account::entity::view::{ACCOUNT_STATUS_CREATED, ACCOUNT_STATUS_SUSPENDED},
+        infrastructure::{
+            adapters::outbound::sqlite::SQLiteAccountViewRepository,
+            replay::sqlite::{ReplayMode, ReplayStatus, SQLiteProjectionReplay},
+        },
+    },
+};
+use sqlx::{Row, Sqlite};
+
+use common::{store, Store, TempDatabases, QUERY_MIGRATIONS};
+
+const SUBSCRIPTION_ID: &str = "account_view_projector";
+
+/// An event store with three accounts, the last one suspended, and a read
+/// model next to it.
+struct Fixture {
+    store: Store,
+    views: Arc<SQLiteAccountViewRepository>,
+    ids: Vec<String>,
+    _databases: TempDatabases,
+}
+
+async fn fixture() -> Result<Fixture, anyhow::Error> {
+    let store = store().await?;
+    let mut ids = vec![];
+    for email in ["a@example.com", "b@example.com", "c@example.com"] {
+        ids.push(common::create_account(&store, email).await?);
+    }
+    let _: AccountAggregate = store
+        .service
+        .suspend_account(
+            SuspendAccountCommand {
+                id: ids[2].clone(),
+                reason: "abuse".into(),
+            },
+            RequestContext::new(),
+        )
+        .await?;
+    let databases = TempDatabases::new()?;
+    let views = SQLiteAccountViewRepository::new(databases.connect("query").await?);
+    views.migrate(QUERY_MIGRATIONS.into()).await?;
+    return Ok(Fixture {
+        store,
+        views: Arc::new(views),
+        ids,
+        _databases: databases,
+    });
+}
+
+impl Fixture {
+    fn log(&self) -> Arc<dyn AccountEventLogRepository + Sync + Send> {
+        return Arc::new(SQLiteAccountRepository::new(self.store.connector.clone()));
+    }
+
+    fn replay(&self, replay_id: &str, mode: ReplayMode) -> SQLiteProjectionReplay {
+        return SQLiteProjectionReplay::new(
+            self.views.connector.clone(),
+            self.log(),
+            replay_id,
+            SUBSCRIPTION_ID,
+            mode,
+        )
+        .unwrap()
+        .with_batch_size(1);
+    }
+
+    /// Projects the log up to `position` the way the live projector would,
+    /// checkpoint included.
+    async fn project_until(&self, position: i64) -> Result<(), anyhow::Error> {
+        let projector = AccountProjector::new(self.views.clone());
+        let start = self.checkpoint().await?;
+        for event in self.log().read_all(start, position - start).await? {
+            projector.handle(&event).await?;
+        }
+        assert!(
+            self.views
+                .save_checkpoint(SUBSCRIPTION_ID.into(), start, position)
+                .await?
+        );
+        return Ok(());
+    }
+
+    async fn checkpoint(&self) -> Result<i64, anyhow::Error> {
+        return self.views.load_checkpoint(SUBSCRIPTION_ID.into()).await;
+    }
+
+    async fn tables(&self) -> Result<Vec<String>, anyhow::Error> {
+        let rows = sqlx::query::<Sqlite>(
+            "SELECT name FROM sqlite_master WHERE name LIKE 'accounts%' ORDER BY name",
+        )
+        .fetch_all(&self.views.connector.pool)
+        .await?;
+        return Ok(rows.iter().map(|x| x.get(0)).collect());
+    }
+}
+
+#[tokio::test]
+async fn shadow_replays_rebuild_and_swap_in_views() -> Result<(), anyhow::Error> {
+    let fixture = fixture().await?;
+    fixture.project_until(4).await?;
+    // A projection bug the replay is meant to repair.
+    sqlx::query::<Sqlite>("UPDATE accounts SET status = 'Broken'")
+        .execute(&fixture.views.connector.pool)
+        .await?;
+
+    let report = fixture.replay("rebuild", ReplayMode::Shadow).run().await?;
+    assert_eq!(report.position, 4);
+    assert_eq!(report.status, ReplayStatus::Completed);
+    let statuses = [
+        ACCOUNT_STATUS_CREATED,
+        ACCOUNT_STATUS_CREATED,
+        ACCOUNT_STATUS_SUSPENDED,
+    ];
+    for (id, status) in fixture.ids.iter().zip(statuses) {
+        let view = fixture.views.find_account(id.clone()).await?.unwrap();
+        assert_eq!(view.status, status);
+    }
+    assert_eq!(fixture.tables().await?, vec!["accounts", "accounts_email"]);
+    // Moved strictly back, so a batch that was in flight cannot save.
+    assert_eq!(fixture.checkpoint().await?, 3);
+
+    let again = fixture.replay("rebuild", ReplayMode::Shadow).run().await?;
+    assert_eq!(again.position, 4);
+    assert_eq!(fixture.checkpoint().await?, 3);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn shadow_replays_rewind_a_projector_that_is_ahead() -> Result<(), anyhow::Error> {
+    let fixture = fixture().await?;
+    fixture.project_until(4).await?;
+    // As if the projector had handled events committed after the replay
+    // read its last batch.
+    assert!(
+        fixture
+            .views
+            .save_checkpoint(SUBSCRIPTION_ID.into(), 4, 6)
+            .await?
+    );
+    assert_eq!(
+        fixture
+            .replay("ahead", ReplayMode::Shadow)
+            .run()
+            .await?
+            .position,
+        4
+    );
+    assert_eq!(fixture.checkpoint().await?, 4);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn in_flight_batches_cannot_undo_the_swap() -> Result<(), anyhow::Error> {
+    let fixture = fixture().await?;
+    fixture.project_until(2).await?;
+    // The live projector loads its checkpoint and starts a batch...
+    let start = fixture.checkpoint().await?;
+    fixture.replay("race", ReplayMode::Shadow).run().await?;
+    // ...and only saves it once the replay has swapped the tables.
+    assert!(
+        !fixture
+            .views
+            .save_checkpoint(SUBSCRIPTION_ID.into(), start, 4)
+            .await?
+    );
+    assert_eq!(fixture.checkpoint().await?, 1);
+
+    // Picking up from the rewound checkpoint re-applies to the new table.
+    fixture.project_until(4).await?;
+    let view = fixture
+        .views
+        .find_account(fixture.ids[2].clone())
+        .await?
+        .unwrap();
+    assert_eq!(view.status, ACCOUNT_STATUS_SUSPENDED);
+    assert_eq!(view.last_position, 4);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn in_place_replays_rebuild_the_live_table() -> Result<(), anyhow::Error> {
+    let fixture = fixture().await?;
+    fixture.project_until(2).await?;
+    sqlx::query::<Sqlite>("UPDATE accounts SET status = 'Broken'")
+        .execute(&fixture.views.connector.pool)
+        .await?;
+
+    let report = fixture
+        .replay("in_place", ReplayMode::InPlace)
+        .run()
+        .await?;
+    assert_eq!(report.position, 4);
+    assert_eq!(fixture.checkpoint().await?, 4);
+    assert_eq!(fixture.tables().await?, vec!["accounts", "accounts_email"]);
+    let view = fixture
+        .views
+        .find_account(fixture.ids[0].clone())
+        .await?
+        .unwrap();
+    assert_eq!(view.status, ACCOUNT_STATUS_CREATED);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn shadow_replays_copy_the_schema_verbatim() -> Result<(), anyhow::Error> {
+    let fixture = fixture().await?;
+    // Names that merely contain the table's keep their spelling.
+    for statement in [
+        "ALTER TABLE accounts ADD COLUMN accounts_note TEXT",
+        "CREATE INDEX by_accounts_note ON accounts(accounts_note)",
+    ] {
+        sqlx::query::<Sqlite>(statement)
+            .execute(&fixture.views.connector.pool)
+            .await?;
+    }
+
+    fixture.replay("verbatim", ReplayMode::Shadow).run().await?;
+    let columns = sqlx::query::<Sqlite>("SELECT name FROM pragma_table_info('accounts')")
+        .fetch_all(&fixture.views.connector.pool)
+        .await?;
+    assert!(columns
+        .iter()
+        .any(|x| x.get::<String, _>(0) == "accounts_note"));
+    let indexes = sqlx::query::<Sqlite>(
+        "SELECT name FROM sqlite_master WHERE type = 'index' AND tbl_name = 'accounts' AND sql IS NOT NULL ORDER BY name",
+    )
+    .fetch_all(&fixture.views.connector.pool)
+    .await?;
+    let indexes: Vec<String> = indexes.iter().map(|x| x.get(0)).collect();
+    assert_eq!(indexes, vec!["accounts_email", "by_accounts_note"]);
+    // Never projected, so there is nothing to move back from.
+    assert_eq!(fixture.checkpoint().await?, 0);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn replay_ids_are_validated() {
+    let fixture = fixture().await.unwrap();
+    let replay = SQLiteProjectionReplay::new(
+        fixture.views.connector.clone(),
+        fixture.log(),
+        "x; DROP TABLE accounts",
+        SUBSCRIPTION_ID,
+        ReplayMode::Shadow,
+    );
+    assert!(replay.is_err());
+}
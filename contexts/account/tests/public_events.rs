@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use account::command::{
+    application::account::context::{RequestContext, CORRELATION_ID, TRACEPARENT},
+    domain::account::entity::{aggregate::AccountAggregate, event::AccountEvent},
+    infrastructure::dtos::transport::public::{
+        PublicAccountEvent, PublicEventEnvelope, PUBLIC_METADATA,
+    },
+};
+use chrono::Utc;
+use cqrs_rs::domain::entity::event::{DomainEvent, EventEnvelope};
+use serde_json::Value;
+
+const SECRET: &str = "$argon2id$v=19$m=4096,t=3,p=1$c2FsdA$secret";
+const SENSITIVE_FIELDS: [&str; 4] = ["password", "password_hash", "email_index", "secret"];
+const CLIENT_IP: &str = "203.0.113.7";
+
+/// One sample of every event. `PublicAccountEvent::from_event` matches
+/// without a wildcard, so a new event has to be mapped there first.
+fn samples() -> Vec<AccountEvent> {
+    return vec![
+        AccountEvent::AccountCreated {
+            id: "id".into(),
+            email: "public@example.com".into(),
+            password_hash: SECRET.into(),
+            created_at: Utc::now(),
+            event_version: "0.0.1".into(),
+            event_id: "1".into(),
+        },
+        AccountEvent::AccountSuspended {
+            id: "id".into(),
+            reason: "abuse".into(),
+            suspended_at: Utc::now(),
+            event_version: "0.0.1".into(),
+            event_id: "2".into(),
+        },
+        AccountEvent::AccountDeleted {
+            id: "id".into(),
+            deleted_at: Utc::now(),
+            event_version: "0.0.1".into(),
+            event_id: "3".into(),
+        },
+        AccountEvent::PasswordRehashed {
+            id: "id".into(),
+            password_hash: SECRET.into(),
+            rehashed_at: Utc::now(),
+            event_version: "0.0.1".into(),
+            event_id: "4".into(),
+        },
+    ];
+}
+
+fn sensitive_keys(value: &Value, found: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                if SENSITIVE_FIELDS.iter().any(|x| key.contains(x)) {
+                    found.push(key.clone());
+                }
+                sensitive_keys(value, found);
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|x| sensitive_keys(x, found)),
+        _ => {}
+    }
+}
+
+/// The metadata of a request that set every field.
+fn metadata() -> HashMap<String, String> {
+    let mut context = RequestContext::new();
+    context.actor = Some("admin@example.com".into());
+    context.client_ip = Some(CLIENT_IP.into());
+    context.user_agent = Some("curl/8.0".into());
+    context.traceparent = Some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".into());
+    context.idempotency_key = Some("create-admin".into());
+    let mut metadata = context.metadata();
+    metadata.insert("internal_note".into(), "not for other services".into());
+    return metadata;
+}
+
+#[test]
+fn public_events_expose_no_sensitive_fields() {
+    for event in samples() {
+        let event_type = event.event_type();
+        let envelope = EventEnvelope::<AccountAggregate> {
+            aggregate_id: "id".into(),
+            aggregate_type: "account".into(),
+            sequence: "1".into(),
+            payload: event,
+            metadata: metadata(),
+            timestamp: Utc::now(),
+        };
+        let public = match PublicEventEnvelope::from_envelope(envelope) {
+            Some(x) => x,
+            None => continue,
+        };
+        let json = serde_json::to_string(&public).unwrap();
+        assert!(
+            !json.contains(SECRET),
+            "{} leaks the password hash",
+            event_type
+        );
+        for (key, value) in metadata() {
+            if !PUBLIC_METADATA.contains(&key.as_str()) {
+                assert!(
+                    !json.contains(&value),
+                    "{} leaks the {} metadata",
+                    event_type,
+                    key
+                );
+            }
+        }
+        let mut found = vec![];
+        sensitive_keys(&serde_json::from_str(&json).unwrap(), &mut found);
+        assert!(found.is_empty(), "{} exposes {:?}", event_type, found);
+    }
+}
+
+#[test]
+fn internal_events_have_no_public_form() {
+    let internal: Vec<AccountEvent> = samples()
+        .into_iter()
+        .filter(|x| matches!(x, AccountEvent::PasswordRehashed { .. }))
+        .collect();
+    assert!(!internal.is_empty());
+    for event in internal {
+        assert_eq!(PublicAccountEvent::from_event(event), None);
+    }
+}
+
+#[test]
+fn only_allowlisted_metadata_is_published() {
+    let metadata = metadata();
+    let envelope = EventEnvelope::<AccountAggregate> {
+        aggregate_id: "id".into(),
+        aggregate_type: "account".into(),
+        sequence: "1".into(),
+        payload: samples().remove(0),
+        metadata: metadata.clone(),
+        timestamp: Utc::now(),
+    };
+    let public = PublicEventEnvelope::from_envelope(envelope).unwrap();
+    let mut keys: Vec<&str> = public.metadata.keys().map(|x| x.as_str()).collect();
+    keys.sort();
+    let mut expected = PUBLIC_METADATA.to_vec();
+    expected.sort();
+    assert_eq!(keys, expected);
+    for key in [CORRELATION_ID, TRACEPARENT] {
+        assert_eq!(public.metadata[key], metadata[key]);
+    }
+}
@@ -0,0 +1,270 @@
+mod common;
+
+use std::sync::Arc;
+
+use account::{
+    command::{
+        application::account::{
+            context::RequestContext,
+            ports::{
+                inbound::suspend_account::SuspendAccountUseCase,
+                outbound::subscription::EventHandler,
+            },
+        },
+        domain::account::entity::{
+            aggregate::AccountAggregate,
+            command::SuspendAccountCommand,
+            event::{AccountEvent, ACCOUNT_SUSPENDED_VERSION},
+        },
+    },
+    query::{
+        application::account::{
+            ports::{
+                inbound::list_accounts::ListAccountsUseCase,
+                outbound::repository::AccountViewRepository,
+            },
+            service::{
+                account::{AccountQueryService, QueryServiceTrait},
+                projector::AccountProjector,
+            },
+        },
+        domain::account::entity::view::{
+            AccountView, ACCOUNT_STATUS_CREATED, ACCOUNT_STATUS_SUSPENDED,
+        },
+        infrastructure::{
+            adapters::{inbound::graphql::schema, outbound::sqlite::SQLiteAccountViewRepository},
+            dtos::transport::graphql::GraphQLAccountView,
+        },
+    },
+};
+use async_graphql::{Request, Value};
+use chrono::Utc;
+use cqrs_rs::domain::entity::event::EventEnvelope;
+use serde_json::json;
+
+use common::{store, Store, TempDatabases, QUERY_MIGRATIONS};
+
+struct Views {
+    repository: Arc<SQLiteAccountViewRepository>,
+    _databases: TempDatabases,
+}
+
+async fn views() -> Result<Views, anyhow::Error> {
+    let databases = TempDatabases::new()?;
+    let repository = SQLiteAccountViewRepository::new(databases.connect("query").await?);
+    repository.migrate(QUERY_MIGRATIONS.into()).await?;
+    return Ok(Views {
+        repository: Arc::new(repository),
+        _databases: databases,
+    });
+}
+
+/// Hands every event of the store's log to the projector.
+async fn project_all(store: &Store, projector: &AccountProjector) -> Result<(), anyhow::Error> {
+    for event in store.repository.read_all(0, 100).await? {
+        projector.handle(&event).await?;
+    }
+    return Ok(());
+}
+
+fn view(id: &str) -> AccountView {
+    return AccountView {
+        id: id.into(),
+        email: format!("{}@example.com", id),
+        status: ACCOUNT_STATUS_CREATED.into(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        last_sequence: id.into(),
+        last_position: 1,
+    };
+}
+
+fn service(views: &Views) -> Arc<dyn QueryServiceTrait<GraphQLAccountView> + Send + Sync> {
+    return Arc::new(AccountQueryService::new(views.repository.clone()));
+}
+
+#[tokio::test]
+async fn projects_account_events_into_views() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let views = views().await?;
+    let projector = AccountProjector::new(views.repository.clone());
+    let kept = common::create_account(&store, "kept@example.com").await?;
+    let suspended = common::create_account(&store, "suspended@example.com").await?;
+    let _: AccountAggregate = store
+        .service
+        .suspend_account(
+            SuspendAccountCommand {
+                id: suspended.clone(),
+                reason: "abuse".into(),
+            },
+            RequestContext::new(),
+        )
+        .await?;
+    project_all(&store, &projector).await?;
+
+    let view = views.repository.find_account(kept).await?.unwrap();
+    assert_eq!(view.email, "kept@example.com");
+    assert_eq!(view.status, ACCOUNT_STATUS_CREATED);
+    assert_eq!(view.last_position, 1);
+    let view = views
+        .repository
+        .find_account_by_email("suspended@example.com".into())
+        .await?
+        .unwrap();
+    assert_eq!(view.id, suspended);
+    assert_eq!(view.status, ACCOUNT_STATUS_SUSPENDED);
+    assert_eq!(view.last_position, 3);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn redelivered_events_do_not_roll_views_back() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let views = views().await?;
+    let projector = AccountProjector::new(views.repository.clone());
+    let id = common::create_account(&store, "redelivered@example.com").await?;
+    let _: AccountAggregate = store
+        .service
+        .suspend_account(
+            SuspendAccountCommand {
+                id: id.clone(),
+                reason: "abuse".into(),
+            },
+            RequestContext::new(),
+        )
+        .await?;
+    project_all(&store, &projector).await?;
+    // The whole log again, as after a lost checkpoint.
+    project_all(&store, &projector).await?;
+
+    let view = views.repository.find_account(id).await?.unwrap();
+    assert_eq!(view.status, ACCOUNT_STATUS_SUSPENDED);
+    assert_eq!(view.last_position, 2);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn views_follow_log_positions_not_sequences() -> Result<(), anyhow::Error> {
+    let views = views().await?;
+    let projector = AccountProjector::new(views.repository.clone());
+    views
+        .repository
+        .save_account(AccountView {
+            last_sequence: "01ZZZZZZZZZZZZZZZZZZZZZZZZ".into(),
+            ..view("a")
+        })
+        .await?;
+    // Written later, but by a host whose clock runs behind, so its ULID
+    // sorts before the one already applied.
+    let suspension = EventEnvelope::<AccountAggregate> {
+        aggregate_id: "a".into(),
+        aggregate_type: "account".into(),
+        sequence: "01000000000000000000000000".into(),
+        payload: AccountEvent::AccountSuspended {
+            id: "a".into(),
+            reason: "abuse".into(),
+            suspended_at: Utc::now(),
+            event_version: ACCOUNT_SUSPENDED_VERSION.into(),
+            event_id: "01000000000000000000000000".into(),
+        },
+        metadata: Default::default(),
+        timestamp: Utc::now(),
+    };
+    projector.project(&suspension, 2).await?;
+    let suspended = views.repository.find_account("a".into()).await?.unwrap();
+    assert_eq!(suspended.status, ACCOUNT_STATUS_SUSPENDED);
+    assert_eq!(suspended.last_position, 2);
+
+    views.repository.save_account(view("a")).await?;
+    let stale = views.repository.find_account("a".into()).await?.unwrap();
+    assert_eq!(stale.status, ACCOUNT_STATUS_SUSPENDED);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn pages_through_accounts_in_id_order() -> Result<(), anyhow::Error> {
+    let views = views().await?;
+    for id in ["e", "c", "a", "d", "b"] {
+        views.repository.save_account(view(id)).await?;
+    }
+    let service = AccountQueryService::new(views.repository.clone());
+
+    let mut pages: Vec<Vec<String>> = vec![];
+    let mut after = None;
+    loop {
+        let page =
+            ListAccountsUseCase::<GraphQLAccountView>::list_accounts(&service, 2, after).await?;
+        pages.push(page.items.into_iter().map(|x| x.id).collect());
+        if !page.has_next_page {
+            assert!(pages.last().unwrap().len() <= 2);
+            break;
+        }
+        after = page.end_cursor;
+    }
+    assert_eq!(pages, vec![vec!["a", "b"], vec!["c", "d"], vec!["e"]]);
+
+    let page = ListAccountsUseCase::<GraphQLAccountView>::list_accounts(&service, 0, None).await?;
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.end_cursor, Some("a".into()));
+    assert!(page.has_next_page);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn graphql_serves_accounts_and_pages() -> Result<(), anyhow::Error> {
+    let views = views().await?;
+    for id in ["a", "b", "c"] {
+        views.repository.save_account(view(id)).await?;
+    }
+    let schema = schema(service(&views));
+
+    let response = schema
+        .execute(r#"{ account(id: "b") { id email status } missing: account(id: "z") { id } }"#)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json()?,
+        json!({
+            "account": { "id": "b", "email": "b@example.com", "status": "Created" },
+            "missing": null,
+        })
+    );
+
+    let response = schema
+        .execute(r#"{ accounts(first: 2, after: "a") { nodes { id } endCursor hasNextPage } }"#)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json()?,
+        json!({
+            "accounts": { "nodes": [{ "id": "b" }, { "id": "c" }], "endCursor": "c", "hasNextPage": false },
+        })
+    );
+    return Ok(());
+}
+
+#[tokio::test]
+async fn account_by_email_needs_an_authenticated_caller() -> Result<(), anyhow::Error> {
+    let views = views().await?;
+    views.repository.save_account(view("a")).await?;
+    let schema = schema(service(&views));
+    let query = r#"{ accountByEmail(email: "a@example.com") { id } }"#;
+
+    let response = schema.execute(query).await;
+    assert_eq!(response.errors.len(), 1);
+    assert_eq!(response.data, Value::Null);
+    let response = schema
+        .execute(Request::new(query).data(RequestContext::new()))
+        .await;
+    assert_eq!(response.errors.len(), 1);
+
+    let mut context = RequestContext::new();
+    context.actor = Some("admin".into());
+    let response = schema.execute(Request::new(query).data(context)).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json()?,
+        json!({ "accountByEmail": { "id": "a" } })
+    );
+    return Ok(());
+}
@@ -0,0 +1,378 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use account::command::{
+    application::account::{
+        ports::{
+            inbound::{
+                claim_events::ClaimEvents, get_events::GetEvents,
+                manage_dead_letters::ManageDeadLetters, send_event::SendEvent,
+            },
+            outbound::dead_letter::OutboxDeadLetter,
+        },
+        service::{
+            outbox::{AccountOutboxService, OutboxServiceTrait},
+            relay::{OutboxRelay, OutboxRelayConfig},
+        },
+    },
+    domain::account::entity::{aggregate::AccountAggregate, event::AccountEvent},
+    infrastructure::adapters::outbound::{
+        memory::InMemoryAccountRepository, memory_bus::InMemoryEventBus,
+    },
+};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use chrono::Utc;
+use cqrs_rs::{
+    application::port::outbound::event_repository::EventRepository,
+    domain::entity::event::EventEnvelope,
+};
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{sleep, Instant},
+};
+use ulid::Ulid;
+
+type Envelope = EventEnvelope<AccountAggregate>;
+type Outbox = AccountOutboxService<Envelope, Envelope>;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+fn config() -> OutboxRelayConfig {
+    return OutboxRelayConfig {
+        poll_interval: POLL_INTERVAL,
+        batch_size: 10,
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_millis(250),
+    };
+}
+
+fn account_created(aggregate_id: &str) -> Envelope {
+    let sequence = Ulid::new().to_string();
+    return EventEnvelope {
+        aggregate_id: aggregate_id.into(),
+        aggregate_type: "account".into(),
+        sequence: sequence.clone(),
+        payload: AccountEvent::AccountCreated {
+            id: aggregate_id.into(),
+            email: format!("{}@example.com", aggregate_id),
+            password_hash: "hash".into(),
+            created_at: Utc::now(),
+            event_version: "0.0.1".into(),
+            event_id: sequence,
+        },
+        metadata: HashMap::new(),
+        timestamp: Utc::now(),
+    };
+}
+
+fn account_suspended(aggregate_id: &str) -> Envelope {
+    let sequence = Ulid::new().to_string();
+    return EventEnvelope {
+        aggregate_id: aggregate_id.into(),
+        aggregate_type: "account".into(),
+        sequence: sequence.clone(),
+        payload: AccountEvent::AccountSuspended {
+            id: aggregate_id.into(),
+            reason: "abuse".into(),
+            suspended_at: Utc::now(),
+            event_version: "0.0.1".into(),
+            event_id: sequence,
+        },
+        metadata: HashMap::new(),
+        timestamp: Utc::now(),
+    };
+}
+
+/// Wraps the outbox service to script claim failures, slow down sends and
+/// record when each claim happened on the paused clock.
+struct Scripted {
+    inner: Outbox,
+    claim_failures: Mutex<Vec<bool>>,
+    claims: Mutex<Vec<Instant>>,
+    send_delay: Duration,
+}
+
+impl Scripted {
+    fn new(inner: Outbox) -> Self {
+        return Self {
+            inner,
+            claim_failures: Mutex::new(vec![]),
+            claims: Mutex::new(vec![]),
+            send_delay: Duration::ZERO,
+        };
+    }
+
+    /// Whether each upcoming claim fails, in order. Claims past the end of
+    /// the script succeed.
+    fn with_claim_failures(self, script: Vec<bool>) -> Self {
+        *self.claim_failures.lock().unwrap() = script;
+        return self;
+    }
+
+    fn with_send_delay(mut self, delay: Duration) -> Self {
+        self.send_delay = delay;
+        return self;
+    }
+
+    fn intervals(&self) -> Vec<Duration> {
+        let claims = self.claims.lock().unwrap();
+        return claims.windows(2).map(|x| x[1] - x[0]).collect();
+    }
+}
+
+#[async_trait]
+impl GetEvents<Envelope> for Scripted {
+    async fn get_events(&self, limit: i64) -> Result<Vec<Envelope>, anyhow::Error> {
+        return self.inner.get_events(limit).await;
+    }
+}
+
+#[async_trait]
+impl ClaimEvents<Envelope> for Scripted {
+    async fn claim_events(&self, limit: i64) -> Result<Vec<Envelope>, anyhow::Error> {
+        self.claims.lock().unwrap().push(Instant::now());
+        let fail = {
+            let mut script = self.claim_failures.lock().unwrap();
+            !script.is_empty() && script.remove(0)
+        };
+        if fail {
+            return Err(anyhow!("scripted claim failure"));
+        }
+        return self.inner.claim_events(limit).await;
+    }
+
+    async fn release_events(&self) -> Result<(), anyhow::Error> {
+        return self.inner.release_events().await;
+    }
+
+    async fn fail_event(&self, sequence: String, error: String) -> Result<bool, anyhow::Error> {
+        return self.inner.fail_event(sequence, error).await;
+    }
+}
+
+#[async_trait]
+impl SendEvent<Envelope> for Scripted {
+    async fn send_event(&self, event: Envelope) -> Result<(), anyhow::Error> {
+        sleep(self.send_delay).await;
+        return self.inner.send_event(event).await;
+    }
+}
+
+#[async_trait]
+impl ManageDeadLetters for Scripted {
+    async fn list_dead_letters(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<OutboxDeadLetter>, anyhow::Error> {
+        return self.inner.list_dead_letters(limit, offset).await;
+    }
+
+    async fn inspect_dead_letter(
+        &self,
+        sequence: String,
+    ) -> Result<Option<OutboxDeadLetter>, anyhow::Error> {
+        return self.inner.inspect_dead_letter(sequence).await;
+    }
+
+    async fn requeue_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        return self.inner.requeue_dead_letter(sequence).await;
+    }
+
+    async fn discard_dead_letter(&self, sequence: String) -> Result<bool, anyhow::Error> {
+        return self.inner.discard_dead_letter(sequence).await;
+    }
+}
+
+impl OutboxServiceTrait for Scripted {}
+
+/// An in-memory outbox relayed onto an in-memory bus. Failed deliveries are
+/// retried right away, since retry delays run on the wall clock.
+struct Harness {
+    repository: InMemoryAccountRepository,
+    bus: InMemoryEventBus,
+}
+
+impl Harness {
+    fn new() -> Self {
+        return Self {
+            repository: InMemoryAccountRepository::new(),
+            bus: InMemoryEventBus::new(),
+        };
+    }
+
+    fn outbox(&self) -> Outbox {
+        return AccountOutboxService::new(
+            Arc::new(self.repository.clone()),
+            Arc::new(self.bus.clone()),
+        )
+        .with_retry_policy(10, Duration::ZERO);
+    }
+
+    async fn store(&self, events: Vec<Envelope>) -> Result<(), anyhow::Error> {
+        return EventRepository::<_, Envelope, Envelope, _, _, _>::store_events(
+            &self.repository,
+            events,
+        )
+        .await;
+    }
+
+    async fn pending(&self) -> Result<usize, anyhow::Error> {
+        return Ok(self.outbox().get_events(100).await?.len());
+    }
+
+    fn published(&self) -> Vec<String> {
+        return self
+            .bus
+            .published()
+            .into_iter()
+            .map(|x| x.sequence)
+            .collect();
+    }
+
+    fn start(
+        &self,
+        service: Arc<dyn OutboxServiceTrait + Sync + Send>,
+        commits: watch::Receiver<u64>,
+    ) -> (watch::Sender<bool>, JoinHandle<Result<(), anyhow::Error>>) {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let relay = OutboxRelay::new(service, config(), commits, shutdown_rx);
+        return (shutdown_tx, tokio::spawn(relay.run()));
+    }
+}
+
+async fn stop(
+    relay: (watch::Sender<bool>, JoinHandle<Result<(), anyhow::Error>>),
+) -> Result<(), anyhow::Error> {
+    relay.0.send(true)?;
+    return relay.1.await?;
+}
+
+#[tokio::test(start_paused = true)]
+async fn wakes_on_commit() -> Result<(), anyhow::Error> {
+    let harness = Harness::new();
+    let relay = harness.start(
+        Arc::new(harness.outbox()),
+        harness.repository.notifier.subscribe(),
+    );
+    sleep(Duration::from_secs(1)).await;
+
+    let event = account_created("woken");
+    harness.store(vec![event.clone()]).await?;
+    sleep(Duration::from_millis(1)).await;
+    assert_eq!(harness.published(), vec![event.sequence]);
+    stop(relay).await?;
+    return Ok(());
+}
+
+#[tokio::test(start_paused = true)]
+async fn polls_when_the_notifier_is_gone() -> Result<(), anyhow::Error> {
+    let harness = Harness::new();
+    let (commits_tx, commits_rx) = watch::channel(0);
+    drop(commits_tx);
+    let relay = harness.start(Arc::new(harness.outbox()), commits_rx);
+    sleep(Duration::from_secs(1)).await;
+
+    let event = account_created("polled");
+    harness.store(vec![event.clone()]).await?;
+    sleep(POLL_INTERVAL / 2).await;
+    assert!(harness.published().is_empty());
+    sleep(POLL_INTERVAL / 2).await;
+    assert_eq!(harness.published(), vec![event.sequence]);
+
+    // Shutdown still interrupts the wait for the next poll.
+    let stopped_at = Instant::now();
+    stop(relay).await?;
+    assert_eq!(stopped_at.elapsed(), Duration::ZERO);
+    return Ok(());
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_deliveries_do_not_back_off() -> Result<(), anyhow::Error> {
+    let harness = Harness::new();
+    let failing = account_created("failing");
+    let follower = account_suspended("failing");
+    let healthy = account_created("healthy");
+    harness
+        .store(vec![failing.clone(), follower.clone(), healthy.clone()])
+        .await?;
+    let sequence = failing.sequence.clone();
+    harness.bus.fail_when(move |x| x.sequence == sequence);
+
+    let service = Arc::new(Scripted::new(harness.outbox()));
+    let (commits_tx, commits_rx) = watch::channel(0);
+    let relay = harness.start(service.clone(), commits_rx);
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!(harness.published(), vec![healthy.sequence.clone()]);
+    assert_eq!(harness.pending().await?, 2);
+
+    // The failure only held back its own aggregate, and the relay went on
+    // to its regular poll instead of backing off.
+    harness.bus.clear_failures();
+    commits_tx.send(1)?;
+    sleep(Duration::from_millis(1)).await;
+    assert_eq!(
+        harness.published(),
+        vec![healthy.sequence, failing.sequence, follower.sequence]
+    );
+    assert_eq!(service.intervals(), vec![Duration::from_secs(1)]);
+    stop(relay).await?;
+    return Ok(());
+}
+
+#[tokio::test(start_paused = true)]
+async fn backs_off_on_claim_errors_and_resets() -> Result<(), anyhow::Error> {
+    let harness = Harness::new();
+    let service = Arc::new(
+        Scripted::new(harness.outbox())
+            .with_claim_failures(vec![true, true, true, false, true, false]),
+    );
+    let (_commits_tx, commits_rx) = watch::channel(0);
+    let relay = harness.start(service.clone(), commits_rx);
+    sleep(POLL_INTERVAL * 2).await;
+    stop(relay).await?;
+
+    let millis = Duration::from_millis;
+    assert_eq!(
+        service.intervals()[..5],
+        [
+            millis(100),
+            millis(200),
+            millis(250),
+            POLL_INTERVAL,
+            millis(100),
+        ]
+    );
+    return Ok(());
+}
+
+#[tokio::test(start_paused = true)]
+async fn shutdown_drains_the_batch_in_flight() -> Result<(), anyhow::Error> {
+    let harness = Harness::new();
+    let events = vec![
+        account_created("first"),
+        account_suspended("first"),
+        account_created("second"),
+    ];
+    harness.store(events.clone()).await?;
+    let service =
+        Arc::new(Scripted::new(harness.outbox()).with_send_delay(Duration::from_millis(100)));
+    let (_commits_tx, commits_rx) = watch::channel(0);
+    let relay = harness.start(service, commits_rx);
+
+    sleep(Duration::from_millis(50)).await;
+    assert!(harness.published().is_empty());
+    stop(relay).await?;
+    let mut published = harness.published();
+    published.sort();
+    let mut expected: Vec<String> = events.into_iter().map(|x| x.sequence).collect();
+    expected.sort();
+    assert_eq!(published, expected);
+    assert_eq!(harness.pending().await?, 0);
+    return Ok(());
+}
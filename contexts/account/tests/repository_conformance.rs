@@ -0,0 +1,45 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use account::command::infrastructure::adapters::outbound::{
+    conformance::{run_conformance_suite, ConformanceRepository, CONFORMANCE_CHAIN_KEY},
+    memory::InMemoryAccountRepository,
+    sqlite::SQLiteAccountRepository,
+};
+
+use common::{TempDatabases, COMMAND_MIGRATIONS};
+
+/// Creates a store in a directory kept in `databases`, which removes it once
+/// the suite is done.
+async fn sqlite_repository(
+    databases: Arc<Mutex<Vec<TempDatabases>>>,
+) -> Result<ConformanceRepository, anyhow::Error> {
+    let directory = TempDatabases::new()?;
+    let repository: ConformanceRepository = Arc::new(
+        SQLiteAccountRepository::new(directory.connect("events").await?)
+            .with_chain_key(Arc::new(CONFORMANCE_CHAIN_KEY.into())),
+    );
+    repository.migrate(COMMAND_MIGRATIONS.into()).await?;
+    databases.lock().unwrap().push(directory);
+    return Ok(repository);
+}
+
+async fn memory_repository() -> Result<ConformanceRepository, anyhow::Error> {
+    return Ok(Arc::new(
+        InMemoryAccountRepository::new().with_chain_key(Arc::new(CONFORMANCE_CHAIN_KEY.into())),
+    ));
+}
+
+#[tokio::test]
+async fn sqlite_repository_conforms() {
+    let databases = Arc::new(Mutex::new(vec![]));
+    run_conformance_suite(|| sqlite_repository(databases.clone()))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn memory_repository_conforms() {
+    run_conformance_suite(memory_repository).await.unwrap();
+}
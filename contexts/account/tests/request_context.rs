@@ -0,0 +1,73 @@
+use std::net::SocketAddr;
+
+use account::command::infrastructure::adapters::inbound::graphql::TrustedProxies;
+use actix_web::test::TestRequest;
+
+const PROXY: &str = "10.0.0.1:4000";
+const INNER_PROXY: &str = "10.0.0.2";
+const CLIENT: &str = "203.0.113.7:5000";
+
+fn request(peer: &str) -> actix_web::HttpRequest {
+    return TestRequest::default()
+        .peer_addr(peer.parse::<SocketAddr>().unwrap())
+        .insert_header(("x-authenticated-user", "admin"))
+        .insert_header(("x-forwarded-for", "198.51.100.9"))
+        .insert_header(("user-agent", "tests"))
+        .to_http_request();
+}
+
+fn forwarded_request(forwarded_for: &str) -> actix_web::HttpRequest {
+    return TestRequest::default()
+        .peer_addr(PROXY.parse::<SocketAddr>().unwrap())
+        .insert_header(("x-forwarded-for", forwarded_for))
+        .to_http_request();
+}
+
+fn proxies() -> TrustedProxies {
+    return TrustedProxies::new(vec![
+        PROXY.parse::<SocketAddr>().unwrap().ip(),
+        INNER_PROXY.parse().unwrap(),
+    ]);
+}
+
+#[test]
+fn forwarded_identity_is_ignored_from_untrusted_peers() {
+    let context = proxies().request_context(&request(CLIENT));
+    assert_eq!(context.actor, None);
+    assert_eq!(context.client_ip.as_deref(), Some("203.0.113.7"));
+    assert_eq!(context.user_agent.as_deref(), Some("tests"));
+}
+
+#[test]
+fn forwarded_identity_is_believed_from_trusted_proxies() {
+    let context = proxies().request_context(&request(PROXY));
+    assert_eq!(context.actor.as_deref(), Some("admin"));
+    assert_eq!(context.client_ip.as_deref(), Some("198.51.100.9"));
+}
+
+#[test]
+fn nothing_is_trusted_by_default() {
+    let context = TrustedProxies::default().request_context(&request(PROXY));
+    assert_eq!(context.actor, None);
+    assert_eq!(context.client_ip.as_deref(), Some("10.0.0.1"));
+}
+
+#[test]
+fn spoofed_forwarded_entries_are_skipped() {
+    let context = proxies().request_context(&forwarded_request("1.2.3.4, 198.51.100.9"));
+    assert_eq!(context.client_ip.as_deref(), Some("198.51.100.9"));
+}
+
+#[test]
+fn forwarded_entries_are_walked_past_trusted_proxies() {
+    let forwarded_for = format!("1.2.3.4, 198.51.100.9, {}", INNER_PROXY);
+    let context = proxies().request_context(&forwarded_request(&forwarded_for));
+    assert_eq!(context.client_ip.as_deref(), Some("198.51.100.9"));
+}
+
+#[test]
+fn malformed_forwarded_entries_stop_the_walk() {
+    let forwarded_for = format!("198.51.100.9, unknown, {}", INNER_PROXY);
+    let context = proxies().request_context(&forwarded_request(&forwarded_for));
+    assert_eq!(context.client_ip.as_deref(), Some(INNER_PROXY));
+}
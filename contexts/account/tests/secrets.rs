@@ -0,0 +1,61 @@
+use account::{
+    command::{
+        domain::account::entity::{
+            command::{AccountCommand, CreateAccountCommand},
+            error::AccountError,
+            event::AccountEvent,
+        },
+        infrastructure::dtos::storage::sql::SQLAccountEvent,
+    },
+    common::domain::secret::{Password, PasswordHash, SecretToken},
+};
+use chrono::Utc;
+
+const PASSWORD: &str = "correct horse battery staple";
+const HASH: &str = "$argon2id$v=19$m=4096,t=3,p=1$c2FsdA$hash";
+
+fn create_account() -> AccountCommand {
+    return AccountCommand::CreateAccount(CreateAccountCommand {
+        email: "user@example.com".into(),
+        password: PASSWORD.into(),
+    });
+}
+
+#[test]
+fn secrets_are_redacted_when_formatted() {
+    let command = create_account();
+    let error = AccountError::StateMachineTransitionFail(create_account());
+    let event = AccountEvent::AccountCreated {
+        id: "id".into(),
+        email: "user@example.com".into(),
+        password_hash: HASH.into(),
+        created_at: Utc::now(),
+        event_version: "0.0.1".into(),
+        event_id: "1".into(),
+    };
+    let formatted = vec![
+        format!("{:?}", command),
+        format!("{}", error),
+        format!("{:?}", error),
+        format!("{:?}", event),
+        format!("{:?}", SQLAccountEvent::from(event.clone())),
+        format!("{}", Password::new(PASSWORD)),
+        format!("{:?}", SecretToken::new(PASSWORD)),
+    ];
+    for x in formatted {
+        assert!(
+            !x.contains(PASSWORD) && !x.contains(HASH),
+            "leaked in {}",
+            x
+        );
+    }
+}
+
+#[test]
+fn password_hash_is_stored_as_plain_string() {
+    let hash = PasswordHash::new(HASH);
+    let json = serde_json::to_string(&hash).unwrap();
+    assert_eq!(json, serde_json::to_string(HASH).unwrap());
+    let parsed: PasswordHash = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.expose(), HASH);
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use std::collections::HashMap;
+
+use account::command::{
+    application::account::ports::inbound::{
+        force_snapshot::ForceSnapshotUseCase, purge_snapshots::PurgeStaleSnapshotsUseCase,
+    },
+    domain::account::entity::{
+        aggregate::AccountAggregate,
+        event::{AccountEvent, PASSWORD_REHASHED_VERSION},
+    },
+    infrastructure::dtos::storage::sql::SNAPSHOT_SCHEMA_VERSION,
+};
+use chrono::Utc;
+use cqrs_rs::domain::entity::{aggregate::Aggregate, event::EventEnvelope};
+use futures::TryStreamExt;
+use ulid::Ulid;
+
+async fn snapshot_count(store: &common::Store) -> Result<i64, anyhow::Error> {
+    let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM account_snapshots")
+        .fetch_one(&store.connector.pool)
+        .await?;
+    return Ok(count.0);
+}
+
+#[tokio::test]
+async fn stale_snapshots_are_skipped_and_purged() {
+    let store = common::store().await.unwrap();
+    let id = common::create_account(&store, "stale@example.com")
+        .await
+        .unwrap();
+    let replayed = store
+        .service
+        .load_aggregate(id.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    store
+        .service
+        .force_snapshot(id.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    // Rewrite the row as an older build would have left it, in a shape the
+    // current one cannot read.
+    sqlx::query("UPDATE account_snapshots SET schema_version = ?1, payload = ?2")
+        .bind(SNAPSHOT_SCHEMA_VERSION - 1)
+        .bind("{\"unexpected\": true}")
+        .execute(&store.connector.pool)
+        .await
+        .unwrap();
+
+    let snapshot = store
+        .repository
+        .retrieve_latest_snapshot(id.clone())
+        .await
+        .unwrap();
+    assert!(snapshot.is_none());
+    let loaded = store
+        .service
+        .load_aggregate(id.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(format!("{:?}", replayed), format!("{:?}", loaded));
+    assert_eq!(replayed.password_hash, loaded.password_hash);
+
+    assert_eq!(store.service.purge_stale_snapshots().await.unwrap(), 1);
+    assert_eq!(snapshot_count(&store).await.unwrap(), 0);
+    assert!(store.service.load_aggregate(id).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn purge_keeps_current_snapshots() {
+    let store = common::store().await.unwrap();
+    let id = common::create_account(&store, "current@example.com")
+        .await
+        .unwrap();
+    store
+        .service
+        .force_snapshot(id.clone())
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(store.service.purge_stale_snapshots().await.unwrap(), 0);
+    assert_eq!(snapshot_count(&store).await.unwrap(), 1);
+    assert!(store
+        .repository
+        .retrieve_latest_snapshot(id)
+        .await
+        .unwrap()
+        .is_some());
+}
+
+async fn rehash(store: &common::Store, id: &str, count: usize) -> Result<(), anyhow::Error> {
+    let hash = store
+        .service
+        .load_aggregate(id.into())
+        .await?
+        .and_then(|x| x.password_hash)
+        .unwrap();
+    let events = (0..count)
+        .map(|_| {
+            let event_id = Ulid::new().to_string();
+            return EventEnvelope::<AccountAggregate> {
+                aggregate_id: id.into(),
+                aggregate_type: "account".into(),
+                sequence: event_id.clone(),
+                payload: AccountEvent::PasswordRehashed {
+                    id: id.into(),
+                    password_hash: hash.clone(),
+                    rehashed_at: Utc::now(),
+                    event_version: PASSWORD_REHASHED_VERSION.into(),
+                    event_id,
+                },
+                metadata: HashMap::new(),
+                timestamp: Utc::now(),
+            };
+        })
+        .collect();
+    store.repository.store_events(events).await?;
+    return Ok(());
+}
+
+async fn full_replay(store: &common::Store, id: &str) -> Result<AccountAggregate, anyhow::Error> {
+    let mut aggregate = AccountAggregate::default();
+    let mut events = store.repository.stream_events(id.into(), None);
+    while let Some(event) = events.try_next().await? {
+        aggregate.apply(event.payload);
+    }
+    return Ok(aggregate);
+}
+
+#[tokio::test]
+async fn snapshot_and_tail_match_a_full_replay() -> Result<(), anyhow::Error> {
+    let store = common::store().await?;
+    let id = common::create_account(&store, "tail@example.com").await?;
+    rehash(&store, &id, 11).await?;
+    store.service.force_snapshot(id.clone()).await?.unwrap();
+    rehash(&store, &id, 3).await?;
+
+    let mut loaded = store.service.load_aggregate(id.clone()).await?.unwrap();
+    let replayed = full_replay(&store, &id).await?;
+    assert_eq!(loaded.applied_events, 15);
+    assert_eq!(loaded.id, replayed.id);
+    assert_eq!(loaded.email, replayed.email);
+    assert_eq!(loaded.status, replayed.status);
+    assert_eq!(loaded.password_hash, replayed.password_hash);
+    assert_eq!(loaded.created_at, replayed.created_at);
+    assert_eq!(loaded.applied_events, replayed.applied_events);
+    assert_eq!(
+        format!("{:?}", loaded.last_event),
+        format!("{:?}", replayed.last_event)
+    );
+    // Only the three events since the snapshot count towards the next one.
+    assert!(loaded.snapshot().is_none());
+    return Ok(());
+}
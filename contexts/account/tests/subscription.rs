@@ -0,0 +1,199 @@
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use account::command::{
+    application::account::{
+        context::RequestContext,
+        ports::{
+            inbound::create_account::CreateAccountUseCase,
+            outbound::subscription::{CheckpointStore, EventHandler, PositionedEvent},
+        },
+        service::{
+            account::AccountService,
+            subscription::{CatchUpSubscription, SubscriptionConfig},
+        },
+    },
+    domain::account::entity::{aggregate::AccountAggregate, command::CreateAccountCommand},
+    infrastructure::adapters::outbound::sqlite::SQLiteAccountRepository,
+};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use tokio::{sync::watch, task::JoinHandle};
+
+use common::{services, store, Repository, Service, Store};
+
+const SUBSCRIPTION_ID: &str = "test";
+
+#[derive(Default)]
+struct Recorder {
+    positions: Mutex<Vec<i64>>,
+}
+
+impl Recorder {
+    fn positions(&self) -> Vec<i64> {
+        return self.positions.lock().unwrap().clone();
+    }
+
+    async fn wait_for(&self, count: usize) -> Result<Vec<i64>, anyhow::Error> {
+        let waited = tokio::time::timeout(Duration::from_secs(5), async {
+            while self.positions().len() < count {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        if waited.is_err() {
+            return Err(anyhow!(
+                "expected {} events, got {:?}",
+                count,
+                self.positions()
+            ));
+        }
+        return Ok(self.positions());
+    }
+}
+
+#[async_trait]
+impl EventHandler for Recorder {
+    async fn handle(&self, event: &PositionedEvent) -> Result<(), anyhow::Error> {
+        self.positions.lock().unwrap().push(event.position);
+        return Ok(());
+    }
+}
+
+/// A subscription over the store, with the service that writes to it
+/// sharing its commit notifications.
+struct Harness {
+    repository: Arc<SQLiteAccountRepository>,
+    service: Service,
+    _store: Store,
+}
+
+async fn harness() -> Result<Harness, anyhow::Error> {
+    let store = store().await?;
+    let repository = Arc::new(SQLiteAccountRepository::new(store.connector.clone()));
+    let writer: Repository = repository.clone();
+    return Ok(Harness {
+        service: AccountService::new(services()?, writer),
+        repository,
+        _store: store,
+    });
+}
+
+impl Harness {
+    async fn create(&self, email: &str) -> Result<(), anyhow::Error> {
+        let _: AccountAggregate = self
+            .service
+            .create_account(
+                CreateAccountCommand {
+                    email: email.into(),
+                    password: "correct horse battery staple".into(),
+                },
+                RequestContext::new(),
+                vec![],
+            )
+            .await?;
+        return Ok(());
+    }
+
+    /// Starts a subscription that only wakes on commits, as its poll
+    /// interval outlasts every test.
+    fn subscribe(
+        &self,
+        handler: Arc<Recorder>,
+        batch_size: i64,
+    ) -> (watch::Sender<bool>, JoinHandle<Result<(), anyhow::Error>>) {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let subscription = CatchUpSubscription::new(
+            SUBSCRIPTION_ID.into(),
+            self.repository.clone(),
+            self.repository.clone(),
+            handler,
+            self.repository.notifier.subscribe(),
+            shutdown_rx,
+        )
+        .with_config(SubscriptionConfig {
+            poll_interval: Duration::from_secs(3600),
+            batch_size,
+            ..Default::default()
+        });
+        return (shutdown_tx, tokio::spawn(subscription.run()));
+    }
+
+    async fn checkpoint(&self) -> Result<i64, anyhow::Error> {
+        return self
+            .repository
+            .load_checkpoint(SUBSCRIPTION_ID.into())
+            .await;
+    }
+}
+
+async fn stop(
+    subscription: (watch::Sender<bool>, JoinHandle<Result<(), anyhow::Error>>),
+) -> Result<(), anyhow::Error> {
+    subscription.0.send(true)?;
+    return subscription.1.await?;
+}
+
+#[tokio::test]
+async fn catches_up_from_the_checkpoint() -> Result<(), anyhow::Error> {
+    let harness = harness().await?;
+    for email in ["one@example.com", "two@example.com", "three@example.com"] {
+        harness.create(email).await?;
+    }
+    harness
+        .repository
+        .save_checkpoint(SUBSCRIPTION_ID.into(), 0, 1)
+        .await?;
+
+    // Batches of one make the catch-up read back to back, with no commit
+    // or poll in between.
+    let recorder = Arc::new(Recorder::default());
+    let subscription = harness.subscribe(recorder.clone(), 1);
+    assert_eq!(recorder.wait_for(2).await?, vec![2, 3]);
+    stop(subscription).await?;
+    assert_eq!(harness.checkpoint().await?, 3);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn switches_to_live_events_once_caught_up() -> Result<(), anyhow::Error> {
+    let harness = harness().await?;
+    harness.create("before@example.com").await?;
+
+    let recorder = Arc::new(Recorder::default());
+    let subscription = harness.subscribe(recorder.clone(), 10);
+    assert_eq!(recorder.wait_for(1).await?, vec![1]);
+    harness.create("live@example.com").await?;
+    harness.create("later@example.com").await?;
+    assert_eq!(recorder.wait_for(3).await?, vec![1, 2, 3]);
+    stop(subscription).await?;
+    assert_eq!(harness.checkpoint().await?, 3);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn resumes_after_a_restart() -> Result<(), anyhow::Error> {
+    let harness = harness().await?;
+    harness.create("first@example.com").await?;
+    harness.create("second@example.com").await?;
+
+    let before = Arc::new(Recorder::default());
+    let subscription = harness.subscribe(before.clone(), 10);
+    assert_eq!(before.wait_for(2).await?, vec![1, 2]);
+    stop(subscription).await?;
+
+    harness.create("offline@example.com").await?;
+    let after = Arc::new(Recorder::default());
+    let subscription = harness.subscribe(after.clone(), 10);
+    assert_eq!(after.wait_for(1).await?, vec![3]);
+    harness.create("online@example.com").await?;
+    assert_eq!(after.wait_for(2).await?, vec![3, 4]);
+    stop(subscription).await?;
+    assert_eq!(before.positions(), vec![1, 2]);
+    assert_eq!(harness.checkpoint().await?, 4);
+    return Ok(());
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use std::collections::HashMap;
+
+use account::command::{
+    application::account::{
+        context::RequestContext,
+        ports::inbound::{
+            delete_account::DeleteAccountUseCase, suspend_account::SuspendAccountUseCase,
+        },
+    },
+    domain::account::entity::{
+        aggregate::{AccountAggregate, ACCOUNT_STATUS_DELETED, ACCOUNT_STATUS_SUSPENDED},
+        command::{DeleteAccountCommand, SuspendAccountCommand},
+        error::AccountError,
+        event::{AccountEvent, PASSWORD_REHASHED_VERSION},
+    },
+};
+use chrono::Utc;
+use cqrs_rs::domain::entity::{
+    aggregate::Aggregate,
+    event::{DomainEvent, EventEnvelope},
+};
+use ulid::Ulid;
+
+use common::{create_account, store, Store};
+
+async fn suspend(store: &Store, id: &str) -> Result<AccountAggregate, anyhow::Error> {
+    return store
+        .service
+        .suspend_account(
+            SuspendAccountCommand {
+                id: id.into(),
+                reason: "abuse".into(),
+            },
+            RequestContext::new(),
+        )
+        .await;
+}
+
+async fn delete(store: &Store, id: &str) -> Result<AccountAggregate, anyhow::Error> {
+    return store
+        .service
+        .delete_account(
+            DeleteAccountCommand { id: id.into() },
+            RequestContext::new(),
+        )
+        .await;
+}
+
+fn refused(result: Result<AccountAggregate, anyhow::Error>) -> bool {
+    return matches!(
+        result.map_err(|e| e.downcast::<AccountError>()),
+        Err(Ok(AccountError::StateMachineTransitionFail(_)))
+    );
+}
+
+async fn event_count(store: &Store, id: &str) -> Result<usize, anyhow::Error> {
+    return Ok(store
+        .repository
+        .retrieve_events(id.into(), None)
+        .await?
+        .len());
+}
+
+#[tokio::test]
+async fn suspended_accounts_can_be_deleted() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = create_account(&store, "suspended@example.com").await?;
+    assert_eq!(
+        suspend(&store, &id).await?.status.as_deref(),
+        Some(ACCOUNT_STATUS_SUSPENDED)
+    );
+    assert!(refused(suspend(&store, &id).await));
+    assert_eq!(
+        delete(&store, &id).await?.status.as_deref(),
+        Some(ACCOUNT_STATUS_DELETED)
+    );
+    assert_eq!(event_count(&store, &id).await?, 3);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn deleted_accounts_accept_no_further_commands() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = create_account(&store, "deleted@example.com").await?;
+    delete(&store, &id).await?;
+    assert!(refused(suspend(&store, &id).await));
+    assert!(refused(delete(&store, &id).await));
+    assert_eq!(event_count(&store, &id).await?, 2);
+    return Ok(());
+}
+
+#[tokio::test]
+async fn rehashes_leave_a_suspended_account_suspended() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = create_account(&store, "rehashed@example.com").await?;
+    suspend(&store, &id).await?;
+    // A login verified just before the suspension rehashes the password
+    // right after it.
+    let event_id = Ulid::new().to_string();
+    let rehash = EventEnvelope::<AccountAggregate> {
+        aggregate_id: id.clone(),
+        aggregate_type: "account".into(),
+        sequence: event_id.clone(),
+        payload: AccountEvent::PasswordRehashed {
+            id: id.clone(),
+            password_hash: "hash".into(),
+            rehashed_at: Utc::now(),
+            event_version: PASSWORD_REHASHED_VERSION.into(),
+            event_id,
+        },
+        metadata: HashMap::new(),
+        timestamp: Utc::now(),
+    };
+    store.repository.append_events(2, vec![rehash]).await?;
+
+    let aggregate = store.service.load_aggregate(id.clone()).await?.unwrap();
+    assert!(matches!(
+        aggregate.last_event,
+        Some(AccountEvent::PasswordRehashed { .. })
+    ));
+    assert_eq!(aggregate.status.as_deref(), Some(ACCOUNT_STATUS_SUSPENDED));
+    assert!(refused(suspend(&store, &id).await));
+    delete(&store, &id).await?;
+    return Ok(());
+}
+
+#[tokio::test]
+async fn commands_decided_on_a_stale_aggregate_are_refused() -> Result<(), anyhow::Error> {
+    let store = store().await?;
+    let id = create_account(&store, "stale@example.com").await?;
+    let stale = store.service.load_aggregate(id.clone()).await?.unwrap();
+    // Another request deletes the account in the meantime.
+    delete(&store, &id).await?;
+
+    let events = stale
+        .handle(
+            SuspendAccountCommand {
+                id: id.clone(),
+                reason: "abuse".into(),
+            }
+            .into(),
+            &common::services()?,
+        )
+        .await?;
+    let envelopes = events
+        .into_iter()
+        .map(|x| EventEnvelope::<AccountAggregate> {
+            aggregate_id: id.clone(),
+            aggregate_type: "account".into(),
+            sequence: x.event_id(),
+            payload: x,
+            metadata: HashMap::new(),
+            timestamp: Utc::now(),
+        })
+        .collect();
+    let appended = store
+        .repository
+        .append_events(stale.applied_events as i64, envelopes)
+        .await;
+    assert!(matches!(
+        appended.map_err(|e| e.downcast::<AccountError>()),
+        Err(Ok(AccountError::ConcurrentModification(_)))
+    ));
+    assert_eq!(event_count(&store, &id).await?, 2);
+    let aggregate = store.service.load_aggregate(id).await?.unwrap();
+    assert_eq!(aggregate.status.as_deref(), Some(ACCOUNT_STATUS_DELETED));
+    return Ok(());
+}
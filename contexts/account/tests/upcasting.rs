@@ -0,0 +1,130 @@
+use account::command::infrastructure::upcasting::{
+    account::account_upcasters,
+    registry::{UpcastError, UpcasterRegistry},
+};
+use serde_json::{json, Value};
+
+fn registry() -> UpcasterRegistry {
+    return UpcasterRegistry::new()
+        .current("Renamed", "0.0.3")
+        .register("Renamed", "0.0.1", "0.0.2", |mut x| {
+            let name = x["name"].take();
+            x["full_name"] = name;
+            return Ok(x);
+        })
+        .register("Renamed", "0.0.2", "0.0.3", |mut x| {
+            x["active"] = Value::Bool(true);
+            return Ok(x);
+        });
+}
+
+#[test]
+fn chained_upcasts_apply_every_step_in_order() {
+    let payload = json!({"event_version": "0.0.1", "name": "alice"});
+    let (version, payload) = registry().upcast("Renamed", "0.0.1", payload).unwrap();
+    assert_eq!(version, "0.0.3");
+    assert_eq!(
+        payload,
+        json!({"event_version": "0.0.3", "name": null, "full_name": "alice", "active": true})
+    );
+}
+
+#[test]
+fn current_payloads_pass_through_untouched() {
+    let payload = json!({"event_version": "0.0.3", "full_name": "alice"});
+    let (version, upcast) = registry()
+        .upcast("Renamed", "0.0.3", payload.clone())
+        .unwrap();
+    assert_eq!(version, "0.0.3");
+    assert_eq!(upcast, payload);
+}
+
+#[test]
+fn unknown_versions_are_rejected() {
+    let error = registry()
+        .upcast("Renamed", "9.9.9", json!({}))
+        .unwrap_err();
+    assert!(
+        matches!(&error, UpcastError::UnknownVersion { event_type, event_version }
+            if event_type == "Renamed" && event_version == "9.9.9"),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn a_gap_in_the_chain_reports_the_missing_step() {
+    let registry = UpcasterRegistry::new()
+        .current("Gapped", "0.0.3")
+        .register("Gapped", "0.0.1", "0.0.2", Ok);
+    let error = registry.upcast("Gapped", "0.0.1", json!({})).unwrap_err();
+    assert!(
+        matches!(&error, UpcastError::UnknownVersion { event_version, .. } if event_version == "0.0.2"),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn unregistered_event_types_are_rejected() {
+    let error = registry()
+        .upcast("Unknown", "0.0.1", json!({}))
+        .unwrap_err();
+    assert!(matches!(&error, UpcastError::UnknownEventType(x) if x == "Unknown"));
+}
+
+#[test]
+fn failing_upcasters_surface_the_step_that_failed() {
+    let registry = registry().register("Renamed", "0.0.0", "0.0.1", |_| {
+        return Err(anyhow::anyhow!("corrupt payload"));
+    });
+    let error = registry.upcast("Renamed", "0.0.0", json!({})).unwrap_err();
+    assert!(
+        matches!(&error, UpcastError::Failed { event_version, .. } if event_version == "0.0.0"),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn cyclic_chains_terminate() {
+    let registry = UpcasterRegistry::new()
+        .current("Looped", "0.0.3")
+        .register("Looped", "0.0.1", "0.0.2", Ok)
+        .register("Looped", "0.0.2", "0.0.1", Ok);
+    let error = registry.upcast("Looped", "0.0.1", json!({})).unwrap_err();
+    assert!(matches!(&error, UpcastError::Cycle(x) if x == "Looped"));
+}
+
+#[test]
+fn tagged_payloads_need_type_and_version() {
+    let error = registry()
+        .upcast_tagged(json!({"event_version": "0.0.1"}))
+        .unwrap_err();
+    assert!(matches!(&error, UpcastError::MissingField(x) if x == "event_type"));
+    let error = registry()
+        .upcast_tagged(json!({"event_type": "Renamed"}))
+        .unwrap_err();
+    assert!(matches!(&error, UpcastError::MissingField(x) if x == "event_version"));
+    let payload = registry()
+        .upcast_tagged(json!({"event_type": "Renamed", "event_version": "0.0.2"}))
+        .unwrap();
+    assert_eq!(payload["event_version"], "0.0.3");
+}
+
+#[test]
+fn account_events_are_registered_at_their_current_version() {
+    let registry = account_upcasters();
+    for event_type in [
+        "AccountCreated",
+        "AccountSuspended",
+        "AccountDeleted",
+        "PasswordRehashed",
+    ] {
+        assert!(
+            registry.current_version(event_type).is_some(),
+            "{}",
+            event_type
+        );
+    }
+}